@@ -0,0 +1,2382 @@
+use anyhow::{Error, Result};
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use log::{debug, info};
+use ollama_rs::Ollama;
+use qdrant_client::client::QdrantClient;
+use rura_core::agent::Agent;
+use rura_core::answer::{generate_answer, AnswerStyle};
+use rura_core::chat::{condense_question, render_chat_prompt, ChatSession};
+use rura_core::classify::QueryClassifier;
+use rura_core::collection_model::CollectionModels;
+use rura_core::config::{Config, DEFAULT_CONFIG_PATH};
+use rura_core::data::{parse_collections, ChunkStrategy, Collection, Document, ScoredDocument};
+use rura_core::embedding::{embedding_size, text_embedding_async, EmbeddingProgress};
+use rura_core::embedding_provider::{
+    EmbeddingConfig, EmbeddingProviderKind, SharedEmbeddingProvider,
+};
+use rura_core::estimate::MEGABYTE;
+use rura_core::eval::{load_eval_cases, run_eval};
+use rura_core::forum::{fetch_threads, ForumKind, Thread};
+use rura_core::git::checkout;
+use rura_core::glossary::{Glossary, Synonyms};
+use rura_core::help_center::{fetch_articles, Article, HelpCenterKind};
+use rura_core::ingest::{
+    diff_crawl, estimate_index, run_upload, QuestionStage, SummaryStage, TransformStage,
+    UploadConfig,
+};
+use rura_core::ingest_source::{
+    documents_of_locations, ingest_source, ingest_source_names, CrawlerSource, SharedIngestSource,
+    SitemapSource, CRAWLER_SOURCE, SITEMAP_SOURCE,
+};
+use rura_core::migrate::migrate_collections;
+use rura_core::mirror::{self, check_consistency, mirror_write};
+use rura_core::ollama::Llm;
+use rura_core::progress_store::ProgressStore;
+use rura_core::progress_tracker::{Stage, StageProgress};
+use rura_core::promote::promote_collections;
+use rura_core::prompt_log::{PromptLogConfig, PromptLogger, TermRedactor};
+use rura_core::qa_dump::{read_dump, DumpFormat};
+use rura_core::qdrant::{
+    create_collections, delete_documents_by_url, get_recorded_commit, list_collections,
+    search_scored_documents, CollectionConfig, SearchFilter, VectorSearch, WriteOptions,
+    WriteOrder,
+};
+use rura_core::query::{
+    citations, context_fragments, pack_context, preview_prompt, render_prompt, AnswerOutput,
+};
+use rura_core::release_notes::PageParser;
+use rura_core::rerank::Reranker;
+use rura_core::retrieval_hook::{RequestMetadata, RetrievalHooks};
+use rura_core::retriever::{fetch_content, sitemap_with_progress, walk_directory, SitemapOptions};
+use rura_core::retry::{retry_qdrant, set_retry_policy};
+use rura_core::spelling::{load_vocabulary, SpellCorrector, SpellingMode};
+use rura_core::summary::{add_queued_summary, SummaryPolicy};
+use rura_core::telemetry;
+use rura_core::transform::TransformChain;
+use rura_core::wizard::{probe, TEST_INGEST_PAGES};
+#[cfg(feature = "server")]
+use rura_server::server::{serve, ServeOptions};
+#[cfg(feature = "server")]
+use rura_server::state::{AppConfigInput, AppState};
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tiktoken_rs::p50k_base;
+
+// GIT_CACHE_DIR is the directory in the temp dir repositories are cloned into by default
+static GIT_CACHE_DIR: &str = "rura-git";
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Address of the Qdrant client
+    /// defaults to the config file or http://localhost:6334
+    #[clap(short, long)]
+    address: Option<String>,
+
+    /// collection used with the Qdrant client
+    /// defaults to the config file or rura_collection
+    #[clap(short, long)]
+    base_collection: Option<String>,
+
+    /// filter_collections is a comma separated list of collections to filter by
+    /// if not specified, the collections of the config file or basic are searched
+    /// valid values are: basic, summary, questions or all, case insensitive
+    /// example: --filter_collections=basic,summary
+    #[clap(short, long, value_parser = parse_collections)]
+    filter_collections: Option<::std::vec::Vec<Collection>>,
+
+    /// prompt_log is the path of a jsonl file prompts and completions are logged to
+    /// if not specified, prompts are not logged
+    #[clap(long)]
+    prompt_log: Option<PathBuf>,
+
+    /// prompt_log_sample_rate is the share of prompts logged, between 0.0 and 1.0
+    #[clap(long, default_value = "1.0")]
+    prompt_log_sample_rate: f64,
+
+    /// prompt_log_redact is a comma separated list of terms redacted from logged prompts
+    #[clap(long, use_value_delimiter = true, value_delimiter = ',')]
+    prompt_log_redact: Vec<String>,
+
+    /// embedding_batch_size is the number of fragments embedded per encode call
+    #[clap(long, default_value = "32")]
+    embedding_batch_size: usize,
+
+    /// embedding_provider embeds the documents and queries
+    /// valid values are: rust_bert, ollama, openai
+    #[clap(long, default_value = "rust_bert")]
+    embedding_provider: EmbeddingProviderKind,
+
+    /// embedding_model is the model of the ollama and openai embedding providers
+    #[clap(long)]
+    embedding_model: Option<String>,
+
+    /// embedding_url is the base url of the ollama and openai embedding providers
+    /// example: --embedding-url=http://localhost:11434
+    #[clap(long)]
+    embedding_url: Option<String>,
+
+    /// embedding_api_key is the api key of the openai embedding provider
+    #[clap(long)]
+    embedding_api_key: Option<String>,
+
+    /// embedding_query_lane loads a second rust-bert model for the queries, so they don't wait
+    /// for the ingest batches
+    #[clap(long)]
+    embedding_query_lane: bool,
+
+    /// glossary is the path of a json file mapping internal terms to their definitions
+    /// the definitions of the terms used in a query are added to the prompt and the query
+    #[clap(long)]
+    glossary: Option<PathBuf>,
+
+    /// synonyms is the path of a json file mapping terms to their synonyms, e.g. k8s to
+    /// kubernetes, the synonyms of the terms used in a query are appended before embedding
+    #[clap(long)]
+    synonyms: Option<PathBuf>,
+
+    /// config is the path of a toml file with the settings and the crawl profiles keyed by
+    /// domain, the profile of the domain of the uploaded url sets its defaults
+    /// if not specified, rura.toml is read if it exists, env variables override the file and
+    /// flags override both
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// shard_number is the number of shards of created collections, for clustered qdrant
+    /// if not specified, the qdrant default is used
+    #[clap(long)]
+    shard_number: Option<u32>,
+
+    /// replication_factor is the number of copies of each shard of created collections
+    #[clap(long)]
+    replication_factor: Option<u32>,
+
+    /// write_consistency_factor is the number of replicas which must acknowledge a write
+    /// to created collections, at most the replication factor
+    #[clap(long)]
+    write_consistency_factor: Option<u32>,
+
+    /// on_disk_payload stores the payloads of created collections on disk instead of in memory
+    #[clap(long)]
+    on_disk_payload: bool,
+
+    /// on_disk_vectors stores the vectors of created collections memory mapped on disk
+    #[clap(long)]
+    on_disk_vectors: bool,
+
+    /// memmap_threshold_kb is the segment size in kilobytes from which qdrant memory maps the
+    /// vectors of created collections
+    /// example: --memmap-threshold-kb=20000
+    #[clap(long)]
+    memmap_threshold_kb: Option<u64>,
+
+    /// named_vectors stores a content and a title vector per point in created collections, to
+    /// search the titles with --vector_search
+    #[clap(long)]
+    named_vectors: bool,
+
+    /// mirror_address is the address of a secondary qdrant the upserts and deletes are mirrored
+    /// to, e.g. a second region or the target of a migration
+    /// example: --mirror-address=http://qdrant-eu:6334
+    #[clap(long)]
+    mirror_address: Option<String>,
+
+    /// metrics_summary logs the ingestion, embedding, qdrant and ollama metrics of the run
+    /// once the command is done
+    #[clap(long)]
+    metrics_summary: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "snake_case")]
+enum Command {
+    Upload {
+        /// url is the site or sitemap uploaded, several urls separated by commas or repeated are
+        /// read concurrently and uploaded in one run
+        #[clap(
+            short,
+            long,
+            required = true,
+            use_value_delimiter = true,
+            value_delimiter = ','
+        )]
+        url: Vec<String>,
+
+        /// source is the ingest source reading the url: sitemap, crawler or file
+        /// defaults to sitemap
+        #[clap(long)]
+        source: Option<String>,
+
+        /// ollama_host defaults to the config file or http://localhost
+        #[clap(long)]
+        ollama_host: Option<String>,
+
+        #[clap(long)]
+        ollama_port: Option<u16>,
+
+        /// ollama_model defaults to the config file or openhermes2.5-mistral:7b-q6_K
+        #[clap(long)]
+        ollama_model: Option<String>,
+
+        /// locales is a comma separated list of languages to restrict the upload to
+        /// if not specified, documents of all languages are uploaded
+        /// example: --locales=en,de-ch
+        #[clap(long, use_value_delimiter = true, value_delimiter = ',')]
+        locales: Vec<String>,
+
+        /// concurrency is the maximum number of concurrent requests of the crawl
+        /// defaults to the crawl profile of the domain or 10
+        #[clap(long)]
+        concurrency: Option<usize>,
+
+        /// crawl_delay_ms is the minimum delay between two requests to the same host
+        /// a longer crawl-delay of the robots.txt of the host is honored
+        /// defaults to the crawl profile of the domain or 200
+        #[clap(long)]
+        crawl_delay_ms: Option<u64>,
+
+        /// ignore_robots fetches the urls disallowed by robots.txt as well
+        #[clap(long)]
+        ignore_robots: bool,
+
+        /// summary_policy handles failing summaries, e.g. when Ollama is down
+        /// valid values are: fail_fast, skip, queue
+        /// queued summaries are retried once after all documents are uploaded
+        #[clap(long, default_value = "skip")]
+        summary_policy: SummaryPolicy,
+
+        /// incremental skips the fragments already stored by a previous upload, e.g. when
+        /// uploading the same sitemap again
+        #[clap(long)]
+        incremental: bool,
+
+        /// lead_first indexes the lead fragments of all pages before their deep fragments, so
+        /// every page is searchable early
+        #[clap(long)]
+        lead_first: bool,
+
+        /// dedup drops the fragments nearly identical to an earlier fragment of the upload,
+        /// e.g. shared footers and cookie banners, before they are embedded
+        #[clap(long)]
+        dedup: bool,
+
+        /// dedup_threshold is the estimated similarity from 0 to 1 fragments are dropped at
+        #[clap(long, default_value = "0.9")]
+        dedup_threshold: f32,
+
+        /// lead_tokens adds a boosted lead fragment of the first lead_tokens tokens of each
+        /// page, defaults to the crawl profile of the domain
+        #[clap(long)]
+        lead_tokens: Option<usize>,
+
+        /// dry_run crawls and chunks the pages and prints the estimated size of the index
+        /// without summarizing, embedding or storing anything
+        #[clap(long)]
+        dry_run: bool,
+
+        /// memory_budget_mb is the memory qdrant can use, the dry run warns if the index
+        /// exceeds it
+        #[clap(long)]
+        memory_budget_mb: Option<u64>,
+
+        /// wait upserts each document before uploading the next one so it is searchable right
+        /// away, trading upload speed for durability
+        #[clap(long)]
+        wait: bool,
+
+        /// write_ordering is the ordering guarantee of the upserts across replicas
+        /// valid values are: weak, medium, strong
+        #[clap(long, default_value = "weak")]
+        write_ordering: WriteOrder,
+
+        /// parser splits the pages into documents, release_notes splits changelogs by version
+        /// heading and stores the version and date in the metadata
+        /// valid values are: default, release_notes
+        /// defaults to the crawl profile of the domain or default
+        #[clap(long)]
+        parser: Option<PageParser>,
+
+        /// chunk_strategy measures the fragments in characters or tokens, or keeps sentences
+        /// and paragraphs whole
+        /// valid values are: characters, tokens, sentences
+        /// defaults to the crawl profile of the domain, the config file or characters
+        #[clap(long)]
+        chunk_strategy: Option<ChunkStrategy>,
+
+        /// fragment_size is the minimum size of a fragment in characters, or tokens with the
+        /// tokens chunk strategy, defaults to the crawl profile of the domain or 1512
+        #[clap(long)]
+        fragment_size: Option<usize>,
+
+        /// overlap_size is the size a fragment may exceed the fragment size by
+        /// defaults to the crawl profile of the domain or 256
+        #[clap(long)]
+        overlap_size: Option<usize>,
+
+        /// parent_size embeds chunks of fragment_size and returns their parent window of
+        /// parent_size in searches, defaults to the crawl profile of the domain
+        #[clap(long)]
+        parent_size: Option<usize>,
+
+        /// follow_pagination crawls the further pages of paginated listings, e.g. blog
+        /// archives, by their rel=next or common next page links
+        #[clap(long)]
+        follow_pagination: bool,
+
+        /// max_pagination_pages is the maximum number of further pages followed from a
+        /// listing, defaults to the crawl profile of the domain or 20
+        #[clap(long)]
+        max_pagination_pages: Option<usize>,
+    },
+    UploadDir {
+        /// path of the directory the .md, .txt and .rst files are read from recursively
+        #[clap(short, long)]
+        path: PathBuf,
+
+        /// ollama_host defaults to the config file or http://localhost
+        #[clap(long)]
+        ollama_host: Option<String>,
+
+        #[clap(long)]
+        ollama_port: Option<u16>,
+
+        /// ollama_model defaults to the config file or openhermes2.5-mistral:7b-q6_K
+        #[clap(long)]
+        ollama_model: Option<String>,
+
+        /// summary_policy handles failing summaries
+        /// valid values are: fail_fast, skip, queue
+        #[clap(long, default_value = "skip")]
+        summary_policy: SummaryPolicy,
+
+        /// incremental skips the fragments already stored by a previous upload of the directory
+        #[clap(long)]
+        incremental: bool,
+    },
+    UploadGit {
+        /// repo is the url of the repository to clone or the path of a local checkout
+        #[clap(short, long)]
+        repo: String,
+
+        /// branch is the branch cloned, the default branch of the repository if not specified
+        #[clap(long)]
+        branch: Option<String>,
+
+        /// glob is a comma separated list of globs of the files indexed
+        /// example: --glob='docs/**/*.md,README.md'
+        #[clap(
+            long,
+            default_value = "**/*.md,**/*.rst,**/*.txt",
+            use_value_delimiter = true,
+            value_delimiter = ','
+        )]
+        glob: Vec<String>,
+
+        /// cache_dir is the directory repositories are cloned into and pulled on the next upload
+        /// if not specified, a directory in the temp dir is used
+        #[clap(long)]
+        cache_dir: Option<PathBuf>,
+
+        /// ollama_host defaults to the config file or http://localhost
+        #[clap(long)]
+        ollama_host: Option<String>,
+
+        #[clap(long)]
+        ollama_port: Option<u16>,
+
+        /// ollama_model defaults to the config file or openhermes2.5-mistral:7b-q6_K
+        #[clap(long)]
+        ollama_model: Option<String>,
+
+        /// summary_policy handles failing summaries
+        /// valid values are: fail_fast, skip, queue
+        #[clap(long, default_value = "skip")]
+        summary_policy: SummaryPolicy,
+    },
+    UploadForum {
+        /// url is the url of the discourse forum or category, or of the github repository
+        /// example: --url=https://github.com/uselagoon/lagoon
+        #[clap(short, long)]
+        url: String,
+
+        /// kind is the source of the threads
+        /// valid values are: discourse, github_issues, github_discussions
+        #[clap(long, default_value = "discourse")]
+        kind: ForumKind,
+
+        /// max_threads is the maximum number of threads fetched
+        #[clap(long, default_value = "500")]
+        max_threads: usize,
+
+        /// token authenticates against github, needed for discussions
+        /// defaults to the GITHUB_TOKEN env variable
+        #[clap(long)]
+        token: Option<String>,
+
+        /// ollama_host defaults to the config file or http://localhost
+        #[clap(long)]
+        ollama_host: Option<String>,
+
+        #[clap(long)]
+        ollama_port: Option<u16>,
+
+        /// ollama_model defaults to the config file or openhermes2.5-mistral:7b-q6_K
+        #[clap(long)]
+        ollama_model: Option<String>,
+
+        /// summary_policy handles failing summaries
+        /// valid values are: fail_fast, skip, queue
+        #[clap(long, default_value = "skip")]
+        summary_policy: SummaryPolicy,
+    },
+    UploadHelpCenter {
+        /// url is the url of the zendesk help center, or of the intercom help center
+        /// example: --url=https://example.zendesk.com
+        #[clap(short, long)]
+        url: String,
+
+        /// kind is the source of the articles
+        /// valid values are: zendesk, intercom
+        #[clap(long, default_value = "zendesk")]
+        kind: HelpCenterKind,
+
+        /// locale of the zendesk articles
+        #[clap(long, default_value = "en-us")]
+        locale: String,
+
+        /// max_articles is the maximum number of articles fetched
+        #[clap(long, default_value = "1000")]
+        max_articles: usize,
+
+        /// token authenticates against the api, needed for intercom and private zendesk help
+        /// centers, defaults to the ZENDESK_TOKEN or INTERCOM_TOKEN env variable
+        #[clap(long)]
+        token: Option<String>,
+
+        /// ollama_host defaults to the config file or http://localhost
+        #[clap(long)]
+        ollama_host: Option<String>,
+
+        #[clap(long)]
+        ollama_port: Option<u16>,
+
+        /// ollama_model defaults to the config file or openhermes2.5-mistral:7b-q6_K
+        #[clap(long)]
+        ollama_model: Option<String>,
+
+        /// summary_policy handles failing summaries
+        /// valid values are: fail_fast, skip, queue
+        #[clap(long, default_value = "skip")]
+        summary_policy: SummaryPolicy,
+    },
+    UploadQaDump {
+        /// path of the Posts.xml of a stack exchange data dump or of a json export of the
+        /// stack exchange api with answers
+        #[clap(short, long)]
+        path: PathBuf,
+
+        /// url is the url of the site the questions link to
+        /// example: --url=https://stackoverflow.example.com
+        #[clap(short, long)]
+        url: String,
+
+        /// format of the dump, defaults to json for .json files and xml otherwise
+        /// valid values are: xml, json
+        #[clap(long)]
+        format: Option<DumpFormat>,
+
+        /// max_threads is the maximum number of questions read
+        #[clap(long, default_value = "10000")]
+        max_threads: usize,
+
+        /// ollama_host defaults to the config file or http://localhost
+        #[clap(long)]
+        ollama_host: Option<String>,
+
+        #[clap(long)]
+        ollama_port: Option<u16>,
+
+        /// ollama_model defaults to the config file or openhermes2.5-mistral:7b-q6_K
+        #[clap(long)]
+        ollama_model: Option<String>,
+
+        /// summary_policy handles failing summaries
+        /// valid values are: fail_fast, skip, queue
+        #[clap(long, default_value = "skip")]
+        summary_policy: SummaryPolicy,
+    },
+    Query {
+        #[clap(short, long)]
+        query: String,
+
+        #[clap(short, long, default_value = "7")]
+        limit: u64,
+
+        /// ollama_host defaults to the config file or http://localhost
+        #[clap(long)]
+        ollama_host: Option<String>,
+
+        #[clap(long)]
+        ollama_port: Option<u16>,
+
+        /// ollama_model defaults to the config file or openhermes2.5-mistral:7b-q6_K
+        #[clap(long)]
+        ollama_model: Option<String>,
+
+        /// explain prints the rendered prompt, the selected chunks and the token usage
+        /// without generating an answer
+        #[clap(long)]
+        explain: bool,
+
+        /// agent lets the LLM search again, fetch pages and list sources before answering
+        #[clap(long)]
+        agent: bool,
+
+        /// agent_steps is the maximum number of tool calls in agent mode
+        #[clap(long, default_value = "4")]
+        agent_steps: usize,
+
+        /// scope describes the topics of the documentation, out of scope queries are
+        /// answered without retrieval
+        #[clap(long)]
+        scope: Option<String>,
+
+        /// retries of empty, refusing or ungrounded answers
+        #[clap(long, default_value = "2")]
+        retries: usize,
+
+        /// answer_style sets the length and detail level of the answer
+        /// valid values are: short, normal, detailed
+        #[clap(long, default_value = "normal")]
+        answer_style: AnswerStyle,
+
+        /// url_prefix restricts the context to urls or url paths starting with it, e.g. /docs/
+        #[clap(long)]
+        url_prefix: Option<String>,
+
+        /// indexed_after restricts the context to documents indexed after the rfc3339 date
+        /// example: --indexed_after=2024-01-01T00:00:00Z
+        #[clap(long)]
+        indexed_after: Option<DateTime<Utc>>,
+
+        /// indexed_before restricts the context to documents indexed before the rfc3339 date
+        #[clap(long)]
+        indexed_before: Option<DateTime<Utc>>,
+
+        /// title restricts the context to documents whose title contains it
+        #[clap(long)]
+        title: Option<String>,
+
+        /// release_version restricts the context to the releases of release notes pages of the
+        /// version, e.g. 2.3 matches 2.3.0 and 2.3.1
+        #[clap(long)]
+        release_version: Option<String>,
+
+        /// include_deprecated includes the archived or deprecated articles in the context,
+        /// downranked, they are excluded by default
+        #[clap(long)]
+        include_deprecated: bool,
+
+        /// vector_search searches the title vectors of collections with named vectors
+        /// title_first fills the results the titles leave with the content
+        /// valid values are: content, title, title_first
+        #[clap(long, default_value = "content")]
+        vector_search: VectorSearch,
+
+        /// spelling corrects the typos of the query before embedding
+        /// valid values are: off, dictionary, llm
+        #[clap(long, default_value = "off")]
+        spelling: SpellingMode,
+
+        /// rerank re-scores the retrieved fragments against the query with the LLM
+        #[clap(long)]
+        rerank: bool,
+
+        /// rerank_top_n is the number of retrieved fragments re-scored
+        #[clap(long, default_value = "10")]
+        rerank_top_n: usize,
+
+        /// max_context_tokens packs the highest scored fragments into the token budget
+        /// lower scored fragments are truncated or dropped, all are used if not specified
+        /// example: --max_context_tokens=3000
+        #[clap(long)]
+        max_context_tokens: Option<usize>,
+
+        /// inline_citations numbers the sources of the context and asks the model to cite them
+        /// inline, e.g. [1], the sources are listed after the answer either way
+        #[clap(long)]
+        inline_citations: bool,
+
+        /// metadata are the attributes of the caller the retrieval hooks of the config file
+        /// decide by, repeatable
+        /// example: --metadata=plan=enterprise
+        #[clap(long, value_parser = parse_metadata)]
+        metadata: Vec<(String, String)>,
+
+        /// json_output prints the answer, its attempt, the citations and the request id as json
+        #[clap(long)]
+        json_output: bool,
+
+        /// show_context prints the retrieved fragments before the answer, or adds them to the
+        /// json output
+        #[clap(long)]
+        show_context: bool,
+
+        /// stream prints the tokens of the answer while they are generated, a streamed answer is
+        /// not checked and retried
+        #[clap(long, conflicts_with = "json_output")]
+        stream: bool,
+    },
+    Chat {
+        #[clap(short, long, default_value = "7")]
+        limit: u64,
+
+        /// ollama_host defaults to the config file or http://localhost
+        #[clap(long)]
+        ollama_host: Option<String>,
+
+        #[clap(long)]
+        ollama_port: Option<u16>,
+
+        /// ollama_model defaults to the config file or openhermes2.5-mistral:7b-q6_K
+        #[clap(long)]
+        ollama_model: Option<String>,
+
+        /// answer_style sets the length and detail level of the answers
+        /// valid values are: short, normal, detailed
+        #[clap(long, default_value = "normal")]
+        answer_style: AnswerStyle,
+
+        /// spelling corrects the typos of the standalone queries before embedding
+        /// valid values are: off, dictionary, llm
+        #[clap(long, default_value = "off")]
+        spelling: SpellingMode,
+
+        /// max_context_tokens packs the highest scored fragments into the token budget
+        #[clap(long)]
+        max_context_tokens: Option<usize>,
+
+        /// inline_citations numbers the sources of the context and asks the model to cite them
+        /// inline, e.g. [1]
+        #[clap(long)]
+        inline_citations: bool,
+    },
+    #[cfg(feature = "server")]
+    Serve {
+        /// listen is the address the server listens on
+        /// defaults to the ADDRESS env variable or 127.0.0.1:3000
+        #[clap(long)]
+        listen: Option<String>,
+
+        /// ollama_host defaults to the config file or http://localhost
+        #[clap(long)]
+        ollama_host: Option<String>,
+
+        #[clap(long)]
+        ollama_port: Option<u16>,
+
+        /// ollama_model defaults to the config file or openhermes2.5-mistral:7b-q6_K
+        #[clap(long)]
+        ollama_model: Option<String>,
+
+        /// fast_model drafts streamed answers and classifies queries
+        /// defaults to the config file, unset if empty
+        #[clap(long)]
+        fast_model: Option<String>,
+
+        /// scope describes the topics of the documentation, out of scope queries are
+        /// answered without retrieval
+        #[clap(long)]
+        scope: Option<String>,
+
+        /// out_of_scope_message is the answer to out of scope queries
+        #[clap(long)]
+        out_of_scope_message: Option<String>,
+
+        /// retries of empty, refusing or ungrounded answers
+        #[clap(long, default_value = "2")]
+        retries: usize,
+
+        /// spelling corrects the typos of the queries before embedding
+        /// valid values are: off, dictionary, llm
+        #[clap(long, default_value = "off")]
+        spelling: SpellingMode,
+
+        /// embedding_cache_size is the number of fragment embeddings cached across jobs, 0
+        /// disables the cache
+        #[clap(long, default_value = "10000")]
+        embedding_cache_size: usize,
+
+        /// answer_cache_size is the number of answers cached to answer queries while qdrant or
+        /// ollama are unavailable, 0 disables the cache
+        #[clap(long, default_value = "1000")]
+        answer_cache_size: usize,
+
+        /// sources_path is the json file the re-crawled sources are persisted to, they are kept
+        /// in memory if not specified
+        #[clap(long)]
+        sources_path: Option<PathBuf>,
+
+        /// jobs_path is the json file the progress of the upload jobs is persisted to, they are
+        /// kept in memory if not specified
+        #[clap(long)]
+        jobs_path: Option<PathBuf>,
+
+        /// max_concurrent_jobs is the number of upload jobs running at once, the other jobs wait
+        /// for a slot, all jobs run at once if not specified
+        #[clap(long)]
+        max_concurrent_jobs: Option<usize>,
+
+        /// memory_budget_mb is the memory qdrant can use, upload dry runs warn if the index
+        /// exceeds it
+        #[clap(long)]
+        memory_budget_mb: Option<u64>,
+    },
+    Drop {},
+    Collections {},
+    Migrate {
+        /// dry_run counts the points of older schema versions without writing them
+        #[clap(long)]
+        dry_run: bool,
+    },
+    Promote {
+        /// staging is the base collection uploaded to and evaluated before it replaces the
+        /// base collection
+        /// example: --staging=docs_staging
+        #[clap(long)]
+        staging: String,
+
+        /// eval_cases is the json file of the eval suite, a list of queries with the urls
+        /// expected among their results
+        /// example: [{"query": "how do i configure backups?", "expected_urls": ["https://docs.lagoon.sh/backups/"]}]
+        #[clap(long)]
+        eval_cases: PathBuf,
+
+        /// min_hit_rate is the share of the eval cases retrieving an expected url required to
+        /// promote the staging collections
+        #[clap(long, default_value = "0.8")]
+        min_hit_rate: f32,
+
+        /// limit is the number of fragments retrieved per eval case
+        #[clap(long, default_value = "7")]
+        limit: u64,
+
+        /// replace deletes the collections of the base collection before the first promotion,
+        /// they are replaced by aliases
+        #[clap(long)]
+        replace: bool,
+
+        /// dry_run runs the eval suite without promoting
+        #[clap(long)]
+        dry_run: bool,
+    },
+    CheckMirror {
+        /// max_ids is the number of ids listed per kind of difference
+        #[clap(long, default_value = "10")]
+        max_ids: usize,
+    },
+    DeleteUrl {
+        /// url of the page whose chunks are deleted from the filter collections
+        #[clap(short, long)]
+        url: String,
+    },
+    SingleDoc {
+        #[clap(short, long)]
+        url: String,
+
+        /// ollama_host defaults to the config file or http://localhost
+        #[clap(long)]
+        ollama_host: Option<String>,
+
+        #[clap(long)]
+        ollama_port: Option<u16>,
+
+        /// ollama_model defaults to the config file or openhermes2.5-mistral:7b-q6_K
+        #[clap(long)]
+        ollama_model: Option<String>,
+    },
+    #[clap(alias = "wizard")]
+    Init {
+        /// url of the site to probe, asked for if not specified
+        #[clap(short, long)]
+        url: Option<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    telemetry::init()?;
+    let args = Args::parse();
+    let metrics_summary = args.metrics_summary;
+    let result = run(args).await;
+    if metrics_summary {
+        println!("Metrics summary:");
+        for line in telemetry::metrics_summary() {
+            println!("{}", line);
+        }
+    }
+    telemetry::shutdown();
+    result
+}
+
+// run runs the parsed command
+async fn run(args: Args) -> Result<(), Error> {
+    let config_file = match &args.config {
+        Some(path) if path.exists() => Config::load(path)?,
+        // init creates the config file
+        Some(_) if matches!(args.command, Command::Init { .. }) => Config::default(),
+        Some(path) => return Err(anyhow::anyhow!("Config file {:?} does not exist", path)),
+        None => Config::discover(None)?,
+    }
+    .with_env()?;
+    // the flags override the env variables and the config file
+    let address = args.address.clone().unwrap_or(config_file.address());
+    let base_collection = args
+        .base_collection
+        .clone()
+        .unwrap_or(config_file.base_collection());
+    let filter_collections = args
+        .filter_collections
+        .clone()
+        .unwrap_or(config_file.collections());
+
+    let prompt_log = args.prompt_log.clone().or(config_file.prompts.log.clone());
+    let prompt_logger = prompt_log.map(|path| {
+        let mut config = PromptLogConfig::new(path);
+        config.sample_rate = args.prompt_log_sample_rate;
+        Arc::new(
+            PromptLogger::new(config)
+                .with_redactor(Box::new(TermRedactor::new(args.prompt_log_redact.clone()))),
+        )
+    });
+
+    let glossary = match args
+        .glossary
+        .as_ref()
+        .or(config_file.prompts.glossary.as_ref())
+    {
+        Some(path) => Glossary::load(path)?,
+        None => Glossary::default(),
+    };
+    let synonyms = match args
+        .synonyms
+        .as_ref()
+        .or(config_file.prompts.synonyms.as_ref())
+    {
+        Some(path) => Synonyms::load(path)?,
+        None => Synonyms::default(),
+    };
+    set_retry_policy(config_file.retry.policy());
+    let config = config_file.qdrant.client_config(&address);
+    let client = Arc::new(QdrantClient::new(Some(config))?);
+    if let Some(mirror_address) = &args.mirror_address {
+        mirror::enable(QdrantClient::new(Some(
+            config_file.qdrant.client_config(mirror_address),
+        ))?);
+    }
+    // upserts are split into batches below the max message size of the config file
+    let write_options = WriteOptions {
+        max_message_size: config_file.qdrant.max_message_size(),
+        ..WriteOptions::default()
+    };
+
+    let embedding_config = EmbeddingConfig {
+        provider: args.embedding_provider,
+        model: args.embedding_model.clone(),
+        url: args.embedding_url.clone(),
+        api_key: args.embedding_api_key.clone(),
+        query_lane: args.embedding_query_lane,
+    };
+    // the base collection is embedded with its model of the config file if it declares one
+    let embedding_provider = match config_file.embeddings.get(&base_collection) {
+        Some(settings) => embedding_config.with_settings(settings)?,
+        None => embedding_config.clone(),
+    }
+    .build()?;
+    let collection_config = CollectionConfig {
+        shard_number: args.shard_number,
+        replication_factor: args.replication_factor,
+        write_consistency_factor: args.write_consistency_factor,
+        on_disk_payload: match args.on_disk_payload {
+            true => Some(true),
+            false => None,
+        },
+        on_disk_vectors: match args.on_disk_vectors {
+            true => Some(true),
+            false => None,
+        },
+        memmap_threshold_kb: args.memmap_threshold_kb,
+        named_vectors: match args.named_vectors {
+            true => Some(true),
+            false => None,
+        },
+    };
+    // promote points the collections of the base collection to the staging ones by aliases,
+    // created collections would have to be replaced
+    if !matches!(args.command, Command::Promote { .. }) {
+        create_collections(
+            &client,
+            &base_collection,
+            filter_collections.clone(),
+            &embedding_provider.name(),
+            embedding_size(&embedding_provider).await?,
+            &collection_config,
+        )
+        .await?;
+    }
+
+    match args.command {
+        Command::Upload {
+            url,
+            source,
+            ollama_host,
+            ollama_port,
+            ollama_model,
+            locales,
+            concurrency,
+            crawl_delay_ms,
+            ignore_robots,
+            summary_policy,
+            incremental,
+            lead_first,
+            dedup,
+            dedup_threshold,
+            lead_tokens,
+            dry_run,
+            memory_budget_mb,
+            wait,
+            write_ordering,
+            parser,
+            chunk_strategy,
+            fragment_size,
+            overlap_size,
+            parent_size,
+            follow_pagination,
+            max_pagination_pages,
+        } => {
+            info!("Fetching {}", url.join(", "));
+            // the crawl profile of the first url applies to all urls
+            let mut profile = config_file.crawl_profile(&url[0]);
+            profile.lead_tokens = lead_tokens.or(profile.lead_tokens);
+            profile.parser = parser.or(profile.parser);
+            profile.chunk_strategy = chunk_strategy.or(profile.chunk_strategy);
+            profile.fragment_size = fragment_size.or(profile.fragment_size);
+            profile.overlap_size = overlap_size.or(profile.overlap_size);
+            profile.parent_size = parent_size.or(profile.parent_size);
+            profile.follow_pagination = match follow_pagination {
+                true => Some(true),
+                false => profile.follow_pagination,
+            };
+            profile.max_pagination_pages = max_pagination_pages.or(profile.max_pagination_pages);
+            let default_options = SitemapOptions::default();
+            let sitemap_options = SitemapOptions {
+                locales: locales,
+                concurrency: concurrency
+                    .or(profile.concurrency)
+                    .unwrap_or(default_options.concurrency),
+                crawl_delay: crawl_delay_ms
+                    .or(profile.crawl_delay_ms)
+                    .map(Duration::from_millis)
+                    .unwrap_or(default_options.crawl_delay),
+                respect_robots: !ignore_robots,
+                profile: profile,
+                max_pages: None,
+                skip_urls: HashSet::new(),
+            };
+            // the sitemap source crawls with the options of the upload, the crawler source with
+            // its profile
+            let ingest_source: SharedIngestSource = match source.as_deref() {
+                None => Arc::new(SitemapSource::new(sitemap_options)),
+                Some(name) if name == SITEMAP_SOURCE => {
+                    Arc::new(SitemapSource::new(sitemap_options))
+                }
+                Some(name) if name == CRAWLER_SOURCE => {
+                    Arc::new(CrawlerSource::new(sitemap_options.profile.clone()))
+                }
+                Some(name) => ingest_source(name).ok_or(anyhow::anyhow!(
+                    "unknown source: {}, registered sources: {}",
+                    name,
+                    ingest_source_names().join(", ")
+                ))?,
+            };
+            let mut fetch_stages = StageProgress::default();
+            let mut fetched = Vec::new();
+            for read in documents_of_locations(ingest_source, &url, None).await {
+                fetch_stages.merge(&read.stages);
+                match read.documents {
+                    Ok(documents) => {
+                        info!(
+                            "Fetched {} docs from {}, {}",
+                            documents.len(),
+                            read.location,
+                            read.stages
+                        );
+                        fetched.push((read.location, documents));
+                    }
+                    Err(e) => info!("Error fetching documents of {}: {}", read.location, e),
+                }
+            }
+            if fetched.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Error fetching documents of {}",
+                    url.join(", ")
+                ));
+            }
+
+            info!("Creating Ollama client");
+            let ollama_host = ollama_host.unwrap_or(config_file.ollama.host());
+            let ollama_port = ollama_port.unwrap_or(config_file.ollama.port());
+            let ollama_model = ollama_model.unwrap_or(config_file.ollama.model());
+            let ollama = Ollama::new(ollama_host.to_string(), ollama_port);
+            let llm = Llm::new(ollama).with_prompt_logger(prompt_logger.clone());
+            let transforms = transform_stage(&config_file, &url[0], &llm, &ollama_model)?;
+
+            let question_stage = match filter_collections.contains(&Collection::Questions) {
+                true => Some(QuestionStage {
+                    llm: llm.clone(),
+                    ollama_model: ollama_model.clone(),
+                }),
+                false => None,
+            };
+            let summary_stage = match filter_collections.contains(&Collection::Summary) {
+                true => Some(SummaryStage {
+                    llm: llm,
+                    ollama_model: ollama_model,
+                    ollama_host: ollama_host,
+                    ollama_port: ollama_port,
+                    policy: summary_policy,
+                }),
+                false => None,
+            };
+            let upload_config = UploadConfig::new(
+                client.clone(),
+                embedding_provider.clone(),
+                &base_collection,
+                filter_collections.clone(),
+            )
+            .with_summary(summary_stage)
+            .with_questions(question_stage)
+            .with_transforms(Some(transforms))
+            .with_batch_size(args.embedding_batch_size)
+            .with_incremental(incremental)
+            .with_lead_first(lead_first)
+            .with_dedup(match dedup {
+                true => Some(dedup_threshold),
+                false => None,
+            })
+            .with_write_options(WriteOptions {
+                wait: wait,
+                ordering: write_ordering,
+                ..write_options
+            });
+
+            let mut docs = Vec::new();
+            for (location, location_docs) in fetched {
+                let crawl_diff = diff_crawl(&upload_config, &location, &location_docs).await?;
+                for removed in &crawl_diff.removed {
+                    info!("Removed page: {}", removed);
+                }
+                docs.extend(location_docs);
+            }
+            if dry_run {
+                let memory_budget_bytes =
+                    memory_budget_mb.map(|memory_budget_mb| memory_budget_mb * MEGABYTE);
+                let estimate = estimate_index(&upload_config, &docs, memory_budget_bytes).await?;
+                println!("{}", serde_json::to_string_pretty(&estimate)?);
+                return Ok(());
+            }
+
+            upload_documents(
+                &upload_config,
+                &client,
+                &embedding_provider,
+                &url.join(","),
+                &mut docs,
+                &fetch_stages,
+            )
+            .await?;
+        }
+        Command::UploadDir {
+            path,
+            ollama_host,
+            ollama_port,
+            ollama_model,
+            summary_policy,
+            incremental,
+        } => {
+            info!("Reading {:?}", path);
+            let mut fetch_stages = StageProgress::default();
+            let mut docs = walk_directory(&path, &mut fetch_stages)?;
+            let dir = std::fs::canonicalize(&path)?.to_string_lossy().to_string();
+            // the files are chunked with the crawl settings of the config file
+            let chunking = config_file.crawl_profile(&dir).chunking();
+            for doc in docs.iter_mut() {
+                doc.chunking = chunking;
+            }
+
+            info!("Creating Ollama client");
+            let ollama_host = ollama_host.unwrap_or(config_file.ollama.host());
+            let ollama_port = ollama_port.unwrap_or(config_file.ollama.port());
+            let ollama_model = ollama_model.unwrap_or(config_file.ollama.model());
+            let ollama = Ollama::new(ollama_host.to_string(), ollama_port);
+            let llm = Llm::new(ollama).with_prompt_logger(prompt_logger.clone());
+            let transforms = transform_stage(&config_file, &dir, &llm, &ollama_model)?;
+
+            let question_stage = match filter_collections.contains(&Collection::Questions) {
+                true => Some(QuestionStage {
+                    llm: llm.clone(),
+                    ollama_model: ollama_model.clone(),
+                }),
+                false => None,
+            };
+            let summary_stage = match filter_collections.contains(&Collection::Summary) {
+                true => Some(SummaryStage {
+                    llm: llm,
+                    ollama_model: ollama_model,
+                    ollama_host: ollama_host,
+                    ollama_port: ollama_port,
+                    policy: summary_policy,
+                }),
+                false => None,
+            };
+            let upload_config = UploadConfig::new(
+                client.clone(),
+                embedding_provider.clone(),
+                &base_collection,
+                filter_collections.clone(),
+            )
+            .with_summary(summary_stage)
+            .with_questions(question_stage)
+            .with_transforms(Some(transforms))
+            .with_batch_size(args.embedding_batch_size)
+            .with_incremental(incremental)
+            .with_write_options(write_options);
+
+            let crawl_diff = diff_crawl(&upload_config, &dir, &docs).await?;
+            for removed in &crawl_diff.removed {
+                info!("Removed file: {}", removed);
+            }
+            upload_documents(
+                &upload_config,
+                &client,
+                &embedding_provider,
+                &dir,
+                &mut docs,
+                &fetch_stages,
+            )
+            .await?;
+        }
+        Command::UploadGit {
+            repo,
+            branch,
+            glob,
+            cache_dir,
+            ollama_host,
+            ollama_port,
+            ollama_model,
+            summary_policy,
+        } => {
+            let cache_dir = cache_dir.unwrap_or(std::env::temp_dir().join(GIT_CACHE_DIR));
+            let checkout = checkout(&repo, branch.as_deref(), &cache_dir)?;
+            let recorded = get_recorded_commit(
+                &client,
+                &base_collection,
+                Collection::Basic,
+                &checkout.url_base,
+            )
+            .await?;
+            // only the files changed since the recorded commit are indexed again, their old
+            // fragments and those of deleted files are removed first
+            let changes = match &recorded {
+                Some(commit) if *commit == checkout.head => {
+                    info!("{} is up to date at {}", repo, commit);
+                    return Ok(());
+                }
+                Some(commit) => checkout.changed_files(commit, &glob)?,
+                None => None,
+            };
+            let paths = match changes {
+                Some((changed, deleted)) => {
+                    for path in changed.iter().chain(deleted.iter()) {
+                        let deleted_chunks = delete_documents_by_url(
+                            &client,
+                            &base_collection,
+                            filter_collections.clone(),
+                            &checkout.url(path),
+                        )
+                        .await?;
+                        debug!("Deleted {} chunks of {}", deleted_chunks, path);
+                    }
+                    info!(
+                        "{} files changed and {} deleted since {:?}",
+                        changed.len(),
+                        deleted.len(),
+                        recorded
+                    );
+                    changed
+                }
+                None => checkout.files(&glob)?,
+            };
+            let mut docs = checkout.documents(&paths);
+            info!("Read {} docs of {} at {}", docs.len(), repo, checkout.head);
+
+            info!("Creating Ollama client");
+            let ollama_host = ollama_host.unwrap_or(config_file.ollama.host());
+            let ollama_port = ollama_port.unwrap_or(config_file.ollama.port());
+            let ollama_model = ollama_model.unwrap_or(config_file.ollama.model());
+            let ollama = Ollama::new(ollama_host.to_string(), ollama_port);
+            let llm = Llm::new(ollama).with_prompt_logger(prompt_logger.clone());
+            let transforms = transform_stage(&config_file, &repo, &llm, &ollama_model)?;
+
+            let question_stage = match filter_collections.contains(&Collection::Questions) {
+                true => Some(QuestionStage {
+                    llm: llm.clone(),
+                    ollama_model: ollama_model.clone(),
+                }),
+                false => None,
+            };
+            let summary_stage = match filter_collections.contains(&Collection::Summary) {
+                true => Some(SummaryStage {
+                    llm: llm,
+                    ollama_model: ollama_model,
+                    ollama_host: ollama_host,
+                    ollama_port: ollama_port,
+                    policy: summary_policy,
+                }),
+                false => None,
+            };
+            let upload_config = UploadConfig::new(
+                client.clone(),
+                embedding_provider.clone(),
+                &base_collection,
+                filter_collections.clone(),
+            )
+            .with_summary(summary_stage)
+            .with_questions(question_stage)
+            .with_transforms(Some(transforms))
+            .with_batch_size(args.embedding_batch_size)
+            .with_write_options(write_options);
+            let mut fetch_stages = StageProgress::default();
+            fetch_stages.record(Stage::Fetched, docs.len(), Duration::default());
+            fetch_stages.record(Stage::Parsed, docs.len(), Duration::default());
+            upload_documents(
+                &upload_config,
+                &client,
+                &embedding_provider,
+                &checkout.url_base,
+                &mut docs,
+                &fetch_stages,
+            )
+            .await?;
+        }
+        Command::UploadForum {
+            url,
+            kind,
+            max_threads,
+            token,
+            ollama_host,
+            ollama_port,
+            ollama_model,
+            summary_policy,
+        } => {
+            let token = token.or(std::env::var("GITHUB_TOKEN").ok());
+            let threads = fetch_threads(kind, &url, max_threads, token.as_deref()).await?;
+            // threads are indexed again as a whole, the fragments of their previous upload are
+            // removed first so new replies and status changes replace them
+            for thread in threads.iter() {
+                let deleted_chunks = delete_documents_by_url(
+                    &client,
+                    &base_collection,
+                    filter_collections.clone(),
+                    &thread.url,
+                )
+                .await?;
+                debug!("Deleted {} chunks of {}", deleted_chunks, thread.url);
+            }
+            let chunking = config_file.crawl_profile(&url).chunking();
+            let mut docs: Vec<Document> = threads.iter().map(Thread::to_document).collect();
+            for doc in docs.iter_mut() {
+                doc.chunking = chunking;
+            }
+
+            info!("Creating Ollama client");
+            let ollama_host = ollama_host.unwrap_or(config_file.ollama.host());
+            let ollama_port = ollama_port.unwrap_or(config_file.ollama.port());
+            let ollama_model = ollama_model.unwrap_or(config_file.ollama.model());
+            let ollama = Ollama::new(ollama_host.to_string(), ollama_port);
+            let llm = Llm::new(ollama).with_prompt_logger(prompt_logger.clone());
+            let transforms = transform_stage(&config_file, &url, &llm, &ollama_model)?;
+
+            let question_stage = match filter_collections.contains(&Collection::Questions) {
+                true => Some(QuestionStage {
+                    llm: llm.clone(),
+                    ollama_model: ollama_model.clone(),
+                }),
+                false => None,
+            };
+            let summary_stage = match filter_collections.contains(&Collection::Summary) {
+                true => Some(SummaryStage {
+                    llm: llm,
+                    ollama_model: ollama_model,
+                    ollama_host: ollama_host,
+                    ollama_port: ollama_port,
+                    policy: summary_policy,
+                }),
+                false => None,
+            };
+            let upload_config = UploadConfig::new(
+                client.clone(),
+                embedding_provider.clone(),
+                &base_collection,
+                filter_collections.clone(),
+            )
+            .with_summary(summary_stage)
+            .with_questions(question_stage)
+            .with_transforms(Some(transforms))
+            .with_batch_size(args.embedding_batch_size)
+            .with_write_options(write_options);
+            let mut fetch_stages = StageProgress::default();
+            fetch_stages.record(Stage::Fetched, docs.len(), Duration::default());
+            fetch_stages.record(Stage::Parsed, docs.len(), Duration::default());
+            upload_documents(
+                &upload_config,
+                &client,
+                &embedding_provider,
+                &url,
+                &mut docs,
+                &fetch_stages,
+            )
+            .await?;
+        }
+        Command::UploadHelpCenter {
+            url,
+            kind,
+            locale,
+            max_articles,
+            token,
+            ollama_host,
+            ollama_port,
+            ollama_model,
+            summary_policy,
+        } => {
+            let token_variable = match kind {
+                HelpCenterKind::Zendesk => "ZENDESK_TOKEN",
+                HelpCenterKind::Intercom => "INTERCOM_TOKEN",
+            };
+            let token = token.or(std::env::var(token_variable).ok());
+            let articles =
+                fetch_articles(kind, &url, &locale, max_articles, token.as_deref()).await?;
+            // articles are indexed again as a whole, the fragments of their previous upload
+            // are removed first so edited articles replace them
+            for article in articles.iter() {
+                let deleted_chunks = delete_documents_by_url(
+                    &client,
+                    &base_collection,
+                    filter_collections.clone(),
+                    &article.url,
+                )
+                .await?;
+                debug!("Deleted {} chunks of {}", deleted_chunks, article.url);
+            }
+            let chunking = config_file.crawl_profile(&url).chunking();
+            let mut docs: Vec<Document> = articles.iter().map(Article::to_document).collect();
+            for doc in docs.iter_mut() {
+                doc.chunking = chunking;
+            }
+
+            info!("Creating Ollama client");
+            let ollama_host = ollama_host.unwrap_or(config_file.ollama.host());
+            let ollama_port = ollama_port.unwrap_or(config_file.ollama.port());
+            let ollama_model = ollama_model.unwrap_or(config_file.ollama.model());
+            let ollama = Ollama::new(ollama_host.to_string(), ollama_port);
+            let llm = Llm::new(ollama).with_prompt_logger(prompt_logger.clone());
+            let transforms = transform_stage(&config_file, &url, &llm, &ollama_model)?;
+
+            let question_stage = match filter_collections.contains(&Collection::Questions) {
+                true => Some(QuestionStage {
+                    llm: llm.clone(),
+                    ollama_model: ollama_model.clone(),
+                }),
+                false => None,
+            };
+            let summary_stage = match filter_collections.contains(&Collection::Summary) {
+                true => Some(SummaryStage {
+                    llm: llm,
+                    ollama_model: ollama_model,
+                    ollama_host: ollama_host,
+                    ollama_port: ollama_port,
+                    policy: summary_policy,
+                }),
+                false => None,
+            };
+            let upload_config = UploadConfig::new(
+                client.clone(),
+                embedding_provider.clone(),
+                &base_collection,
+                filter_collections.clone(),
+            )
+            .with_summary(summary_stage)
+            .with_questions(question_stage)
+            .with_transforms(Some(transforms))
+            .with_batch_size(args.embedding_batch_size)
+            .with_write_options(write_options);
+            let mut fetch_stages = StageProgress::default();
+            fetch_stages.record(Stage::Fetched, docs.len(), Duration::default());
+            fetch_stages.record(Stage::Parsed, docs.len(), Duration::default());
+            upload_documents(
+                &upload_config,
+                &client,
+                &embedding_provider,
+                &url,
+                &mut docs,
+                &fetch_stages,
+            )
+            .await?;
+        }
+        Command::UploadQaDump {
+            path,
+            url,
+            format,
+            max_threads,
+            ollama_host,
+            ollama_port,
+            ollama_model,
+            summary_policy,
+        } => {
+            let format = format.unwrap_or(DumpFormat::from_path(&path));
+            let threads = read_dump(&path, format, &url, max_threads)?;
+            // questions are indexed again as a whole, the fragments of their previous upload
+            // are removed first so new answers and votes replace them
+            for thread in threads.iter() {
+                let deleted_chunks = delete_documents_by_url(
+                    &client,
+                    &base_collection,
+                    filter_collections.clone(),
+                    &thread.url,
+                )
+                .await?;
+                debug!("Deleted {} chunks of {}", deleted_chunks, thread.url);
+            }
+            let chunking = config_file.crawl_profile(&url).chunking();
+            let mut docs: Vec<Document> = threads.iter().map(Thread::to_document).collect();
+            for doc in docs.iter_mut() {
+                doc.chunking = chunking;
+            }
+
+            info!("Creating Ollama client");
+            let ollama_host = ollama_host.unwrap_or(config_file.ollama.host());
+            let ollama_port = ollama_port.unwrap_or(config_file.ollama.port());
+            let ollama_model = ollama_model.unwrap_or(config_file.ollama.model());
+            let ollama = Ollama::new(ollama_host.to_string(), ollama_port);
+            let llm = Llm::new(ollama).with_prompt_logger(prompt_logger.clone());
+            let transforms = transform_stage(&config_file, &url, &llm, &ollama_model)?;
+
+            let question_stage = match filter_collections.contains(&Collection::Questions) {
+                true => Some(QuestionStage {
+                    llm: llm.clone(),
+                    ollama_model: ollama_model.clone(),
+                }),
+                false => None,
+            };
+            let summary_stage = match filter_collections.contains(&Collection::Summary) {
+                true => Some(SummaryStage {
+                    llm: llm,
+                    ollama_model: ollama_model,
+                    ollama_host: ollama_host,
+                    ollama_port: ollama_port,
+                    policy: summary_policy,
+                }),
+                false => None,
+            };
+            let upload_config = UploadConfig::new(
+                client.clone(),
+                embedding_provider.clone(),
+                &base_collection,
+                filter_collections.clone(),
+            )
+            .with_summary(summary_stage)
+            .with_questions(question_stage)
+            .with_transforms(Some(transforms))
+            .with_batch_size(args.embedding_batch_size)
+            .with_write_options(write_options);
+            let mut fetch_stages = StageProgress::default();
+            fetch_stages.record(Stage::Fetched, docs.len(), Duration::default());
+            fetch_stages.record(Stage::Parsed, docs.len(), Duration::default());
+            upload_documents(
+                &upload_config,
+                &client,
+                &embedding_provider,
+                &url,
+                &mut docs,
+                &fetch_stages,
+            )
+            .await?;
+        }
+        Command::Query {
+            query,
+            limit,
+            ollama_host,
+            ollama_port,
+            ollama_model,
+            explain,
+            agent,
+            agent_steps,
+            scope,
+            retries,
+            answer_style,
+            url_prefix,
+            indexed_after,
+            indexed_before,
+            title,
+            release_version,
+            include_deprecated,
+            vector_search,
+            spelling,
+            rerank,
+            rerank_top_n,
+            max_context_tokens,
+            inline_citations,
+            metadata,
+            json_output,
+            show_context,
+            stream,
+        } => {
+            let filter = SearchFilter {
+                url_prefix: url_prefix,
+                indexed_after: indexed_after,
+                indexed_before: indexed_before,
+                title: title,
+                version: release_version,
+                include_deprecated: match include_deprecated {
+                    true => Some(true),
+                    false => None,
+                },
+            };
+            info!("Creating Ollama client");
+            let ollama_host = ollama_host.unwrap_or(config_file.ollama.host());
+            let ollama_port = ollama_port.unwrap_or(config_file.ollama.port());
+            let ollama_model = ollama_model.unwrap_or(config_file.ollama.model());
+            let ollama = Ollama::new(ollama_host.to_string(), ollama_port);
+            // the request id tags the logged prompts, report it with a bad answer
+            let request_id = uuid::Uuid::new_v4().to_string();
+            info!("Request id: {}", request_id);
+            let llm = Llm::new(ollama)
+                .with_prompt_logger(prompt_logger.clone())
+                .with_max_tokens(answer_style.max_tokens())
+                .with_request_id(Some(request_id.clone()));
+
+            let query_classifier = QueryClassifier::default().with_scope(scope);
+            let query_class = query_classifier.classify(&llm, &ollama_model, &query).await;
+            if let Some(answer) = query_classifier.answer(query_class) {
+                info!("Answering {:?} query without retrieval", query_class);
+                print_answer(
+                    AnswerOutput {
+                        request_id: request_id,
+                        answer: answer,
+                        attempt: None,
+                        citations: Vec::new(),
+                        context: None,
+                    },
+                    json_output,
+                )?;
+                return Ok(());
+            }
+
+            if agent {
+                let agent = Agent::new(
+                    &llm,
+                    &ollama_model,
+                    &client,
+                    &embedding_provider,
+                    &base_collection,
+                    filter_collections,
+                    limit,
+                )
+                .with_max_steps(agent_steps)
+                .with_glossary(&glossary)
+                .with_synonyms(&synonyms)
+                .with_filter(filter);
+                let agent_answer = agent.answer(&query).await?;
+                for step in &agent_answer.steps {
+                    info!("Agent step: {}: {}", step.tool, step.input);
+                    debug!("Agent observation: {}", step.observation);
+                }
+                print_answer(
+                    AnswerOutput {
+                        request_id: request_id,
+                        answer: agent_answer.answer,
+                        attempt: None,
+                        citations: Vec::new(),
+                        context: None,
+                    },
+                    json_output,
+                )?;
+                return Ok(());
+            }
+
+            info!("Querying {} with limit {}", query, limit);
+            let mut spell_corrector = SpellCorrector::new(spelling);
+            if spelling == SpellingMode::Dictionary {
+                let vocabulary = load_vocabulary(&client, &base_collection).await?;
+                spell_corrector = spell_corrector.with_vocabulary(vocabulary);
+            }
+            let corrected_query = spell_corrector.correct(&llm, &ollama_model, &query).await;
+            let expanded_query = glossary.expand_query(&synonyms.expand(&corrected_query));
+            let embeddings = text_embedding_async(&embedding_provider, expanded_query).await?;
+            check_query_embedding(
+                &client,
+                &base_collection,
+                &filter_collections,
+                &embedding_provider,
+                embeddings.len(),
+            )
+            .await?;
+            let reranker = match rerank {
+                true => Some(Reranker::default().with_top_n(rerank_top_n)),
+                false => None,
+            };
+            let docs = search_scored_documents(
+                &client,
+                &base_collection,
+                filter_collections,
+                embeddings,
+                reranker
+                    .as_ref()
+                    .map_or(limit, |reranker| reranker.candidates(limit)),
+                &filter,
+                vector_search,
+            )
+            .await?;
+            let docs = match &reranker {
+                Some(reranker) => {
+                    reranker
+                        .rerank(&llm, &ollama_model, &query, docs, limit)
+                        .await
+                }
+                None => docs,
+            };
+            let metadata: RequestMetadata = metadata.into_iter().collect();
+            let docs = RetrievalHooks::new(&config_file.retrieval_hooks)?
+                .apply(&query, docs, &metadata)
+                .await?;
+            let docs = match max_context_tokens {
+                Some(max_context_tokens) => pack_context(docs, max_context_tokens)?.docs,
+                None => docs,
+            };
+            for doc in &docs {
+                debug!(
+                    "Found doc: id: {:?}, score: {}, text: {}",
+                    doc.metadata.id, doc.score, doc.metadata.text
+                );
+            }
+            if explain {
+                let preview =
+                    preview_prompt(&query, &docs, answer_style, &glossary, inline_citations)?;
+                println!("{}", serde_json::to_string_pretty(&preview)?);
+                return Ok(());
+            }
+            let formatted_prompt =
+                render_prompt(&query, &docs, answer_style, &glossary, inline_citations);
+            debug!("Formatted prompt: {}", formatted_prompt);
+            let bpe = p50k_base().unwrap();
+            let tokens = bpe.encode_with_special_tokens(&formatted_prompt);
+            info!("Token count: {}", tokens.len());
+            if show_context && !json_output {
+                for fragment in context_fragments(&docs) {
+                    println!(
+                        "--- {} {} ({:.3})\n{}",
+                        fragment.title, fragment.url, fragment.score, fragment.text
+                    );
+                }
+                println!("---");
+            }
+            let start = std::time::Instant::now();
+            if stream {
+                // the answer is printed by the stream, it's generated once without the checks
+                llm.generate_stream(&ollama_model, &formatted_prompt)
+                    .await?;
+                println!();
+                info!(
+                    "Streamed answer, took: {} seconds",
+                    start.elapsed().as_secs()
+                );
+                log_sources(&docs);
+                return Ok(());
+            }
+            let generated = generate_answer(
+                &llm,
+                &ollama_model,
+                &query,
+                &docs,
+                answer_style,
+                &glossary,
+                inline_citations,
+                retries,
+            )
+            .await?;
+            info!(
+                "Answer: {}, attempt: {}, took: {} seconds",
+                generated.answer,
+                generated.attempt,
+                start.elapsed().as_secs()
+            );
+            log_sources(&docs);
+            print_answer(
+                AnswerOutput {
+                    request_id: request_id,
+                    answer: generated.answer,
+                    attempt: Some(generated.attempt),
+                    citations: citations(&docs),
+                    context: match show_context {
+                        true => Some(context_fragments(&docs)),
+                        false => None,
+                    },
+                },
+                json_output,
+            )?;
+        }
+        Command::Chat {
+            limit,
+            ollama_host,
+            ollama_port,
+            ollama_model,
+            answer_style,
+            spelling,
+            max_context_tokens,
+            inline_citations,
+        } => {
+            let ollama_host = ollama_host.unwrap_or(config_file.ollama.host());
+            let ollama_port = ollama_port.unwrap_or(config_file.ollama.port());
+            let ollama_model = ollama_model.unwrap_or(config_file.ollama.model());
+            let ollama = Ollama::new(ollama_host.to_string(), ollama_port);
+            let llm = Llm::new(ollama)
+                .with_prompt_logger(prompt_logger.clone())
+                .with_max_tokens(answer_style.max_tokens());
+            let mut spell_corrector = SpellCorrector::new(spelling);
+            if spelling == SpellingMode::Dictionary {
+                let vocabulary = load_vocabulary(&client, &base_collection).await?;
+                spell_corrector = spell_corrector.with_vocabulary(vocabulary);
+            }
+
+            println!("Ask a question, exit or an empty input ends the chat");
+            let mut session = ChatSession::default();
+            loop {
+                print!("> ");
+                std::io::stdout().flush()?;
+                let mut question = String::new();
+                if std::io::stdin().read_line(&mut question)? == 0 {
+                    break;
+                }
+                let question = question.trim().to_string();
+                if question.is_empty() || question == "exit" {
+                    break;
+                }
+
+                let standalone_query =
+                    condense_question(&llm, &ollama_model, session.history(), &question).await;
+                let corrected_query = spell_corrector
+                    .correct(&llm, &ollama_model, &standalone_query)
+                    .await;
+                let expanded_query = glossary.expand_query(&synonyms.expand(&corrected_query));
+                let embeddings = text_embedding_async(&embedding_provider, expanded_query).await?;
+                check_query_embedding(
+                    &client,
+                    &base_collection,
+                    &filter_collections,
+                    &embedding_provider,
+                    embeddings.len(),
+                )
+                .await?;
+                let docs = search_scored_documents(
+                    &client,
+                    &base_collection,
+                    filter_collections.clone(),
+                    embeddings,
+                    limit,
+                    &SearchFilter::default(),
+                    VectorSearch::default(),
+                )
+                .await?;
+                let docs = match max_context_tokens {
+                    Some(max_context_tokens) => pack_context(docs, max_context_tokens)?.docs,
+                    None => docs,
+                };
+                let formatted_prompt = render_chat_prompt(
+                    &question,
+                    session.history(),
+                    &docs,
+                    answer_style,
+                    &glossary,
+                    inline_citations,
+                );
+                debug!("Formatted prompt: {}", formatted_prompt);
+                let answer = llm.generate(&ollama_model, &formatted_prompt).await?;
+                println!("{}", answer.trim());
+                for citation in citations(&docs) {
+                    info!(
+                        "[{}] {} {} ({:.3})",
+                        citation.index, citation.title, citation.url, citation.score
+                    );
+                }
+                session.add_turn(question, answer);
+            }
+        }
+        #[cfg(feature = "server")]
+        Command::Serve {
+            listen,
+            ollama_host,
+            ollama_port,
+            ollama_model,
+            fast_model,
+            scope,
+            out_of_scope_message,
+            retries,
+            spelling,
+            embedding_cache_size,
+            answer_cache_size,
+            sources_path,
+            jobs_path,
+            max_concurrent_jobs,
+            memory_budget_mb,
+        } => {
+            let mut query_classifier = QueryClassifier::default().with_scope(scope);
+            if let Some(message) = out_of_scope_message {
+                query_classifier = query_classifier.with_out_of_scope_message(message);
+            }
+            let mut spell_corrector = SpellCorrector::new(spelling);
+            if spelling == SpellingMode::Dictionary {
+                let vocabulary = load_vocabulary(&client, &base_collection).await?;
+                spell_corrector = spell_corrector.with_vocabulary(vocabulary);
+            }
+            // the server embeds the base collections without a model of their own with the
+            // provider of the flags
+            let default_provider = match config_file.embeddings.contains_key(&base_collection) {
+                true => embedding_config.build()?,
+                false => embedding_provider,
+            };
+            let app_config_input = AppConfigInput {
+                address: Some(
+                    listen
+                        .or(std::env::var("ADDRESS").ok())
+                        .unwrap_or("127.0.0.1:3000".to_string()),
+                ),
+                base_collection: Some(base_collection),
+                filter_collections: Some(filter_collections),
+                ollama_model: Some(ollama_model.unwrap_or(config_file.ollama.model())),
+                ollama_host: Some(ollama_host.unwrap_or(config_file.ollama.host())),
+                ollama_port: Some(ollama_port.unwrap_or(config_file.ollama.port())),
+                fast_model: fast_model.or(config_file.ollama.fast_model.clone()),
+                qdrant_client: Some(client),
+                prompt_logger: prompt_logger,
+                query_classifier: Some(query_classifier),
+                answer_retries: Some(retries),
+                embedding_batch_size: Some(args.embedding_batch_size),
+                embedding_provider: Some(default_provider),
+                embedding_config: Some(embedding_config),
+                embedding_cache_size: Some(embedding_cache_size),
+                answer_cache_size: Some(answer_cache_size),
+                glossary: Some(glossary),
+                synonyms: Some(synonyms),
+                spell_corrector: Some(spell_corrector),
+                config_file: Some(config_file),
+                qdrant_memory_budget_mb: memory_budget_mb,
+                collection_config: Some(collection_config),
+                // the retrieval hooks are built from the config file
+                retrieval_hooks: None,
+                sources_path: sources_path
+                    .or(std::env::var("SOURCES_PATH").ok().map(PathBuf::from)),
+                jobs_path: jobs_path.or(std::env::var("JOBS_PATH").ok().map(PathBuf::from)),
+                max_concurrent_jobs: max_concurrent_jobs,
+            };
+            let state = Arc::new(AppState::<EmbeddingProgress>::new(app_config_input)?);
+            // the request limits and the retention of jobs and chats are read from the env
+            // variables of the server
+            serve(state, ServeOptions::from_env()?).await?;
+        }
+        Command::Drop {} => {
+            for collection in filter_collections {
+                let collection_name = format!("{}_{}", base_collection, collection.to_string());
+                info!("Dropping collection {}", collection_name);
+                retry_qdrant("delete_collection", || {
+                    client.delete_collection(&collection_name)
+                })
+                .await?;
+                if let Some(mirror) = mirror::mirror() {
+                    mirror_write("delete_collection", || {
+                        mirror.delete_collection(&collection_name)
+                    })
+                    .await;
+                }
+            }
+        }
+        Command::Collections {} => {
+            let collections = list_collections(&client, &base_collection).await?;
+            if collections.is_empty() {
+                println!("No collections of {}", base_collection);
+            }
+            for summary in collections {
+                println!(
+                    "{}: {} points, vector size: {}, distance: {}, status: {}",
+                    summary.collection,
+                    summary.points_count,
+                    summary
+                        .vector_size
+                        .map_or("named vectors".to_string(), |size| size.to_string()),
+                    summary.distance.unwrap_or("named vectors".to_string()),
+                    summary.status
+                );
+            }
+        }
+        Command::Promote {
+            staging,
+            eval_cases,
+            min_hit_rate,
+            limit,
+            replace,
+            dry_run,
+        } => {
+            let cases = load_eval_cases(&eval_cases)?;
+            let staging_provider = match config_file.embeddings.get(&staging) {
+                Some(settings) => embedding_config.with_settings(settings)?,
+                None => embedding_config.clone(),
+            }
+            .build()?;
+            let report = run_eval(
+                &client,
+                &staging_provider,
+                &staging,
+                filter_collections.clone(),
+                &cases,
+                limit,
+            )
+            .await?;
+            for result in report.results.iter().filter(|result| !result.hit) {
+                println!("missed: {}", result.query);
+            }
+            println!(
+                "{}: {} of {} cases hit, hit rate {:.2}, mrr {:.2}",
+                report.base_collection, report.hits, report.cases, report.hit_rate, report.mrr
+            );
+            if report.hit_rate < min_hit_rate {
+                return Err(anyhow::anyhow!(
+                    "Not promoting {}, hit rate {:.2} below {:.2}",
+                    staging,
+                    report.hit_rate,
+                    min_hit_rate
+                ));
+            }
+            if dry_run {
+                println!("{} passes the eval suite, not promoted", staging);
+                return Ok(());
+            }
+            let promotions = promote_collections(
+                &client,
+                &staging,
+                &base_collection,
+                filter_collections,
+                replace,
+            )
+            .await?;
+            for promotion in &promotions {
+                match &promotion.previous {
+                    Some(previous) => println!(
+                        "{} -> {}, previously {}",
+                        promotion.alias, promotion.collection, previous
+                    ),
+                    None => println!("{} -> {}", promotion.alias, promotion.collection),
+                }
+            }
+        }
+        Command::Migrate { dry_run } => {
+            let reports =
+                migrate_collections(&client, &base_collection, filter_collections, dry_run).await?;
+            for report in &reports {
+                println!(
+                    "{}: {} of {} points {}, {} unreadable",
+                    report.collection,
+                    report.migrated,
+                    report.scanned,
+                    match dry_run {
+                        true => "to migrate",
+                        false => "migrated",
+                    },
+                    report.failed.len()
+                );
+                if !report.failed.is_empty() {
+                    println!("  unreadable: {}", report.failed.join(", "));
+                }
+            }
+        }
+        Command::CheckMirror { max_ids } => {
+            let mirror = mirror::mirror()
+                .ok_or(anyhow::anyhow!("check_mirror needs the --mirror-address"))?;
+            let diffs =
+                check_consistency(&client, mirror, &base_collection, filter_collections).await?;
+            for diff in &diffs {
+                println!(
+                    "{}: {} points, mirror: {} points",
+                    diff.collection, diff.primary_points, diff.secondary_points
+                );
+                for (kind, ids) in [
+                    ("missing in the mirror", &diff.missing_in_secondary),
+                    ("missing in the primary", &diff.missing_in_primary),
+                    ("differing", &diff.differing),
+                ] {
+                    if ids.is_empty() {
+                        continue;
+                    }
+                    let listed: Vec<&str> = ids.iter().take(max_ids).map(String::as_str).collect();
+                    println!("  {} {}: {}", ids.len(), kind, listed.join(", "));
+                }
+            }
+            if !diffs.iter().all(|diff| diff.is_consistent()) {
+                return Err(anyhow::anyhow!("The mirror is not consistent"));
+            }
+            println!("The mirror is consistent");
+        }
+        Command::DeleteUrl { url } => {
+            let deleted =
+                delete_documents_by_url(&client, &base_collection, filter_collections, &url)
+                    .await?;
+            info!("Deleted {} chunks of {}", deleted, url);
+        }
+        Command::SingleDoc {
+            url,
+            ollama_host,
+            ollama_port,
+            ollama_model,
+        } => {
+            info!("Creating Ollama client");
+            let ollama_host = ollama_host.unwrap_or(config_file.ollama.host());
+            let ollama_port = ollama_port.unwrap_or(config_file.ollama.port());
+            let ollama_model = ollama_model.unwrap_or(config_file.ollama.model());
+            let ollama = Ollama::new(ollama_host.to_string(), ollama_port);
+            let llm = Llm::new(ollama).with_prompt_logger(prompt_logger.clone());
+
+            info!("Fetching {}", url);
+            let mut doc = fetch_content(url).await?;
+            info!("Fetched doc: {:?}", doc);
+
+            let basic_text = doc.text.get(&Collection::Basic).ok_or(anyhow::anyhow!(
+                "Could not find basic text for document: {:?}",
+                doc
+            ))?;
+            let bpe = p50k_base().unwrap();
+            let tokens = bpe.encode_with_special_tokens(basic_text);
+            println!("Token count: {}", tokens.len());
+
+            let start = std::time::Instant::now();
+            doc.add_summary(&ollama_model, &llm).await?;
+
+            let summary = doc.text.get(&Collection::Summary).ok_or(anyhow::anyhow!(
+                "Could not find summary for document: {:?}",
+                doc
+            ))?;
+            info!("Answer: {}, took: {}", summary, start.elapsed().as_secs());
+            let bpe = p50k_base().unwrap();
+            let tokens = bpe.encode_with_special_tokens(&summary);
+            println!("Token count: {}", tokens.len());
+        }
+        Command::Init { url } => {
+            let config_path = args
+                .config
+                .clone()
+                .unwrap_or(PathBuf::from(DEFAULT_CONFIG_PATH));
+            let config_file = match config_path.exists() {
+                true => Config::load(&config_path)?,
+                false => Config::default(),
+            };
+            init(
+                url,
+                &config_path,
+                config_file,
+                &client,
+                &embedding_provider,
+                &base_collection,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+// upload_documents summarizes, embeds and upserts the fetched documents of a source, queued
+// summaries are retried once after all documents are uploaded
+async fn upload_documents(
+    upload_config: &UploadConfig,
+    client: &QdrantClient,
+    embedding_provider: &SharedEmbeddingProvider,
+    source: &str,
+    docs: &mut [Document],
+    fetch_stages: &StageProgress,
+) -> Result<(), Error> {
+    let id = uuid::Uuid::new_v5(
+        &uuid::Uuid::NAMESPACE_URL,
+        format!("{}{}", source, docs.len()).as_bytes(),
+    );
+    let tracker = Arc::new(ProgressStore::new());
+    let outcome = run_upload(upload_config, id, docs, fetch_stages, &tracker).await;
+
+    if let Some(mut summary_report) = outcome.summaries {
+        for pending in outcome.pending {
+            match add_queued_summary(client, embedding_provider, &pending).await {
+                Ok(_) => summary_report.mark_generated(&pending.document.url),
+                Err(e) => info!(
+                    "Error adding queued summary of {}: {}",
+                    pending.document.url, e
+                ),
+            }
+        }
+        info!(
+            "Summaries: {} generated, {} skipped, {} queued",
+            summary_report.generated,
+            summary_report.skipped.len(),
+            summary_report.queued.len()
+        );
+        for url in summary_report.queued.iter() {
+            info!("Missing summary: {}", url);
+        }
+        if let Some(failed) = summary_report.failed {
+            return Err(anyhow::anyhow!("Upload aborted, {}", failed));
+        }
+    }
+    Ok(())
+}
+
+// transform_stage returns the transform stage of the transformers of the crawl profile of the
+// url, they run with the LLM of the upload
+fn transform_stage(
+    config_file: &Config,
+    url: &str,
+    llm: &Llm,
+    ollama_model: &str,
+) -> Result<TransformStage, Error> {
+    Ok(TransformStage {
+        chain: TransformChain::new(&config_file.crawl_profile(url).transforms)?,
+        llm: llm.clone(),
+        ollama_model: ollama_model.to_string(),
+    })
+}
+
+// check_query_embedding returns an error if the query embedding doesn't match the embedding model
+// the collections were created for
+async fn check_query_embedding(
+    client: &QdrantClient,
+    base_collection: &str,
+    filter_collections: &[Collection],
+    embedding_provider: &SharedEmbeddingProvider,
+    embedding_size: usize,
+) -> Result<(), Error> {
+    let collection_names: Vec<String> = filter_collections
+        .iter()
+        .map(|collection| format!("{}_{}", base_collection, collection.to_string()))
+        .collect();
+    CollectionModels::default()
+        .check(
+            client,
+            &collection_names,
+            &embedding_provider.name(),
+            embedding_size,
+        )
+        .await
+}
+
+// print_answer prints the answer, or the answer with its sources as json
+fn print_answer(output: AnswerOutput, json_output: bool) -> Result<(), Error> {
+    match json_output {
+        true => println!("{}", serde_json::to_string_pretty(&output)?),
+        false => println!("{}", output.answer),
+    }
+    Ok(())
+}
+
+// log_sources logs the source pages of the context of an answer
+fn log_sources(docs: &[ScoredDocument]) {
+    info!("Sources:");
+    for citation in citations(docs) {
+        info!(
+            "[{}] {} {} ({:.3})",
+            citation.index, citation.title, citation.url, citation.score
+        );
+    }
+}
+
+// init probes a site, proposes a crawl profile, optionally test-ingests a few pages with it and
+// writes it to the config file
+async fn init(
+    url: Option<String>,
+    config_path: &Path,
+    mut config_file: Config,
+    client: &Arc<QdrantClient>,
+    embedding_provider: &SharedEmbeddingProvider,
+    base_collection: &str,
+) -> Result<(), Error> {
+    let url = match url {
+        Some(url) => url,
+        None => ask("Url of the site", "")?,
+    };
+    println!("Probing {}", url);
+    let probe = probe(&url).await?;
+    for sitemap in &probe.robots_sitemaps {
+        println!("Sitemap listed in robots.txt: {}", sitemap);
+    }
+    if let Some(crawl_delay) = probe.crawl_delay {
+        println!("Crawl-delay of robots.txt: {:?}", crawl_delay);
+    }
+    match &probe.sitemap_url {
+        Some(sitemap_url) => println!("Found {} pages in {}", probe.page_count, sitemap_url),
+        None => println!("No sitemap found, uploads need a sitemap.xml"),
+    }
+    match (&probe.content_selector, &probe.sample_url) {
+        (Some(selector), Some(sample_url)) => {
+            println!("Detected content selector {} on {}", selector, sample_url)
+        }
+        _ => println!("No content selector detected, the whole body is indexed"),
+    }
+
+    let mut profile = probe.profile();
+    let content_selector = ask(
+        "Content selector, none to index the whole body",
+        profile.content_selector.as_deref().unwrap_or("none"),
+    )?;
+    profile.content_selector = match content_selector.as_str() {
+        "none" => None,
+        _ => Some(content_selector),
+    };
+    profile.include = split_list(&ask("Url paths to include, comma separated", "")?);
+    profile.exclude = split_list(&ask("Url paths to exclude, comma separated", "")?);
+    println!(
+        "Proposed profile for {}:\n{}",
+        probe.domain,
+        serde_json::to_string_pretty(&profile)?
+    );
+
+    if let Some(sitemap_url) = &probe.sitemap_url {
+        let question = format!("Test-ingest {} pages", TEST_INGEST_PAGES);
+        if confirm(&question, false)? {
+            let default_options = SitemapOptions::default();
+            let sitemap_options = SitemapOptions {
+                concurrency: profile.concurrency.unwrap_or(default_options.concurrency),
+                crawl_delay: profile
+                    .crawl_delay_ms
+                    .map(Duration::from_millis)
+                    .unwrap_or(default_options.crawl_delay),
+                profile: profile.clone(),
+                max_pages: Some(TEST_INGEST_PAGES),
+                ..default_options
+            };
+            let mut fetch_stages = StageProgress::default();
+            let mut docs =
+                sitemap_with_progress(sitemap_url, &sitemap_options, &mut fetch_stages).await?;
+            for doc in &docs {
+                let text = doc
+                    .text
+                    .get(&Collection::Basic)
+                    .cloned()
+                    .unwrap_or_default();
+                println!(
+                    "{}: {}, {} characters, {} fragments",
+                    doc.url,
+                    doc.title,
+                    text.len(),
+                    doc.to_fragments()?.len()
+                );
+            }
+            // the test pages are stored incrementally, the full upload skips them later
+            let upload_config = UploadConfig::new(
+                client.clone(),
+                embedding_provider.clone(),
+                base_collection,
+                vec![Collection::Basic],
+            )
+            .with_incremental(true);
+            let id = uuid::Uuid::new_v4();
+            let tracker = Arc::new(ProgressStore::new());
+            let outcome = run_upload(&upload_config, id, &mut docs, &fetch_stages, &tracker).await;
+            println!("Test ingest of {} pages: {:?}", docs.len(), outcome.status);
+        }
+    }
+
+    let question = format!("Write the profile to {}", config_path.display());
+    if confirm(&question, true)? {
+        config_file.profiles.insert(probe.domain.clone(), profile);
+        config_file.save(config_path)?;
+        println!(
+            "Upload the site with: rust-a-rag-us --config={} upload --url={}",
+            config_path.display(),
+            probe.sitemap_url.as_deref().unwrap_or(&url)
+        );
+    }
+    Ok(())
+}
+
+// ask prints the question and returns the answer read from stdin, the default if empty
+fn ask(question: &str, default: &str) -> Result<String, Error> {
+    match default.is_empty() {
+        true => print!("{}: ", question),
+        false => print!("{} [{}]: ", question, default),
+    }
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    match answer.trim().is_empty() {
+        true => Ok(default.to_string()),
+        false => Ok(answer.trim().to_string()),
+    }
+}
+
+// confirm asks a yes or no question
+fn confirm(question: &str, default: bool) -> Result<bool, Error> {
+    let default = match default {
+        true => "y",
+        false => "n",
+    };
+    let answer = ask(&format!("{} (y/n)", question), default)?;
+    Ok(answer.to_lowercase().starts_with('y'))
+}
+
+// parse_metadata parses a key=value pair of the request metadata
+fn parse_metadata(pair: &str) -> Result<(String, String), String> {
+    pair.split_once('=')
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .ok_or(format!("invalid metadata {}, expected key=value", pair))
+}
+
+// split_list returns the trimmed non empty items of a comma separated list
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Args;
+    use clap::error::ErrorKind;
+    use clap::Parser;
+
+    #[test]
+    fn streamed_answers_have_no_json_output() {
+        let query = ["rust-a-rag-us", "query", "--query", "backups", "--stream"];
+        assert!(Args::try_parse_from(query).is_ok());
+        let query = [
+            "rust-a-rag-us",
+            "query",
+            "--query",
+            "backups",
+            "--stream",
+            "--json_output",
+        ];
+        let e = Args::try_parse_from(query).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ArgumentConflict);
+    }
+}
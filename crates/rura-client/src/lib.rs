@@ -0,0 +1,273 @@
+mod types;
+
+pub use types::*;
+
+use anyhow::{anyhow, Error, Result};
+use reqwest::{RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+// Client is a typed client of the rust-a-rag-us server API
+#[derive(Debug, Clone)]
+pub struct Client {
+    base_url: String,
+    http: reqwest::Client,
+}
+
+impl Client {
+    // new creates a new client for the server at base_url, e.g. http://127.0.0.1:3000
+    pub fn new(base_url: &str) -> Self {
+        Client {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    // with_http_client uses the given reqwest client, e.g. to configure timeouts
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    // upload starts an upload job and returns its id
+    pub async fn upload(&self, params: &UploadParams) -> Result<Uuid, Error> {
+        let request = self.http.post(self.url("/upload")).query(params);
+        let id: String = self.send(request).await?;
+        Ok(Uuid::parse_str(&id)?)
+    }
+
+    // upload_document ingests a single document synchronously
+    pub async fn upload_document(
+        &self,
+        params: &DocumentUrlParams,
+    ) -> Result<DocumentUrlResponse, Error> {
+        let request = self.http.post(self.url("/documents/url")).query(params);
+        self.send(request).await
+    }
+
+    // delete_documents deletes all chunks of the page with the given url
+    pub async fn delete_documents(
+        &self,
+        params: &DeleteDocumentsParams,
+    ) -> Result<DeleteDocumentsResponse, Error> {
+        let request = self.http.delete(self.url("/documents")).query(params);
+        self.send(request).await
+    }
+
+    // job_status returns a page of jobs with their progress
+    pub async fn job_status(&self, params: &StateParams) -> Result<StateResponse, Error> {
+        let request = self.http.get(self.url("/get-state")).query(params);
+        self.send(request).await
+    }
+
+    // list_jobs returns a page of jobs with their progress, e.g. the completed ones, including
+    // the jobs persisted before a restart of the server
+    pub async fn list_jobs(&self, params: &StateParams) -> Result<StateResponse, Error> {
+        let request = self.http.get(self.url("/jobs")).query(params);
+        self.send(request).await
+    }
+
+    // job returns the progress of a job with the counts and timings of each stage
+    pub async fn job(&self, id: Uuid) -> Result<JobState, Error> {
+        let request = self.http.get(self.url(&format!("/jobs/{}", id)));
+        self.send(request).await
+    }
+
+    // cancel_job cancels a running upload job before its next document
+    pub async fn cancel_job(&self, id: Uuid) -> Result<JobState, Error> {
+        let request = self.http.post(self.url(&format!("/jobs/{}/cancel", id)));
+        self.send(request).await
+    }
+
+    // pause_job pauses a running upload job before its next document
+    pub async fn pause_job(&self, id: Uuid) -> Result<JobState, Error> {
+        let request = self.http.post(self.url(&format!("/jobs/{}/pause", id)));
+        self.send(request).await
+    }
+
+    // resume_job lets a paused upload job continue
+    pub async fn resume_job(&self, id: Uuid) -> Result<JobState, Error> {
+        let request = self.http.post(self.url(&format!("/jobs/{}/resume", id)));
+        self.send(request).await
+    }
+
+    // job_report returns the report of a finished upload job
+    pub async fn job_report(&self, id: Uuid) -> Result<JobReport, Error> {
+        let request = self.http.get(self.url(&format!("/jobs/{}/report", id)));
+        self.send(request).await
+    }
+
+    // summarize summarizes a text or the content of a url
+    pub async fn summarize(&self, params: &SummarizeParams) -> Result<SummarizeResponse, Error> {
+        let request = self.http.post(self.url("/summarize")).json(params);
+        self.send(request).await
+    }
+
+    // search returns the chunks matching a query
+    pub async fn search(&self, params: &SearchParams) -> Result<SearchResponse, Error> {
+        let request = self.http.post(self.url("/search")).json(params);
+        self.send(request).await
+    }
+
+    // query answers a query, with dry_run the rendered prompt is returned instead and with
+    // agent the LLM can call tools before answering
+    pub async fn query(
+        &self,
+        params: &QueryParams,
+        options: &QueryOptions,
+    ) -> Result<QueryResponse, Error> {
+        let request = self
+            .http
+            .post(self.url("/query"))
+            .query(options)
+            .json(params);
+        self.send(request).await
+    }
+
+    // chat answers a question of a conversation, the session id of the response continues it
+    pub async fn chat(&self, params: &ChatParams) -> Result<ChatResponse, Error> {
+        let request = self.http.post(self.url("/chat")).json(params);
+        self.send(request).await
+    }
+
+    // query_stream answers a query calling on_text with each generated part of the answer
+    // and returns the whole answer, with a fast model the parts are a draft and the answer
+    // of the quality model is returned once ready
+    pub async fn query_stream<F>(
+        &self,
+        params: &QueryParams,
+        mut on_text: F,
+    ) -> Result<String, Error>
+    where
+        F: FnMut(&str),
+    {
+        let request = self.http.post(self.url("/query/stream")).json(params);
+        let mut answer = String::new();
+        read_events(request, |name, text| match name {
+            Some("answer") => answer = text,
+            _ => {
+                on_text(&text);
+                answer.push_str(&text);
+            }
+        })
+        .await?;
+        Ok(answer)
+    }
+
+    // job_events calls on_update with the progress of a job on each update until the job is
+    // finished
+    pub async fn job_events<F>(&self, id: Uuid, mut on_update: F) -> Result<(), Error>
+    where
+        F: FnMut(&JobState),
+    {
+        let request = self.http.get(self.url(&format!("/jobs/{}/events", id)));
+        let mut result = Ok(());
+        read_events(request, |_, data| match serde_json::from_str(&data) {
+            Ok(job_state) => on_update(&job_state),
+            Err(e) => result = Err(e.into()),
+        })
+        .await?;
+        result
+    }
+
+    // collections lists the collections of the base collection with their point counts and
+    // vector params
+    pub async fn collections(
+        &self,
+        params: &CollectionsParams,
+    ) -> Result<CollectionsResponse, Error> {
+        let request = self.http.get(self.url("/collections")).query(params);
+        self.send(request).await
+    }
+
+    // tiering returns the tiers of the collections and the most retrieved pages
+    pub async fn tiering(&self, params: &TieringParams) -> Result<AccessReport, Error> {
+        let request = self.http.get(self.url("/tiering")).query(params);
+        self.send(request).await
+    }
+
+    // add_source registers a source re-crawled on its schedule
+    pub async fn add_source(&self, params: &SourceParams) -> Result<Source, Error> {
+        let request = self.http.post(self.url("/sources")).json(params);
+        self.send(request).await
+    }
+
+    // sources lists the sources re-crawled on their schedule
+    pub async fn sources(&self) -> Result<Vec<Source>, Error> {
+        let request = self.http.get(self.url("/sources"));
+        self.send(request).await
+    }
+
+    // url returns the url of an API path
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    // send sends a request and deserializes the json response
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, Error> {
+        let response = check_status(request.send().await?).await?;
+        Ok(response.json::<T>().await?)
+    }
+}
+
+// check_status turns non success responses into an error containing the code, message and
+// details of the error body, or the raw body if it isn't an ErrorResponse
+async fn check_status(response: Response) -> Result<Response, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(error) => Err(anyhow!(
+            "request failed with status {}: {}: {}{}",
+            status,
+            error.code,
+            error.message,
+            error
+                .details
+                .map(|details| format!(" ({})", details))
+                .unwrap_or_default()
+        )),
+        Err(_) => Err(anyhow!("request failed with status {}: {}", status, body)),
+    }
+}
+
+// read_events sends a request and calls on_event with the type and the data of each
+// server-sent event of the response
+async fn read_events<F>(request: RequestBuilder, mut on_event: F) -> Result<(), Error>
+where
+    F: FnMut(Option<&str>, String),
+{
+    let mut response = check_status(request.send().await?).await?;
+    let mut buffer: Vec<u8> = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        buffer.extend_from_slice(&chunk);
+        // events are separated by an empty line
+        while let Some(end) = buffer.windows(2).position(|window| window == b"\n\n") {
+            let event: Vec<u8> = buffer.drain(..end + 2).collect();
+            if let Some((name, data)) = parse_event(&String::from_utf8_lossy(&event)) {
+                on_event(name.as_deref(), data);
+            }
+        }
+    }
+    Ok(())
+}
+
+// parse_event returns the type and the data of a server-sent event, multi line data is sent
+// in multiple data fields, events without data like keep-alive comments are skipped
+fn parse_event(event: &str) -> Option<(Option<String>, String)> {
+    let field = |line: &str, name: &str| {
+        line.strip_prefix(name)
+            .map(|value| value.strip_prefix(' ').unwrap_or(value).to_string())
+    };
+    let name = event.lines().find_map(|line| field(line, "event:"));
+    let lines: Vec<String> = event
+        .lines()
+        .filter_map(|line| field(line, "data:"))
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+    Some((name, lines.join("\n")))
+}
@@ -0,0 +1,668 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+// The types mirror the schemas of the OpenAPI document served at /api-docs, the tests of
+// rura-server check their fields against the document
+
+// Collection represents a collection
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Collection {
+    Basic,
+    Summary,
+    Questions,
+}
+
+// JobPriority represents the priority of a background job
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum JobPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+// SummaryPolicy represents how an upload handles failing summaries
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SummaryPolicy {
+    FailFast,
+    #[default]
+    Skip,
+    Queue,
+}
+
+// WriteOrder represents the ordering guarantee of upserts across the replicas of a shard
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum WriteOrder {
+    #[default]
+    Weak,
+    Medium,
+    Strong,
+}
+
+// VectorSearch represents the vectors a search compares the query embedding with
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum VectorSearch {
+    #[default]
+    Content,
+    Title,
+    TitleFirst,
+}
+
+// PageParser represents how the content of crawled pages is turned into documents
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PageParser {
+    #[default]
+    Default,
+    ReleaseNotes,
+}
+
+// ChunkStrategy represents the unit the fragment sizes are measured in and the boundaries
+// fragments are cut at
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ChunkStrategy {
+    #[default]
+    Characters,
+    Tokens,
+    Sentences,
+}
+
+// JobStatus represents the status of a job
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum JobStatus {
+    Running,
+    Paused,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+// Stage represents a stage of the upload pipeline
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Stage {
+    Fetched,
+    Parsed,
+    Summarized,
+    Embedded,
+    Upserted,
+    Failed,
+}
+
+// StageStats represents the number of documents done by a stage and the time spent on them
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct StageStats {
+    pub count: usize,
+    pub duration_ms: u64,
+}
+
+// StageProgress represents the progress of the stages of the upload pipeline
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct StageProgress {
+    pub fetched: StageStats,
+    pub parsed: StageStats,
+    pub summarized: StageStats,
+    pub embedded: StageStats,
+    pub upserted: StageStats,
+    pub failed: StageStats,
+}
+
+// SourceProgress represents the crawl of one url of an upload of several sites
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceProgress {
+    pub url: String,
+    #[serde(default)]
+    pub stages: StageProgress,
+    pub documents: Option<usize>,
+    pub error: Option<String>,
+}
+
+// JobProgress represents the progress of an upload job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobProgress {
+    pub total_documents: usize,
+    pub processed_documents: usize,
+    #[serde(default)]
+    pub generated_tokens: usize,
+    #[serde(default)]
+    pub stages: StageProgress,
+    #[serde(default)]
+    pub current_stage: Option<Stage>,
+    // sources are the urls of an upload of several sites, empty for a single site
+    #[serde(default)]
+    pub sources: Vec<SourceProgress>,
+    pub status: JobStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// JobState represents the progress of a job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobState {
+    pub id: Uuid,
+    pub progress: JobProgress,
+}
+
+// StateParams filters and paginates the jobs returned by GET /get-state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StateParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_page: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<JobStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_after: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_before: Option<DateTime<Utc>>,
+}
+
+// StateResponse represents a page of jobs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateResponse {
+    pub progress_data: Vec<JobState>,
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+}
+
+// UploadParams represents the parameters of POST /upload, list parameters are passed as comma
+// separated strings in the query string
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UploadParams {
+    pub url: String,
+    // urls is a comma separated list of further urls uploaded in the same job
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub urls: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ollama_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ollama_host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ollama_port: Option<u16>,
+    // filter_collections is a comma separated list of the collections uploaded to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter_collections: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_collection: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<JobPriority>,
+    // locales is a comma separated list of the languages the upload is restricted to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locales: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary_policy: Option<SummaryPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crawl_delay_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub respect_robots: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub incremental: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed_only: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lead_first: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dedup: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dedup_threshold: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lead_tokens: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wait: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write_ordering: Option<WriteOrder>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parser: Option<PageParser>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_strategy: Option<ChunkStrategy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fragment_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overlap_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_size: Option<usize>,
+    // skip_urls is a comma separated list of the pages of the sitemap not crawled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_urls: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub follow_pagination: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_pagination_pages: Option<usize>,
+}
+
+// CrawlDiff represents the changes of a crawl compared to the previous crawl of the same site
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrawlDiff {
+    pub url: String,
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub removed: Vec<String>,
+    pub unchanged: usize,
+}
+
+// JobReport represents the outcome of an upload job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobReport {
+    pub id: String,
+    pub crawl_diff: Option<CrawlDiff>,
+    #[serde(default)]
+    pub summaries: Option<SummaryReport>,
+    #[serde(default)]
+    pub skipped_fragments: Option<usize>,
+    #[serde(default)]
+    pub dedup: Option<DedupReport>,
+    #[serde(default)]
+    pub estimate: Option<IndexEstimate>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+// IndexEstimate represents the estimated size of the index reported by dry runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEstimate {
+    pub documents: usize,
+    pub chunks: usize,
+    pub dimension: u64,
+    pub vector_bytes: u64,
+    pub payload_bytes: u64,
+    pub collections: Vec<CollectionStats>,
+    pub total_memory_bytes: u64,
+    pub warnings: Vec<String>,
+}
+
+// CollectionStats represents the size and health of a collection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionStats {
+    pub collection: String,
+    pub points_count: u64,
+    pub green: bool,
+}
+
+// CollectionsParams represents the parameters of GET /collections
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CollectionsParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_collection: Option<String>,
+}
+
+// CollectionsResponse represents the collections of the base collection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionsResponse {
+    pub collections: Vec<CollectionSummary>,
+}
+
+// CollectionSummary represents an existing collection with the size and metric of its vectors
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionSummary {
+    pub collection: String,
+    pub points_count: u64,
+    pub vector_size: Option<u64>,
+    pub distance: Option<String>,
+    pub status: String,
+}
+
+// TieringParams represents the parameters of GET /tiering
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TieringParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+// Tier represents the storage of a collection
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Tier {
+    Hot,
+    Cold,
+}
+
+// CollectionTier represents the tier of a collection set by the last tiering run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionTier {
+    pub collection: String,
+    pub tier: Tier,
+    pub accesses: u64,
+}
+
+// UrlAccess represents the number of retrievals of the fragments of a page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UrlAccess {
+    pub url: String,
+    pub accesses: u64,
+}
+
+// AccessReport represents the tiers of the collections and the most retrieved pages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessReport {
+    #[serde(default)]
+    pub tiers: Vec<CollectionTier>,
+    #[serde(default)]
+    pub hot_urls: Vec<UrlAccess>,
+}
+
+// SourceParams represents the parameters of POST /sources, the schedule is @hourly, @daily,
+// @weekly or e.g. 30m, 6h or 2d
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceParams {
+    pub url: String,
+    pub schedule: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter_collections: Option<Vec<Collection>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_collection: Option<String>,
+}
+
+// Source represents a site re-crawled on a schedule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Source {
+    pub id: Uuid,
+    pub url: String,
+    pub schedule: String,
+    pub filter_collections: Option<Vec<Collection>>,
+    pub base_collection: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_job_id: Option<Uuid>,
+    #[serde(default)]
+    pub observed_job_id: Option<Uuid>,
+    // pages are the re-crawl intervals by url adapted to how often the pages change
+    #[serde(default)]
+    pub pages: BTreeMap<String, PageFreshness>,
+}
+
+// PageFreshness represents how often a page of a source changed and when it is crawled next
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageFreshness {
+    pub checks: u32,
+    pub changes: u32,
+    pub last_checked_at: DateTime<Utc>,
+    pub last_changed_at: Option<DateTime<Utc>>,
+    pub interval_secs: i64,
+}
+
+// SummaryReport represents the outcome of the summaries of an upload
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SummaryReport {
+    pub generated: usize,
+    pub skipped: Vec<String>,
+    pub queued: Vec<String>,
+    pub failed: Option<String>,
+}
+
+// DedupReport represents the near duplicate fragments dropped by an upload
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DedupReport {
+    pub fragments: usize,
+    pub dropped: usize,
+    pub threshold: f32,
+}
+
+// DocumentUrlParams represents the parameters of POST /documents/url
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DocumentUrlParams {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ollama_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ollama_host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ollama_port: Option<u16>,
+    // filter_collections is a comma separated list of the collections uploaded to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter_collections: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_collection: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wait: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write_ordering: Option<WriteOrder>,
+}
+
+// DocumentUrlResponse represents an ingested document with the ids of its chunks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentUrlResponse {
+    pub id: String,
+    pub url: String,
+    pub chunk_ids: Vec<String>,
+}
+
+// DeleteDocumentsParams represents the parameters of DELETE /documents
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeleteDocumentsParams {
+    pub url: String,
+    // filter_collections is a comma separated list of the collections deleted from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter_collections: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_collection: Option<String>,
+}
+
+// DeleteDocumentsResponse represents the number of deleted chunks of a page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeleteDocumentsResponse {
+    pub url: String,
+    pub deleted: u64,
+}
+
+// SummarizeParams represents the parameters of POST /summarize, either text or url is set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SummarizeParams {
+    pub text: Option<String>,
+    pub url: Option<String>,
+    pub ollama_model: Option<String>,
+    pub ollama_host: Option<String>,
+    pub ollama_port: Option<u16>,
+}
+
+// SummarizeResponse represents a summary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SummarizeResponse {
+    pub summary: String,
+    pub url: Option<String>,
+}
+
+// SearchParams represents the parameters of POST /search
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchParams {
+    pub query: String,
+    pub limit: Option<u64>,
+    pub filter_collections: Option<Vec<Collection>>,
+    pub base_collection: Option<String>,
+    pub highlights: Option<usize>,
+    pub preview_sentences: Option<usize>,
+    pub filter: Option<SearchFilter>,
+    pub vector_search: Option<VectorSearch>,
+}
+
+// Highlight represents a passage of a text similar to a query in character offsets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Highlight {
+    pub start: usize,
+    pub end: usize,
+    pub score: f32,
+}
+
+// SearchResult represents a retrieved chunk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub text: String,
+    pub collection: Collection,
+    pub highlights: Vec<Highlight>,
+    pub truncated: bool,
+}
+
+// SearchResponse represents the retrieved chunks of a search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResponse {
+    pub results: Vec<SearchResult>,
+    #[serde(default)]
+    pub degraded: bool,
+}
+
+// QueryParams represents the parameters of POST /query and POST /query/stream
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueryParams {
+    pub query: String,
+    pub limit: Option<u64>,
+    pub filter_collections: Option<Vec<Collection>>,
+    pub base_collection: Option<String>,
+    pub ollama_model: Option<String>,
+    pub ollama_host: Option<String>,
+    pub ollama_port: Option<u16>,
+    pub fast_model: Option<String>,
+    pub answer_style: Option<AnswerStyle>,
+    pub filter: Option<SearchFilter>,
+    pub rerank: Option<bool>,
+    pub rerank_top_n: Option<usize>,
+    pub max_context_tokens: Option<usize>,
+    pub inline_citations: Option<bool>,
+    // metadata are the attributes of the caller the retrieval hooks of the server decide by
+    pub metadata: Option<BTreeMap<String, String>>,
+}
+
+// SearchFilter restricts a search or the context of a query by the metadata of the documents
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchFilter {
+    pub url_prefix: Option<String>,
+    pub indexed_after: Option<DateTime<Utc>>,
+    pub indexed_before: Option<DateTime<Utc>>,
+    pub title: Option<String>,
+    pub version: Option<String>,
+    pub include_deprecated: Option<bool>,
+}
+
+// AnswerStyle represents the length and detail level of the answers
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AnswerStyle {
+    Short,
+    #[default]
+    Normal,
+    Detailed,
+}
+
+// ContextChunk represents a retrieved chunk used in the context of a prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextChunk {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub score: f32,
+    pub tokens: usize,
+}
+
+// PromptPreview represents a rendered prompt with its context and token usage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptPreview {
+    pub prompt: String,
+    pub chunks: Vec<ContextChunk>,
+    pub context_tokens: usize,
+    pub prompt_tokens: usize,
+}
+
+// Citation represents a source page of the context of an answer, numbered as referenced inline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Citation {
+    pub index: usize,
+    pub title: String,
+    pub url: String,
+    pub score: f32,
+}
+
+// QueryOptions represents the query string options of POST /query
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueryOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent_steps: Option<usize>,
+}
+
+// AgentStep represents a tool call of the agent mode with its result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStep {
+    pub tool: String,
+    pub input: String,
+    pub observation: String,
+}
+
+// QueryClass represents the kind of a query, only questions use retrieval
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum QueryClass {
+    ChitChat,
+    Meta,
+    OutOfScope,
+    Question,
+}
+
+// QueryResponse represents the answer of a query or the prompt preview of a dry run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryResponse {
+    pub answer: Option<String>,
+    pub explain: Option<PromptPreview>,
+    #[serde(default)]
+    pub steps: Option<Vec<AgentStep>>,
+    pub query_class: QueryClass,
+    pub attempt: Option<usize>,
+    #[serde(default)]
+    pub citations: Option<Vec<Citation>>,
+    // cached is true if the answer was served from the cache during an outage
+    #[serde(default)]
+    pub cached: bool,
+    // request_id is the id of the request in the server logs, report it with bad answers
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+// ChatParams represents the parameters of POST /chat, without session id a new conversation is
+// started
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<Uuid>,
+    #[serde(flatten)]
+    pub query: QueryParams,
+}
+
+// ChatTurn represents a question of a conversation and its answer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatTurn {
+    pub question: String,
+    pub answer: String,
+}
+
+// ChatResponse represents the answer of a question of a conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatResponse {
+    pub session_id: Uuid,
+    pub answer: String,
+    pub standalone_query: String,
+    #[serde(default)]
+    pub citations: Vec<Citation>,
+    #[serde(default)]
+    pub history: Vec<ChatTurn>,
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+// ErrorResponse represents the json body of the error responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+    #[serde(default)]
+    pub details: Option<String>,
+}
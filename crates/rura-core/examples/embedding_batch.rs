@@ -0,0 +1,56 @@
+// embedding_batch compares the embedding time of the fragments of a sitemap upload per
+// encode batch size
+//
+// cargo run --release -p rura-core --example embedding_batch -- https://docs.lagoon.sh/sitemap.xml
+use anyhow::{Error, Result};
+use rura_core::retriever::sitemap;
+use rust_bert::pipelines::sentence_embeddings::{
+    SentenceEmbeddingsBuilder, SentenceEmbeddingsModelType,
+};
+use std::time::Instant;
+use tch::Device;
+
+// BATCH_SIZES are the compared batch sizes, 1 is the unbatched baseline
+static BATCH_SIZES: &[usize] = &[1, 16, 32, 64];
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let url = std::env::args()
+        .nth(1)
+        .ok_or(anyhow::anyhow!("usage: embedding_batch <sitemap url>"))?;
+    let docs = sitemap(&url).await?;
+    let mut texts = Vec::new();
+    for doc in &docs {
+        for fragment in doc.to_fragments()? {
+            texts.push(fragment.text);
+        }
+    }
+    println!("{} documents, {} fragments", docs.len(), texts.len());
+
+    // rust-bert models are blocking, run the benchmark on a blocking thread
+    tokio::task::spawn_blocking(move || -> Result<(), Error> {
+        let model = SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL12V2)
+            .with_device(Device::cuda_if_available())
+            .create_model()?;
+        // warm up the model before timing
+        model.encode(&texts[..texts.len().min(8)])?;
+
+        let mut baseline = None;
+        for &batch_size in BATCH_SIZES {
+            let start = Instant::now();
+            for batch in texts.chunks(batch_size) {
+                model.encode(batch)?;
+            }
+            let elapsed = start.elapsed();
+            let baseline = *baseline.get_or_insert(elapsed);
+            println!(
+                "batch size {:>3}: {:>8.2?}, speedup: {:.2}x",
+                batch_size,
+                elapsed,
+                baseline.as_secs_f64() / elapsed.as_secs_f64()
+            );
+        }
+        Ok(())
+    })
+    .await?
+}
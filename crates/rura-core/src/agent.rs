@@ -0,0 +1,277 @@
+use crate::answer::AnswerStyle;
+use crate::data::{Collection, ScoredDocument};
+use crate::embedding::text_embedding_async;
+use crate::embedding_provider::SharedEmbeddingProvider;
+use crate::glossary::{Glossary, Synonyms};
+use crate::ollama::{Llm, PROMPT_AGENT};
+use crate::qdrant::{search_scored_documents, SearchFilter, VectorSearch};
+use crate::query::{render_context, render_prompt};
+use crate::retriever::fetch_content_with_limit;
+use anyhow::{Error, Result};
+use log::{debug, info};
+use qdrant_client::client::QdrantClient;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+// DEFAULT_AGENT_STEPS is the default number of tool calls before the answer is forced
+pub static DEFAULT_AGENT_STEPS: usize = 4;
+// MAX_AGENT_STEPS is the upper bound of tool calls a caller can request
+pub static MAX_AGENT_STEPS: usize = 10;
+// MAX_OBSERVATION_SIZE is the maximum number of characters of a tool result in the prompt
+static MAX_OBSERVATION_SIZE: usize = 4096;
+// MAX_FETCH_SIZE is the maximum size in bytes of a page fetched by the agent
+static MAX_FETCH_SIZE: usize = 2 * 1024 * 1024;
+
+// ToolCall represents the action chosen by the LLM in an agent step
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolCall {
+    // search searches the collections again with a refined query
+    Search(String),
+    // fetch fetches the full page of a source by url
+    Fetch(String),
+    // sources lists the sources retrieved so far
+    Sources,
+    // answer is the final answer
+    Answer(String),
+}
+
+impl ToolCall {
+    // parse parses the reply of the LLM, replies without a tool call are the final answer
+    pub fn parse(reply: &str) -> Self {
+        for (i, line) in reply.lines().enumerate() {
+            let line = line.trim();
+            let (name, input) = match line.split_once(':') {
+                Some((name, input)) => (name.trim().to_uppercase(), input.trim()),
+                None => (line.to_uppercase(), ""),
+            };
+            match name.as_str() {
+                "SEARCH" if !input.is_empty() => return ToolCall::Search(input.to_string()),
+                "FETCH" if !input.is_empty() => return ToolCall::Fetch(input.to_string()),
+                "SOURCES" => return ToolCall::Sources,
+                "ANSWER" => {
+                    // the answer may span multiple lines
+                    let rest: Vec<&str> = reply.lines().skip(i + 1).collect();
+                    let answer = format!("{}\n{}", input, rest.join("\n"));
+                    return ToolCall::Answer(answer.trim().to_string());
+                }
+                _ => {}
+            }
+        }
+        ToolCall::Answer(reply.trim().to_string())
+    }
+}
+
+// AgentStep represents a tool call of the agent with its result
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct AgentStep {
+    pub tool: String,
+    pub input: String,
+    pub observation: String,
+}
+
+// AgentAnswer represents the final answer of the agent and the tool calls leading to it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct AgentAnswer {
+    pub answer: String,
+    pub steps: Vec<AgentStep>,
+}
+
+// Agent answers questions in a bounded loop where the LLM can call tools before answering
+pub struct Agent<'a> {
+    llm: &'a Llm,
+    model: &'a str,
+    client: &'a QdrantClient,
+    embedding_provider: &'a SharedEmbeddingProvider,
+    base_collection: &'a str,
+    filter_collections: Vec<Collection>,
+    limit: u64,
+    max_steps: usize,
+    // glossary explains the terms of the question and expands the search queries if set
+    glossary: Option<&'a Glossary>,
+    // synonyms expand the search queries if set
+    synonyms: Option<&'a Synonyms>,
+    // filter restricts the searches by the metadata of the documents
+    filter: SearchFilter,
+}
+
+impl<'a> Agent<'a> {
+    // new creates a new agent searching the given collections
+    pub fn new(
+        llm: &'a Llm,
+        model: &'a str,
+        client: &'a QdrantClient,
+        embedding_provider: &'a SharedEmbeddingProvider,
+        base_collection: &'a str,
+        filter_collections: Vec<Collection>,
+        limit: u64,
+    ) -> Self {
+        Agent {
+            llm: llm,
+            model: model,
+            client: client,
+            embedding_provider: embedding_provider,
+            base_collection: base_collection,
+            filter_collections: filter_collections,
+            limit: limit,
+            max_steps: DEFAULT_AGENT_STEPS,
+            glossary: None,
+            synonyms: None,
+            filter: SearchFilter::default(),
+        }
+    }
+
+    // with_max_steps sets the number of tool calls, capped at MAX_AGENT_STEPS
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps.min(MAX_AGENT_STEPS);
+        self
+    }
+
+    // with_glossary adds the terms used in the question to the prompts and expands the
+    // search queries with their definitions
+    pub fn with_glossary(mut self, glossary: &'a Glossary) -> Self {
+        self.glossary = Some(glossary);
+        self
+    }
+
+    // with_synonyms expands the search queries with the synonyms of the terms they use
+    pub fn with_synonyms(mut self, synonyms: &'a Synonyms) -> Self {
+        self.synonyms = Some(synonyms);
+        self
+    }
+
+    // with_filter restricts the searches by the metadata of the documents, e.g. a url prefix
+    pub fn with_filter(mut self, filter: SearchFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    // answer answers the question, once the steps are used up the answer is generated from
+    // the sources retrieved so far
+    #[tracing::instrument(skip(self))]
+    pub async fn answer(&self, question: &str) -> Result<AgentAnswer, Error> {
+        let mut sources = self.search(question).await?;
+        let mut transcript = format!("Initial search results:\n{}", render_context(&sources));
+        let mut steps = Vec::new();
+        let glossary = self.glossary.cloned().unwrap_or_default();
+
+        for step in 0..self.max_steps {
+            let prompt = PROMPT_AGENT
+                .replace("{glossary}", &glossary.prompt_section(&[question]))
+                .replace("{question}", question)
+                .replace("{transcript}", &transcript);
+            let reply = self.llm.generate(self.model, &prompt).await?;
+            let tool_call = ToolCall::parse(&reply);
+            debug!("Agent step {}: {:?}", step, tool_call);
+
+            let (tool, input, observation) = match tool_call {
+                ToolCall::Answer(answer) => {
+                    info!("Agent answered after {} steps", step);
+                    return Ok(AgentAnswer {
+                        answer: answer,
+                        steps: steps,
+                    });
+                }
+                ToolCall::Search(query) => {
+                    let docs = self.search(&query).await?;
+                    let observation = render_context(&docs);
+                    for doc in docs {
+                        if !sources.iter().any(|s| s.metadata.id == doc.metadata.id) {
+                            sources.push(doc);
+                        }
+                    }
+                    ("search", query, observation)
+                }
+                ToolCall::Fetch(url) => {
+                    let observation = self.fetch(&sources, &url).await;
+                    ("fetch", url, observation)
+                }
+                ToolCall::Sources => ("sources", String::new(), list_sources(&sources)),
+            };
+            let observation = truncate(&observation, MAX_OBSERVATION_SIZE);
+            transcript.push_str(&format!(
+                "\n{}: {}\nResult:\n{}\n",
+                tool.to_uppercase(),
+                input,
+                observation
+            ));
+            steps.push(AgentStep {
+                tool: tool.to_string(),
+                input: input,
+                observation: observation,
+            });
+        }
+
+        info!("Agent used all {} steps, answering", self.max_steps);
+        let answer = self
+            .llm
+            .generate(
+                self.model,
+                &render_prompt(question, &sources, AnswerStyle::default(), &glossary, false),
+            )
+            .await?;
+        Ok(AgentAnswer {
+            answer: answer,
+            steps: steps,
+        })
+    }
+
+    // search returns the documents matching the query
+    async fn search(&self, query: &str) -> Result<Vec<ScoredDocument>, Error> {
+        let query = match self.synonyms {
+            Some(synonyms) => synonyms.expand(query),
+            None => query.to_string(),
+        };
+        let query = match self.glossary {
+            Some(glossary) => glossary.expand_query(&query),
+            None => query,
+        };
+        let embeddings = text_embedding_async(self.embedding_provider, query).await?;
+        search_scored_documents(
+            self.client,
+            self.base_collection,
+            self.filter_collections.clone(),
+            embeddings,
+            self.limit,
+            &self.filter,
+            VectorSearch::default(),
+        )
+        .await
+    }
+
+    // fetch returns the text of a page, only urls of retrieved sources can be fetched
+    async fn fetch(&self, sources: &[ScoredDocument], url: &str) -> String {
+        if !sources.iter().any(|s| s.metadata.url == url) {
+            return format!(
+                "{} is not one of the sources, use SOURCES to list them",
+                url
+            );
+        }
+        match fetch_content_with_limit(url.to_string(), MAX_FETCH_SIZE).await {
+            Ok(document) => document
+                .text
+                .get(&Collection::Basic)
+                .cloned()
+                .unwrap_or_default(),
+            Err(e) => format!("Error fetching {}: {}", url, e),
+        }
+    }
+}
+
+// list_sources returns the title and url of the sources, one per line
+fn list_sources(sources: &[ScoredDocument]) -> String {
+    let mut urls: Vec<String> = sources
+        .iter()
+        .map(|s| format!("- {}: {}", s.metadata.title, s.metadata.url))
+        .collect();
+    urls.sort();
+    urls.dedup();
+    urls.join("\n")
+}
+
+// truncate returns the first max_chars characters of a text
+fn truncate(text: &str, max_chars: usize) -> String {
+    text.chars().take(max_chars).collect()
+}
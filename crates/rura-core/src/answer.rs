@@ -0,0 +1,198 @@
+use crate::data::ScoredDocument;
+use crate::glossary::Glossary;
+use crate::ollama::{Llm, PROMPT, PROMPT_RETRY};
+use crate::query::{render_context, render_prompt_with_template};
+use anyhow::{Error, Result};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+// DEFAULT_ANSWER_RETRIES is the default number of retries of a low quality answer
+pub static DEFAULT_ANSWER_RETRIES: usize = 2;
+// TEMPERATURES are the temperatures of the retries, the first attempt uses the model default
+static TEMPERATURES: &[f32] = &[0.2, 0.6, 1.0];
+// MIN_GROUNDING is the minimum share of answer words found in the context
+static MIN_GROUNDING: f32 = 0.3;
+// MIN_WORD_LENGTH is the minimum length of the words checked for grounding
+static MIN_WORD_LENGTH: usize = 4;
+// REFUSALS are phrases of answers refusing to answer
+static REFUSALS: &[&str] = &[
+    "i don't know",
+    "i do not know",
+    "i cannot answer",
+    "i can't answer",
+    "i'm sorry, but",
+    "i am sorry, but",
+    "as an ai",
+    "no information",
+    "not provided in the context",
+];
+
+// AnswerStyle represents the length and detail level of the answers
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum AnswerStyle {
+    // Short answers are terse, e.g. for support teams
+    Short,
+    // Normal answers use the default prompt and model settings
+    #[default]
+    Normal,
+    // Detailed answers are exhaustive, e.g. for internal users
+    Detailed,
+}
+
+// string to answer style
+impl From<&str> for AnswerStyle {
+    fn from(s: &str) -> Self {
+        match s {
+            "short" => AnswerStyle::Short,
+            "normal" => AnswerStyle::Normal,
+            "detailed" => AnswerStyle::Detailed,
+            _ => {
+                error!("Error converting answer style, unknown style: {}", s);
+                AnswerStyle::Normal
+            }
+        }
+    }
+}
+
+impl AnswerStyle {
+    // instruction returns the instruction added to the answer prompts
+    pub fn instruction(&self) -> &'static str {
+        match self {
+            AnswerStyle::Short => " Keep the answer short, at most three sentences.",
+            AnswerStyle::Normal => "",
+            AnswerStyle::Detailed => {
+                " Answer exhaustively, cover every relevant detail, step and caveat of the context."
+            }
+        }
+    }
+
+    // max_tokens returns the maximum number of generated tokens, None uses the model default
+    pub fn max_tokens(&self) -> Option<i32> {
+        match self {
+            AnswerStyle::Short => Some(256),
+            AnswerStyle::Normal => None,
+            AnswerStyle::Detailed => Some(2048),
+        }
+    }
+}
+
+// AnswerIssue represents the reason an answer was rejected
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum AnswerIssue {
+    // Empty answers contain no text
+    Empty,
+    // Refusal answers refuse although context was retrieved
+    Refusal,
+    // Ungrounded answers use too few words of the context
+    Ungrounded,
+}
+
+// GeneratedAnswer represents the served answer and the attempt it was generated in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct GeneratedAnswer {
+    pub answer: String,
+    // attempt is the zero based attempt of the served answer
+    pub attempt: usize,
+    // issues are the issues of the rejected attempts
+    pub issues: Vec<AnswerIssue>,
+}
+
+// check_answer returns the issue of an answer, or None if the answer is fine
+pub fn check_answer(answer: &str, docs: &[ScoredDocument]) -> Option<AnswerIssue> {
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Some(AnswerIssue::Empty);
+    }
+    if docs.is_empty() {
+        // without context there is nothing to refuse or ground on
+        return None;
+    }
+    let lowercase = answer.to_lowercase().replace('’', "'");
+    if REFUSALS.iter().any(|refusal| lowercase.contains(refusal)) {
+        return Some(AnswerIssue::Refusal);
+    }
+    if grounding(answer, &render_context(docs)) < MIN_GROUNDING {
+        return Some(AnswerIssue::Ungrounded);
+    }
+    None
+}
+
+// grounding returns the share of the answer words found in the context
+pub fn grounding(answer: &str, context: &str) -> f32 {
+    let context_words: HashSet<String> = words(context).collect();
+    let answer_words: Vec<String> = words(answer).collect();
+    if answer_words.is_empty() {
+        return 1.0;
+    }
+    let grounded = answer_words
+        .iter()
+        .filter(|word| context_words.contains(*word))
+        .count();
+    grounded as f32 / answer_words.len() as f32
+}
+
+// words returns the lowercase words of a text long enough to be checked for grounding
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_WORD_LENGTH)
+        .map(|word| word.to_lowercase())
+}
+
+// generate_answer answers a question from the retrieved documents in the given style, citing
+// the numbered sources inline if inline_citations is set, the maximum number of tokens of the
+// style is set on the Llm, empty, refusing or ungrounded answers are retried up to retries times
+// with the alternate prompt and an increasing temperature, the last answer is served if all
+// attempts fail the checks
+pub async fn generate_answer(
+    llm: &Llm,
+    model: &str,
+    question: &str,
+    docs: &[ScoredDocument],
+    style: AnswerStyle,
+    glossary: &Glossary,
+    inline_citations: bool,
+    retries: usize,
+) -> Result<GeneratedAnswer, Error> {
+    let mut issues = Vec::new();
+    let mut attempt = 0;
+    loop {
+        let (template, temperature) = match attempt {
+            0 => (PROMPT, None),
+            _ => (
+                PROMPT_RETRY,
+                Some(TEMPERATURES[(attempt - 1).min(TEMPERATURES.len() - 1)]),
+            ),
+        };
+        let prompt = render_prompt_with_template(
+            template,
+            question,
+            docs,
+            style,
+            glossary,
+            inline_citations,
+        );
+        let answer = llm
+            .generate_with_temperature(model, &prompt, temperature)
+            .await?;
+        match check_answer(&answer, docs) {
+            Some(issue) if attempt < retries => {
+                info!("Retrying answer of attempt {}: {:?}", attempt, issue);
+                issues.push(issue);
+                attempt += 1;
+            }
+            _ => {
+                return Ok(GeneratedAnswer {
+                    answer: answer,
+                    attempt: attempt,
+                    issues: issues,
+                })
+            }
+        }
+    }
+}
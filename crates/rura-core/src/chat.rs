@@ -0,0 +1,155 @@
+use crate::answer::AnswerStyle;
+use crate::data::ScoredDocument;
+use crate::glossary::Glossary;
+use crate::ollama::{Llm, PROMPT_CHAT, PROMPT_CONDENSE};
+use crate::query::render_prompt_with_template;
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// MAX_HISTORY_TURNS is the number of previous turns used to condense questions and answer them,
+// older turns are kept in the session but left out of the prompts
+pub static MAX_HISTORY_TURNS: usize = 6;
+
+// ChatTurn represents a question of a conversation and its answer
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ChatTurn {
+    pub question: String,
+    pub answer: String,
+}
+
+// ChatSession represents the turns of a conversation
+#[derive(Debug, Clone)]
+pub struct ChatSession {
+    pub turns: Vec<ChatTurn>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Default for ChatSession {
+    fn default() -> Self {
+        ChatSession {
+            turns: Vec::new(),
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+impl ChatSession {
+    // history returns the latest turns used in the prompts
+    pub fn history(&self) -> &[ChatTurn] {
+        &self.turns[self.turns.len().saturating_sub(MAX_HISTORY_TURNS)..]
+    }
+
+    // add_turn appends the answered question to the conversation
+    pub fn add_turn(&mut self, question: String, answer: String) {
+        self.turns.push(ChatTurn {
+            question: question,
+            answer: answer,
+        });
+        self.updated_at = Utc::now();
+    }
+}
+
+// ChatStore keeps the chat sessions of the server by id
+#[derive(Debug, Default)]
+pub struct ChatStore {
+    sessions: Mutex<HashMap<Uuid, ChatSession>>,
+}
+
+impl ChatStore {
+    // get returns a copy of the session, None if the session is unknown or was evicted
+    pub fn get(&self, id: &Uuid) -> Option<ChatSession> {
+        self.sessions.lock().unwrap().get(id).cloned()
+    }
+
+    // save stores the session, replacing a previous version of it
+    pub fn save(&self, id: Uuid, session: ChatSession) {
+        self.sessions.lock().unwrap().insert(id, session);
+    }
+
+    // evict removes the sessions not updated since the cutoff and returns how many were removed
+    pub fn evict(&self, cutoff: DateTime<Utc>) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, session| session.updated_at >= cutoff);
+        let evicted = before - sessions.len();
+        if evicted > 0 {
+            info!("Evicted {} chat sessions", evicted);
+        }
+        evicted
+    }
+}
+
+// render_history renders the turns of a conversation for the prompts
+pub fn render_history(turns: &[ChatTurn]) -> String {
+    turns
+        .iter()
+        .map(|turn| format!("User: {}\nAssistant: {}", turn.question, turn.answer.trim()))
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+// condense_question rewrites a follow-up question into a standalone query used for retrieval,
+// the first question of a conversation is kept, errors of the LLM are logged and the question is
+// kept as well
+pub async fn condense_question(
+    llm: &Llm,
+    model: &str,
+    turns: &[ChatTurn],
+    question: &str,
+) -> String {
+    if turns.is_empty() {
+        return question.to_string();
+    }
+    let prompt = PROMPT_CONDENSE
+        .replace("{history}", &render_history(turns))
+        .replace("{question}", question);
+    match llm.generate(model, &prompt).await {
+        Ok(reply) => {
+            let reply = reply.trim().trim_matches('"').trim();
+            if reply.is_empty() || reply.lines().count() > 1 {
+                info!("Ignoring condensed question: {}", reply);
+                return question.to_string();
+            }
+            info!("Condensed question {} to {}", question, reply);
+            reply.to_string()
+        }
+        Err(e) => {
+            info!("Error condensing question, keeping it: {}", e);
+            question.to_string()
+        }
+    }
+}
+
+// render_chat_prompt renders the prompt answering the latest question of a conversation with the
+// previous turns and the context retrieved for the standalone query
+pub fn render_chat_prompt(
+    question: &str,
+    turns: &[ChatTurn],
+    docs: &[ScoredDocument],
+    style: AnswerStyle,
+    glossary: &Glossary,
+    inline_citations: bool,
+) -> String {
+    render_prompt_with_template(
+        PROMPT_CHAT,
+        question,
+        docs,
+        style,
+        glossary,
+        inline_citations,
+    )
+    .replace(
+        "{history}",
+        &match turns.is_empty() {
+            true => "No previous questions.".to_string(),
+            false => render_history(turns),
+        },
+    )
+}
@@ -0,0 +1,156 @@
+use crate::ollama::{Llm, PROMPT_CLASSIFY};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+// GREETINGS are short chit-chat phrases answered without retrieval
+static GREETINGS: &[&str] = &[
+    "hi",
+    "hello",
+    "hey",
+    "hallo",
+    "howdy",
+    "good morning",
+    "good afternoon",
+    "good evening",
+    "thanks",
+    "thank you",
+    "thx",
+    "bye",
+    "goodbye",
+    "ok",
+    "okay",
+    "cool",
+];
+// META_QUESTIONS are questions about the assistant itself answered without retrieval
+static META_QUESTIONS: &[&str] = &[
+    "help",
+    "who are you",
+    "what are you",
+    "what can you do",
+    "how can you help",
+    "what is your name",
+    "whats your name",
+    "are you a bot",
+    "are you human",
+];
+// MAX_CHIT_CHAT_WORDS is the number of words a greeting may be followed by, e.g. hi there
+static MAX_CHIT_CHAT_WORDS: usize = 2;
+
+static GREETING_ANSWER: &str =
+    "Hello! Ask me anything about the documentation and I'll look it up for you.";
+static META_ANSWER: &str = "I'm a support assistant answering questions based on the indexed documentation. Ask a question and I'll search the documentation for the answer.";
+static DEFAULT_OUT_OF_SCOPE_MESSAGE: &str =
+    "Sorry, I can only answer questions about the indexed documentation.";
+
+// QueryClass represents the kind of a query
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum QueryClass {
+    // ChitChat are greetings, thanks and goodbyes
+    ChitChat,
+    // Meta are questions about the assistant itself
+    Meta,
+    // OutOfScope are questions not related to the configured scope
+    OutOfScope,
+    // Question are questions answered using retrieval
+    Question,
+}
+
+// QueryClassifier detects queries which are answered without retrieval
+pub struct QueryClassifier {
+    // scope describes the topics of the documentation, questions outside of it are answered
+    // with the out of scope message, without a scope the LLM check is skipped
+    scope: Option<String>,
+    out_of_scope_message: String,
+}
+
+impl Default for QueryClassifier {
+    fn default() -> Self {
+        QueryClassifier {
+            scope: None,
+            out_of_scope_message: DEFAULT_OUT_OF_SCOPE_MESSAGE.to_string(),
+        }
+    }
+}
+
+impl QueryClassifier {
+    // with_scope enables the LLM out of scope check for the given scope, e.g. docs of lagoon
+    pub fn with_scope(mut self, scope: Option<String>) -> Self {
+        self.scope = scope.filter(|scope| !scope.trim().is_empty());
+        self
+    }
+
+    // with_out_of_scope_message sets the answer to out of scope questions
+    pub fn with_out_of_scope_message(mut self, message: String) -> Self {
+        self.out_of_scope_message = message;
+        self
+    }
+
+    // classify classifies a query using the heuristics first and the LLM for the scope check,
+    // errors of the LLM are logged and the query is treated as a question
+    pub async fn classify(&self, llm: &Llm, model: &str, query: &str) -> QueryClass {
+        if let Some(class) = classify_heuristic(query) {
+            debug!("Classified query {} as {:?}", query, class);
+            return class;
+        }
+        let scope = match &self.scope {
+            Some(scope) => scope,
+            None => return QueryClass::Question,
+        };
+        let prompt = PROMPT_CLASSIFY
+            .replace("{scope}", scope)
+            .replace("{question}", query);
+        match llm.generate(model, &prompt).await {
+            Ok(reply) if reply.to_uppercase().contains("OUT_OF_SCOPE") => {
+                info!("Query {} is out of scope", query);
+                QueryClass::OutOfScope
+            }
+            Ok(_) => QueryClass::Question,
+            Err(e) => {
+                info!("Error classifying query, falling back to retrieval: {}", e);
+                QueryClass::Question
+            }
+        }
+    }
+
+    // answer returns the answer of queries which skip retrieval
+    pub fn answer(&self, class: QueryClass) -> Option<String> {
+        match class {
+            QueryClass::ChitChat => Some(GREETING_ANSWER.to_string()),
+            QueryClass::Meta => Some(META_ANSWER.to_string()),
+            QueryClass::OutOfScope => Some(self.out_of_scope_message.clone()),
+            QueryClass::Question => None,
+        }
+    }
+}
+
+// classify_heuristic detects greetings and meta questions, returns None for everything else
+pub fn classify_heuristic(query: &str) -> Option<QueryClass> {
+    let normalized: String = query
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect();
+    let words: Vec<&str> = normalized.split_whitespace().collect();
+    if words.is_empty() {
+        return None;
+    }
+    let normalized = words.join(" ");
+
+    let matches = |phrases: &[&str]| {
+        phrases.iter().any(|phrase| {
+            let phrase_words = phrase.split_whitespace().count();
+            (normalized == *phrase || normalized.starts_with(&format!("{} ", phrase)))
+                && words.len() <= phrase_words + MAX_CHIT_CHAT_WORDS
+        })
+    };
+    if matches(META_QUESTIONS) {
+        return Some(QueryClass::Meta);
+    }
+    if matches(GREETINGS) {
+        return Some(QueryClass::ChitChat);
+    }
+    None
+}
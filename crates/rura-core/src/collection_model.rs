@@ -0,0 +1,224 @@
+use crate::mirror::{mirror, mirror_write};
+use crate::qdrant::{collection_exists, CONTENT_VECTOR};
+use crate::retry::retry_qdrant;
+use anyhow::{Error, Result};
+use chrono::{DateTime, Utc};
+use log::info;
+use qdrant_client::prelude::*;
+use qdrant_client::qdrant::vectors_config::Config;
+use qdrant_client::qdrant::{CreateCollection, VectorParams, Vectors, VectorsConfig};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+// COLLECTION_MODELS_COLLECTION is the collection the embedding models of the collections are
+// recorded in, qdrant keeps no metadata of its own with a collection
+pub static COLLECTION_MODELS_COLLECTION: &str = "rura_collection_models";
+
+// CollectionModel represents the embedding model a collection was created for
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CollectionModel {
+    pub collection: String,
+    // model is the name of the embedding provider and model, e.g. ollama nomic-embed-text, None
+    // for collections created before the models were recorded
+    pub model: Option<String>,
+    // size is the size of the vectors of the collection
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl CollectionModel {
+    // check returns an error if the query embedding of the model doesn't match the collection
+    pub fn check(&self, model: &str, size: usize) -> Result<(), Error> {
+        if self.size != size as u64 {
+            return Err(anyhow::anyhow!(
+                "Collection {} has vectors of size {}, the query embedding of {} has size {}",
+                self.collection,
+                self.size,
+                model,
+                size
+            ));
+        }
+        match &self.model {
+            Some(collection_model) if collection_model != model => Err(anyhow::anyhow!(
+                "Collection {} was embedded with {}, the query with {}",
+                self.collection,
+                collection_model,
+                model
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// point_id returns the id of the point recording the model of a collection
+fn point_id(collection_name: &str) -> String {
+    Uuid::new_v5(&Uuid::NAMESPACE_OID, collection_name.as_bytes()).to_string()
+}
+
+// record_collection_model records the embedding model of a collection, on the mirror as well if
+// enabled, the collection of the models is created on the first record
+pub async fn record_collection_model(
+    client: &QdrantClient,
+    collection_model: &CollectionModel,
+) -> Result<(), Error> {
+    let payload: Payload = json!(collection_model)
+        .try_into()
+        .map_err(|e| anyhow::anyhow!("Error converting payload: {}", e))?;
+    let point = PointStruct {
+        id: Some(point_id(&collection_model.collection).into()),
+        payload: payload.into(),
+        vectors: Some(Vectors::from(vec![1.0])),
+    };
+    create_models_collection(client).await?;
+    retry_qdrant("upsert", || {
+        client.upsert_points_blocking(COLLECTION_MODELS_COLLECTION, vec![point.clone()], None)
+    })
+    .await?;
+    if let Some(mirror) = mirror() {
+        if let Err(e) = create_models_collection(mirror).await {
+            info!("Error creating mirror collection of the models: {}", e);
+        }
+        mirror_write("upsert", || {
+            mirror.upsert_points_blocking(COLLECTION_MODELS_COLLECTION, vec![point.clone()], None)
+        })
+        .await;
+    }
+    info!(
+        "Recorded embedding model {:?} of collection {}",
+        collection_model.model, collection_model.collection
+    );
+    Ok(())
+}
+
+// create_models_collection creates the collection of the models, its points only carry the
+// payload, the vectors have a single dimension
+async fn create_models_collection(client: &QdrantClient) -> Result<(), Error> {
+    if retry_qdrant("has_collection", || {
+        client.has_collection(COLLECTION_MODELS_COLLECTION)
+    })
+    .await?
+    {
+        return Ok(());
+    }
+    let create_collection = CreateCollection {
+        collection_name: COLLECTION_MODELS_COLLECTION.into(),
+        vectors_config: Some(VectorsConfig {
+            config: Some(Config::Params(VectorParams {
+                size: 1,
+                distance: Distance::Dot.into(),
+                ..Default::default()
+            })),
+        }),
+        ..Default::default()
+    };
+    retry_qdrant("create_collection", || {
+        client.create_collection(&create_collection)
+    })
+    .await?;
+    Ok(())
+}
+
+// get_collection_model returns the recorded embedding model of a collection, the vector size of
+// the collection info without a model for collections created before the models were recorded,
+// None if the collection does not exist
+pub async fn get_collection_model(
+    client: &QdrantClient,
+    collection_name: &str,
+) -> Result<Option<CollectionModel>, Error> {
+    if retry_qdrant("has_collection", || {
+        client.has_collection(COLLECTION_MODELS_COLLECTION)
+    })
+    .await?
+    {
+        let point_ids = vec![point_id(collection_name).into()];
+        let response = retry_qdrant("get_points", || {
+            client.get_points(
+                COLLECTION_MODELS_COLLECTION,
+                &point_ids,
+                Some(false),
+                Some(true),
+                None,
+            )
+        })
+        .await?;
+        if let Some(point) = response.result.into_iter().next() {
+            let payload_json = serde_json::to_value(&point.payload)?;
+            return Ok(Some(serde_json::from_value(payload_json)?));
+        }
+    }
+    if !collection_exists(client, collection_name).await? {
+        return Ok(None);
+    }
+    let size = retry_qdrant("collection_info", || {
+        client.collection_info(collection_name)
+    })
+    .await?
+    .result
+    .as_ref()
+    .and_then(|info| info.config.as_ref())
+    .and_then(|config| config.params.as_ref())
+    .and_then(|params| params.vectors_config.as_ref())
+    .and_then(|vectors_config| match &vectors_config.config {
+        Some(Config::Params(vector_params)) => Some(vector_params.size),
+        Some(Config::ParamsMap(params_map)) => params_map
+            .map
+            .get(CONTENT_VECTOR)
+            .map(|vector_params| vector_params.size),
+        None => None,
+    });
+    Ok(size.map(|size| CollectionModel {
+        collection: collection_name.to_string(),
+        model: None,
+        size: size,
+        created_at: None,
+    }))
+}
+
+// CollectionModels caches the embedding models of the collections to validate the query
+// embeddings without a qdrant request per query
+#[derive(Default)]
+pub struct CollectionModels {
+    models: RwLock<HashMap<String, CollectionModel>>,
+}
+
+impl CollectionModels {
+    // check returns an error if the query embedding of the model doesn't match the model of one
+    // of the collections, collections whose model can't be read are not checked
+    pub async fn check(
+        &self,
+        client: &QdrantClient,
+        collection_names: &[String],
+        model: &str,
+        size: usize,
+    ) -> Result<(), Error> {
+        for collection_name in collection_names {
+            let cached = self.models.read().unwrap().get(collection_name).cloned();
+            let collection_model = match cached {
+                Some(collection_model) => collection_model,
+                None => match get_collection_model(client, collection_name).await {
+                    Ok(Some(collection_model)) => {
+                        self.models
+                            .write()
+                            .unwrap()
+                            .insert(collection_name.clone(), collection_model.clone());
+                        collection_model
+                    }
+                    Ok(None) => continue,
+                    Err(e) => {
+                        info!(
+                            "Error reading the embedding model of {}: {}",
+                            collection_name, e
+                        );
+                        continue;
+                    }
+                },
+            };
+            collection_model.check(model, size)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,693 @@
+use crate::data::{parse_collections, ChunkStrategy, Chunking, Collection};
+use crate::embedding_provider::EmbeddingProviderKind;
+use crate::estimate::MEGABYTE;
+use crate::qdrant::CollectionConfig;
+use crate::release_notes::PageParser;
+use crate::retrieval_hook::HookSpec;
+use crate::retry::RetryPolicy;
+use crate::robots::matches;
+use crate::spelling::SpellingMode;
+use crate::transform::TransformSpec;
+use anyhow::{Error, Result};
+use log::info;
+use qdrant_client::client::QdrantClientConfig;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+// DEFAULT_CONFIG_PATH is the config file read from the working directory if no path is set
+pub static DEFAULT_CONFIG_PATH: &str = "rura.toml";
+// DEFAULT_QDRANT_ADDRESS is the address of qdrant by default
+pub static DEFAULT_QDRANT_ADDRESS: &str = "http://localhost:6334";
+// DEFAULT_BASE_COLLECTION is the prefix of the collections by default
+pub static DEFAULT_BASE_COLLECTION: &str = "rura_collection";
+// DEFAULT_OLLAMA_HOST is the host of ollama by default
+pub static DEFAULT_OLLAMA_HOST: &str = "http://localhost";
+// DEFAULT_OLLAMA_PORT is the port of ollama by default
+pub static DEFAULT_OLLAMA_PORT: u16 = 11434;
+// DEFAULT_OLLAMA_MODEL is the model answering and summarizing by default
+pub static DEFAULT_OLLAMA_MODEL: &str = "openhermes2.5-mistral:7b-q6_K";
+
+// DEFAULT_MAX_MESSAGE_SIZE_MB is the request size limit of qdrant by default
+pub static DEFAULT_MAX_MESSAGE_SIZE_MB: u64 = 32;
+
+// CrawlProfile represents the crawl settings of a domain, unset settings fall back to the flags
+// or the defaults of the crawl
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct CrawlProfile {
+    // content_selector selects the main content of the pages, e.g. main or article, the whole
+    // body is indexed if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_selector: Option<String>,
+    // exclude_selectors are removed from the content in addition to scripts and navigations,
+    // e.g. footer or .sidebar
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude_selectors: Vec<String>,
+    // concurrency is the maximum number of concurrent requests of the crawl
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+    // crawl_delay_ms is the minimum delay between two requests to the same host
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crawl_delay_ms: Option<u64>,
+    // headers are sent with every request of the crawl, e.g. an authorization header, values
+    // starting with $ are read from the env variable of that name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    // include restricts the crawl to url paths matching one of the patterns, e.g. /docs/*
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    // exclude skips the url paths matching one of the patterns, e.g. /blog/
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    // chunk_strategy measures the fragments in characters or tokens, or keeps sentences whole
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_strategy: Option<ChunkStrategy>,
+    // fragment_size is the minimum size of a fragment in characters, or tokens with the Tokens
+    // chunk strategy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fragment_size: Option<usize>,
+    // overlap_size is the number of characters or tokens a fragment may exceed the fragment
+    // size by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overlap_size: Option<usize>,
+    // lead_tokens adds a boosted lead fragment of the first lead_tokens tokens of each page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lead_tokens: Option<usize>,
+    // parent_size embeds chunks of fragment_size and returns their parent window of
+    // parent_size in searches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_size: Option<usize>,
+    // parser splits the pages into documents, e.g. ReleaseNotes splits changelogs by version
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parser: Option<PageParser>,
+    // readability extracts the main content of pages without content selector by scoring their
+    // paragraphs, false indexes the whole body
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub readability: Option<bool>,
+    // transforms are applied in order to the parsed documents before they are chunked, e.g.
+    // strip_boilerplate, redact, translate or enrich_metadata
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<TransformSpec>,
+    // follow_pagination crawls the further pages of paginated listings, e.g. blog archives, by
+    // their rel=next or common next page links
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub follow_pagination: Option<bool>,
+    // max_pagination_pages is the maximum number of further pages followed from a page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_pagination_pages: Option<usize>,
+}
+
+impl CrawlProfile {
+    // allows returns true if the url path matches the include patterns, if any, and none of the
+    // exclude patterns, patterns use the robots.txt syntax
+    pub fn allows(&self, url: &str) -> bool {
+        let path = match Url::parse(url) {
+            Ok(parsed) => parsed.path().to_string(),
+            Err(_) => return true,
+        };
+        let included =
+            self.include.is_empty() || self.include.iter().any(|pattern| matches(pattern, &path));
+        included && !self.exclude.iter().any(|pattern| matches(pattern, &path))
+    }
+
+    // chunking returns the chunking of the documents crawled with the profile
+    pub fn chunking(&self) -> Chunking {
+        let default = Chunking::default();
+        Chunking {
+            strategy: self.chunk_strategy.unwrap_or(default.strategy),
+            fragment_size: self.fragment_size.unwrap_or(default.fragment_size),
+            overlap_size: self.overlap_size.unwrap_or(default.overlap_size),
+            lead_tokens: self.lead_tokens.or(default.lead_tokens),
+            parent_size: self.parent_size.or(default.parent_size),
+        }
+    }
+
+    // header_map returns the headers sent with the requests of the crawl, resolving the values
+    // read from env variables
+    pub fn header_map(&self) -> Result<HeaderMap, Error> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let value = match value.strip_prefix('$') {
+                Some(variable) => std::env::var(variable).map_err(|_| {
+                    anyhow::anyhow!("Env variable {} of header {} is not set", variable, name)
+                })?,
+                None => value.clone(),
+            };
+            let mut value = HeaderValue::from_str(&value)?;
+            value.set_sensitive(true);
+            headers.insert(HeaderName::from_bytes(name.as_bytes())?, value);
+        }
+        Ok(headers)
+    }
+}
+
+// QdrantConnection represents the connection settings of the qdrant client, unset settings use
+// the defaults of the qdrant client
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct QdrantConnection {
+    // connect_timeout_secs limits establishing the connection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<u64>,
+    // timeout_secs limits each request, large upserts of slow clusters need more than the
+    // default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    // keep_alive_while_idle pings qdrant while no request is in flight so idle connections
+    // are not dropped by proxies
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive_while_idle: Option<bool>,
+    // max_message_size_mb is the maximum size of a request, upserts are split into batches
+    // below it, defaults to the qdrant limit of 32 MB
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_message_size_mb: Option<u64>,
+}
+
+impl QdrantConnection {
+    // client_config returns the config of the qdrant client at the url with the settings applied
+    pub fn client_config(&self, url: &str) -> QdrantClientConfig {
+        let mut config = QdrantClientConfig::from_url(url);
+        if let Some(connect_timeout_secs) = self.connect_timeout_secs {
+            config.set_connect_timeout(Duration::from_secs(connect_timeout_secs));
+        }
+        if let Some(timeout_secs) = self.timeout_secs {
+            config.set_timeout(Duration::from_secs(timeout_secs));
+        }
+        if let Some(keep_alive_while_idle) = self.keep_alive_while_idle {
+            config.set_keep_alive_while_idle(keep_alive_while_idle);
+        }
+        config
+    }
+
+    // max_message_size returns the maximum size of a request in bytes
+    pub fn max_message_size(&self) -> usize {
+        (self
+            .max_message_size_mb
+            .unwrap_or(DEFAULT_MAX_MESSAGE_SIZE_MB)
+            * MEGABYTE) as usize
+    }
+}
+
+// RetrySettings represents the retries of the qdrant calls and the generations of the LLM after
+// transient errors, unset settings use the defaults of the retry policy
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RetrySettings {
+    // max_retries is the number of retries after the first attempt, 0 disables the retries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
+    // base_delay_ms is the backoff of the first retry, doubled on each further retry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_delay_ms: Option<u64>,
+    // max_delay_ms caps the backoff
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_delay_ms: Option<u64>,
+}
+
+impl RetrySettings {
+    // policy returns the retry policy with the settings applied
+    pub fn policy(&self) -> RetryPolicy {
+        let default_policy = RetryPolicy::default();
+        RetryPolicy {
+            max_retries: self.max_retries.unwrap_or(default_policy.max_retries),
+            base_delay: self
+                .base_delay_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default_policy.base_delay),
+            max_delay: self
+                .max_delay_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default_policy.max_delay),
+        }
+    }
+}
+
+// OllamaSettings represents the connection and models of ollama, unset settings use the defaults
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct OllamaSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    // model answers the queries and summarizes the documents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    // fast_model classifies the queries, the model is used if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fast_model: Option<String>,
+}
+
+impl OllamaSettings {
+    // host returns the host of ollama
+    pub fn host(&self) -> String {
+        self.host.clone().unwrap_or(DEFAULT_OLLAMA_HOST.to_string())
+    }
+
+    // port returns the port of ollama
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_OLLAMA_PORT)
+    }
+
+    // model returns the model answering and summarizing
+    pub fn model(&self) -> String {
+        self.model
+            .clone()
+            .unwrap_or(DEFAULT_OLLAMA_MODEL.to_string())
+    }
+}
+
+// CrawlSettings represents the crawl settings of all domains, the crawl profile of a domain
+// overrides them
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct CrawlSettings {
+    // concurrency is the maximum number of concurrent requests of the crawl
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+    // chunk_strategy measures the fragments in characters or tokens, or keeps sentences whole
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_strategy: Option<ChunkStrategy>,
+    // fragment_size is the minimum size of a fragment in the unit of the chunk strategy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fragment_size: Option<usize>,
+    // overlap_size is the number of characters or tokens a fragment may exceed the fragment
+    // size by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overlap_size: Option<usize>,
+}
+
+// PromptPaths represents the files shaping the prompts
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PromptPaths {
+    // glossary is a json file mapping internal terms to their definitions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glossary: Option<PathBuf>,
+    // synonyms is a json file mapping terms to their synonyms
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub synonyms: Option<PathBuf>,
+    // log is a jsonl file prompts and completions are logged to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log: Option<PathBuf>,
+}
+
+// EmbeddingSettings represents the embedding model of the collections of a base collection,
+// unset settings use the embedding provider of the env variables or the flags
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct EmbeddingSettings {
+    // provider is one of rust_bert, ollama or openai
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    // model is the embedding model of the ollama and openai providers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    // url is the base url of the ollama and openai providers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+// PromptLogSettings represents the sampling and size caps of the prompt log, unset settings use
+// the defaults of the prompt logger
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PromptLogSettings {
+    // sample_rate is the share of prompts logged, between 0.0 and 1.0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f64>,
+    // max_entry_size is the maximum number of characters logged per prompt or completion
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_entry_size: Option<usize>,
+    // max_file_size is the size in bytes after which no more prompts are logged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_file_size: Option<u64>,
+    // redact_terms are redacted from the logged prompts in addition to the email addresses
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub redact_terms: Vec<String>,
+}
+
+// ServerSettings represents the settings of the server, unset settings use the defaults of the
+// server
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ServerSettings {
+    // address is the address the server listens on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    // mirror_address is the address of a secondary qdrant the upserts and deletes are mirrored to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirror_address: Option<String>,
+    // query_scope is the topics of the indexed documentation, enables the out of scope check
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_scope: Option<String>,
+    // out_of_scope_message answers the out of scope queries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub out_of_scope_message: Option<String>,
+    // spelling corrects the typos of the queries before embedding
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spelling: Option<SpellingMode>,
+    // answer_retries is the number of retries of empty, refusing or ungrounded answers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer_retries: Option<usize>,
+    // embedding_batch_size is the number of fragments embedded per encode call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding_batch_size: Option<usize>,
+    // embedding_cache_size is the number of fragment embeddings cached across jobs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding_cache_size: Option<usize>,
+    // embedding_query_lane embeds the queries with a second rust-bert model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding_query_lane: Option<bool>,
+    // answer_cache_size is the number of answers cached to answer queries during outages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer_cache_size: Option<usize>,
+    // memory_budget_mb is the memory qdrant can use, upload dry runs warn if the index exceeds it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_budget_mb: Option<u64>,
+    // max_concurrent_jobs is the maximum number of upload jobs running at once
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_jobs: Option<usize>,
+    // sources_path is a json file the re-crawled sources are persisted to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sources_path: Option<PathBuf>,
+    // jobs_path is a json file the progress of the jobs is persisted to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jobs_path: Option<PathBuf>,
+}
+
+// Config represents the config file shared by the client and the server, rura.toml by default,
+// e.g.
+//
+// address = "http://localhost:6334"
+// collections = ["Basic", "Summary"]
+//
+// [ollama]
+// model = "mistral"
+//
+// [profiles."docs.lagoon.sh"]
+// content_selector = "main"
+//
+// [embeddings.docs_v2]
+// provider = "ollama"
+// model = "nomic-embed-text"
+//
+// [server]
+// spelling = "dictionary"
+// max_concurrent_jobs = 2
+//
+// the settings are layered, env variables override the file and flags override both
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Config {
+    // address is the address of qdrant
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    // base_collection is the prefix of the collections
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_collection: Option<String>,
+    // collections are the collections indexed into and searched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collections: Option<Vec<Collection>>,
+    // ollama sets the connection and models of ollama
+    pub ollama: OllamaSettings,
+    // crawl sets the concurrency and chunk sizes of all domains
+    pub crawl: CrawlSettings,
+    // prompts sets the glossary, synonyms and prompt log files
+    pub prompts: PromptPaths,
+    // profiles are the crawl profiles keyed by domain, a profile also applies to the
+    // subdomains of its domain
+    pub profiles: BTreeMap<String, CrawlProfile>,
+    // qdrant sets the timeouts, keep-alive and request size of the qdrant client
+    pub qdrant: QdrantConnection,
+    // retry sets the attempts and the backoff of the retries of qdrant and ollama
+    pub retry: RetrySettings,
+    // retrieval_hooks filter and boost the retrieved documents of the queries before they are
+    // prompted, in their order, e.g. label_gate or boost
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub retrieval_hooks: Vec<HookSpec>,
+    // embeddings are the embedding models keyed by base collection, e.g. to serve a collection
+    // embedded with another model, the other base collections use the default provider
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub embeddings: BTreeMap<String, EmbeddingSettings>,
+    // embedding sets the default embedding provider of the server
+    pub embedding: EmbeddingSettings,
+    // collection sets the sharding, replication and storage of the created collections
+    pub collection: CollectionConfig,
+    // prompt_log sets the sampling and size caps of the prompt log
+    pub prompt_log: PromptLogSettings,
+    // server sets the listen address, caches, jobs and query handling of the server
+    pub server: ServerSettings,
+}
+
+impl Config {
+    // load reads the config from a toml file, or a json file if the extension is json
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)?;
+        let config: Config = match is_json(path) {
+            true => serde_json::from_str(&content)?,
+            false => toml::from_str(&content)?,
+        };
+        info!(
+            "Loaded {} crawl profiles from {:?}",
+            config.profiles.len(),
+            path
+        );
+        Ok(config)
+    }
+
+    // discover reads the config from the path, or from rura.toml in the working directory if
+    // no path is set, without a file the defaults are used
+    pub fn discover(path: Option<&Path>) -> Result<Self, Error> {
+        match path {
+            Some(path) => Config::load(path),
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Config::load(Path::new(DEFAULT_CONFIG_PATH))
+            }
+            None => Ok(Config::default()),
+        }
+    }
+
+    // save writes the config to a toml file, or a json file if the extension is json
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let content = match is_json(path) {
+            true => serde_json::to_string_pretty(self)?,
+            false => toml::to_string_pretty(self)?,
+        };
+        fs::write(path, content)?;
+        info!("Saved {} crawl profiles to {:?}", self.profiles.len(), path);
+        Ok(())
+    }
+
+    // with_env overrides the settings of the file by the env variables set, e.g. OLLAMA_MODEL
+    pub fn with_env(mut self) -> Result<Self, Error> {
+        let var = |name: &str| std::env::var(name).ok();
+        self.address = var("QDRANT_CLIENT_ADDRESS").or(self.address);
+        self.base_collection = var("BASE_COLLECTION").or(self.base_collection);
+        if let Some(collections) = var("FILTER_COLLECTIONS") {
+            self.collections = Some(
+                parse_collections(&collections)
+                    .map_err(|e| anyhow::anyhow!("Invalid value for FILTER_COLLECTIONS: {}", e))?,
+            );
+        }
+        self.ollama.host = var("OLLAMA_HOST").or(self.ollama.host);
+        self.ollama.port = env_parse("OLLAMA_PORT")?.or(self.ollama.port);
+        self.ollama.model = var("OLLAMA_MODEL").or(self.ollama.model);
+        self.ollama.fast_model = var("OLLAMA_FAST_MODEL").or(self.ollama.fast_model);
+        self.crawl.concurrency = env_parse("CRAWL_CONCURRENCY")?.or(self.crawl.concurrency);
+        self.crawl.chunk_strategy = env_parse("CHUNK_STRATEGY")?.or(self.crawl.chunk_strategy);
+        self.crawl.fragment_size = env_parse("FRAGMENT_SIZE")?.or(self.crawl.fragment_size);
+        self.crawl.overlap_size = env_parse("OVERLAP_SIZE")?.or(self.crawl.overlap_size);
+        self.prompts.glossary = env_parse("GLOSSARY_PATH")?.or(self.prompts.glossary);
+        self.prompts.synonyms = env_parse("SYNONYMS_PATH")?.or(self.prompts.synonyms);
+        self.prompts.log = env_parse("PROMPT_LOG_PATH")?.or(self.prompts.log);
+        self.retry.max_retries = env_parse("RETRY_MAX_RETRIES")?.or(self.retry.max_retries);
+        self.retry.base_delay_ms = env_parse("RETRY_BASE_DELAY_MS")?.or(self.retry.base_delay_ms);
+        self.retry.max_delay_ms = env_parse("RETRY_MAX_DELAY_MS")?.or(self.retry.max_delay_ms);
+        self.embedding.provider = var("EMBEDDING_PROVIDER").or(self.embedding.provider);
+        if let Some(provider) = &self.embedding.provider {
+            provider
+                .parse::<EmbeddingProviderKind>()
+                .map_err(|e| anyhow::anyhow!("Invalid embedding provider: {}", e))?;
+        }
+        self.embedding.model = var("EMBEDDING_MODEL").or(self.embedding.model);
+        self.embedding.url = var("EMBEDDING_URL").or(self.embedding.url);
+        self.collection.shard_number =
+            env_parse("QDRANT_SHARD_NUMBER")?.or(self.collection.shard_number);
+        self.collection.replication_factor =
+            env_parse("QDRANT_REPLICATION_FACTOR")?.or(self.collection.replication_factor);
+        self.collection.write_consistency_factor = env_parse("QDRANT_WRITE_CONSISTENCY_FACTOR")?
+            .or(self.collection.write_consistency_factor);
+        self.collection.on_disk_payload =
+            env_parse("QDRANT_ON_DISK_PAYLOAD")?.or(self.collection.on_disk_payload);
+        self.collection.on_disk_vectors =
+            env_parse("QDRANT_ON_DISK_VECTORS")?.or(self.collection.on_disk_vectors);
+        self.collection.memmap_threshold_kb =
+            env_parse("QDRANT_MEMMAP_THRESHOLD_KB")?.or(self.collection.memmap_threshold_kb);
+        self.collection.named_vectors =
+            env_parse("QDRANT_NAMED_VECTORS")?.or(self.collection.named_vectors);
+        self.prompt_log.sample_rate =
+            env_parse("PROMPT_LOG_SAMPLE_RATE")?.or(self.prompt_log.sample_rate);
+        self.prompt_log.max_entry_size =
+            env_parse("PROMPT_LOG_MAX_ENTRY_SIZE")?.or(self.prompt_log.max_entry_size);
+        self.prompt_log.max_file_size =
+            env_parse("PROMPT_LOG_MAX_FILE_SIZE")?.or(self.prompt_log.max_file_size);
+        if let Some(redact_terms) = var("PROMPT_LOG_REDACT_TERMS") {
+            self.prompt_log.redact_terms = redact_terms
+                .split(',')
+                .map(|term| term.trim().to_string())
+                .filter(|term| !term.is_empty())
+                .collect();
+        }
+        self.server.address = var("ADDRESS").or(self.server.address);
+        self.server.mirror_address = var("QDRANT_MIRROR_ADDRESS").or(self.server.mirror_address);
+        self.server.query_scope = var("QUERY_SCOPE").or(self.server.query_scope);
+        self.server.out_of_scope_message =
+            var("OUT_OF_SCOPE_MESSAGE").or(self.server.out_of_scope_message);
+        self.server.spelling = env_parse("QUERY_SPELLING")?.or(self.server.spelling);
+        self.server.answer_retries = env_parse("ANSWER_RETRIES")?.or(self.server.answer_retries);
+        self.server.embedding_batch_size =
+            env_parse("EMBEDDING_BATCH_SIZE")?.or(self.server.embedding_batch_size);
+        self.server.embedding_cache_size =
+            env_parse("EMBEDDING_CACHE_SIZE")?.or(self.server.embedding_cache_size);
+        self.server.embedding_query_lane =
+            env_parse("EMBEDDING_QUERY_LANE")?.or(self.server.embedding_query_lane);
+        self.server.answer_cache_size =
+            env_parse("ANSWER_CACHE_SIZE")?.or(self.server.answer_cache_size);
+        self.server.memory_budget_mb =
+            env_parse("QDRANT_MEMORY_BUDGET_MB")?.or(self.server.memory_budget_mb);
+        self.server.max_concurrent_jobs =
+            env_parse("MAX_CONCURRENT_JOBS")?.or(self.server.max_concurrent_jobs);
+        self.server.sources_path = env_parse("SOURCES_PATH")?.or(self.server.sources_path);
+        self.server.jobs_path = env_parse("JOBS_PATH")?.or(self.server.jobs_path);
+        self.collection.validate()?;
+        Ok(self)
+    }
+
+    // address returns the address of qdrant
+    pub fn address(&self) -> String {
+        self.address
+            .clone()
+            .unwrap_or(DEFAULT_QDRANT_ADDRESS.to_string())
+    }
+
+    // base_collection returns the prefix of the collections
+    pub fn base_collection(&self) -> String {
+        self.base_collection
+            .clone()
+            .unwrap_or(DEFAULT_BASE_COLLECTION.to_string())
+    }
+
+    // collections returns the collections indexed into and searched, basic by default
+    pub fn collections(&self) -> Vec<Collection> {
+        self.collections.clone().unwrap_or(vec![Collection::Basic])
+    }
+
+    // crawl_profile returns the crawl profile of the domain of the url with the unset settings
+    // taken from the crawl settings of all domains
+    pub fn crawl_profile(&self, url: &str) -> CrawlProfile {
+        let mut profile = self.profile(url).cloned().unwrap_or_default();
+        profile.concurrency = profile.concurrency.or(self.crawl.concurrency);
+        profile.chunk_strategy = profile.chunk_strategy.or(self.crawl.chunk_strategy);
+        profile.fragment_size = profile.fragment_size.or(self.crawl.fragment_size);
+        profile.overlap_size = profile.overlap_size.or(self.crawl.overlap_size);
+        profile
+    }
+
+    // profile returns the crawl profile of the domain of the url, the profile of the most
+    // specific domain wins, e.g. docs.lagoon.sh over lagoon.sh
+    pub fn profile(&self, url: &str) -> Option<&CrawlProfile> {
+        let host = Url::parse(url).ok()?.host_str()?.to_lowercase();
+        let (domain, profile) = self
+            .profiles
+            .iter()
+            .filter(|(domain, _)| {
+                let domain = domain.to_lowercase();
+                host == domain || host.ends_with(&format!(".{}", domain))
+            })
+            .max_by_key(|(domain, _)| domain.len())?;
+        info!("Using crawl profile {} for {}", domain, url);
+        Some(profile)
+    }
+}
+
+// is_json returns true if the config file is a json file, e.g. written by earlier versions
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension == "json")
+}
+
+// env_parse returns the parsed env variable, None if it is not set, the error names the variable
+// and the reason the value is invalid
+pub fn env_parse<T>(name: &str) -> Result<Option<T>, Error>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse::<T>()
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Invalid value for {}: {}, {}", name, value, e)),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_sections_are_read_from_the_file() {
+        let config: Config = toml::from_str(
+            r#"
+            [server]
+            spelling = "dictionary"
+            max_concurrent_jobs = 2
+            sources_path = "sources.json"
+
+            [prompt_log]
+            sample_rate = 0.5
+            redact_terms = ["acme"]
+
+            [collection]
+            replication_factor = 2
+            on_disk_vectors = true
+
+            [embedding]
+            provider = "ollama"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.server.spelling, Some(SpellingMode::Dictionary));
+        assert_eq!(config.server.max_concurrent_jobs, Some(2));
+        assert_eq!(
+            config.server.sources_path,
+            Some(PathBuf::from("sources.json"))
+        );
+        assert_eq!(config.prompt_log.sample_rate, Some(0.5));
+        assert_eq!(config.prompt_log.redact_terms, vec!["acme".to_string()]);
+        assert_eq!(config.collection.replication_factor, Some(2));
+        assert_eq!(config.collection.on_disk_vectors, Some(true));
+        assert_eq!(config.embedding.provider.as_deref(), Some("ollama"));
+    }
+
+    #[test]
+    fn unknown_spelling_mode_fails_the_file() {
+        let config = toml::from_str::<Config>("[server]\nspelling = \"fuzzy\"\n");
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn unknown_spelling_mode_is_rejected() {
+        let e = "fuzzy".parse::<SpellingMode>().unwrap_err();
+        assert!(e.to_string().contains("off, dictionary, llm"));
+        assert_eq!(" LLM ".parse::<SpellingMode>().unwrap(), SpellingMode::Llm);
+    }
+}
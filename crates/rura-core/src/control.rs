@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+// JobSignal represents what a running job is asked to do at its next checkpoint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JobSignal {
+    #[default]
+    Run,
+    Pause,
+    Cancel,
+}
+
+// JobControl lets a running job be paused, resumed and cancelled between documents
+#[derive(Debug)]
+pub struct JobControl {
+    signal: watch::Sender<JobSignal>,
+}
+
+impl Default for JobControl {
+    fn default() -> Self {
+        let (signal, _) = watch::channel(JobSignal::Run);
+        JobControl { signal: signal }
+    }
+}
+
+impl JobControl {
+    // signal returns the current signal of the job
+    pub fn signal(&self) -> JobSignal {
+        *self.signal.borrow()
+    }
+
+    // pause pauses the job at its next checkpoint, cancelled jobs stay cancelled
+    pub fn pause(&self) {
+        self.signal.send_if_modified(|signal| match *signal {
+            JobSignal::Run => {
+                *signal = JobSignal::Pause;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    // resume lets a paused job continue, cancelled jobs stay cancelled
+    pub fn resume(&self) {
+        self.signal.send_if_modified(|signal| match *signal {
+            JobSignal::Pause => {
+                *signal = JobSignal::Run;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    // cancel stops the job at its next checkpoint, paused jobs are woken up to stop
+    pub fn cancel(&self) {
+        self.signal.send_replace(JobSignal::Cancel);
+    }
+
+    // checkpoint waits while the job is paused and returns false if the job was cancelled
+    pub async fn checkpoint(&self) -> bool {
+        let mut receiver = self.signal.subscribe();
+        loop {
+            match *receiver.borrow_and_update() {
+                JobSignal::Run => return true,
+                JobSignal::Cancel => return false,
+                JobSignal::Pause => {}
+            }
+            if receiver.changed().await.is_err() {
+                return true;
+            }
+        }
+    }
+}
+
+// JobControls keeps the controls of the running jobs by id
+#[derive(Debug, Default)]
+pub struct JobControls {
+    controls: Mutex<HashMap<Uuid, Arc<JobControl>>>,
+}
+
+// ControlGuard keeps the control of a job registered until it is dropped
+pub struct ControlGuard {
+    controls: Arc<JobControls>,
+    id: Uuid,
+    control: Arc<JobControl>,
+}
+
+impl JobControls {
+    // register registers the control of a running job until the returned guard is dropped
+    pub fn register(self: &Arc<Self>, id: Uuid) -> ControlGuard {
+        let control = Arc::new(JobControl::default());
+        self.controls.lock().unwrap().insert(id, control.clone());
+        ControlGuard {
+            controls: self.clone(),
+            id: id,
+            control: control,
+        }
+    }
+
+    // get returns the control of a running job, None if the job is unknown or finished
+    pub fn get(&self, id: &Uuid) -> Option<Arc<JobControl>> {
+        self.controls.lock().unwrap().get(id).cloned()
+    }
+}
+
+impl ControlGuard {
+    // control returns the control of the job
+    pub fn control(&self) -> Arc<JobControl> {
+        self.control.clone()
+    }
+}
+
+impl Drop for ControlGuard {
+    fn drop(&mut self) {
+        self.controls.lock().unwrap().remove(&self.id);
+    }
+}
@@ -0,0 +1,907 @@
+use crate::faq::Faq;
+use crate::forum::{ThreadStatus, UNANSWERED_PENALTY};
+use crate::ollama::Llm;
+use crate::qa_dump::vote_boost;
+use crate::release_notes::version_prefixes;
+use anyhow::Error;
+use chrono::prelude::*;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::str::FromStr;
+use text_splitter::TextSplitter;
+use tiktoken_rs::p50k_base;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// FRAGMENT_SIZE is the default size of a fragment
+pub static FRAGMENT_SIZE: usize = 1512;
+// OVERLAP_SIZE is the default size of the overlap between fragments
+pub static OVERLAP_SIZE: usize = 256;
+// MAX_TITLE_SIZE is the maximum size of a title
+static MAX_TITLE_SIZE: usize = 128;
+// MAX_URL_SIZE is the maximum size of a url
+static MAX_URL_SIZE: usize = 128;
+// META_FRAGMENT_SIZE is the size of the meta embedding
+pub static META_FRAGMENT_SIZE: usize = 384;
+// LEAD_BOOST is the factor the score of lead fragments is multiplied by in searches
+pub static LEAD_BOOST: f32 = 1.1;
+// DEPRECATED_PENALTY is the factor the score of archived or deprecated articles is multiplied by
+// in searches including them
+pub static DEPRECATED_PENALTY: f32 = 0.5;
+// PAYLOAD_SCHEMA_VERSION is the version of the payload of the points, points stored before the
+// payload was versioned have no version and are upgraded when read until `migrate` backfilled
+// them, version 1 derives timestamp_secs, version_prefixes and deprecated from the stored fields
+pub static PAYLOAD_SCHEMA_VERSION: u32 = 1;
+// DEPRECATED_LABELS are the labels marking an article or thread of a source system as archived
+// or deprecated
+static DEPRECATED_LABELS: [&str; 5] = ["archived", "deprecated", "legacy", "obsolete", "outdated"];
+
+// Collection represents a collection
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum Collection {
+    // the lowercase aliases accept the names of the collections used by the client and the env
+    // variables in requests
+    #[serde(alias = "basic")]
+    Basic,
+    #[serde(alias = "summary")]
+    Summary,
+    // Questions holds the questions generated for the basic fragments, hits are mapped back to
+    // the text of their fragment
+    #[serde(alias = "questions")]
+    Questions,
+}
+
+impl Collection {
+    // all returns all collections
+    pub fn all() -> Vec<Collection> {
+        vec![
+            Collection::Basic,
+            Collection::Summary,
+            Collection::Questions,
+        ]
+    }
+
+    // limit by collection
+    pub fn limit_by_collection(&self) -> f32 {
+        match self {
+            // basic collection is weighted higher
+            Collection::Basic => 0.8,
+            // summary collection is weighted lower
+            Collection::Summary => 0.2,
+            // question hits are joined with the basic fragments they map to
+            Collection::Questions => 0.4,
+        }
+    }
+}
+
+// collection to string
+impl ToString for Collection {
+    fn to_string(&self) -> String {
+        match self {
+            Collection::Basic => "basic".to_string(),
+            Collection::Summary => "summary".to_string(),
+            Collection::Questions => "questions".to_string(),
+        }
+    }
+}
+
+// string to collection, case insensitive so Summary and summary are the same collection
+impl FromStr for Collection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "basic" => Ok(Collection::Basic),
+            "summary" => Ok(Collection::Summary),
+            "questions" => Ok(Collection::Questions),
+            _ => Err(anyhow::anyhow!(
+                "unknown collection: {}, valid values are: basic, summary, questions, all",
+                s
+            )),
+        }
+    }
+}
+
+// parse_collections returns the collections of a comma separated list, all selects every
+// collection, duplicates are dropped
+pub fn parse_collections(s: &str) -> Result<Vec<Collection>, Error> {
+    let mut collections = Vec::new();
+    for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let selected = match name.eq_ignore_ascii_case("all") {
+            true => Collection::all(),
+            false => vec![name.parse()?],
+        };
+        for collection in selected {
+            if !collections.contains(&collection) {
+                collections.push(collection);
+            }
+        }
+    }
+    if collections.is_empty() {
+        return Err(anyhow::anyhow!("no collection in: {:?}", s));
+    }
+    Ok(collections)
+}
+
+// EmbeddedMetadata represents metadata embedded in a document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddedMetadata {
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    pub url: String,
+    pub text: String,
+    #[serde(default)]
+    pub timestamp: String,
+    pub collection: Collection,
+    // schema_version is the PAYLOAD_SCHEMA_VERSION the payload was written with, 0 for points
+    // stored before the payload was versioned
+    #[serde(default)]
+    pub schema_version: u32,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub canonical_url: Option<String>,
+    #[serde(default)]
+    pub page_hash: Option<String>,
+    // timestamp_secs is the timestamp as unix seconds to filter by date ranges in qdrant
+    #[serde(default)]
+    pub timestamp_secs: Option<i64>,
+    // commit is the git commit the document was read at, None for crawled pages
+    #[serde(default)]
+    pub commit: Option<String>,
+    // lead is true for the lead fragment of a page, boosted in searches
+    #[serde(default)]
+    pub lead: bool,
+    // version and release_date are set for the releases of release notes pages
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub release_date: Option<String>,
+    // version_prefixes are the version and its shorter prefixes, e.g. 2, 2.3 and 2.3.1, so a
+    // version filter of 2.3 matches all its patch releases
+    #[serde(default)]
+    pub version_prefixes: Vec<String>,
+    // author, created_at and thread_status are set for the threads of forums and issue trackers
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub thread_status: Option<ThreadStatus>,
+    // votes is the score of the question of a question answer site, boosted in searches
+    #[serde(default)]
+    pub votes: Option<i64>,
+    // category and labels are set for the articles of help centers
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    // deprecated is true for articles and threads flagged as archived or deprecated by their
+    // source system, excluded from searches unless included explicitly
+    #[serde(default)]
+    pub deprecated: bool,
+    // question and parent_id are set for the fragments of the questions collection, the text is
+    // the text of the basic fragment the question was generated for, chunks of parent windows
+    // have the id of their window as parent_id and its text as text
+    #[serde(default)]
+    pub question: Option<String>,
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    // chunk_index is the position of the chunk in the page, None for lead and FAQ fragments
+    #[serde(default)]
+    pub chunk_index: Option<usize>,
+    // section is the breadcrumb of the h1 to h3 headings above the fragment, e.g.
+    // Install > Linux, None for pages without headings
+    #[serde(default)]
+    pub section: Option<String>,
+}
+
+impl EmbeddedMetadata {
+    // from_payload returns the metadata of the payload of a point, the fields missing in
+    // payloads of older schema versions are backfilled
+    pub fn from_payload(payload: serde_json::Value) -> Result<Self, Error> {
+        let mut metadata: EmbeddedMetadata = serde_json::from_value(payload)
+            .map_err(|e| anyhow::anyhow!("Error converting metadata: {}", e))?;
+        metadata.upgrade();
+        Ok(metadata)
+    }
+
+    // upgrade backfills the fields derived from the stored fields that are missing in payloads
+    // of older schema versions and sets the current version, returns false if the payload is
+    // already current
+    pub fn upgrade(&mut self) -> bool {
+        if self.schema_version >= PAYLOAD_SCHEMA_VERSION {
+            return false;
+        }
+        if self.timestamp_secs.is_none() {
+            self.timestamp_secs = DateTime::parse_from_rfc3339(&self.timestamp)
+                .ok()
+                .map(|timestamp| timestamp.timestamp());
+        }
+        if self.version_prefixes.is_empty() {
+            self.version_prefixes = self
+                .version
+                .as_deref()
+                .map(version_prefixes)
+                .unwrap_or_default();
+        }
+        self.deprecated = self.deprecated || deprecated_label(&self.labels);
+        self.schema_version = PAYLOAD_SCHEMA_VERSION;
+        true
+    }
+
+    // from_document returns a new EmbeddedMetadata from a document
+    pub fn from_document(
+        document: &Document,
+        text: String,
+        collection: Collection,
+    ) -> Result<Self, Error> {
+        Ok(EmbeddedMetadata {
+            id: fragment_id(&document.url, &text),
+            title: document.title.clone(),
+            url: document.url.clone(),
+            text: text,
+            timestamp: document.timestamp.to_rfc3339(),
+            collection: collection,
+            schema_version: PAYLOAD_SCHEMA_VERSION,
+            language: document.language.clone(),
+            canonical_url: document.canonical_url.clone(),
+            page_hash: Some(document.page_hash()),
+            timestamp_secs: Some(document.timestamp.timestamp()),
+            commit: document.commit.clone(),
+            lead: false,
+            version: document.version.clone(),
+            release_date: document.release_date.clone(),
+            version_prefixes: document
+                .version
+                .as_deref()
+                .map(version_prefixes)
+                .unwrap_or_default(),
+            author: document.author.clone(),
+            created_at: document.created_at.clone(),
+            thread_status: document.thread_status,
+            votes: document.votes,
+            category: document.category.clone(),
+            labels: document.labels.clone(),
+            deprecated: document.deprecated,
+            question: None,
+            parent_id: None,
+            chunk_index: None,
+            section: None,
+        })
+    }
+
+    // from_fragment returns a new EmbeddedMetadata of a fragment of a document
+    pub fn from_fragment(document: &Document, fragment: Fragment) -> Result<Self, Error> {
+        let mut metadata =
+            EmbeddedMetadata::from_document(document, fragment.text.clone(), fragment.collection)?;
+        metadata.lead = fragment.lead;
+        if let Some(title) = fragment.title {
+            metadata.title = title;
+        }
+        metadata.chunk_index = fragment.chunk_index;
+        metadata.section = fragment.section;
+        // the question or chunk is embedded, the payload keeps the text of its parent
+        if let Some(parent) = fragment.parent {
+            metadata.parent_id = Some(fragment_id(&document.url, &parent));
+            if fragment.collection == Collection::Questions {
+                metadata.question = Some(fragment.text);
+            }
+            metadata.text = parent;
+        }
+        Ok(metadata)
+    }
+
+    // lead_boost returns the boost of lead fragments, None for other fragments
+    pub fn lead_boost(&self) -> Option<f32> {
+        match self.lead {
+            true => Some(LEAD_BOOST),
+            false => None,
+        }
+    }
+
+    // thread_penalty returns the penalty of unanswered threads, None for other fragments
+    pub fn thread_penalty(&self) -> Option<f32> {
+        match self.thread_status {
+            Some(ThreadStatus::Unanswered) => Some(UNANSWERED_PENALTY),
+            _ => None,
+        }
+    }
+
+    // vote_boost returns the boost of upvoted questions, None for other fragments
+    pub fn vote_boost(&self) -> Option<f32> {
+        self.votes.and_then(vote_boost)
+    }
+
+    // deprecation_penalty returns the penalty of deprecated articles, None for other fragments
+    pub fn deprecation_penalty(&self) -> Option<f32> {
+        match self.deprecated {
+            true => Some(DEPRECATED_PENALTY),
+            false => None,
+        }
+    }
+
+    // score_factor returns the factor the search score of the fragment is multiplied by
+    pub fn score_factor(&self) -> f32 {
+        self.lead_boost().unwrap_or(1.0)
+            * self.thread_penalty().unwrap_or(1.0)
+            * self.vote_boost().unwrap_or(1.0)
+            * self.deprecation_penalty().unwrap_or(1.0)
+    }
+}
+
+// deprecated_label returns true if one of the labels marks an article as archived or deprecated,
+// e.g. Deprecated or outdated-docs
+pub fn deprecated_label(labels: &[String]) -> bool {
+    labels.iter().any(|label| {
+        let label = label.to_lowercase();
+        DEPRECATED_LABELS
+            .iter()
+            .any(|deprecated| label.contains(deprecated))
+    })
+}
+
+// lead_text returns the first tokens of a text
+pub fn lead_text(text: &str, tokens: usize) -> Result<String, Error> {
+    let bpe = p50k_base()?;
+    let encoded = bpe.encode_with_special_tokens(text);
+    bpe.decode(encoded[..tokens.min(encoded.len())].to_vec())
+}
+
+// fragment_id returns the id of a fragment, a hash of the url and text to avoid duplicates
+pub fn fragment_id(url: &str, text: &str) -> String {
+    let hash_text = format!("{}{}", url, text);
+    let mut hasher = Sha1::new();
+    hasher.update(hash_text);
+    let hash = hasher.finalize();
+    let hash = format!("{:x}", hash);
+    Uuid::new_v5(&Uuid::NAMESPACE_OID, hash.as_bytes()).to_string()
+}
+
+// EmbeddedDocument represents a document with embeddings
+#[derive(Debug, Clone)]
+pub struct EmbeddedDocument {
+    pub text_embeddings: Vec<f32>,
+    // title_embeddings is the embedding of the title of the document, stored as title vector by
+    // collections with named vectors, which store the text embeddings if None
+    pub title_embeddings: Option<Vec<f32>>,
+    pub metadata: EmbeddedMetadata,
+}
+
+// ScoredDocument represents a document retrieved by a search with its similarity score
+#[derive(Debug, Clone)]
+pub struct ScoredDocument {
+    pub metadata: EmbeddedMetadata,
+    pub score: f32,
+}
+
+// Document represents a document
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub title: String,
+    pub url: String,
+    pub text: HashMap<Collection, String>,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    // language is the detected language tag of the document, e.g. en or de-ch
+    pub language: Option<String>,
+    // canonical_url groups the translations of a document
+    pub canonical_url: Option<String>,
+    // chunking sets the size of the fragments of the document
+    pub chunking: Chunking,
+    // commit is the git commit of documents read from a repository
+    pub commit: Option<String>,
+    // faqs are the question answer pairs of FAQ pages, indexed as their own basic fragments
+    pub faqs: Vec<Faq>,
+    // version and release_date are set for the releases of release notes pages, e.g. 2.3.1
+    // and 2024-01-05
+    pub version: Option<String>,
+    pub release_date: Option<String>,
+    // author, created_at and thread_status are set for the threads of forums and issue
+    // trackers, created_at is the rfc3339 date of the opening post
+    pub author: Option<String>,
+    pub created_at: Option<String>,
+    pub thread_status: Option<ThreadStatus>,
+    // votes is the score of the question of a question answer site
+    pub votes: Option<i64>,
+    // category and labels are set for the articles of help centers, e.g. Billing and refund
+    pub category: Option<String>,
+    pub labels: Vec<String>,
+    // deprecated is true if the source system flags the article or thread as archived or
+    // deprecated
+    pub deprecated: bool,
+    // sections split the basic text by its h1 to h3 headings, the basic fragments are cut per
+    // section, empty for pages without headings
+    pub sections: Vec<Section>,
+    // questions are the generated questions by basic chunk text, indexed in the questions
+    // collection
+    pub questions: HashMap<String, Vec<String>>,
+}
+
+// ChunkStrategy represents the unit the fragment and overlap sizes are measured in and the
+// boundaries fragments are cut at
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum ChunkStrategy {
+    // Characters measures the fragments in characters, cut at the largest semantic unit fitting,
+    // e.g. a sentence or a word
+    #[default]
+    Characters,
+    // Tokens measures the fragments in p50k tokens of the LLM, so fragments fill a known share
+    // of the context window
+    Tokens,
+    // Sentences measures the fragments in characters but never cuts a paragraph or sentence,
+    // unless a single sentence exceeds the fragment and overlap size
+    Sentences,
+}
+
+// string to chunk strategy, case insensitive, unknown strategies are an error so a typo doesn't
+// chunk the corpus differently
+impl FromStr for ChunkStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "characters" => Ok(ChunkStrategy::Characters),
+            "tokens" => Ok(ChunkStrategy::Tokens),
+            "sentences" => Ok(ChunkStrategy::Sentences),
+            _ => Err(anyhow::anyhow!(
+                "unknown chunk strategy: {}, valid values are: characters, tokens, sentences",
+                s
+            )),
+        }
+    }
+}
+
+// Chunking represents how the text of a document is split into fragments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunking {
+    // strategy sets the unit of the sizes and the boundaries of the fragments
+    pub strategy: ChunkStrategy,
+    // fragment_size is the minimum size of a fragment in the unit of the strategy
+    pub fragment_size: usize,
+    // overlap_size is the number of characters or tokens a fragment may exceed the fragment
+    // size by
+    pub overlap_size: usize,
+    // lead_tokens adds a lead fragment of the first lead_tokens tokens of the basic text of
+    // pages split into more than one fragment, None disables the lead fragment
+    pub lead_tokens: Option<usize>,
+    // parent_size splits the basic text into parent windows of parent_size first, the chunks
+    // of a window are embedded and searches return the window, None embeds and returns the
+    // chunks
+    pub parent_size: Option<usize>,
+}
+
+impl Default for Chunking {
+    fn default() -> Self {
+        Chunking {
+            strategy: ChunkStrategy::default(),
+            fragment_size: FRAGMENT_SIZE,
+            overlap_size: OVERLAP_SIZE,
+            lead_tokens: None,
+            parent_size: None,
+        }
+    }
+}
+
+impl Chunking {
+    // chunks splits a text into the fragments of the strategy
+    pub fn chunks(&self, text: &str) -> Result<Vec<String>, Error> {
+        let range = self.fragment_size..self.fragment_size + self.overlap_size;
+        match self.strategy {
+            ChunkStrategy::Characters => Ok(TextSplitter::default()
+                .with_trim_chunks(true)
+                .chunks(text, range)
+                .map(str::to_string)
+                .collect()),
+            ChunkStrategy::Tokens => Ok(TextSplitter::new(p50k_base()?)
+                .with_trim_chunks(true)
+                .chunks(text, range)
+                .map(str::to_string)
+                .collect()),
+            ChunkStrategy::Sentences => Ok(sentence_chunks(text, range)),
+        }
+    }
+
+    // windows splits a text into the chunks of the strategy paired with the parent window they
+    // are part of, chunks have no window without parent_size
+    pub fn windows(&self, text: &str) -> Result<Vec<(String, Option<String>)>, Error> {
+        let parent_size = match self.parent_size {
+            Some(parent_size) => parent_size,
+            None => {
+                return Ok(self
+                    .chunks(text)?
+                    .into_iter()
+                    .map(|chunk| (chunk, None))
+                    .collect())
+            }
+        };
+        let parents = Chunking {
+            fragment_size: parent_size,
+            ..*self
+        }
+        .chunks(text)?;
+        let mut windows = Vec::new();
+        for parent in parents {
+            for chunk in self.chunks(&parent)? {
+                windows.push((chunk, Some(parent.clone())));
+            }
+        }
+        Ok(windows)
+    }
+}
+
+// sentence_chunks packs whole paragraphs and sentences into chunks of at least range.start
+// characters, a chunk grows up to range.end to keep a sentence whole, longer sentences are split
+// by the character splitter
+fn sentence_chunks(text: &str, range: Range<usize>) -> Vec<String> {
+    let splitter = TextSplitter::default().with_trim_chunks(true);
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    for sentence in sentences(text) {
+        let size = chunk.chars().count() + sentence.chars().count() + 1;
+        if !chunk.is_empty() && (chunk.chars().count() >= range.start || size > range.end) {
+            chunks.push(std::mem::take(&mut chunk));
+        }
+        if sentence.chars().count() > range.end {
+            chunks.extend(splitter.chunks(sentence, range.clone()).map(str::to_string));
+            continue;
+        }
+        chunk = match chunk.is_empty() {
+            true => sentence.to_string(),
+            false => format!("{} {}", chunk, sentence),
+        };
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+// sentences returns the trimmed sentences of a text, paragraphs end a sentence as well
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    for paragraph in text.split("\n\n") {
+        let mut start = 0;
+        let mut chars = paragraph.char_indices().peekable();
+        while let Some((index, c)) = chars.next() {
+            let at_end = matches!(c, '.' | '!' | '?')
+                && chars.peek().map_or(true, |(_, next)| next.is_whitespace());
+            if at_end {
+                let end = index + c.len_utf8();
+                sentences.push(paragraph[start..end].trim());
+                start = end;
+            }
+        }
+        sentences.push(paragraph[start..].trim());
+    }
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
+
+// Fragment represents a fragment of a document
+#[derive(Debug, Clone)]
+pub struct Fragment {
+    pub text: String,
+    pub collection: Collection,
+    // lead is true for the lead fragment of the page
+    pub lead: bool,
+    // title overrides the title of the document, e.g. the question of a FAQ fragment
+    pub title: Option<String>,
+    // parent is the text a question or the chunk of a parent window maps to, returned by
+    // searches instead of the embedded text
+    pub parent: Option<String>,
+    // chunk_index is the position of the chunk in the text of its collection, None for lead
+    // and FAQ fragments
+    pub chunk_index: Option<usize>,
+    // section is the heading breadcrumb of the chunk, None for pages without headings
+    pub section: Option<String>,
+}
+
+// Section represents the text below a heading of a page with the breadcrumb of the h1 to h3
+// headings it is nested in, e.g. Install > Linux
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    // breadcrumb is None for the text before the first heading
+    pub breadcrumb: Option<String>,
+    pub text: String,
+}
+
+impl Document {
+    // new returns a new document from a url, title and text using collection.
+    pub fn new(collection: Collection, url: String, title: String, text: String) -> Self {
+        let mut text_map = HashMap::new();
+        text_map.insert(collection, text);
+
+        Document {
+            title: title,
+            url: url,
+            text: text_map.clone(),
+            timestamp: Utc::now(),
+            language: None,
+            canonical_url: None,
+            chunking: Chunking::default(),
+            commit: None,
+            faqs: Vec::new(),
+            version: None,
+            release_date: None,
+            author: None,
+            created_at: None,
+            thread_status: None,
+            votes: None,
+            category: None,
+            labels: Vec::new(),
+            deprecated: false,
+            sections: Vec::new(),
+            questions: HashMap::new(),
+        }
+    }
+
+    // page_hash returns a hash of the basic text of the document to detect changed pages
+    pub fn page_hash(&self) -> String {
+        let mut hasher = Sha1::new();
+        hasher.update(
+            self.text
+                .get(&Collection::Basic)
+                .cloned()
+                .unwrap_or_default(),
+        );
+        format!("{:x}", hasher.finalize())
+    }
+
+    pub fn update_text(&mut self, collection: Collection, text: String) {
+        debug!(
+            "Updating text {} for collection: {}",
+            text,
+            collection.to_string()
+        );
+        self.text.insert(collection, text);
+    }
+
+    // to_fragments returns a vector of fragments of the document
+    pub fn to_fragments(&self) -> Result<Vec<Fragment>, Error> {
+        info!("Splitting text into fragments by collections",);
+
+        // titles and urls are truncated by characters whatever the chunk strategy
+        let splitter = TextSplitter::default().with_trim_chunks(true);
+
+        // truncate title to MAX_TITLE_SIZE characters
+        let title = splitter.chunks(&self.title, MAX_TITLE_SIZE).next();
+
+        // truncate url to MAX_URL_SIZE characters
+        let url = splitter.chunks(&self.url, MAX_URL_SIZE).next();
+
+        let mut result = Vec::new();
+        for (collection, text) in &self.text {
+            info!("Collection: {}", collection.to_string());
+            // split text into chunks of fragment_size characters or tokens, a chunk may exceed
+            // it by overlap_size, basic chunks are part of a parent window with parent_size
+            let windows = match collection {
+                Collection::Basic => self.basic_windows(text)?,
+                _ => self
+                    .chunking
+                    .chunks(text)?
+                    .into_iter()
+                    .map(|chunk| (chunk, None, None))
+                    .collect(),
+            };
+            let mut text_results: Vec<(String, Option<String>, Option<usize>, Option<String>)> =
+                windows
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, (chunk, parent, section))| (chunk, parent, Some(index), section))
+                    .collect();
+            // the lead fragment comes first, a page of a single fragment is its own lead
+            if let (Collection::Basic, Some(lead_tokens)) = (collection, self.chunking.lead_tokens)
+            {
+                if text_results.len() > 1 {
+                    text_results.insert(0, (lead_text(text, lead_tokens)?, None, None, None));
+                }
+            }
+            for (text_result, parent, chunk_index, section) in text_results {
+                let title = title.clone();
+                let url = url.clone();
+                // the breadcrumb of the section follows the title and url in the prefix
+                let section = section
+                    .as_deref()
+                    .and_then(|section| splitter.chunks(section, MAX_TITLE_SIZE).next())
+                    .map(str::to_string);
+                match (title, url) {
+                    (Some(title), Some(url)) => {
+                        let prefix = match &section {
+                            Some(section) => {
+                                format!("Title: {} URL: {} Section: {}", title, url, section)
+                            }
+                            None => format!("Title: {} URL: {}", title, url),
+                        };
+                        let text = format!("{} Content: {}", prefix, text_result);
+                        let parent = parent.map(|parent| format!("{} Content: {}", prefix, parent));
+                        let lead = chunk_index.is_none();
+                        // the questions of a basic chunk map to its fragment or its window
+                        let questions = match (collection, lead) {
+                            (Collection::Basic, false) => self.questions.get(&text_result),
+                            _ => None,
+                        };
+                        for question in questions.into_iter().flatten() {
+                            result.push(Fragment {
+                                text: question.clone(),
+                                collection: Collection::Questions,
+                                lead: false,
+                                title: None,
+                                parent: Some(parent.clone().unwrap_or(text.clone())),
+                                chunk_index: chunk_index,
+                                section: section.clone(),
+                            });
+                        }
+                        result.push(Fragment {
+                            text: text,
+                            collection: collection.clone(),
+                            lead: lead,
+                            title: None,
+                            parent: parent,
+                            chunk_index: chunk_index,
+                            section: section,
+                        });
+                    }
+                    _ => {
+                        error!("Error splitting text, title or url not found");
+                        Err(anyhow::anyhow!(
+                            "Error splitting text, title or url not found"
+                        ))?
+                    }
+                }
+            }
+        }
+        // each question answer pair of a FAQ page is a fragment titled by the question
+        if let Some(url) = url {
+            for faq in &self.faqs {
+                let question = splitter
+                    .chunks(&faq.question, MAX_TITLE_SIZE)
+                    .next()
+                    .unwrap_or_default();
+                for answer in self.chunking.chunks(&faq.answer)? {
+                    result.push(Fragment {
+                        text: format!(
+                            "Title: {} URL: {} Content: {} {}",
+                            question, url, faq.question, answer
+                        ),
+                        collection: Collection::Basic,
+                        lead: false,
+                        title: Some(question.to_string()),
+                        parent: None,
+                        chunk_index: None,
+                        section: None,
+                    });
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    // basic_windows returns the windows of the basic text with the breadcrumb of their section,
+    // cut per section so no chunk spans two sections
+    pub fn basic_windows(
+        &self,
+        text: &str,
+    ) -> Result<Vec<(String, Option<String>, Option<String>)>, Error> {
+        if self.sections.is_empty() {
+            return Ok(self
+                .chunking
+                .windows(text)?
+                .into_iter()
+                .map(|(chunk, parent)| (chunk, parent, None))
+                .collect());
+        }
+        let mut windows = Vec::new();
+        for section in &self.sections {
+            for (chunk, parent) in self.chunking.windows(&section.text)? {
+                windows.push((chunk, parent, section.breadcrumb.clone()));
+            }
+        }
+        Ok(windows)
+    }
+
+    pub async fn add_summary(&mut self, model: &str, llm: &Llm) -> Result<(), Error> {
+        // retrieve the basic collection text
+        let basic_text = self.text.get(&Collection::Basic);
+        match basic_text {
+            Some(basic_text) => {
+                // get summary
+                let summary = llm.summarize(model, basic_text).await?;
+                // update text with summary
+                self.update_text(Collection::Summary, summary);
+                Ok(())
+            }
+            None => {
+                error!("Error adding summary, basic text not found");
+                Err(anyhow::anyhow!(
+                    "Error adding summary, basic text not found"
+                ))
+            }
+        }
+    }
+
+    // add_questions generates the questions each basic chunk answers, the chunks are the ones
+    // split by to_fragments so the questions map to their fragments
+    pub async fn add_questions(&mut self, model: &str, llm: &Llm) -> Result<(), Error> {
+        let basic_text = self.text.get(&Collection::Basic).ok_or(anyhow::anyhow!(
+            "Error adding questions, basic text not found"
+        ))?;
+        let mut questions = HashMap::new();
+        for (chunk, _, _) in self.basic_windows(basic_text)? {
+            let chunk_questions = llm.questions(model, &chunk).await?;
+            debug!("Generated {} questions for chunk", chunk_questions.len());
+            questions.insert(chunk, chunk_questions);
+        }
+        self.questions = questions;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_strategies_are_parsed() {
+        assert_eq!(
+            " Tokens ".parse::<ChunkStrategy>().unwrap(),
+            ChunkStrategy::Tokens
+        );
+        assert_eq!(
+            "sentences".parse::<ChunkStrategy>().unwrap(),
+            ChunkStrategy::Sentences
+        );
+        let e = "words".parse::<ChunkStrategy>().unwrap_err();
+        assert!(e.to_string().contains("characters, tokens, sentences"));
+    }
+
+    #[test]
+    fn sentences_end_at_punctuation_and_paragraphs() {
+        assert_eq!(
+            sentences("Version 1.2 is out. Update now! Why?\n\nA paragraph without end"),
+            vec![
+                "Version 1.2 is out.",
+                "Update now!",
+                "Why?",
+                "A paragraph without end"
+            ]
+        );
+        assert!(sentences(" \n\n ").is_empty());
+    }
+
+    #[test]
+    fn sentence_chunks_keep_sentences_whole() {
+        let chunking = Chunking {
+            strategy: ChunkStrategy::Sentences,
+            fragment_size: 20,
+            overlap_size: 20,
+            ..Chunking::default()
+        };
+        assert_eq!(
+            chunking
+                .chunks("Alpha is first. Beta is second. Gamma is third.")
+                .unwrap(),
+            vec!["Alpha is first. Beta is second.", "Gamma is third."]
+        );
+    }
+
+    #[test]
+    fn character_chunks_stay_within_the_range() {
+        let chunking = Chunking {
+            fragment_size: 40,
+            overlap_size: 10,
+            ..Chunking::default()
+        };
+        let text = "Backups are taken every night. ".repeat(10);
+        let chunks = chunking.chunks(&text).unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 50));
+        assert_eq!(chunks.join(" "), text.trim());
+    }
+}
@@ -0,0 +1,269 @@
+use crate::data::{Collection, ScoredDocument};
+use crate::ollama::Llm;
+use crate::qdrant::{
+    collection_limit, join_parents, rescore, search_collection, SearchFilter, VectorSearch,
+};
+use crate::query::pack_context;
+use crate::rerank::Reranker;
+use crate::retrieval_hook::{RequestMetadata, RetrievalHooks};
+use anyhow::Result;
+use qdrant_client::prelude::QdrantClient;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+// PipelineStage represents the stage of the query pipeline a candidate was dropped at
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum PipelineStage {
+    // CollectionLimit is the share of the limit of the collection, the candidate isn't retrieved
+    CollectionLimit,
+    // ParentJoin keeps the best candidate of a parent window or of the fragment of a question
+    ParentJoin,
+    // Rerank keeps the limit best reranked candidates
+    Rerank,
+    // Hooks are the retrieval hooks of the deployment
+    Hooks,
+    // ContextBudget packs the candidates into the maximum context tokens
+    ContextBudget,
+}
+
+// ScoreBreakdown represents how a retrieved candidate was scored and ranked
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ScoreBreakdown {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub collection: Collection,
+    // parent_id is the parent window or fragment the candidate is joined to in the context
+    pub parent_id: Option<String>,
+    // score is the raw cosine similarity returned by qdrant
+    pub score: f32,
+    // collection_weight is the share of the limit assigned to the collection
+    pub collection_weight: f32,
+    // collection_limit is the number of candidates selected from the collection
+    pub collection_limit: u64,
+    // lead_boost is the boost of lead fragments, None for other fragments
+    pub lead_boost: Option<f32>,
+    // thread_penalty is the penalty of unanswered threads, None for other fragments
+    pub thread_penalty: Option<f32>,
+    // vote_boost is the boost of upvoted questions, None for other fragments
+    pub vote_boost: Option<f32>,
+    // deprecation_penalty is the penalty of deprecated articles, None for other fragments
+    pub deprecation_penalty: Option<f32>,
+    // rescored_score is the score with the boosts and penalties applied, None if the candidate
+    // was cut by the collection limit
+    pub rescored_score: Option<f32>,
+    // rerank_score is the relevance from 0 to 10 assigned by the LLM, None if reranking is
+    // disabled or the candidate wasn't re-scored
+    pub rerank_score: Option<f32>,
+    // hook_factor is the factor the retrieval hooks multiplied the score by, None if no hook is
+    // configured or the candidate didn't reach the hooks
+    pub hook_factor: Option<f32>,
+    // dropped_at is the stage the candidate was dropped at, None if it is in the context
+    pub dropped_at: Option<PipelineStage>,
+    // final_score is the score in the context, or at the stage the candidate was dropped at
+    pub final_score: f32,
+    // rank is the position in the context, None if the candidate was not selected
+    pub rank: Option<usize>,
+}
+
+// ExplainStages represents the stages of the query pipeline run after the search, set like the
+// ones of the explained query
+pub struct ExplainStages<'a> {
+    // query is the query the reranker and the hooks see, before its expansion
+    pub query: &'a str,
+    // reranker re-scores the candidates with the llm and model if reranking is enabled
+    pub reranker: Option<&'a Reranker>,
+    pub llm: &'a Llm,
+    pub model: &'a str,
+    pub hooks: &'a RetrievalHooks,
+    pub metadata: &'a RequestMetadata,
+    pub max_context_tokens: Option<usize>,
+}
+
+// explain_search runs the retrieval of the query path and returns the score breakdown of the
+// candidates of each collection ordered by their rank in the context, up to the search limit
+// candidates are fetched per collection to also show the ones cut by the collection limit
+pub async fn explain_search(
+    client: &QdrantClient,
+    base_collection: &str,
+    filter_by_collections: Vec<Collection>,
+    embeddings: Vec<f32>,
+    limit: u64,
+    filter: &SearchFilter,
+    stages: &ExplainStages<'_>,
+) -> Result<Vec<ScoreBreakdown>> {
+    // the reranker chooses from more candidates than the limit
+    let search_limit = match stages.reranker {
+        Some(reranker) => reranker.candidates(limit),
+        None => limit,
+    };
+    let total_collections = filter_by_collections.len();
+    let mut breakdowns = Vec::new();
+    let mut selected = Vec::new();
+    for collection in filter_by_collections {
+        let collection_limit = collection_limit(search_limit, total_collections, collection);
+        let collection_weight = if total_collections > 1 {
+            collection.limit_by_collection()
+        } else {
+            1.0
+        };
+        let candidates = search_collection(
+            client,
+            base_collection,
+            collection,
+            embeddings.clone(),
+            search_limit.max(collection_limit),
+            filter,
+            VectorSearch::default(),
+        )
+        .await?;
+        let mut collection_selected = Vec::new();
+        for (i, candidate) in candidates.into_iter().enumerate() {
+            let mut breakdown = breakdown(&candidate, collection_weight, collection_limit);
+            match (i as u64) < collection_limit {
+                true => collection_selected.push(candidate),
+                false => breakdown.dropped_at = Some(PipelineStage::CollectionLimit),
+            }
+            breakdowns.push(breakdown);
+        }
+        rescore(&mut collection_selected);
+        selected.extend(collection_selected);
+    }
+
+    // the selected candidates are found by collection and id until they are joined to their
+    // parents, then by the id of the parent
+    let positions: HashMap<(Collection, String), usize> = breakdowns
+        .iter()
+        .enumerate()
+        .map(|(i, b)| ((b.collection, b.id.clone()), i))
+        .collect();
+    for doc in &selected {
+        let b = &mut breakdowns[positions[&(doc.metadata.collection, doc.metadata.id.clone())]];
+        b.rescored_score = Some(doc.score);
+        b.final_score = doc.score;
+    }
+    let docs = join_parents(selected.clone());
+    let mut context_positions: HashMap<String, usize> = HashMap::new();
+    for doc in &selected {
+        let position = positions[&(doc.metadata.collection, doc.metadata.id.clone())];
+        let context_id = doc
+            .metadata
+            .parent_id
+            .clone()
+            .unwrap_or(doc.metadata.id.clone());
+        // join_parents keeps the first candidate with the best score of a parent
+        let kept = !context_positions.contains_key(&context_id)
+            && docs.iter().any(|joined| {
+                joined.metadata.id == context_id
+                    && joined.metadata.collection == doc.metadata.collection
+                    && joined.score == doc.score
+            });
+        match kept {
+            true => {
+                context_positions.insert(context_id, position);
+            }
+            false => breakdowns[position].dropped_at = Some(PipelineStage::ParentJoin),
+        }
+    }
+
+    let docs = match stages.reranker {
+        Some(reranker) => {
+            let mut reranked = reranker
+                .relevances(stages.llm, stages.model, stages.query, docs)
+                .await;
+            for (doc, relevance) in &reranked {
+                breakdowns[context_positions[&doc.metadata.id]].rerank_score =
+                    relevance.map(f32::from);
+            }
+            for (doc, _) in reranked.split_off((limit as usize).min(reranked.len())) {
+                breakdowns[context_positions[&doc.metadata.id]].dropped_at =
+                    Some(PipelineStage::Rerank);
+            }
+            reranked.into_iter().map(|(doc, _)| doc).collect()
+        }
+        None => docs,
+    };
+
+    let docs = match stages.hooks.is_empty() {
+        true => docs,
+        false => {
+            let retrieved: Vec<ScoredDocument> = docs.clone();
+            let kept = stages
+                .hooks
+                .apply(stages.query, docs, stages.metadata)
+                .await?;
+            for doc in &retrieved {
+                let b = &mut breakdowns[context_positions[&doc.metadata.id]];
+                match kept.iter().find(|k| k.metadata.id == doc.metadata.id) {
+                    Some(k) => {
+                        b.hook_factor = Some(k.score / doc.score);
+                        b.final_score = k.score;
+                    }
+                    None => b.dropped_at = Some(PipelineStage::Hooks),
+                }
+            }
+            kept
+        }
+    };
+
+    let docs = match stages.max_context_tokens {
+        Some(max_context_tokens) => {
+            let packed = pack_context(docs.clone(), max_context_tokens)?.docs;
+            for doc in &docs {
+                if !packed.iter().any(|p| p.metadata.id == doc.metadata.id) {
+                    breakdowns[context_positions[&doc.metadata.id]].dropped_at =
+                        Some(PipelineStage::ContextBudget);
+                }
+            }
+            packed
+        }
+        None => docs,
+    };
+
+    for (rank, doc) in docs.iter().enumerate() {
+        let b = &mut breakdowns[context_positions[&doc.metadata.id]];
+        b.rank = Some(rank + 1);
+        b.final_score = doc.score;
+    }
+    // the context comes first in its order, then the dropped candidates by score
+    breakdowns.sort_by(|a, b| match (a.rank, b.rank) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => b.final_score.total_cmp(&a.final_score),
+    });
+    Ok(breakdowns)
+}
+
+// breakdown returns the breakdown of a retrieved candidate before the stages of the pipeline
+fn breakdown(
+    candidate: &ScoredDocument,
+    collection_weight: f32,
+    collection_limit: u64,
+) -> ScoreBreakdown {
+    let metadata = &candidate.metadata;
+    ScoreBreakdown {
+        id: metadata.id.clone(),
+        title: metadata.title.clone(),
+        url: metadata.url.clone(),
+        collection: metadata.collection,
+        parent_id: metadata.parent_id.clone(),
+        score: candidate.score,
+        collection_weight: collection_weight,
+        collection_limit: collection_limit,
+        lead_boost: metadata.lead_boost(),
+        thread_penalty: metadata.thread_penalty(),
+        vote_boost: metadata.vote_boost(),
+        deprecation_penalty: metadata.deprecation_penalty(),
+        rescored_score: None,
+        rerank_score: None,
+        hook_factor: None,
+        dropped_at: None,
+        final_score: candidate.score,
+        rank: None,
+    }
+}
@@ -0,0 +1,172 @@
+use crate::data::{fragment_id, Collection, Document};
+use anyhow::{Error, Result};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+// DEFAULT_DEDUP_THRESHOLD is the default estimated jaccard similarity a fragment is dropped at
+pub static DEFAULT_DEDUP_THRESHOLD: f32 = 0.9;
+// SHINGLE_SIZE is the number of words of the shingles compared between fragments
+static SHINGLE_SIZE: usize = 5;
+// BANDS and ROWS split the minhash signature into bands, fragments sharing a band are compared,
+// BANDS * ROWS is the length of the signature
+static BANDS: usize = 16;
+static ROWS: usize = 4;
+
+// DedupReport represents the outcome of the dedup stage of an upload
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct DedupReport {
+    // fragments is the number of basic fragments compared
+    pub fragments: usize,
+    // dropped is the number of fragments dropped as near duplicates of an earlier fragment
+    pub dropped: usize,
+    // threshold is the similarity fragments were dropped at
+    pub threshold: f32,
+}
+
+// find_duplicates returns the ids of the basic fragments of each document, keyed by url, that
+// are near duplicates of a fragment of an earlier document or of an earlier fragment of the same
+// document, e.g. shared footers or cookie banners, the first occurrence is kept
+pub fn find_duplicates(
+    docs: &[Document],
+    threshold: f32,
+) -> Result<(HashMap<String, HashSet<String>>, DedupReport), Error> {
+    let mut report = DedupReport {
+        threshold: threshold,
+        ..Default::default()
+    };
+    let mut duplicates: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut signatures: Vec<Vec<u64>> = Vec::new();
+    let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+    for doc in docs {
+        for fragment in doc.to_fragments()? {
+            if fragment.collection != Collection::Basic {
+                continue;
+            }
+            report.fragments += 1;
+            // the title and url of the fragment differ between pages, only the content is
+            // compared
+            let content = match fragment.text.split_once(" Content: ") {
+                Some((_, content)) => content,
+                None => fragment.text.as_str(),
+            };
+            let signature = minhash(content);
+            let bands: Vec<(usize, u64)> = signature
+                .chunks(ROWS)
+                .enumerate()
+                .map(|(band, rows)| (band, hash_of(rows)))
+                .collect();
+            let duplicate = bands
+                .iter()
+                .filter_map(|band| buckets.get(band))
+                .flatten()
+                .any(|&kept| similarity(&signatures[kept], &signature) >= threshold);
+            if duplicate {
+                debug!("Dropping near duplicate fragment of {}", doc.url);
+                duplicates
+                    .entry(doc.url.clone())
+                    .or_default()
+                    .insert(fragment_id(&doc.url, &fragment.text));
+                report.dropped += 1;
+                continue;
+            }
+            for band in bands {
+                buckets.entry(band).or_default().push(signatures.len());
+            }
+            signatures.push(signature);
+        }
+    }
+    info!(
+        "Dropped {} of {} fragments as near duplicates",
+        report.dropped, report.fragments
+    );
+    Ok((duplicates, report))
+}
+
+// minhash returns the minhash signature of the word shingles of a text, texts shorter than a
+// shingle are a single shingle
+fn minhash(text: &str) -> Vec<u64> {
+    let words: Vec<String> = text
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect();
+    let shingles: Vec<&[String]> = match words.len() > SHINGLE_SIZE {
+        true => words.windows(SHINGLE_SIZE).collect(),
+        false => vec![words.as_slice()],
+    };
+    (0..BANDS * ROWS)
+        .map(|seed| {
+            shingles
+                .iter()
+                .map(|shingle| hash_of(&(seed, shingle)))
+                .min()
+                .unwrap_or(u64::MAX)
+        })
+        .collect()
+}
+
+// similarity returns the estimated jaccard similarity of two signatures
+fn similarity(a: &[u64], b: &[u64]) -> f32 {
+    let equal = a.iter().zip(b).filter(|(a, b)| a == b).count();
+    equal as f32 / a.len() as f32
+}
+
+// hash_of returns the hash of a value
+fn hash_of<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static FOOTER: &str = "Accept all cookies to keep browsing, we use cookies to improve your \
+        experience and to measure the traffic of our pages.";
+    static BACKUPS: &str = "Backups of the environments are taken every night and kept for \
+        seven days unless the retention of the project is changed.";
+
+    fn doc(url: &str, text: &str) -> Document {
+        Document::new(
+            Collection::Basic,
+            url.to_string(),
+            format!("Page {}", url),
+            text.to_string(),
+        )
+    }
+
+    #[test]
+    fn signatures_ignore_case_and_whitespace() {
+        let shouted = FOOTER.to_uppercase().replace(' ', " \n ");
+        assert_eq!(similarity(&minhash(FOOTER), &minhash(&shouted)), 1.0);
+        assert!(similarity(&minhash(FOOTER), &minhash(BACKUPS)) < 0.5);
+    }
+
+    #[test]
+    fn short_texts_are_a_single_shingle() {
+        assert_eq!(minhash("Next page"), minhash("next  PAGE"));
+        assert_ne!(minhash("Next page"), minhash("Previous page"));
+        assert_eq!(minhash("Next page").len(), BANDS * ROWS);
+    }
+
+    #[test]
+    fn repeated_fragments_of_later_documents_are_dropped() {
+        let docs = vec![
+            doc("https://example.com/a", FOOTER),
+            doc("https://example.com/b", BACKUPS),
+            doc("https://example.com/c", FOOTER),
+        ];
+        let (duplicates, report) = find_duplicates(&docs, DEFAULT_DEDUP_THRESHOLD).unwrap();
+        assert_eq!(report.fragments, 3);
+        assert_eq!(report.dropped, 1);
+        // the first occurrence is kept, the title and url of the fragments differ
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates["https://example.com/c"].len(), 1);
+    }
+}
@@ -0,0 +1,458 @@
+use crate::data::{fragment_id, Document, EmbeddedDocument, EmbeddedMetadata, Fragment};
+use crate::embedding_cache::{embedding_key, EmbeddingCache};
+use crate::embedding_provider::SharedEmbeddingProvider;
+use crate::progress_store::ProgressStore;
+use crate::progress_tracker::{JobStatus, ProgressTracker, SourceProgress, Stage, StageProgress};
+use crate::telemetry::{record_duration, record_embedded_fragments, record_reused_embeddings};
+use anyhow::{Error, Result};
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{
+    sync::mpsc,
+    thread::{self, JoinHandle},
+};
+use tokio::{sync::oneshot, task};
+use tracing::{info_span, Span};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// DEFAULT_BATCH_SIZE is the default number of fragments embedded per encode call
+pub static DEFAULT_BATCH_SIZE: usize = 32;
+
+// Message represents a message, the document with the ids of the fragments to skip and the span
+// of the caller, the embedding of the document is traced within it
+type Message = (
+    Document,
+    HashSet<String>,
+    oneshot::Sender<Vec<EmbeddedDocument>>,
+    Span,
+);
+
+// EmbeddingProgress represents the progress of an embedding task
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct EmbeddingProgress {
+    total_documents: usize,
+    processed_documents: usize,
+    // generated_tokens is the number of tokens generated by the LLM, e.g. for summaries
+    generated_tokens: usize,
+    // stages are the per stage counts and timings of the upload pipeline
+    stages: StageProgress,
+    // current_stage is the stage the job is working on, e.g. Fetched while crawling
+    current_stage: Option<Stage>,
+    // sources are the locations of an upload of several sites
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sources: Vec<SourceProgress>,
+    status: JobStatus,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl ProgressTracker for EmbeddingProgress {
+    fn new(total_documents: usize) -> Self {
+        EmbeddingProgress {
+            total_documents: total_documents,
+            processed_documents: 0,
+            generated_tokens: 0,
+            stages: StageProgress::default(),
+            current_stage: None,
+            sources: Vec::new(),
+            status: JobStatus::Running,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn set_total_items(&mut self, total_documents: usize) {
+        self.total_documents = total_documents;
+        self.updated_at = Utc::now();
+    }
+
+    // increment_total increments the total documents
+    fn increment_processed(&mut self) {
+        self.processed_documents += 1;
+        self.updated_at = Utc::now();
+        if self.processed_documents >= self.total_documents {
+            self.status = JobStatus::Completed;
+            self.current_stage = None;
+        }
+    }
+
+    // progress_status returns the current progress status
+    fn progress_status(&self) -> (usize, usize) {
+        (self.processed_documents, self.total_documents)
+    }
+
+    fn status(&self) -> JobStatus {
+        self.status
+    }
+
+    fn set_status(&mut self, status: JobStatus) {
+        self.status = status;
+        if status.is_finished() {
+            self.current_stage = None;
+        }
+        self.updated_at = Utc::now();
+    }
+
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    fn add_generated_tokens(&mut self, tokens: usize) {
+        self.generated_tokens += tokens;
+        self.updated_at = Utc::now();
+    }
+
+    fn generated_tokens(&self) -> usize {
+        self.generated_tokens
+    }
+
+    fn record_stage(&mut self, stage: Stage, count: usize, duration: Duration) {
+        self.stages.record(stage, count, duration);
+        self.updated_at = Utc::now();
+    }
+
+    fn stages(&self) -> StageProgress {
+        self.stages
+    }
+
+    fn set_current_stage(&mut self, stage: Stage) {
+        self.current_stage = Some(stage);
+        self.updated_at = Utc::now();
+    }
+
+    fn current_stage(&self) -> Option<Stage> {
+        self.current_stage
+    }
+
+    fn set_sources(&mut self, urls: &[String]) {
+        self.sources = urls
+            .iter()
+            .map(|url| SourceProgress {
+                url: url.clone(),
+                ..Default::default()
+            })
+            .collect();
+        self.updated_at = Utc::now();
+    }
+
+    fn record_source_stage(&mut self, url: &str, stage: Stage, count: usize, duration: Duration) {
+        if let Some(source) = self.sources.iter_mut().find(|source| source.url == url) {
+            source.stages.record(stage, count, duration);
+            self.updated_at = Utc::now();
+        }
+    }
+
+    fn finish_source(&mut self, url: &str, documents: usize, error: Option<String>) {
+        if let Some(source) = self.sources.iter_mut().find(|source| source.url == url) {
+            source.documents = Some(documents);
+            source.error = error;
+            self.updated_at = Utc::now();
+        }
+    }
+
+    fn sources(&self) -> Vec<SourceProgress> {
+        self.sources.clone()
+    }
+}
+
+// Model represents a model
+// based on https://github.com/guillaume-be/rust-bert/blob/main/examples/async-sentiment.rs
+pub struct Model {
+    sender: mpsc::SyncSender<Message>,
+}
+
+impl Model {
+    // spawn returns a new model and a handle to the model
+    pub fn spawn(
+        provider: SharedEmbeddingProvider,
+        progress_state: Arc<ProgressStore<EmbeddingProgress>>,
+        id: Uuid,
+    ) -> (JoinHandle<anyhow::Result<()>>, Model) {
+        Self::spawn_with_batch_size(
+            provider,
+            progress_state,
+            id,
+            DEFAULT_BATCH_SIZE,
+            Arc::new(EmbeddingCache::default()),
+        )
+    }
+
+    // spawn_with_batch_size returns a new model embedding up to batch_size fragments per
+    // encode call and a handle to the model, the embeddings of identical fragments are reused
+    // from the cache
+    pub fn spawn_with_batch_size(
+        provider: SharedEmbeddingProvider,
+        progress_state: Arc<ProgressStore<EmbeddingProgress>>,
+        id: Uuid,
+        batch_size: usize,
+        cache: Arc<EmbeddingCache>,
+    ) -> (JoinHandle<anyhow::Result<()>>, Model) {
+        let (sender, receiver) = mpsc::sync_channel(100);
+        let batch_size = batch_size.max(1);
+        let handle = thread::spawn(move || {
+            Self::runner(receiver, provider, progress_state, id, batch_size, cache)
+        });
+        (handle, Model { sender })
+    }
+
+    // runner runs the model
+    fn runner(
+        receiver: mpsc::Receiver<Message>,
+        provider: SharedEmbeddingProvider,
+        progress_state: Arc<ProgressStore<EmbeddingProgress>>,
+        id: Uuid,
+        batch_size: usize,
+        cache: Arc<EmbeddingCache>,
+    ) -> anyhow::Result<(), Error> {
+        info!("Embedding with {}", provider.name());
+        let mut reused_total = 0;
+        while let Ok((document, skip, sender, span)) = receiver.recv() {
+            let _entered = span.enter();
+            let _span = info_span!("embed", url = %document.url).entered();
+            let doc_start = Instant::now();
+            let embedded_documents =
+                match Self::embed_document(&provider, &cache, &document, &skip, batch_size) {
+                    Ok((embedded_documents, reused)) => {
+                        reused_total += reused;
+                        embedded_documents
+                    }
+                    Err(e) => {
+                        // dropping the sender fails the encode call of the document, the runner
+                        // keeps serving the next documents, e.g. after a provider timeout
+                        info!("Error embedding {}: {}", document.url, e);
+                        continue;
+                    }
+                };
+            info!(
+                "Embedded {} fragments of {}, batch size: {}",
+                embedded_documents.len(),
+                document.url,
+                batch_size
+            );
+            record_duration("embed", doc_start.elapsed());
+
+            sender.send(embedded_documents).expect("sending results");
+            let updated = progress_state.blocking_update(&id, |s| {
+                s.record_stage(Stage::Embedded, 1, doc_start.elapsed());
+                s.increment_processed();
+            });
+            if !updated {
+                return Err(anyhow::anyhow!("Failed to get state"));
+            }
+        }
+        if reused_total > 0 {
+            info!("Reused {} embeddings of identical fragments", reused_total);
+        }
+
+        Ok(())
+    }
+
+    // embed_document embeds the fragments of a document in batches of batch_size, the fragments
+    // whose id is in skip are not embedded, identical fragments are embedded once and the
+    // embeddings of the cache are reused, each fragment keeps its own payload, returns the
+    // embedded fragments and the number of reused embeddings
+    fn embed_document(
+        provider: &SharedEmbeddingProvider,
+        cache: &EmbeddingCache,
+        document: &Document,
+        skip: &HashSet<String>,
+        batch_size: usize,
+    ) -> Result<(Vec<EmbeddedDocument>, usize), Error> {
+        let provider_name = provider.name();
+        let fragments: Vec<(String, Fragment)> = document
+            .to_fragments()?
+            .into_iter()
+            .filter(|fragment| !skip.contains(&fragment_id(&document.url, &fragment.text)))
+            .map(|fragment| (embedding_key(&provider_name, &fragment.text), fragment))
+            .collect();
+        // the title is embedded once for the title vectors of the fragments
+        let title = match fragments.is_empty() || document.title.is_empty() {
+            true => None,
+            false => Some((
+                embedding_key(&provider_name, &document.title),
+                document.title.clone(),
+            )),
+        };
+
+        // the vectors of the document are kept apart from the cache, which may evict them
+        let mut vectors: HashMap<String, Vec<f32>> = HashMap::new();
+        let mut missing: Vec<(String, String)> = Vec::new();
+        let mut missing_keys: HashSet<String> = HashSet::new();
+        let texts = fragments
+            .iter()
+            .map(|(key, fragment)| (key, &fragment.text))
+            .chain(title.iter().map(|(key, title)| (key, title)));
+        for (key, text) in texts {
+            if vectors.contains_key(key) || missing_keys.contains(key) {
+                continue;
+            }
+            match cache.get(key) {
+                Some(vector) => {
+                    vectors.insert(key.clone(), vector);
+                }
+                None => {
+                    missing_keys.insert(key.clone());
+                    missing.push((key.clone(), text.clone()));
+                }
+            }
+        }
+        for batch in missing.chunks(batch_size) {
+            let texts: Vec<String> = batch.iter().map(|(_, text)| text.clone()).collect();
+            let _span = info_span!("embed_batch", texts = texts.len()).entered();
+            let batch_start = Instant::now();
+            let text_embeddings = provider.embed(&texts)?;
+            record_duration("embed_batch", batch_start.elapsed());
+            record_embedded_fragments(&provider_name, texts.len());
+            // the embeddings are returned in the order of the texts
+            for ((key, _), text_embedding) in batch.iter().zip(text_embeddings) {
+                cache.insert(key.clone(), text_embedding.clone());
+                vectors.insert(key.clone(), text_embedding);
+            }
+        }
+        let reused = fragments.len() + title.iter().len() - missing.len();
+        record_reused_embeddings(reused);
+        let title_embeddings = title.and_then(|(key, _)| vectors.get(&key).cloned());
+
+        let mut embedded_documents = Vec::new();
+        for (key, fragment) in fragments {
+            let text_embedding = vectors
+                .get(&key)
+                .cloned()
+                .ok_or(anyhow::anyhow!("No embedding returned"))?;
+            embedded_documents.push(EmbeddedDocument {
+                text_embeddings: text_embedding,
+                title_embeddings: title_embeddings.clone(),
+                metadata: EmbeddedMetadata::from_fragment(document, fragment)?,
+            });
+        }
+        Ok((embedded_documents, reused))
+    }
+
+    // encode returns a vector of embedded documents
+    pub async fn encode(&self, document: Document) -> Result<Vec<EmbeddedDocument>, Error> {
+        self.encode_skipping(document, HashSet::new()).await
+    }
+
+    // encode_skipping returns a vector of embedded documents without the fragments whose id is
+    // in skip, e.g. fragments already stored by a previous upload
+    #[tracing::instrument(skip_all, fields(url = %document.url, skip = skip.len()))]
+    pub async fn encode_skipping(
+        &self,
+        document: Document,
+        skip: HashSet<String>,
+    ) -> Result<Vec<EmbeddedDocument>, Error> {
+        let (sender, receiver) = oneshot::channel();
+        task::block_in_place(|| self.sender.send((document, skip, sender, Span::current())))?;
+        Ok(receiver.await?)
+    }
+}
+
+// text_embedding_async returns the embedding of a query, embedded in the query lane of the
+// provider
+pub async fn text_embedding_async(
+    provider: &SharedEmbeddingProvider,
+    text: String,
+) -> Result<Vec<f32>, Error> {
+    let mut embeddings = query_embeddings_async(provider, vec![text]).await?;
+    embeddings
+        .pop()
+        .ok_or(anyhow::anyhow!("No embedding returned"))
+}
+
+// text_embeddings_async returns the embeddings of the texts in the order of the texts
+#[tracing::instrument(skip_all, fields(texts = texts.len()))]
+pub async fn text_embeddings_async(
+    provider: &SharedEmbeddingProvider,
+    texts: Vec<String>,
+) -> Result<Vec<Vec<f32>>, Error> {
+    embeddings_async(provider, texts, false).await
+}
+
+// query_embeddings_async returns the embeddings of the texts of a query in the order of the
+// texts, e.g. the highlighted sentences, they don't wait for the ingest batches if the provider
+// has a query lane
+#[tracing::instrument(skip_all, fields(texts = texts.len()))]
+pub async fn query_embeddings_async(
+    provider: &SharedEmbeddingProvider,
+    texts: Vec<String>,
+) -> Result<Vec<Vec<f32>>, Error> {
+    embeddings_async(provider, texts, true).await
+}
+
+// embeddings_async embeds the texts in a blocking thread, in the query lane if query is set
+async fn embeddings_async(
+    provider: &SharedEmbeddingProvider,
+    texts: Vec<String>,
+    query: bool,
+) -> Result<Vec<Vec<f32>>, Error> {
+    let provider = provider.clone();
+    let embedding_start = Instant::now();
+    let span = Span::current();
+    let embeddings = task::spawn_blocking(move || {
+        span.in_scope(|| match query {
+            true => provider.embed_query(&texts),
+            false => provider.embed(&texts),
+        })
+    })
+    .await??;
+    info!("{} embeddings generated", embeddings.len());
+    record_duration("embed_query", embedding_start.elapsed());
+    Ok(embeddings)
+}
+
+// document_embeddings_async embeds the fragments of a single document with the shared provider
+// in a blocking thread, e.g. a page ingested synchronously, without spawning a model runner
+#[tracing::instrument(skip_all, fields(url = %document.url))]
+pub async fn document_embeddings_async(
+    provider: &SharedEmbeddingProvider,
+    cache: &Arc<EmbeddingCache>,
+    document: Document,
+    batch_size: usize,
+) -> Result<Vec<EmbeddedDocument>, Error> {
+    let provider = provider.clone();
+    let cache = cache.clone();
+    let span = Span::current();
+    let (embedded_documents, _) = task::spawn_blocking(move || {
+        span.in_scope(|| {
+            Model::embed_document(
+                &provider,
+                &cache,
+                &document,
+                &HashSet::new(),
+                batch_size.max(1),
+            )
+        })
+    })
+    .await??;
+    Ok(embedded_documents)
+}
+
+// warm_up_embeddings embeds a short text so the provider loads its model, e.g. rust-bert, or the
+// ollama server loads the embedding model before the first query waits for it
+pub async fn warm_up_embeddings(provider: &SharedEmbeddingProvider) -> Result<(), Error> {
+    let start = Instant::now();
+    text_embedding_async(provider, "warm up".to_string()).await?;
+    info!(
+        "Warmed up embedding provider {} in {:?}",
+        provider.name(),
+        start.elapsed()
+    );
+    Ok(())
+}
+
+// embedding_size returns the embedding size of the provider, used to create the collections
+pub async fn embedding_size(provider: &SharedEmbeddingProvider) -> Result<u64, Error> {
+    let provider = provider.clone();
+    task::spawn_blocking(move || provider.size()).await?
+}
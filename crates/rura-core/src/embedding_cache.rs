@@ -0,0 +1,82 @@
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+// DEFAULT_EMBEDDING_CACHE_SIZE is the default number of embeddings kept by the cache, about 15MB
+// with the 384 dimensions of rust-bert
+pub static DEFAULT_EMBEDDING_CACHE_SIZE: usize = 10000;
+
+// EmbeddingCache keeps the embeddings of fragment texts by hash so identical fragments, e.g.
+// warnings or legal blurbs shared by many pages, are embedded once, the oldest embeddings are
+// evicted once the cache is full
+#[derive(Debug)]
+pub struct EmbeddingCache {
+    capacity: usize,
+    entries: Mutex<CacheEntries>,
+}
+
+#[derive(Debug, Default)]
+struct CacheEntries {
+    vectors: HashMap<String, Vec<f32>>,
+    order: VecDeque<String>,
+}
+
+impl Default for EmbeddingCache {
+    fn default() -> Self {
+        EmbeddingCache::new(DEFAULT_EMBEDDING_CACHE_SIZE)
+    }
+}
+
+impl EmbeddingCache {
+    // new returns a cache keeping up to capacity embeddings, 0 disables the cache
+    pub fn new(capacity: usize) -> Self {
+        EmbeddingCache {
+            capacity: capacity,
+            entries: Mutex::new(CacheEntries::default()),
+        }
+    }
+
+    // get returns the cached embedding of the key
+    pub fn get(&self, key: &str) -> Option<Vec<f32>> {
+        self.entries.lock().unwrap().vectors.get(key).cloned()
+    }
+
+    // insert caches the embedding of the key, evicting the oldest embeddings if the cache is full
+    pub fn insert(&self, key: String, vector: Vec<f32>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.vectors.contains_key(&key) {
+            return;
+        }
+        while entries.order.len() >= self.capacity {
+            match entries.order.pop_front() {
+                Some(oldest) => entries.vectors.remove(&oldest),
+                None => break,
+            };
+        }
+        entries.order.push_back(key.clone());
+        entries.vectors.insert(key, vector);
+    }
+
+    // len returns the number of cached embeddings
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().vectors.len()
+    }
+
+    // is_empty returns true if no embedding is cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// embedding_key returns the cache key of a text, the provider is part of the key as the
+// embeddings of different models can't be mixed
+pub fn embedding_key(provider: &str, text: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(provider);
+    hasher.update([0]);
+    hasher.update(text);
+    format!("{:x}", hasher.finalize())
+}
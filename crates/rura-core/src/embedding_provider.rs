@@ -0,0 +1,391 @@
+use crate::config::EmbeddingSettings;
+use anyhow::{anyhow, Error, Result};
+use log::info;
+#[cfg(feature = "rust-bert")]
+use rust_bert::pipelines::sentence_embeddings::{
+    SentenceEmbeddingsBuilder, SentenceEmbeddingsModel, SentenceEmbeddingsModelType,
+};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+#[cfg(feature = "rust-bert")]
+use std::sync::Mutex;
+use std::sync::{Arc, OnceLock};
+#[cfg(feature = "rust-bert")]
+use tch::Device;
+use tokio::runtime::Handle;
+#[cfg(feature = "rust-bert")]
+use tracing::info_span;
+
+// RUST_BERT_EMBEDDING_SIZE is the size of the AllMiniLmL12V2 embeddings
+pub static RUST_BERT_EMBEDDING_SIZE: u64 = 384;
+// DEFAULT_OLLAMA_EMBEDDING_URL is the default url of the Ollama server
+static DEFAULT_OLLAMA_EMBEDDING_URL: &str = "http://localhost:11434";
+// DEFAULT_OLLAMA_EMBEDDING_MODEL is the default embedding model of the Ollama provider
+static DEFAULT_OLLAMA_EMBEDDING_MODEL: &str = "nomic-embed-text";
+// DEFAULT_OPENAI_EMBEDDING_URL is the default base url of OpenAI compatible endpoints
+static DEFAULT_OPENAI_EMBEDDING_URL: &str = "https://api.openai.com/v1";
+// DEFAULT_OPENAI_EMBEDDING_MODEL is the default embedding model of the OpenAI provider
+static DEFAULT_OPENAI_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+// SIZE_PROBE is embedded to detect the embedding size of the HTTP providers
+static SIZE_PROBE: &str = "size probe";
+
+// EmbeddingProvider embeds texts, the calls block and must be made from blocking threads, e.g.
+// the embedding runner or tokio::task::spawn_blocking
+pub trait EmbeddingProvider: Send + Sync {
+    // name returns the provider and model, e.g. for logs
+    fn name(&self) -> String;
+
+    // embed returns the embeddings of the texts in the order of the texts
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Error>;
+
+    // embed_query returns the embeddings of the texts of a query, providers with a query lane
+    // don't wait for the ingest batches, the others embed them like any text
+    fn embed_query(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Error> {
+        self.embed(texts)
+    }
+
+    // size returns the size of the embeddings, used to create the collections
+    fn size(&self) -> Result<u64, Error>;
+}
+
+// SharedEmbeddingProvider is an embedding provider shared by the server, the client and the
+// embedding runners
+pub type SharedEmbeddingProvider = Arc<dyn EmbeddingProvider>;
+
+// EmbeddingProviderKind represents the available embedding providers
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmbeddingProviderKind {
+    // RustBert embeds locally with rust-bert AllMiniLmL12V2, requires the rust-bert feature
+    #[default]
+    RustBert,
+    // Ollama embeds with the /api/embeddings endpoint of an Ollama server
+    Ollama,
+    // OpenAi embeds with the /embeddings endpoint of an OpenAI compatible server
+    OpenAi,
+}
+
+// string to embedding provider kind, unknown providers are an error so a typo doesn't write
+// vectors of another model into the collections
+impl FromStr for EmbeddingProviderKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "rust_bert" => Ok(EmbeddingProviderKind::RustBert),
+            "ollama" => Ok(EmbeddingProviderKind::Ollama),
+            "openai" => Ok(EmbeddingProviderKind::OpenAi),
+            _ => Err(anyhow!(
+                "unknown embedding provider: {}, valid values are: rust_bert, ollama, openai",
+                s
+            )),
+        }
+    }
+}
+
+// EmbeddingConfig represents the configuration of the embedding provider
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddingConfig {
+    pub provider: EmbeddingProviderKind,
+    // model is the embedding model of the HTTP providers, a default is used if None
+    pub model: Option<String>,
+    // url is the base url of the HTTP providers, a default is used if None
+    pub url: Option<String>,
+    // api_key is sent as bearer token to OpenAI compatible endpoints
+    pub api_key: Option<String>,
+    // query_lane loads a second rust-bert model for the queries, so they don't wait for the
+    // ingest batches at the cost of the memory of the model
+    pub query_lane: bool,
+}
+
+impl EmbeddingConfig {
+    // with_settings returns the config with the provider, model and url of the settings of a
+    // base collection, the api key and query lane are kept, fails on an unknown provider
+    pub fn with_settings(&self, settings: &EmbeddingSettings) -> Result<Self, Error> {
+        let provider = match settings.provider.as_deref() {
+            Some(provider) => provider.parse()?,
+            None => self.provider,
+        };
+        Ok(EmbeddingConfig {
+            provider: provider,
+            model: settings.model.clone().or(self.model.clone()),
+            url: settings.url.clone().or(self.url.clone()),
+            api_key: self.api_key.clone(),
+            query_lane: self.query_lane,
+        })
+    }
+
+    // build returns the configured provider, HTTP providers must be built within the tokio
+    // runtime they send their requests with
+    pub fn build(&self) -> Result<SharedEmbeddingProvider, Error> {
+        let provider: SharedEmbeddingProvider = match self.provider {
+            #[cfg(feature = "rust-bert")]
+            EmbeddingProviderKind::RustBert => {
+                Arc::new(RustBertProvider::default().with_query_lane(self.query_lane))
+            }
+            #[cfg(not(feature = "rust-bert"))]
+            EmbeddingProviderKind::RustBert => {
+                return Err(anyhow!(
+                    "rust_bert embedding provider is not available, build with the rust-bert feature or use ollama or openai"
+                ))
+            }
+            EmbeddingProviderKind::Ollama => Arc::new(OllamaProvider::new(
+                self.url.as_deref().unwrap_or(DEFAULT_OLLAMA_EMBEDDING_URL),
+                self.model
+                    .as_deref()
+                    .unwrap_or(DEFAULT_OLLAMA_EMBEDDING_MODEL),
+            )?),
+            EmbeddingProviderKind::OpenAi => Arc::new(
+                OpenAiProvider::new(
+                    self.url.as_deref().unwrap_or(DEFAULT_OPENAI_EMBEDDING_URL),
+                    self.model
+                        .as_deref()
+                        .unwrap_or(DEFAULT_OPENAI_EMBEDDING_MODEL),
+                )?
+                .with_api_key(self.api_key.clone()),
+            ),
+        };
+        info!("Using embedding provider {}", provider.name());
+        Ok(provider)
+    }
+}
+
+// RustBertProvider embeds locally with rust-bert, the model is loaded on the first call
+#[cfg(feature = "rust-bert")]
+#[derive(Default)]
+pub struct RustBertProvider {
+    model: Mutex<Option<SentenceEmbeddingsModel>>,
+    // query_model is the model of the query lane, None embeds the queries with model
+    query_model: Option<Mutex<Option<SentenceEmbeddingsModel>>>,
+}
+
+#[cfg(feature = "rust-bert")]
+impl RustBertProvider {
+    // with_query_lane embeds the queries with a model of their own, loaded on the first query
+    pub fn with_query_lane(mut self, query_lane: bool) -> Self {
+        self.query_model = match query_lane {
+            true => Some(Mutex::new(None)),
+            false => None,
+        };
+        self
+    }
+}
+
+// encode_with encodes the texts with the model, loading it on the first call
+#[cfg(feature = "rust-bert")]
+fn encode_with(
+    model: &Mutex<Option<SentenceEmbeddingsModel>>,
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>, Error> {
+    let mut model = model.lock().unwrap();
+    if model.is_none() {
+        info!("Loading rust-bert embedding model");
+        let _span = info_span!("load_model").entered();
+        *model = Some(
+            SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL12V2)
+                .with_device(Device::cuda_if_available())
+                .create_model()?,
+        );
+        info!("Model started");
+    }
+    match model.as_ref() {
+        Some(model) => Ok(model.encode(texts)?),
+        None => Err(anyhow!("rust-bert embedding model is not loaded")),
+    }
+}
+
+#[cfg(feature = "rust-bert")]
+impl EmbeddingProvider for RustBertProvider {
+    fn name(&self) -> String {
+        "rust_bert AllMiniLmL12V2".to_string()
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Error> {
+        encode_with(&self.model, texts)
+    }
+
+    fn embed_query(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Error> {
+        encode_with(self.query_model.as_ref().unwrap_or(&self.model), texts)
+    }
+
+    fn size(&self) -> Result<u64, Error> {
+        Ok(RUST_BERT_EMBEDDING_SIZE)
+    }
+}
+
+#[derive(Serialize)]
+struct OllamaEmbeddingRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbeddingResponse {
+    embedding: Vec<f32>,
+}
+
+// OllamaProvider embeds with the /api/embeddings endpoint of an Ollama server, one request
+// per text
+pub struct OllamaProvider {
+    url: String,
+    model: String,
+    http: reqwest::Client,
+    runtime: Handle,
+    size: OnceLock<u64>,
+}
+
+impl OllamaProvider {
+    // new returns a provider for the Ollama server at url, e.g. http://localhost:11434
+    pub fn new(url: &str, model: &str) -> Result<Self, Error> {
+        Ok(OllamaProvider {
+            url: url.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+            http: reqwest::Client::new(),
+            runtime: Handle::try_current()?,
+            size: OnceLock::new(),
+        })
+    }
+
+    // embed_text returns the embedding of a single text
+    async fn embed_text(&self, text: &str) -> Result<Vec<f32>, Error> {
+        let response = self
+            .http
+            .post(format!("{}/api/embeddings", self.url))
+            .json(&OllamaEmbeddingRequest {
+                model: &self.model,
+                prompt: text,
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<OllamaEmbeddingResponse>()
+            .await?;
+        Ok(response.embedding)
+    }
+}
+
+impl EmbeddingProvider for OllamaProvider {
+    fn name(&self) -> String {
+        format!("ollama {} at {}", self.model, self.url)
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Error> {
+        self.runtime.block_on(async {
+            let mut embeddings = Vec::new();
+            for text in texts {
+                embeddings.push(self.embed_text(text).await?);
+            }
+            Ok(embeddings)
+        })
+    }
+
+    fn size(&self) -> Result<u64, Error> {
+        probe_size(self, &self.size)
+    }
+}
+
+#[derive(Serialize)]
+struct OpenAiEmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbeddingResponse {
+    data: Vec<OpenAiEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbedding {
+    embedding: Vec<f32>,
+    index: usize,
+}
+
+// OpenAiProvider embeds with the /embeddings endpoint of OpenAI compatible servers, e.g. OpenAI,
+// vLLM or LocalAI, all texts are embedded in a single request
+pub struct OpenAiProvider {
+    url: String,
+    model: String,
+    api_key: Option<String>,
+    http: reqwest::Client,
+    runtime: Handle,
+    size: OnceLock<u64>,
+}
+
+impl OpenAiProvider {
+    // new returns a provider for the endpoint at the base url, e.g. https://api.openai.com/v1
+    pub fn new(url: &str, model: &str) -> Result<Self, Error> {
+        Ok(OpenAiProvider {
+            url: url.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+            api_key: None,
+            http: reqwest::Client::new(),
+            runtime: Handle::try_current()?,
+            size: OnceLock::new(),
+        })
+    }
+
+    // with_api_key sends the api key as bearer token
+    pub fn with_api_key(mut self, api_key: Option<String>) -> Self {
+        self.api_key = api_key;
+        self
+    }
+}
+
+impl EmbeddingProvider for OpenAiProvider {
+    fn name(&self) -> String {
+        format!("openai {} at {}", self.model, self.url)
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Error> {
+        let mut request =
+            self.http
+                .post(format!("{}/embeddings", self.url))
+                .json(&OpenAiEmbeddingRequest {
+                    model: &self.model,
+                    input: texts,
+                });
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let mut response = self.runtime.block_on(async {
+            request
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<OpenAiEmbeddingResponse>()
+                .await
+        })?;
+        if response.data.len() != texts.len() {
+            return Err(anyhow!(
+                "Expected {} embeddings, got {}",
+                texts.len(),
+                response.data.len()
+            ));
+        }
+        // the embeddings are not guaranteed to be in the order of the texts
+        response.data.sort_by_key(|embedding| embedding.index);
+        Ok(response
+            .data
+            .into_iter()
+            .map(|embedding| embedding.embedding)
+            .collect())
+    }
+
+    fn size(&self) -> Result<u64, Error> {
+        probe_size(self, &self.size)
+    }
+}
+
+// probe_size returns the embedding size of a provider by embedding a probe text once
+fn probe_size(provider: &dyn EmbeddingProvider, size: &OnceLock<u64>) -> Result<u64, Error> {
+    if let Some(size) = size.get() {
+        return Ok(*size);
+    }
+    let embeddings = provider.embed(&[SIZE_PROBE.to_string()])?;
+    let probed = embeddings
+        .first()
+        .map(|embedding| embedding.len() as u64)
+        .ok_or(anyhow!("No embedding returned by {}", provider.name()))?;
+    info!("Detected embedding size {} of {}", probed, provider.name());
+    Ok(*size.get_or_init(|| probed))
+}
@@ -0,0 +1,140 @@
+use crate::data::{Collection, Document, EmbeddedMetadata};
+use crate::ollama::MAX_QUESTIONS;
+use crate::qdrant::{get_collection_stats, CollectionStats};
+use anyhow::{Error, Result};
+use log::info;
+use qdrant_client::client::QdrantClient;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+// BYTES_PER_DIMENSION is the size of a float32 vector dimension
+static BYTES_PER_DIMENSION: u64 = 4;
+// MEGABYTE is the number of bytes of a megabyte
+pub static MEGABYTE: u64 = 1024 * 1024;
+// INDEX_OVERHEAD is the share the HNSW graph and the bookkeeping of qdrant add to the vectors
+static INDEX_OVERHEAD: f64 = 0.5;
+
+// IndexEstimate represents the estimated size of the index after an upload, reported by dry
+// runs before anything is embedded
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct IndexEstimate {
+    pub documents: usize,
+    // chunks is the number of fragments the upload adds, summaries are estimated as one
+    // fragment per document
+    pub chunks: usize,
+    pub dimension: u64,
+    // vector_bytes is the memory of the vectors of the chunks, chunks x dimension x 4 bytes
+    pub vector_bytes: u64,
+    // payload_bytes is the size of the json payloads of the chunks
+    pub payload_bytes: u64,
+    // collections are the stats of the existing collections queried from qdrant
+    pub collections: Vec<CollectionStats>,
+    // total_memory_bytes is the estimated memory of the vectors and their index of the existing
+    // points and the chunks
+    pub total_memory_bytes: u64,
+    pub warnings: Vec<String>,
+}
+
+impl IndexEstimate {
+    // new estimates the chunks, vector memory and payload size of the documents uploaded to the
+    // collections
+    pub fn new(
+        docs: &[Document],
+        collections: &[Collection],
+        dimension: u64,
+    ) -> Result<Self, Error> {
+        let mut estimate = IndexEstimate {
+            documents: docs.len(),
+            dimension: dimension,
+            ..Default::default()
+        };
+        for doc in docs {
+            let fragments = doc.to_fragments()?;
+            let mut payload_bytes = 0;
+            for fragment in fragments
+                .iter()
+                .filter(|fragment| collections.contains(&fragment.collection))
+            {
+                let metadata = EmbeddedMetadata::from_fragment(doc, fragment.clone())?;
+                payload_bytes = serde_json::to_vec(&metadata)?.len() as u64;
+                estimate.chunks += 1;
+                estimate.payload_bytes += payload_bytes;
+            }
+            // summaries are generated by the upload, one fragment of the size of the last
+            // basic one is assumed
+            if collections.contains(&Collection::Summary)
+                && !doc.text.contains_key(&Collection::Summary)
+            {
+                estimate.chunks += 1;
+                estimate.payload_bytes += payload_bytes;
+            }
+            // questions are generated by the upload, MAX_QUESTIONS per basic fragment carrying
+            // its text are assumed, FAQ fragments titled by their question get none
+            if collections.contains(&Collection::Questions) && doc.questions.is_empty() {
+                for fragment in fragments.iter().filter(|fragment| {
+                    fragment.collection == Collection::Basic
+                        && !fragment.lead
+                        && fragment.title.is_none()
+                }) {
+                    let metadata = EmbeddedMetadata::from_fragment(doc, fragment.clone())?;
+                    let payload_bytes = serde_json::to_vec(&metadata)?.len() as u64;
+                    estimate.chunks += MAX_QUESTIONS;
+                    estimate.payload_bytes += payload_bytes * MAX_QUESTIONS as u64;
+                }
+            }
+        }
+        estimate.vector_bytes = vector_bytes(estimate.chunks as u64, dimension);
+        estimate.total_memory_bytes = memory_bytes(estimate.chunks as u64, dimension);
+        Ok(estimate)
+    }
+
+    // with_qdrant_stats adds the existing points of the collections to the total memory and
+    // warns if the collections are unhealthy or the total exceeds the memory budget
+    pub async fn with_qdrant_stats(
+        mut self,
+        client: &QdrantClient,
+        base_collection: &str,
+        collections: &[Collection],
+        memory_budget_bytes: Option<u64>,
+    ) -> Result<Self, Error> {
+        for collection in collections {
+            let stats = match get_collection_stats(client, base_collection, *collection).await? {
+                Some(stats) => stats,
+                None => continue,
+            };
+            if !stats.green {
+                self.warnings.push(format!(
+                    "Collection {} is not green, qdrant is optimizing it or it failed",
+                    stats.collection
+                ));
+            }
+            self.total_memory_bytes += memory_bytes(stats.points_count, self.dimension);
+            self.collections.push(stats);
+        }
+        if let Some(memory_budget_bytes) = memory_budget_bytes {
+            if self.total_memory_bytes > memory_budget_bytes {
+                self.warnings.push(format!(
+                    "Estimated memory of {} MB exceeds the qdrant memory budget of {} MB",
+                    self.total_memory_bytes / MEGABYTE,
+                    memory_budget_bytes / MEGABYTE
+                ));
+            }
+        }
+        for warning in &self.warnings {
+            info!("Index estimate warning: {}", warning);
+        }
+        Ok(self)
+    }
+}
+
+// vector_bytes returns the memory of the vectors of the points
+fn vector_bytes(points: u64, dimension: u64) -> u64 {
+    points * dimension * BYTES_PER_DIMENSION
+}
+
+// memory_bytes returns the memory of the vectors of the points and their index
+fn memory_bytes(points: u64, dimension: u64) -> u64 {
+    (vector_bytes(points, dimension) as f64 * (1.0 + INDEX_OVERHEAD)) as u64
+}
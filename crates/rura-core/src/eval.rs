@@ -0,0 +1,127 @@
+use crate::data::Collection;
+use crate::embedding::text_embedding_async;
+use crate::embedding_provider::SharedEmbeddingProvider;
+use crate::qdrant::{search_scored_documents, SearchFilter, VectorSearch};
+use anyhow::{Error, Result};
+use log::info;
+use qdrant_client::prelude::QdrantClient;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// EvalCase represents a query of the eval suite with the urls expected among its results
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EvalCase {
+    pub query: String,
+    // expected_urls are the pages answering the query, a case passes if any of them is
+    // retrieved
+    pub expected_urls: Vec<String>,
+}
+
+// CaseResult represents the outcome of an eval case
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CaseResult {
+    pub query: String,
+    pub hit: bool,
+    // rank is the 1-based position of the first expected url among the retrieved pages
+    pub rank: Option<usize>,
+    pub retrieved_urls: Vec<String>,
+}
+
+// EvalReport represents the retrieval quality of a base collection on the eval suite
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EvalReport {
+    pub base_collection: String,
+    pub cases: usize,
+    pub hits: usize,
+    // hit_rate is the share of the cases retrieving an expected url
+    pub hit_rate: f32,
+    // mrr is the mean reciprocal rank of the first expected url, 0 for misses
+    pub mrr: f32,
+    pub results: Vec<CaseResult>,
+}
+
+// load_eval_cases reads the eval suite from a json file holding a list of cases
+pub fn load_eval_cases(path: &Path) -> Result<Vec<EvalCase>, Error> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Error reading eval cases {}: {}", path.display(), e))?;
+    let cases: Vec<EvalCase> = serde_json::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("Error parsing eval cases {}: {}", path.display(), e))?;
+    if cases.is_empty() {
+        return Err(anyhow::anyhow!("No eval cases in {}", path.display()));
+    }
+    Ok(cases)
+}
+
+// run_eval retrieves the limit best fragments of each case from the collections of the base
+// collection and reports the cases retrieving an expected url, the answers are not generated
+pub async fn run_eval(
+    client: &QdrantClient,
+    embedding_provider: &SharedEmbeddingProvider,
+    base_collection: &str,
+    collections: Vec<Collection>,
+    cases: &[EvalCase],
+    limit: u64,
+) -> Result<EvalReport, Error> {
+    let mut results = Vec::new();
+    for case in cases {
+        let embeddings = text_embedding_async(embedding_provider, case.query.clone()).await?;
+        let docs = search_scored_documents(
+            client,
+            base_collection,
+            collections.clone(),
+            embeddings,
+            limit,
+            &SearchFilter::default(),
+            VectorSearch::default(),
+        )
+        .await?;
+        // a page is ranked once, by its best fragment
+        let mut retrieved_urls: Vec<String> = Vec::new();
+        for doc in docs {
+            if !retrieved_urls.contains(&doc.metadata.url) {
+                retrieved_urls.push(doc.metadata.url);
+            }
+        }
+        let rank = retrieved_urls
+            .iter()
+            .position(|url| {
+                case.expected_urls
+                    .iter()
+                    .any(|expected| same_url(url, expected))
+            })
+            .map(|position| position + 1);
+        info!(
+            "Eval case {}: {}",
+            case.query,
+            match rank {
+                Some(rank) => format!("expected url at rank {}", rank),
+                None => "no expected url retrieved".to_string(),
+            }
+        );
+        results.push(CaseResult {
+            query: case.query.clone(),
+            hit: rank.is_some(),
+            rank: rank,
+            retrieved_urls: retrieved_urls,
+        });
+    }
+    let hits = results.iter().filter(|result| result.hit).count();
+    let reciprocal_ranks: f32 = results
+        .iter()
+        .filter_map(|result| result.rank)
+        .map(|rank| 1.0 / rank as f32)
+        .sum();
+    Ok(EvalReport {
+        base_collection: base_collection.to_string(),
+        cases: results.len(),
+        hits: hits,
+        hit_rate: hits as f32 / results.len().max(1) as f32,
+        mrr: reciprocal_ranks / results.len().max(1) as f32,
+        results: results,
+    })
+}
+
+// same_url returns true if the urls only differ by a trailing slash
+fn same_url(url: &str, expected: &str) -> bool {
+    url.trim_end_matches('/') == expected.trim_end_matches('/')
+}
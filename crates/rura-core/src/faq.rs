@@ -0,0 +1,143 @@
+use anyhow::Error;
+use scraper::{ElementRef, Html, Selector};
+
+// MIN_FAQ_PAIRS is the number of question answer pairs a page needs to be treated as a FAQ
+pub static MIN_FAQ_PAIRS: usize = 2;
+
+// Faq represents a question answer pair of a FAQ page, indexed as its own fragment titled by the
+// question
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Faq {
+    pub question: String,
+    pub answer: String,
+}
+
+// extract_faqs returns the question answer pairs of a page from definition lists (dt/dd),
+// disclosure widgets (details/summary) and Q:/A: prefixed texts, pages with less than
+// MIN_FAQ_PAIRS pairs are not FAQ pages and return no pairs
+pub fn extract_faqs(document: &Html) -> Result<Vec<Faq>, Error> {
+    let mut faqs = definition_faqs(document)?;
+    faqs.extend(details_faqs(document)?);
+    if faqs.is_empty() {
+        faqs = prefixed_faqs(document);
+    }
+    match faqs.len() >= MIN_FAQ_PAIRS {
+        true => Ok(faqs),
+        false => Ok(Vec::new()),
+    }
+}
+
+// definition_faqs returns the pairs of definition lists, the dd elements following a dt are
+// its answer
+fn definition_faqs(document: &Html) -> Result<Vec<Faq>, Error> {
+    let selector = Selector::parse("dl").or(Err(anyhow::anyhow!("Failed to parse dl selector")))?;
+    let mut faqs = Vec::new();
+    for list in document.select(&selector) {
+        let mut question: Option<String> = None;
+        let mut answer = String::new();
+        for child in list.children().filter_map(ElementRef::wrap) {
+            match child.value().name() {
+                "dt" => {
+                    push_faq(&mut faqs, question.take(), &answer);
+                    question = Some(element_text(child));
+                    answer.clear();
+                }
+                "dd" => answer = join_text(&answer, &element_text(child)),
+                _ => {}
+            }
+        }
+        push_faq(&mut faqs, question, &answer);
+    }
+    Ok(faqs)
+}
+
+// details_faqs returns the pairs of details elements, the summary is the question and the rest
+// of the details the answer
+fn details_faqs(document: &Html) -> Result<Vec<Faq>, Error> {
+    let selector =
+        Selector::parse("details").or(Err(anyhow::anyhow!("Failed to parse details selector")))?;
+    let mut faqs = Vec::new();
+    for details in document.select(&selector) {
+        let mut question: Option<String> = None;
+        let mut answer = String::new();
+        for child in details.children() {
+            match ElementRef::wrap(child) {
+                Some(element) if element.value().name() == "summary" => {
+                    question = Some(element_text(element))
+                }
+                Some(element) => answer = join_text(&answer, &element_text(element)),
+                None => {
+                    if let Some(text) = child.value().as_text() {
+                        answer = join_text(&answer, text.trim());
+                    }
+                }
+            }
+        }
+        push_faq(&mut faqs, question, &answer);
+    }
+    Ok(faqs)
+}
+
+// prefixed_faqs returns the pairs of texts starting with Q: or Question: followed by texts
+// starting with A: or Answer:, the texts up to the next question belong to the answer
+fn prefixed_faqs(document: &Html) -> Vec<Faq> {
+    let mut faqs = Vec::new();
+    let mut question: Option<String> = None;
+    let mut answer = String::new();
+    let mut answering = false;
+    for text in document.root_element().text().map(str::trim) {
+        if let Some(rest) = strip_prefixes(text, &["Q:", "Question:"]) {
+            push_faq(&mut faqs, question.take(), &answer);
+            question = Some(rest.to_string());
+            answer.clear();
+            answering = false;
+        } else if let Some(rest) = strip_prefixes(text, &["A:", "Answer:"]) {
+            answer = join_text(&answer, rest);
+            answering = true;
+        } else if answering {
+            answer = join_text(&answer, text);
+        } else if let Some(current) = question.as_mut() {
+            // questions may span several text nodes, e.g. with inline code or links
+            *current = join_text(current, text);
+        }
+    }
+    push_faq(&mut faqs, question, &answer);
+    faqs
+}
+
+// strip_prefixes returns the trimmed text after the first matching prefix, ignoring case
+fn strip_prefixes<'a>(text: &'a str, prefixes: &[&str]) -> Option<&'a str> {
+    prefixes.iter().find_map(|prefix| {
+        text.get(..prefix.len())
+            .filter(|start| start.eq_ignore_ascii_case(prefix))
+            .map(|_| text[prefix.len()..].trim())
+    })
+}
+
+// push_faq adds a pair if both the question and the answer have text
+fn push_faq(faqs: &mut Vec<Faq>, question: Option<String>, answer: &str) {
+    if let Some(question) = question {
+        if !question.is_empty() && !answer.is_empty() {
+            faqs.push(Faq {
+                question: question,
+                answer: answer.to_string(),
+            });
+        }
+    }
+}
+
+// element_text returns the text of an element as a single line
+fn element_text(element: ElementRef) -> String {
+    element
+        .text()
+        .fold(String::new(), |acc, text| join_text(&acc, text.trim()))
+}
+
+// join_text appends a text separated by a space, skipping empty texts
+fn join_text(acc: &str, text: &str) -> String {
+    match (acc.is_empty(), text.is_empty()) {
+        (_, true) => acc.to_string(),
+        (true, false) => text.to_string(),
+        (false, false) => format!("{} {}", acc, text),
+    }
+}
@@ -0,0 +1,465 @@
+use crate::data::{deprecated_label, Collection, Document};
+use crate::retriever::USER_AGENT;
+use anyhow::{Error, Result};
+use log::{error, info};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION};
+use reqwest::Url;
+use scraper::Html;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+// UNANSWERED_PENALTY is the factor the score of unanswered threads is multiplied by in searches
+pub static UNANSWERED_PENALTY: f32 = 0.8;
+// GITHUB_API is the base url of the github rest and graphql apis
+static GITHUB_API: &str = "https://api.github.com";
+// MAINTAINER_ASSOCIATIONS are the author associations of github comments resolving issues
+static MAINTAINER_ASSOCIATIONS: [&str; 3] = ["OWNER", "MEMBER", "COLLABORATOR"];
+
+// ForumKind represents the source of the threads of a forum upload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForumKind {
+    // Discourse reads the topics of a discourse forum or category
+    Discourse,
+    // GithubIssues reads the issues of a github repository, without pull requests
+    GithubIssues,
+    // GithubDiscussions reads the discussions of a github repository, it needs a token
+    GithubDiscussions,
+}
+
+// string to forum kind
+impl From<&str> for ForumKind {
+    fn from(s: &str) -> Self {
+        match s {
+            "discourse" => ForumKind::Discourse,
+            "github_issues" => ForumKind::GithubIssues,
+            "github_discussions" => ForumKind::GithubDiscussions,
+            _ => {
+                error!("Error converting forum kind, unknown kind: {}", s);
+                ForumKind::Discourse
+            }
+        }
+    }
+}
+
+// ThreadStatus represents whether a thread got an answer
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum ThreadStatus {
+    // Answered threads have an accepted answer
+    Answered,
+    // Resolved threads are closed as completed, e.g. fixed issues
+    Resolved,
+    // Open threads have replies but no accepted answer or resolution
+    Open,
+    // Unanswered threads have no reply, they are downweighted in searches
+    Unanswered,
+}
+
+// Post represents the opening post or a reply of a thread
+#[derive(Debug, Clone)]
+pub struct Post {
+    pub author: String,
+    pub created_at: String,
+    pub text: String,
+}
+
+// Thread represents a forum topic, an issue or a discussion with its answer
+#[derive(Debug, Clone)]
+pub struct Thread {
+    pub url: String,
+    pub title: String,
+    pub question: Post,
+    // answer is the accepted answer or the resolution, indexed right after the question
+    pub answer: Option<Post>,
+    pub replies: Vec<Post>,
+    pub status: ThreadStatus,
+    // votes is the score of the question of a question answer site, boosted in searches
+    pub votes: Option<i64>,
+    // deprecated is true for archived topics, discussions closed as outdated and issues labeled
+    // as deprecated
+    pub deprecated: bool,
+}
+
+impl Thread {
+    // to_document returns the document of the thread, the accepted answer follows the question
+    // so it lands in the first fragment, the other replies come after it
+    pub fn to_document(&self) -> Document {
+        let mut text = format!("Question: {}", self.question.text);
+        if let Some(answer) = &self.answer {
+            let label = match self.status {
+                ThreadStatus::Resolved => "Resolution",
+                _ => "Accepted answer",
+            };
+            text = format!("{} {} by {}: {}", text, label, answer.author, answer.text);
+        }
+        for reply in &self.replies {
+            text = format!("{} Reply by {}: {}", text, reply.author, reply.text);
+        }
+        let mut document = Document::new(
+            Collection::Basic,
+            self.url.clone(),
+            self.title.clone(),
+            text,
+        );
+        document.author = Some(self.question.author.clone());
+        document.created_at = Some(self.question.created_at.clone());
+        document.thread_status = Some(self.status);
+        document.votes = self.votes;
+        document.deprecated = self.deprecated;
+        document
+    }
+}
+
+// thread_status returns the status of a thread by its answer and number of replies
+pub fn thread_status(answer: &Option<Post>, replies: usize, resolved: bool) -> ThreadStatus {
+    match (answer, resolved, replies) {
+        (Some(_), true, _) => ThreadStatus::Resolved,
+        (Some(_), false, _) => ThreadStatus::Answered,
+        (None, _, 0) => ThreadStatus::Unanswered,
+        (None, _, _) => ThreadStatus::Open,
+    }
+}
+
+// fetch_threads returns up to max_threads threads of a forum, e.g. https://forum.example.com or
+// https://github.com/owner/repo, the token authenticates against github
+pub async fn fetch_threads(
+    kind: ForumKind,
+    url: &str,
+    max_threads: usize,
+    token: Option<&str>,
+) -> Result<Vec<Thread>, Error> {
+    let threads = match kind {
+        ForumKind::Discourse => fetch_discourse(url, max_threads).await?,
+        ForumKind::GithubIssues => fetch_github_issues(url, max_threads, token).await?,
+        ForumKind::GithubDiscussions => {
+            let token =
+                token.ok_or(anyhow::anyhow!("Fetching github discussions needs a token"))?;
+            fetch_github_discussions(url, max_threads, token).await?
+        }
+    };
+    info!("Fetched {} threads of {}", threads.len(), url);
+    Ok(threads)
+}
+
+// fetch_discourse returns the topics of a discourse forum, or of a category if the url is the
+// url of a category, the posts marked as solution by the solved plugin are the accepted answers
+async fn fetch_discourse(url: &str, max_threads: usize) -> Result<Vec<Thread>, Error> {
+    let client = reqwest::Client::builder().user_agent(USER_AGENT).build()?;
+    let url = url.trim_end_matches('/');
+    let parsed = Url::parse(url)?;
+    let base = parsed.origin().ascii_serialization();
+    let list_url = match parsed.path().starts_with("/c/") {
+        true => format!("{}.json", url),
+        false => format!("{}/latest.json", url),
+    };
+    let mut threads = Vec::new();
+    let mut page = 0;
+    while threads.len() < max_threads {
+        let list: Value = client
+            .get(format!("{}?page={}", list_url, page))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let topics = list["topic_list"]["topics"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        if topics.is_empty() {
+            break;
+        }
+        for topic in topics.iter().take(max_threads - threads.len()) {
+            let topic_url = format!("{}/t/{}/{}", base, str_of(&topic["slug"]), topic["id"]);
+            match fetch_discourse_topic(&client, &topic_url).await {
+                Ok(thread) => threads.push(thread),
+                Err(e) => info!("Error fetching topic {}: {}", topic_url, e),
+            }
+        }
+        page += 1;
+    }
+    Ok(threads)
+}
+
+// fetch_discourse_topic returns the thread of a discourse topic
+async fn fetch_discourse_topic(client: &reqwest::Client, url: &str) -> Result<Thread, Error> {
+    let topic: Value = client
+        .get(format!("{}.json", url))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let posts = topic["post_stream"]["posts"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let mut posts = posts.iter().map(|post| {
+        (
+            post["accepted_answer"].as_bool().unwrap_or(false),
+            Post {
+                author: str_of(&post["username"]),
+                created_at: str_of(&post["created_at"]),
+                text: html_text(&str_of(&post["cooked"])),
+            },
+        )
+    });
+    let (_, question) = posts
+        .next()
+        .ok_or(anyhow::anyhow!("Topic {} has no posts", url))?;
+    let (accepted, replies): (Vec<_>, Vec<_>) = posts.partition(|(accepted, _)| *accepted);
+    let answer = accepted.into_iter().next().map(|(_, post)| post);
+    let replies: Vec<Post> = replies.into_iter().map(|(_, post)| post).collect();
+    Ok(Thread {
+        url: url.to_string(),
+        title: str_of(&topic["title"]),
+        status: thread_status(&answer, replies.len(), false),
+        question: question,
+        answer: answer,
+        replies: replies,
+        votes: None,
+        deprecated: topic["archived"].as_bool().unwrap_or(false),
+    })
+}
+
+// fetch_github_issues returns the issues of a github repository, the last comment of a
+// maintainer, or the last comment, resolves issues closed as completed
+async fn fetch_github_issues(
+    url: &str,
+    max_threads: usize,
+    token: Option<&str>,
+) -> Result<Vec<Thread>, Error> {
+    let client = github_client(token)?;
+    let (owner, repo) = github_repository(url)?;
+    let mut threads = Vec::new();
+    let mut page = 1;
+    while threads.len() < max_threads {
+        let issues: Vec<Value> = client
+            .get(format!(
+                "{}/repos/{}/{}/issues?state=all&per_page=100&page={}",
+                GITHUB_API, owner, repo, page
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if issues.is_empty() {
+            break;
+        }
+        // the issues endpoint lists pull requests as well
+        for issue in issues
+            .iter()
+            .filter(|issue| issue.get("pull_request").is_none())
+            .take(max_threads - threads.len())
+        {
+            match fetch_github_issue(&client, issue).await {
+                Ok(thread) => threads.push(thread),
+                Err(e) => info!("Error fetching issue {}: {}", issue["html_url"], e),
+            }
+        }
+        page += 1;
+    }
+    Ok(threads)
+}
+
+// fetch_github_issue returns the thread of an issue with its comments
+async fn fetch_github_issue(client: &reqwest::Client, issue: &Value) -> Result<Thread, Error> {
+    let comments: Vec<Value> = match issue["comments"].as_u64().unwrap_or(0) {
+        0 => Vec::new(),
+        _ => {
+            client
+                .get(format!("{}?per_page=100", str_of(&issue["comments_url"])))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?
+        }
+    };
+    let resolved = issue["state"] == "closed" && issue["state_reason"] == "completed";
+    let resolution = match resolved {
+        true => comments
+            .iter()
+            .rposition(|comment| {
+                MAINTAINER_ASSOCIATIONS
+                    .contains(&comment["author_association"].as_str().unwrap_or(""))
+            })
+            .or(comments.len().checked_sub(1)),
+        false => None,
+    };
+    let mut replies: Vec<Post> = comments.iter().map(github_post).collect();
+    let answer = resolution.map(|index| replies.remove(index));
+    let labels: Vec<String> = issue["labels"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+        .iter()
+        .map(|label| str_of(&label["name"]))
+        .collect();
+    Ok(Thread {
+        url: str_of(&issue["html_url"]),
+        title: str_of(&issue["title"]),
+        question: github_post(issue),
+        status: thread_status(&answer, replies.len(), resolved),
+        answer: answer,
+        replies: replies,
+        votes: None,
+        deprecated: deprecated_label(&labels),
+    })
+}
+
+// DISCUSSIONS_QUERY is the graphql query of a page of discussions with their answer
+static DISCUSSIONS_QUERY: &str = r#"query($owner: String!, $name: String!, $after: String) {
+  repository(owner: $owner, name: $name) {
+    discussions(first: 50, after: $after) {
+      pageInfo { hasNextPage endCursor }
+      nodes {
+        url title bodyText createdAt stateReason author { login }
+        answer { bodyText createdAt author { login } }
+        comments(first: 50) { nodes { bodyText createdAt author { login } } }
+      }
+    }
+  }
+}"#;
+
+// fetch_github_discussions returns the discussions of a github repository, the answer marked by
+// the author or a maintainer is the accepted answer
+async fn fetch_github_discussions(
+    url: &str,
+    max_threads: usize,
+    token: &str,
+) -> Result<Vec<Thread>, Error> {
+    let client = github_client(Some(token))?;
+    let (owner, repo) = github_repository(url)?;
+    let mut threads = Vec::new();
+    let mut after: Option<String> = None;
+    while threads.len() < max_threads {
+        let response: Value = client
+            .post(format!("{}/graphql", GITHUB_API))
+            .json(&json!({
+                "query": DISCUSSIONS_QUERY,
+                "variables": {"owner": owner, "name": repo, "after": after},
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(errors) = response.get("errors") {
+            return Err(anyhow::anyhow!("Error fetching discussions: {}", errors));
+        }
+        let discussions = &response["data"]["repository"]["discussions"];
+        let nodes = discussions["nodes"].as_array().cloned().unwrap_or_default();
+        for node in nodes.iter().take(max_threads - threads.len()) {
+            let answer = match node["answer"].is_null() {
+                true => None,
+                false => Some(github_post(&node["answer"])),
+            };
+            // the answer is one of the comments as well
+            let replies: Vec<Post> = node["comments"]["nodes"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default()
+                .iter()
+                .map(github_post)
+                .filter(|reply| {
+                    answer.as_ref().map_or(true, |answer| {
+                        answer.text != reply.text || answer.created_at != reply.created_at
+                    })
+                })
+                .collect();
+            threads.push(Thread {
+                url: str_of(&node["url"]),
+                title: str_of(&node["title"]),
+                question: github_post(node),
+                status: thread_status(&answer, replies.len(), false),
+                answer: answer,
+                replies: replies,
+                votes: None,
+                deprecated: node["stateReason"] == "OUTDATED",
+            });
+        }
+        match (
+            discussions["pageInfo"]["hasNextPage"].as_bool(),
+            discussions["pageInfo"]["endCursor"].as_str(),
+        ) {
+            (Some(true), Some(cursor)) => after = Some(cursor.to_string()),
+            _ => break,
+        }
+    }
+    Ok(threads)
+}
+
+// github_client returns the http client of the github apis, authenticated if a token is set
+fn github_client(token: Option<&str>) -> Result<reqwest::Client, Error> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        ACCEPT,
+        HeaderValue::from_static("application/vnd.github+json"),
+    );
+    if let Some(token) = token {
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token))?,
+        );
+    }
+    Ok(reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .default_headers(headers)
+        .build()?)
+}
+
+// github_repository returns the owner and name of a repository url, e.g. owner and repo of
+// https://github.com/owner/repo
+fn github_repository(url: &str) -> Result<(String, String), Error> {
+    let parsed = Url::parse(url)?;
+    let mut segments = parsed
+        .path_segments()
+        .ok_or(anyhow::anyhow!("Invalid github repository url: {}", url))?;
+    match (segments.next(), segments.next()) {
+        (Some(owner), Some(repo)) if !owner.is_empty() && !repo.is_empty() => {
+            Ok((owner.to_string(), repo.trim_end_matches(".git").to_string()))
+        }
+        _ => Err(anyhow::anyhow!("Invalid github repository url: {}", url)),
+    }
+}
+
+// github_post returns the post of an issue, comment or discussion of the rest or graphql api
+fn github_post(value: &Value) -> Post {
+    let author = match value["user"]["login"].as_str() {
+        Some(login) => login.to_string(),
+        None => str_of(&value["author"]["login"]),
+    };
+    let created_at = match value["created_at"].as_str() {
+        Some(created_at) => created_at.to_string(),
+        None => str_of(&value["createdAt"]),
+    };
+    let text = match value["bodyText"].as_str() {
+        Some(text) => text.to_string(),
+        None => str_of(&value["body"]),
+    };
+    Post {
+        author: author,
+        created_at: created_at,
+        text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+    }
+}
+
+// html_text returns the text of an html fragment as a single line
+pub fn html_text(html: &str) -> String {
+    Html::parse_fragment(html)
+        .root_element()
+        .text()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// str_of returns the string of a json value, empty if it is no string
+fn str_of(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
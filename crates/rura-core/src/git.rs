@@ -0,0 +1,181 @@
+use crate::data::Document;
+use crate::retriever::read_text_file;
+use anyhow::{Error, Result};
+use log::info;
+use sha1::{Digest, Sha1};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Checkout represents a local checkout of a repository at its head commit
+#[derive(Debug, Clone)]
+pub struct Checkout {
+    // dir is the working tree of the checkout
+    pub dir: PathBuf,
+    // head is the commit hash the documents are read at
+    pub head: String,
+    // url_base prefixes the paths of the files to build the urls of their documents
+    pub url_base: String,
+}
+
+impl Checkout {
+    // url returns the url of the document of a file of the checkout
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.url_base, path)
+    }
+
+    // files returns the paths of the tracked files matching one of the globs
+    pub fn files(&self, globs: &[String]) -> Result<Vec<String>, Error> {
+        let files = run_git(&self.dir, &["ls-files", "-z"])?;
+        Ok(files
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .filter(|path| globs.iter().any(|glob| glob_matches(glob, path)))
+            .map(str::to_string)
+            .collect())
+    }
+
+    // changed_files returns the paths of the files matching one of the globs which were changed
+    // and deleted since the commit, None if the commit is unknown to the checkout, e.g. after a
+    // force push, and everything has to be indexed again
+    pub fn changed_files(
+        &self,
+        since: &str,
+        globs: &[String],
+    ) -> Result<Option<(Vec<String>, Vec<String>)>, Error> {
+        let range = format!("{}..{}", since, self.head);
+        let diff = match run_git(
+            &self.dir,
+            &["diff", "--name-status", "--no-renames", &range],
+        ) {
+            Ok(diff) => diff,
+            Err(e) => {
+                info!("Error diffing {}, indexing all files: {}", range, e);
+                return Ok(None);
+            }
+        };
+        let mut changed = Vec::new();
+        let mut deleted = Vec::new();
+        for line in diff.lines() {
+            let (status, path) = match line.split_once('\t') {
+                Some((status, path)) => (status, path.to_string()),
+                None => continue,
+            };
+            if !globs.iter().any(|glob| glob_matches(glob, &path)) {
+                continue;
+            }
+            match status.starts_with('D') {
+                true => deleted.push(path),
+                false => changed.push(path),
+            }
+        }
+        Ok(Some((changed, deleted)))
+    }
+
+    // documents returns the documents of the files at the head commit, unreadable and empty
+    // files are skipped
+    pub fn documents(&self, paths: &[String]) -> Vec<Document> {
+        let mut documents = Vec::new();
+        for path in paths {
+            match read_text_file(&self.dir.join(path), self.url(path)) {
+                Ok(Some(mut document)) => {
+                    document.commit = Some(self.head.clone());
+                    documents.push(document);
+                }
+                Ok(None) => continue,
+                Err(e) => info!("Error reading {}, skipping it: {}", path, e),
+            }
+        }
+        documents
+    }
+}
+
+// checkout returns the checkout of a repository, local checkouts are read as they are, remote
+// repositories are cloned into the cache directory or pulled if cloned before
+pub fn checkout(repo: &str, branch: Option<&str>, cache_dir: &Path) -> Result<Checkout, Error> {
+    let local = Path::new(repo);
+    if local.is_dir() {
+        let dir = std::fs::canonicalize(local)?;
+        let dir = PathBuf::from(run_git(&dir, &["rev-parse", "--show-toplevel"])?.trim());
+        let head = run_git(&dir, &["rev-parse", "HEAD"])?.trim().to_string();
+        info!("Reading local checkout {:?} at {}", dir, head);
+        return Ok(Checkout {
+            url_base: dir.to_string_lossy().to_string(),
+            dir: dir,
+            head: head,
+        });
+    }
+
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{}{}", repo, branch.unwrap_or("")));
+    let dir = cache_dir.join(format!("{:x}", hasher.finalize()));
+    match dir.join(".git").is_dir() {
+        true => {
+            info!("Pulling {} into {:?}", repo, dir);
+            run_git(&dir, &["pull", "--ff-only"])?;
+        }
+        false => {
+            info!("Cloning {} into {:?}", repo, dir);
+            std::fs::create_dir_all(cache_dir)?;
+            let dir_arg = dir.to_string_lossy().to_string();
+            let mut args = vec!["clone", "--single-branch"];
+            if let Some(branch) = branch {
+                args.extend(["--branch", branch]);
+            }
+            args.extend([repo, dir_arg.as_str()]);
+            run_git(cache_dir, &args)?;
+        }
+    }
+    let head = run_git(&dir, &["rev-parse", "HEAD"])?.trim().to_string();
+    Ok(Checkout {
+        dir: dir,
+        head: head,
+        url_base: repo
+            .trim_end_matches('/')
+            .trim_end_matches(".git")
+            .to_string(),
+    })
+}
+
+// run_git runs a git command in the directory and returns its output
+fn run_git(dir: &Path, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("git").current_dir(dir).args(args).output()?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// glob_matches returns true if the path matches the glob, * matches any characters but /, **
+// matches any characters including / and ? matches a single character, globs without / match
+// the file name in any directory
+pub fn glob_matches(glob: &str, path: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let path: Vec<char> = match glob.contains(&'/') {
+        true => path.chars().collect(),
+        false => path.rsplit('/').next().unwrap_or(path).chars().collect(),
+    };
+    matches_from(&glob, &path)
+}
+
+// matches_from matches the rest of the glob against the rest of the path
+fn matches_from(glob: &[char], path: &[char]) -> bool {
+    match glob.first() {
+        None => path.is_empty(),
+        Some('*') if glob.get(1) == Some(&'*') => match glob.get(2) {
+            // **/ matches any number of directories, including none
+            Some('/') => (0..=path.len())
+                .filter(|i| *i == 0 || path[i - 1] == '/')
+                .any(|i| matches_from(&glob[3..], &path[i..])),
+            _ => (0..=path.len()).any(|i| matches_from(&glob[2..], &path[i..])),
+        },
+        Some('*') => (0..=path.len())
+            .take_while(|i| *i == 0 || path[i - 1] != '/')
+            .any(|i| matches_from(&glob[1..], &path[i..])),
+        Some('?') => !path.is_empty() && path[0] != '/' && matches_from(&glob[1..], &path[1..]),
+        Some(c) => path.first() == Some(c) && matches_from(&glob[1..], &path[1..]),
+    }
+}
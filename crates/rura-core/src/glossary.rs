@@ -0,0 +1,141 @@
+use anyhow::{Error, Result};
+use log::{debug, info};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// Glossary represents the terminology of an organization, e.g. internal jargon or product
+// codenames, with the definition of each term
+#[derive(Debug, Clone, Default)]
+pub struct Glossary {
+    terms: BTreeMap<String, String>,
+}
+
+impl Glossary {
+    // new returns a glossary of the given terms and definitions
+    pub fn new(terms: BTreeMap<String, String>) -> Self {
+        Glossary { terms: terms }
+    }
+
+    // load reads a glossary from a json file mapping each term to its definition, e.g.
+    // {"lagoon": "the docker build and deploy system of amazee.io"}
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let terms: BTreeMap<String, String> = serde_json::from_str(&fs::read_to_string(path)?)?;
+        info!("Loaded {} glossary terms from {:?}", terms.len(), path);
+        Ok(Glossary::new(terms))
+    }
+
+    // is_empty returns true if the glossary has no terms
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    // matching returns the terms and definitions of the terms used in one of the texts
+    pub fn matching(&self, texts: &[&str]) -> Vec<(&str, &str)> {
+        let texts: Vec<String> = texts.iter().map(|text| text.to_lowercase()).collect();
+        self.terms
+            .iter()
+            .filter(|(term, _)| {
+                let term = term.to_lowercase();
+                texts.iter().any(|text| contains_term(text, &term))
+            })
+            .map(|(term, definition)| (term.as_str(), definition.as_str()))
+            .collect()
+    }
+
+    // expand_query appends the definitions of the terms used in the query, so the embedding of
+    // the query also matches documents using the spelled out terms
+    pub fn expand_query(&self, query: &str) -> String {
+        let matching = self.matching(&[query]);
+        if matching.is_empty() {
+            return query.to_string();
+        }
+        let definitions: Vec<String> = matching
+            .iter()
+            .map(|(term, definition)| format!("{}: {}", term, definition))
+            .collect();
+        format!("{}\n{}", query, definitions.join("\n"))
+    }
+
+    // prompt_section returns the glossary section of a prompt with the terms used in the
+    // texts, e.g. the question and the context, empty if no term is used
+    pub fn prompt_section(&self, texts: &[&str]) -> String {
+        let matching = self.matching(texts);
+        if matching.is_empty() {
+            return String::new();
+        }
+        let mut section = "Glossary:\n".to_string();
+        for (term, definition) in matching {
+            section.push_str(&format!("- {}: {}\n", term, definition));
+        }
+        section
+    }
+}
+
+// Synonyms represents the acronyms and synonyms of terms, e.g. k8s for kubernetes, used to
+// expand queries before embedding
+#[derive(Debug, Clone, Default)]
+pub struct Synonyms {
+    terms: BTreeMap<String, Vec<String>>,
+}
+
+impl Synonyms {
+    // new returns the synonyms of the given terms, the terms are matched case insensitive
+    pub fn new(terms: BTreeMap<String, Vec<String>>) -> Self {
+        Synonyms {
+            terms: terms
+                .into_iter()
+                .map(|(term, synonyms)| (term.to_lowercase(), synonyms))
+                .collect(),
+        }
+    }
+
+    // load reads the synonyms from a json file mapping each term to its synonyms, e.g.
+    // {"k8s": ["kubernetes"], "db": ["database"]}
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let terms: BTreeMap<String, Vec<String>> =
+            serde_json::from_str(&fs::read_to_string(path)?)?;
+        info!("Loaded synonyms of {} terms from {:?}", terms.len(), path);
+        Ok(Synonyms::new(terms))
+    }
+
+    // is_empty returns true if no synonyms are configured
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    // expand appends the synonyms of the terms used in the query which are not used yet
+    pub fn expand(&self, query: &str) -> String {
+        let lowercase = query.to_lowercase();
+        let mut expansions: Vec<&str> = Vec::new();
+        for (term, synonyms) in &self.terms {
+            if !contains_term(&lowercase, term) {
+                continue;
+            }
+            for synonym in synonyms {
+                if !contains_term(&lowercase, &synonym.to_lowercase())
+                    && !expansions.contains(&synonym.as_str())
+                {
+                    expansions.push(synonym);
+                }
+            }
+        }
+        if expansions.is_empty() {
+            return query.to_string();
+        }
+        debug!("Expanding query {} with {:?}", query, expansions);
+        format!("{} {}", query, expansions.join(" "))
+    }
+}
+
+// contains_term returns true if the text contains the term as whole words, both lowercase
+fn contains_term(text: &str, term: &str) -> bool {
+    if term.is_empty() {
+        return false;
+    }
+    text.match_indices(term).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + term.len()..].chars().next();
+        !before.map_or(false, char::is_alphanumeric) && !after.map_or(false, char::is_alphanumeric)
+    })
+}
@@ -0,0 +1,256 @@
+use crate::data::{deprecated_label, Collection, Document};
+use crate::forum::html_text;
+use crate::retriever::USER_AGENT;
+use anyhow::{Error, Result};
+use log::{error, info};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION};
+use serde_json::Value;
+use std::collections::HashMap;
+
+// INTERCOM_API is the base url of the intercom rest api
+static INTERCOM_API: &str = "https://api.intercom.io";
+// INTERCOM_VERSION is the intercom api version the responses are parsed for
+static INTERCOM_VERSION: &str = "2.10";
+
+// HelpCenterKind represents the source of the articles of a help center upload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HelpCenterKind {
+    // Zendesk reads the articles of a zendesk guide help center
+    Zendesk,
+    // Intercom reads the articles of an intercom workspace, it needs a token
+    Intercom,
+}
+
+// string to help center kind
+impl From<&str> for HelpCenterKind {
+    fn from(s: &str) -> Self {
+        match s {
+            "zendesk" => HelpCenterKind::Zendesk,
+            "intercom" => HelpCenterKind::Intercom,
+            _ => {
+                error!("Error converting help center kind, unknown kind: {}", s);
+                HelpCenterKind::Zendesk
+            }
+        }
+    }
+}
+
+// Article represents a published article of a help center
+#[derive(Debug, Clone)]
+pub struct Article {
+    pub url: String,
+    pub title: String,
+    pub text: String,
+    // category is the category of a zendesk article or the collection of an intercom article
+    pub category: Option<String>,
+    // labels are the labels of a zendesk article, intercom articles have none
+    pub labels: Vec<String>,
+    // deprecated is true for zendesk articles flagged as outdated or labeled as deprecated
+    pub deprecated: bool,
+}
+
+impl Article {
+    // to_document returns the document of the article with its category and labels
+    pub fn to_document(&self) -> Document {
+        let mut document = Document::new(
+            Collection::Basic,
+            self.url.clone(),
+            self.title.clone(),
+            self.text.clone(),
+        );
+        document.category = self.category.clone();
+        document.labels = self.labels.clone();
+        document.deprecated = self.deprecated;
+        document
+    }
+}
+
+// fetch_articles returns up to max_articles published articles of a help center, url is the
+// url of the zendesk help center, e.g. https://example.zendesk.com, or of the intercom help
+// center the articles link to, the token authenticates against the api
+pub async fn fetch_articles(
+    kind: HelpCenterKind,
+    url: &str,
+    locale: &str,
+    max_articles: usize,
+    token: Option<&str>,
+) -> Result<Vec<Article>, Error> {
+    let articles = match kind {
+        HelpCenterKind::Zendesk => fetch_zendesk(url, locale, max_articles, token).await?,
+        HelpCenterKind::Intercom => {
+            let token = token.ok_or(anyhow::anyhow!("Fetching intercom articles needs a token"))?;
+            fetch_intercom(max_articles, token).await?
+        }
+    };
+    info!("Fetched {} articles of {}", articles.len(), url);
+    Ok(articles)
+}
+
+// fetch_zendesk returns the articles of a zendesk guide help center in a locale, the sections
+// and categories are sideloaded to resolve the category of each article, public help centers
+// are read without token
+async fn fetch_zendesk(
+    url: &str,
+    locale: &str,
+    max_articles: usize,
+    token: Option<&str>,
+) -> Result<Vec<Article>, Error> {
+    let client = api_client(token, &[])?;
+    let mut next_page = Some(format!(
+        "{}/api/v2/help_center/{}/articles.json?include=sections,categories&per_page=100",
+        url.trim_end_matches('/'),
+        locale
+    ));
+    let mut articles = Vec::new();
+    while let Some(page_url) = next_page.take() {
+        if articles.len() >= max_articles {
+            break;
+        }
+        let page: Value = client
+            .get(&page_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let categories = names_by_id(&page["categories"]);
+        let section_categories: HashMap<String, String> = page["sections"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|section| {
+                categories
+                    .get(&id_of(&section["category_id"]))
+                    .map(|category| (id_of(&section["id"]), category.clone()))
+            })
+            .collect();
+        for article in page["articles"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+            .iter()
+            .filter(|article| !article["draft"].as_bool().unwrap_or(false))
+            .take(max_articles - articles.len())
+        {
+            let labels: Vec<String> = article["label_names"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default()
+                .iter()
+                .map(str_of)
+                .collect();
+            articles.push(Article {
+                url: str_of(&article["html_url"]),
+                title: str_of(&article["title"]),
+                text: html_text(&str_of(&article["body"])),
+                category: section_categories
+                    .get(&id_of(&article["section_id"]))
+                    .cloned(),
+                deprecated: article["outdated"].as_bool().unwrap_or(false)
+                    || deprecated_label(&labels),
+                labels: labels,
+            });
+        }
+        next_page = page["next_page"].as_str().map(str::to_string);
+    }
+    Ok(articles)
+}
+
+// fetch_intercom returns the published articles of an intercom workspace, the collection an
+// article is in is its category
+async fn fetch_intercom(max_articles: usize, token: &str) -> Result<Vec<Article>, Error> {
+    let client = api_client(Some(token), &[("Intercom-Version", INTERCOM_VERSION)])?;
+    let collections: Value = client
+        .get(format!("{}/help_center/collections", INTERCOM_API))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let collections = names_by_id(&collections["data"]);
+    let mut articles = Vec::new();
+    let mut page = 1;
+    while articles.len() < max_articles {
+        let list: Value = client
+            .get(format!(
+                "{}/articles?page={}&per_page=50",
+                INTERCOM_API, page
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let data = list["data"].as_array().cloned().unwrap_or_default();
+        if data.is_empty() {
+            break;
+        }
+        // drafts and articles not published to a help center have no url
+        for article in data
+            .iter()
+            .filter(|article| article["state"] == "published" && article["url"].is_string())
+            .take(max_articles - articles.len())
+        {
+            articles.push(Article {
+                url: str_of(&article["url"]),
+                title: str_of(&article["title"]),
+                text: html_text(&str_of(&article["body"])),
+                category: collections.get(&id_of(&article["parent_id"])).cloned(),
+                labels: Vec::new(),
+                deprecated: false,
+            });
+        }
+        match list["pages"]["total_pages"].as_u64() {
+            Some(total_pages) if page < total_pages => page += 1,
+            _ => break,
+        }
+    }
+    Ok(articles)
+}
+
+// api_client returns the http client of a help center api, authenticated if a token is set
+fn api_client(
+    token: Option<&str>,
+    extra_headers: &[(&'static str, &'static str)],
+) -> Result<reqwest::Client, Error> {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+    if let Some(token) = token {
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token))?,
+        );
+    }
+    for (name, value) in extra_headers {
+        headers.insert(*name, HeaderValue::from_static(value));
+    }
+    Ok(reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .default_headers(headers)
+        .build()?)
+}
+
+// names_by_id returns the names of a list of categories or collections by their id
+fn names_by_id(value: &Value) -> HashMap<String, String> {
+    value
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+        .iter()
+        .map(|item| (id_of(&item["id"]), str_of(&item["name"])))
+        .collect()
+}
+
+// id_of returns an id as string, zendesk ids are numbers and intercom ids strings
+fn id_of(value: &Value) -> String {
+    match value.as_str() {
+        Some(id) => id.to_string(),
+        None => value.to_string(),
+    }
+}
+
+// str_of returns the string of a json value, empty if it is no string
+fn str_of(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
@@ -0,0 +1,185 @@
+use crate::embedding::query_embeddings_async;
+use crate::embedding_provider::SharedEmbeddingProvider;
+use log::info;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+// Sentence represents a sentence of a text with its character (not byte) offsets
+#[derive(Debug, Clone)]
+pub struct Sentence {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+// Highlight represents a passage of a text similar to a query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct Highlight {
+    // start is the character offset of the first character of the passage
+    pub start: usize,
+    // end is the character offset after the last character of the passage
+    pub end: usize,
+    // score is the cosine similarity of the passage to the query
+    pub score: f32,
+}
+
+// split_sentences splits a text into sentences ending with '.', '!', '?' or a line break
+pub fn split_sentences(text: &str) -> Vec<Sentence> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    for i in 0..chars.len() {
+        let is_last = i == chars.len() - 1;
+        let is_break = chars[i] == '\n'
+            || (matches!(chars[i], '.' | '!' | '?') && (is_last || chars[i + 1].is_whitespace()));
+        if is_break || is_last {
+            push_sentence(&chars, start, i + 1, &mut sentences);
+            start = i + 1;
+        }
+    }
+    sentences
+}
+
+// push_sentence adds the trimmed sentence between start and end, skipping empty ones
+fn push_sentence(chars: &[char], start: usize, end: usize, sentences: &mut Vec<Sentence>) {
+    let mut start = start;
+    let mut end = end;
+    while start < end && chars[start].is_whitespace() {
+        start += 1;
+    }
+    while end > start && chars[end - 1].is_whitespace() {
+        end -= 1;
+    }
+    if start < end {
+        sentences.push(Sentence {
+            start: start,
+            end: end,
+            text: chars[start..end].iter().collect(),
+        });
+    }
+}
+
+// cosine_similarity returns the cosine similarity of two vectors
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+// ScoredSentence represents a sentence with its similarity to a query
+#[derive(Debug, Clone)]
+pub struct ScoredSentence {
+    pub sentence: Sentence,
+    pub score: f32,
+}
+
+// top_highlights returns the n sentences most similar to the query ordered by their offset
+pub fn top_highlights(scored_sentences: &[ScoredSentence], n: usize) -> Vec<Highlight> {
+    let mut highlights: Vec<Highlight> = scored_sentences
+        .iter()
+        .map(|s| Highlight {
+            start: s.sentence.start,
+            end: s.sentence.end,
+            score: s.score,
+        })
+        .collect();
+    highlights.sort_by(|a, b| b.score.total_cmp(&a.score));
+    highlights.truncate(n);
+    highlights.sort_by_key(|h| h.start);
+    highlights
+}
+
+// preview_window returns the character offsets of n sentences centered around the sentence
+// most similar to the query
+pub fn preview_window(scored_sentences: &[ScoredSentence], n: usize) -> Option<(usize, usize)> {
+    if n == 0 || scored_sentences.is_empty() {
+        return None;
+    }
+    let best = scored_sentences
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.score.total_cmp(&b.score))
+        .map(|(i, _)| i)?;
+    // center the window on the best sentence, shifting it to stay within the text
+    let n = n.min(scored_sentences.len());
+    let first = best
+        .saturating_sub((n - 1) / 2)
+        .min(scored_sentences.len() - n);
+    let last = first + n - 1;
+    Some((
+        scored_sentences[first].sentence.start,
+        scored_sentences[last].sentence.end,
+    ))
+}
+
+// preview returns the text trimmed to the preview window of n sentences, with the highlights
+// moved relative to the trimmed text, or None if no preview window exists
+pub fn preview(
+    text: &str,
+    scored_sentences: &[ScoredSentence],
+    highlights: &[Highlight],
+    n: usize,
+) -> Option<(String, Vec<Highlight>)> {
+    let (start, end) = preview_window(scored_sentences, n)?;
+    let preview_text: String = text.chars().skip(start).take(end - start).collect();
+    let preview_highlights = highlights
+        .iter()
+        .filter(|h| h.start >= start && h.end <= end)
+        .map(|h| Highlight {
+            start: h.start - start,
+            end: h.end - start,
+            score: h.score,
+        })
+        .collect();
+    Some((preview_text, preview_highlights))
+}
+
+// score_sentences returns the sentences of each text scored against the query embedding
+//
+// all sentences are embedded with a single model invocation
+pub async fn score_sentences(
+    provider: &SharedEmbeddingProvider,
+    texts: &[String],
+    query_embedding: &[f32],
+) -> Vec<Vec<ScoredSentence>> {
+    let sentences: Vec<Vec<Sentence>> = texts.iter().map(|t| split_sentences(t)).collect();
+    let sentence_texts: Vec<String> = sentences
+        .iter()
+        .flat_map(|s| s.iter().map(|s| s.text.clone()))
+        .collect();
+    if sentence_texts.is_empty() {
+        return vec![vec![]; texts.len()];
+    }
+    let embeddings = match query_embeddings_async(provider, sentence_texts).await {
+        Ok(embeddings) => embeddings,
+        Err(e) => {
+            // highlights are optional, the results are returned without them
+            info!("Error embedding sentences: {}", e);
+            return vec![vec![]; texts.len()];
+        }
+    };
+
+    let mut offset = 0;
+    let mut results = Vec::new();
+    for text_sentences in sentences {
+        let text_embeddings = &embeddings[offset..offset + text_sentences.len()];
+        offset += text_sentences.len();
+        results.push(
+            text_sentences
+                .into_iter()
+                .zip(text_embeddings.iter())
+                .map(|(sentence, embedding)| ScoredSentence {
+                    sentence: sentence,
+                    score: cosine_similarity(embedding, query_embedding),
+                })
+                .collect(),
+        );
+    }
+    results
+}
@@ -0,0 +1,823 @@
+use crate::control::{JobControl, JobSignal};
+use crate::data::{fragment_id, Collection, Document};
+use crate::dedup::{find_duplicates, DedupReport};
+use crate::embedding::{embedding_size, EmbeddingProgress, Model, DEFAULT_BATCH_SIZE};
+use crate::embedding_cache::EmbeddingCache;
+use crate::embedding_provider::SharedEmbeddingProvider;
+use crate::estimate::{IndexEstimate, MEGABYTE};
+use crate::ollama::Llm;
+use crate::progress_store::ProgressStore;
+use crate::progress_tracker::{JobStatus, ProgressTracker, Stage, StageProgress};
+use crate::qdrant::{
+    add_documents, get_existing_ids, get_page_hashes, wait_for_writes, WriteOptions,
+};
+use crate::report::CrawlDiff;
+use crate::scheduler::{JobPriority, JobScheduler};
+use crate::summary::{apply_policy, PendingSummary, PolicyOutcome, SummaryPolicy, SummaryReport};
+use crate::telemetry::record_ingested_document;
+use crate::transform::TransformChain;
+use anyhow::{Error, Result};
+use log::info;
+use qdrant_client::client::QdrantClient;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// PROGRESS_LOG_INTERVAL is the number of documents between two progress logs
+static PROGRESS_LOG_INTERVAL: usize = 10;
+// FETCH_STAGES are the stages recorded by the retriever before the upload
+static FETCH_STAGES: &[Stage] = &[Stage::Fetched, Stage::Parsed, Stage::Failed];
+// LEAD_FRAGMENTS is the number of first fragments per collection of a page indexed by the lead
+// pass of a lead first upload
+pub static LEAD_FRAGMENTS: usize = 1;
+
+// IndexPass represents the fragments indexed by a pass over the documents, lead first uploads
+// index the lead fragments of all pages before the deep fragments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexPass {
+    All,
+    Lead,
+    Deep,
+}
+
+impl IndexPass {
+    // passes returns the passes of an upload
+    fn passes(lead_first: bool) -> Vec<IndexPass> {
+        match lead_first {
+            true => vec![IndexPass::Lead, IndexPass::Deep],
+            false => vec![IndexPass::All],
+        }
+    }
+
+    // excludes returns true if the fragment at the position of its collection isn't indexed by
+    // the pass
+    fn excludes(&self, position: usize) -> bool {
+        match self {
+            IndexPass::All => false,
+            IndexPass::Lead => position >= LEAD_FRAGMENTS,
+            IndexPass::Deep => position < LEAD_FRAGMENTS,
+        }
+    }
+}
+
+// SummaryStage configures the summary stage of an upload
+#[derive(Clone)]
+pub struct SummaryStage {
+    pub llm: Llm,
+    pub ollama_model: String,
+    // ollama_host and ollama_port are kept to generate queued summaries later
+    pub ollama_host: String,
+    pub ollama_port: u16,
+    pub policy: SummaryPolicy,
+}
+
+// QuestionStage configures the question stage of an upload, generating the questions each
+// basic fragment answers for the questions collection
+#[derive(Clone)]
+pub struct QuestionStage {
+    pub llm: Llm,
+    pub ollama_model: String,
+}
+
+// TransformStage configures the transformers of the crawl profile of an upload, applied to
+// each document before it is deduplicated and chunked, the llm is used by transformers like
+// translate
+#[derive(Clone)]
+pub struct TransformStage {
+    pub chain: TransformChain,
+    pub llm: Llm,
+    pub ollama_model: String,
+}
+
+// UploadOutcome represents the outcome of an upload
+pub struct UploadOutcome {
+    pub status: JobStatus,
+    // summaries is the report of the summary stage, None if the stage is disabled
+    pub summaries: Option<SummaryReport>,
+    // pending are the summaries queued by the queue summary policy
+    pub pending: Vec<PendingSummary>,
+    // skipped_fragments is the number of fragments already stored, None if not incremental
+    pub skipped_fragments: Option<usize>,
+    // dedup is the report of the dedup stage, None if the stage is disabled
+    pub dedup: Option<DedupReport>,
+}
+
+// UploadConfig represents the injected store, embedder and LLM of an upload and its stages,
+// the optional summary and question stages and the index stage embedding and upserting the fragments of the
+// collections
+pub struct UploadConfig {
+    client: Arc<QdrantClient>,
+    embedding_provider: SharedEmbeddingProvider,
+    base_collection: String,
+    collections: Vec<Collection>,
+    summary: Option<SummaryStage>,
+    questions: Option<QuestionStage>,
+    transforms: Option<TransformStage>,
+    batch_size: usize,
+    embedding_cache: Arc<EmbeddingCache>,
+    scheduler: Option<(Arc<JobScheduler>, JobPriority)>,
+    incremental: bool,
+    lead_first: bool,
+    dedup_threshold: Option<f32>,
+    write_options: WriteOptions,
+    control: Option<Arc<JobControl>>,
+}
+
+impl UploadConfig {
+    // new returns a config indexing into the given collections without summary stage
+    pub fn new(
+        client: Arc<QdrantClient>,
+        embedding_provider: SharedEmbeddingProvider,
+        base_collection: &str,
+        collections: Vec<Collection>,
+    ) -> Self {
+        UploadConfig {
+            client: client,
+            embedding_provider: embedding_provider,
+            base_collection: base_collection.to_string(),
+            collections: collections,
+            summary: None,
+            questions: None,
+            transforms: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            embedding_cache: Arc::new(EmbeddingCache::default()),
+            scheduler: None,
+            incremental: false,
+            lead_first: false,
+            dedup_threshold: None,
+            write_options: WriteOptions::default(),
+            control: None,
+        }
+    }
+
+    // with_summary enables the summary stage, None disables it
+    pub fn with_summary(mut self, summary: Option<SummaryStage>) -> Self {
+        self.summary = summary;
+        self
+    }
+
+    // with_questions enables the question stage, None disables it
+    pub fn with_questions(mut self, questions: Option<QuestionStage>) -> Self {
+        self.questions = questions;
+        self
+    }
+
+    // with_transforms enables the transform stage, None or an empty chain disables it
+    pub fn with_transforms(mut self, transforms: Option<TransformStage>) -> Self {
+        self.transforms = transforms.filter(|stage| !stage.chain.is_empty());
+        self
+    }
+
+    // with_batch_size sets the number of fragments embedded per encode call
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    // with_embedding_cache shares the cache of the embeddings with other jobs, by default
+    // identical fragments are only deduplicated within the job
+    pub fn with_embedding_cache(mut self, embedding_cache: Arc<EmbeddingCache>) -> Self {
+        self.embedding_cache = embedding_cache;
+        self
+    }
+
+    // with_scheduler yields to higher priority jobs and to the queries in flight before
+    // processing the next document
+    pub fn with_scheduler(mut self, scheduler: Arc<JobScheduler>, priority: JobPriority) -> Self {
+        self.scheduler = Some((scheduler, priority));
+        self
+    }
+
+    // with_incremental skips the fragments already stored by a previous upload, the fragment
+    // ids are a hash of the url and text so unchanged fragments are neither embedded nor upserted
+    pub fn with_incremental(mut self, incremental: bool) -> Self {
+        self.incremental = incremental;
+        self
+    }
+
+    // with_lead_first indexes the lead fragments of all pages before their deep fragments, so
+    // every page of a large upload is searchable early, the summaries are generated by the lead
+    // pass
+    pub fn with_lead_first(mut self, lead_first: bool) -> Self {
+        self.lead_first = lead_first;
+        self
+    }
+
+    // with_dedup drops the basic fragments whose estimated similarity to an earlier fragment of
+    // the upload reaches the threshold before they are embedded, None disables the dedup stage
+    pub fn with_dedup(mut self, dedup_threshold: Option<f32>) -> Self {
+        self.dedup_threshold = dedup_threshold;
+        self
+    }
+
+    // with_write_options sets whether upserts wait until the points are searchable and their
+    // ordering across replicas
+    pub fn with_write_options(mut self, write_options: WriteOptions) -> Self {
+        self.write_options = write_options;
+        self
+    }
+
+    // with_control lets the upload be paused and cancelled before processing the next document
+    pub fn with_control(mut self, control: Arc<JobControl>) -> Self {
+        self.control = Some(control);
+        self
+    }
+}
+
+// diff_crawl returns the diff of the crawled documents against the previous crawl of the url,
+// it must be called before the upload stores the new page hashes
+pub async fn diff_crawl(
+    config: &UploadConfig,
+    url: &str,
+    docs: &[Document],
+) -> Result<CrawlDiff, Error> {
+    let previous = get_page_hashes(
+        &config.client,
+        &config.base_collection,
+        Collection::Basic,
+        url.trim_end_matches("sitemap.xml"),
+    )
+    .await?;
+    let crawl_diff = CrawlDiff::new(url, &previous, docs);
+    info!(
+        "Crawl diff: {} added, {} modified, {} removed, {} unchanged",
+        crawl_diff.added.len(),
+        crawl_diff.modified.len(),
+        crawl_diff.removed.len(),
+        crawl_diff.unchanged
+    );
+    Ok(crawl_diff)
+}
+
+// estimate_index returns the estimated size of the index after uploading the documents, the
+// documents are chunked but neither summarized nor embedded
+pub async fn estimate_index(
+    config: &UploadConfig,
+    docs: &[Document],
+    memory_budget_bytes: Option<u64>,
+) -> Result<IndexEstimate, Error> {
+    let dimension = embedding_size(&config.embedding_provider).await?;
+    let estimate = IndexEstimate::new(docs, &config.collections, dimension)?
+        .with_qdrant_stats(
+            &config.client,
+            &config.base_collection,
+            &config.collections,
+            memory_budget_bytes,
+        )
+        .await?;
+    info!(
+        "Estimated {} chunks, {} MB of vectors, {} MB of payloads",
+        estimate.chunks,
+        estimate.vector_bytes / MEGABYTE,
+        estimate.payload_bytes / MEGABYTE
+    );
+    Ok(estimate)
+}
+
+// run_upload registers the progress of the job with the fetch stages of the retriever and
+// processes the documents, the server upload task and the client upload both run it, jobs
+// registered before fetching already recorded their fetch stages and only get their total set
+//
+// failing documents are recorded as failed and skipped, the upload is aborted only by the
+// fail fast summary policy, the final status is set in the tracker and returned
+#[tracing::instrument(skip_all, fields(%id, documents = docs.len()))]
+pub async fn run_upload(
+    config: &UploadConfig,
+    id: Uuid,
+    docs: &mut [Document],
+    fetch_stages: &StageProgress,
+    tracker: &Arc<ProgressStore<EmbeddingProgress>>,
+) -> UploadOutcome {
+    let total_docs = docs.len();
+    info!("Adding {} documents", total_docs);
+    // each pass embeds every document once
+    let passes = IndexPass::passes(config.lead_first);
+    let total_items = total_docs * passes.len();
+    match tracker.get(&id).await {
+        Some(_) => {
+            tracker
+                .update(&id, |progress| progress.set_total_items(total_items))
+                .await;
+        }
+        None => {
+            let mut progress = EmbeddingProgress::new(total_items);
+            for stage in FETCH_STAGES {
+                let stats = fetch_stages.get(*stage);
+                progress.record_stage(
+                    *stage,
+                    stats.count,
+                    Duration::from_millis(stats.duration_ms),
+                );
+            }
+            tracker.insert(id, progress).await;
+        }
+    }
+
+    let (_handle, model) = Model::spawn_with_batch_size(
+        config.embedding_provider.clone(),
+        tracker.clone(),
+        id,
+        config.batch_size,
+        config.embedding_cache.clone(),
+    );
+    let mut outcome = UploadOutcome {
+        status: JobStatus::Completed,
+        summaries: config.summary.as_ref().map(|_| SummaryReport::default()),
+        pending: Vec::new(),
+        skipped_fragments: match config.incremental {
+            true => Some(0),
+            false => None,
+        },
+        dedup: None,
+    };
+    // documents failing a transformer are skipped, e.g. so a failed redaction isn't indexed
+    let mut untransformed = HashSet::new();
+    if let Some(stage) = &config.transforms {
+        info!(
+            "Transforming documents with {}",
+            stage.chain.names().join(", ")
+        );
+        for (i, doc) in docs.iter_mut().enumerate() {
+            if let Err(e) = stage
+                .chain
+                .apply(doc, &stage.llm, &stage.ollama_model)
+                .await
+            {
+                info!("Error transforming document, skipping it: {}", e);
+                record_stage(tracker, id, Stage::Failed, Duration::default()).await;
+                untransformed.insert(i);
+            }
+        }
+    }
+    // near duplicates are found across all documents before the first one is indexed
+    let mut duplicates: HashMap<String, HashSet<String>> = HashMap::new();
+    if let Some(threshold) = config.dedup_threshold {
+        match find_duplicates(docs, threshold) {
+            Ok((found, report)) => {
+                duplicates = found;
+                outcome.dedup = Some(report);
+            }
+            Err(e) => info!("Error finding duplicate fragments: {}", e),
+        }
+    }
+
+    let last_pass = passes.len() - 1;
+    // unflushed is set while the last upsert didn't wait, e.g. the last document was skipped
+    let mut unflushed = false;
+    'passes: for (p, pass) in passes.into_iter().enumerate() {
+        for (i, doc) in docs.iter_mut().enumerate() {
+            if untransformed.contains(&i) {
+                continue;
+            }
+            if let Some(control) = &config.control {
+                if !checkpoint(control, id, tracker).await {
+                    info!("Cancelled upload job {} after {} documents", id, i);
+                    outcome.status = JobStatus::Cancelled;
+                    break 'passes;
+                }
+            }
+            if let Some((scheduler, priority)) = &config.scheduler {
+                scheduler.wait_turn(*priority).await;
+                scheduler.yield_to_queries().await;
+            }
+            // the summary is the lead of the summary collection, it's generated before indexing
+            let summary = match pass {
+                IndexPass::Deep => None,
+                _ => config.summary.as_ref().zip(outcome.summaries.as_mut()),
+            };
+            if let Some((stage, report)) = summary {
+                set_current_stage(tracker, id, Stage::Summarized).await;
+                let abort = summarize(
+                    config,
+                    id,
+                    doc,
+                    stage,
+                    report,
+                    &mut outcome.pending,
+                    tracker,
+                )
+                .await;
+                if abort {
+                    outcome.status = JobStatus::Failed;
+                    break 'passes;
+                }
+            }
+            // the questions are generated once, a failure indexes the document without them
+            if let (Some(stage), false) = (&config.questions, pass == IndexPass::Deep) {
+                info!("Generating questions for {}", doc.url);
+                if let Err(e) = doc.add_questions(&stage.ollama_model, &stage.llm).await {
+                    info!("Error adding questions: {}", e);
+                }
+            }
+            set_current_stage(tracker, id, Stage::Embedded).await;
+            // the last upsert waits so the whole upload is searchable once the job completes
+            let last = p == last_pass && i + 1 == total_docs;
+            // the fragments of other passes and the near duplicates are not indexed
+            let mut skip = pass_excluded(doc, pass);
+            skip.extend(duplicates.get(&doc.url).into_iter().flatten().cloned());
+            let indexed = index(config, id, doc, skip, &model, tracker, last).await;
+            if let Some(waited) = indexed.waited {
+                unflushed = !waited;
+            }
+            if let Some(skipped_fragments) = outcome.skipped_fragments.as_mut() {
+                *skipped_fragments += indexed.skipped;
+            }
+
+            if (i + 1) % PROGRESS_LOG_INTERVAL == 0 || i + 1 == total_docs {
+                let stages = tracker
+                    .get(&id)
+                    .await
+                    .map(|progress| progress.stages())
+                    .unwrap_or_default();
+                info!(
+                    "Added {}/{} documents, {:?} pass, {}",
+                    i + 1,
+                    total_docs,
+                    pass,
+                    stages
+                );
+            }
+        }
+    }
+    // the last document had nothing to upsert, the earlier upserts are waited for instead
+    if unflushed {
+        if let Err(e) = wait_for_writes(
+            &config.client,
+            &config.base_collection,
+            config.collections.clone(),
+            config.write_options.ordering,
+        )
+        .await
+        {
+            info!("Error waiting for the upserts: {}", e);
+        }
+    }
+    if let Some(skipped_fragments) = outcome.skipped_fragments {
+        info!("Skipped {} unchanged fragments", skipped_fragments);
+    }
+
+    tracker
+        .update(&id, |progress| progress.set_status(outcome.status))
+        .await;
+    outcome
+}
+
+// summarize runs the summary stage of a document, returns true if the upload must be aborted
+#[tracing::instrument(skip_all, fields(url = %doc.url))]
+async fn summarize(
+    config: &UploadConfig,
+    id: Uuid,
+    doc: &mut Document,
+    stage: &SummaryStage,
+    report: &mut SummaryReport,
+    pending: &mut Vec<PendingSummary>,
+    tracker: &ProgressStore<EmbeddingProgress>,
+) -> bool {
+    info!("Creating summary document");
+    let start = Instant::now();
+    let result = doc.add_summary(&stage.ollama_model, &stage.llm).await;
+    let e = match result {
+        Ok(_) => {
+            report.generated += 1;
+            record_stage(tracker, id, Stage::Summarized, start.elapsed()).await;
+            return false;
+        }
+        Err(e) => e,
+    };
+    info!("Error adding summary: {}", e);
+    record_stage(tracker, id, Stage::Failed, start.elapsed()).await;
+    if stage.policy == SummaryPolicy::Queue {
+        pending.push(PendingSummary {
+            job_id: id,
+            document: doc.clone(),
+            base_collection: config.base_collection.clone(),
+            ollama_model: stage.ollama_model.clone(),
+            ollama_host: stage.ollama_host.clone(),
+            ollama_port: stage.ollama_port,
+        });
+    }
+    match apply_policy(stage.policy, report, &doc.url, &e) {
+        PolicyOutcome::Abort => {
+            info!("Aborting upload job {}: {}", id, e);
+            true
+        }
+        PolicyOutcome::Continue => false,
+    }
+}
+
+// Indexed represents the outcome of the index stage of a document
+struct Indexed {
+    // skipped is the number of fragments skipped because they are already stored
+    skipped: usize,
+    // waited is true if the upsert waited until the points were searchable, None if nothing was
+    // upserted
+    waited: Option<bool>,
+}
+
+// index runs the index stage of a document, embedding and upserting the fragments not in skip,
+// the upsert of the last document waits until the points are searchable
+#[tracing::instrument(skip_all, fields(url = %doc.url))]
+async fn index(
+    config: &UploadConfig,
+    id: Uuid,
+    doc: &Document,
+    mut skip: HashSet<String>,
+    model: &Model,
+    tracker: &ProgressStore<EmbeddingProgress>,
+    last: bool,
+) -> Indexed {
+    let stored = match config.incremental {
+        true => stored_fragments(config, doc).await,
+        false => HashSet::new(),
+    };
+    let mut indexed = Indexed {
+        skipped: stored.difference(&skip).count(),
+        waited: None,
+    };
+    skip.extend(stored);
+    let embeddings = match model.encode_skipping(doc.clone(), skip).await {
+        Ok(embeddings) => embeddings,
+        Err(e) => {
+            info!("Error encoding document: {}", e);
+            record_stage(tracker, id, Stage::Failed, Duration::default()).await;
+            return indexed;
+        }
+    };
+    if embeddings.is_empty() {
+        info!("No fragments to index for {}", doc.url);
+        return indexed;
+    }
+    set_current_stage(tracker, id, Stage::Upserted).await;
+    // the upserts of the jobs have a lower priority than the searches of the queries
+    if let Some((scheduler, _)) = &config.scheduler {
+        scheduler.yield_to_queries().await;
+    }
+    let start = Instant::now();
+    let wait = config.write_options.wait || last;
+    let result = add_documents(
+        &config.client,
+        &config.base_collection,
+        config.collections.clone(),
+        embeddings,
+        WriteOptions {
+            wait: wait,
+            ..config.write_options
+        },
+    )
+    .await;
+    match result {
+        Ok(_) => {
+            indexed.waited = Some(wait);
+            record_ingested_document();
+            record_stage(tracker, id, Stage::Upserted, start.elapsed()).await
+        }
+        Err(e) => {
+            info!("Error adding documents: {}", e);
+            record_stage(tracker, id, Stage::Failed, start.elapsed()).await;
+        }
+    }
+    indexed
+}
+
+// pass_excluded returns the ids of the fragments of a document not indexed by the pass
+fn pass_excluded(doc: &Document, pass: IndexPass) -> HashSet<String> {
+    if pass == IndexPass::All {
+        return HashSet::new();
+    }
+    let fragments = match doc.to_fragments() {
+        Ok(fragments) => fragments,
+        Err(e) => {
+            info!("Error splitting document {}: {}", doc.url, e);
+            return HashSet::new();
+        }
+    };
+    let mut positions: HashMap<Collection, usize> = HashMap::new();
+    let mut excluded = HashSet::new();
+    for fragment in fragments {
+        let position = positions.entry(fragment.collection).or_insert(0);
+        if pass.excludes(*position) {
+            excluded.insert(fragment_id(&doc.url, &fragment.text));
+        }
+        *position += 1;
+    }
+    excluded
+}
+
+// stored_fragments returns the ids of the fragments of a document already stored in the
+// collections, a failing lookup stores all fragments again
+async fn stored_fragments(config: &UploadConfig, doc: &Document) -> HashSet<String> {
+    let fragments = match doc.to_fragments() {
+        Ok(fragments) => fragments,
+        Err(e) => {
+            info!("Error splitting document {}: {}", doc.url, e);
+            return HashSet::new();
+        }
+    };
+    let mut stored = HashSet::new();
+    for collection in &config.collections {
+        let ids: Vec<String> = fragments
+            .iter()
+            .filter(|fragment| fragment.collection == *collection)
+            .map(|fragment| fragment_id(&doc.url, &fragment.text))
+            .collect();
+        match get_existing_ids(&config.client, &config.base_collection, *collection, &ids).await {
+            Ok(existing) => stored.extend(existing),
+            Err(e) => info!("Error getting stored fragments of {}: {}", doc.url, e),
+        }
+    }
+    stored
+}
+
+// checkpoint waits while the job is paused, showing it as paused in the progress, and returns
+// false if the job was cancelled
+async fn checkpoint(
+    control: &JobControl,
+    id: Uuid,
+    tracker: &ProgressStore<EmbeddingProgress>,
+) -> bool {
+    if control.signal() != JobSignal::Pause {
+        return control.checkpoint().await;
+    }
+    info!("Pausing upload job {}", id);
+    tracker
+        .update(&id, |progress| progress.set_status(JobStatus::Paused))
+        .await;
+    let resumed = control.checkpoint().await;
+    if resumed {
+        info!("Resuming upload job {}", id);
+        tracker
+            .update(&id, |progress| progress.set_status(JobStatus::Running))
+            .await;
+    }
+    resumed
+}
+
+// set_current_stage sets the stage the job is working on
+async fn set_current_stage(tracker: &ProgressStore<EmbeddingProgress>, id: Uuid, stage: Stage) {
+    tracker
+        .update(&id, |progress| progress.set_current_stage(stage))
+        .await;
+}
+
+// record_stage records a document done by a stage of the upload of a job
+async fn record_stage(
+    tracker: &ProgressStore<EmbeddingProgress>,
+    id: Uuid,
+    stage: Stage,
+    duration: Duration,
+) {
+    tracker
+        .update(&id, |progress| progress.record_stage(stage, 1, duration))
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_upload, UploadConfig, UploadOutcome};
+    use crate::config::DEFAULT_QDRANT_ADDRESS;
+    use crate::data::{Collection, Document};
+    use crate::embedding::EmbeddingProgress;
+    use crate::embedding_provider::{EmbeddingProvider, SharedEmbeddingProvider};
+    use crate::progress_store::ProgressStore;
+    use crate::progress_tracker::{JobStatus, ProgressTracker, StageProgress};
+    use crate::qdrant::{create_collections, get_collection_stats, CollectionConfig};
+    use crate::retry::{set_retry_policy, RetryPolicy};
+    use anyhow::Error;
+    use qdrant_client::client::{QdrantClient, QdrantClientConfig};
+    use std::sync::{Arc, Mutex};
+    use uuid::Uuid;
+
+    // CountingEmbeddings embeds every text with the same small vector and keeps the texts
+    #[derive(Default)]
+    struct CountingEmbeddings {
+        texts: Mutex<Vec<String>>,
+    }
+
+    impl EmbeddingProvider for CountingEmbeddings {
+        fn name(&self) -> String {
+            "counting".to_string()
+        }
+
+        fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Error> {
+            self.texts.lock().unwrap().extend(texts.iter().cloned());
+            Ok(texts.iter().map(|_| vec![0.5; 4]).collect())
+        }
+
+        fn size(&self) -> Result<u64, Error> {
+            Ok(4)
+        }
+    }
+
+    // basic_docs returns two basic pages of a single fragment each
+    fn basic_docs() -> Vec<Document> {
+        vec![
+            Document::new(
+                Collection::Basic,
+                "https://docs.example.com/backups".to_string(),
+                "Backups".to_string(),
+                "Backups of the environments are taken every night.".to_string(),
+            ),
+            Document::new(
+                Collection::Basic,
+                "https://docs.example.com/restores".to_string(),
+                "Restores".to_string(),
+                "Restores are requested through the support portal.".to_string(),
+            ),
+        ]
+    }
+
+    // upload runs a basic only upload without summary stage into the base collection
+    async fn upload(
+        client: QdrantClient,
+        provider: Arc<CountingEmbeddings>,
+        base_collection: &str,
+        docs: &mut [Document],
+    ) -> (UploadOutcome, EmbeddingProgress) {
+        set_retry_policy(RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        });
+        let shared: SharedEmbeddingProvider = provider;
+        let config = UploadConfig::new(
+            Arc::new(client),
+            shared,
+            base_collection,
+            vec![Collection::Basic],
+        )
+        .with_summary(None);
+        let tracker = Arc::new(ProgressStore::<EmbeddingProgress>::new());
+        let id = Uuid::new_v4();
+        let outcome = run_upload(&config, id, docs, &StageProgress::default(), &tracker).await;
+        let progress = tracker.get(&id).await.unwrap();
+        (outcome, progress)
+    }
+
+    #[tokio::test]
+    async fn basic_only_uploads_embed_without_summary_stage() {
+        // the upserts fail right away, the stages before them don't need qdrant or ollama
+        let client =
+            QdrantClient::new(Some(QdrantClientConfig::from_url("http://127.0.0.1:1"))).unwrap();
+        let provider = Arc::new(CountingEmbeddings::default());
+        let mut docs = basic_docs();
+        let (outcome, progress) = upload(client, provider.clone(), "basic_only", &mut docs).await;
+
+        assert!(outcome.summaries.is_none());
+        assert!(outcome.pending.is_empty());
+        let stages = progress.stages();
+        assert_eq!(stages.summarized.count, 0);
+        // each document reached the upsert, which failed without qdrant
+        assert_eq!(stages.upserted.count, 0);
+        assert_eq!(stages.failed.count, 2);
+        let texts = provider.texts.lock().unwrap();
+        // the fragments are embedded with their title and url prefix
+        for doc in &docs {
+            let content = &doc.text[&Collection::Basic];
+            assert!(texts.iter().any(|text| text.ends_with(content.as_str())));
+            assert!(!doc.text.contains_key(&Collection::Summary));
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs qdrant on localhost:6334, see qdrant.sh"]
+    async fn basic_only_uploads_upsert_the_basic_fragments() {
+        let client =
+            QdrantClient::new(Some(QdrantClientConfig::from_url(DEFAULT_QDRANT_ADDRESS))).unwrap();
+        let base_collection = format!("basic_only_{}", Uuid::new_v4().simple());
+        create_collections(
+            &client,
+            &base_collection,
+            vec![Collection::Basic],
+            "counting",
+            4,
+            &CollectionConfig::default(),
+        )
+        .await
+        .unwrap();
+        let provider = Arc::new(CountingEmbeddings::default());
+        let mut docs = basic_docs();
+        let (outcome, progress) =
+            upload(client, provider.clone(), &base_collection, &mut docs).await;
+
+        let client =
+            QdrantClient::new(Some(QdrantClientConfig::from_url(DEFAULT_QDRANT_ADDRESS))).unwrap();
+        let stats = get_collection_stats(&client, &base_collection, Collection::Basic)
+            .await
+            .unwrap()
+            .unwrap();
+        let summary_stats = get_collection_stats(&client, &base_collection, Collection::Summary)
+            .await
+            .unwrap();
+        let collection_name = format!("{}_{}", base_collection, Collection::Basic.to_string());
+        client.delete_collection(&collection_name).await.unwrap();
+
+        assert_eq!(outcome.status, JobStatus::Completed);
+        assert!(outcome.summaries.is_none());
+        let stages = progress.stages();
+        assert_eq!(stages.summarized.count, 0);
+        assert_eq!(stages.upserted.count, 2);
+        assert_eq!(provider.texts.lock().unwrap().len(), 4);
+        assert_eq!(stats.points_count, 2);
+        assert!(summary_stats.is_none());
+    }
+}
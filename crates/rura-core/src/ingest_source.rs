@@ -0,0 +1,420 @@
+use crate::config::CrawlProfile;
+use crate::data::Document;
+use crate::progress_tracker::{Stage, StageCallback, StageProgress};
+use crate::retriever::{
+    crawl_client, pages_with_callback, parse_body, sitemap_urls, sitemap_with_callback,
+    text_document, text_files, SitemapOptions,
+};
+use anyhow::{Error, Result};
+use log::info;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{info_span, Instrument};
+
+// SITEMAP_SOURCE is the name of the built-in source of the pages of a sitemap.xml
+pub static SITEMAP_SOURCE: &str = "sitemap";
+// CRAWLER_SOURCE is the name of the built-in source of a single page
+pub static CRAWLER_SOURCE: &str = "crawler";
+// FILE_SOURCE is the name of the built-in source of the text files of a directory
+pub static FILE_SOURCE: &str = "file";
+
+// INGEST_SOURCES are the sources uploads can read from by name, the built-in sources are
+// registered on first use
+static INGEST_SOURCES: OnceLock<RwLock<HashMap<String, SharedIngestSource>>> = OnceLock::new();
+
+// SourceFuture is the boxed future returned by ingest sources, boxed so sources can be
+// registered as trait objects
+pub type SourceFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + 'a>>;
+
+// SharedIngestSource is an ingest source shared by the uploads of the server and the client
+pub type SharedIngestSource = Arc<dyn IngestSource>;
+
+// LocationStageCallback is called with the location besides the items done by a stage, e.g. to
+// report the progress of each site of an upload of several sites
+pub type LocationStageCallback = Arc<dyn Fn(&str, Stage, usize, Duration) + Send + Sync>;
+
+// LocationDocuments represents the documents read from one location of an upload of several
+// sites, or the error reading them
+pub struct LocationDocuments {
+    pub location: String,
+    pub documents: Result<Vec<Document>, Error>,
+    pub stages: StageProgress,
+}
+
+// RawContent represents an item fetched by an ingest source before it is parsed
+#[derive(Debug, Clone)]
+pub struct RawContent {
+    // url identifies the item, it is the url of the documents parsed from it
+    pub url: String,
+    // content_type is the media type of the bytes, e.g. text/html or application/pdf
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+}
+
+// IngestSource lists, fetches and parses the documents of a location, e.g. a sitemap url, a
+// directory or the space of an internal CMS, implement it and register it with register_source
+// to upload from systems rura doesn't support
+pub trait IngestSource: Send + Sync {
+    // name is the name uploads select the source by
+    fn name(&self) -> &str;
+
+    // list returns the urls of the items of a location
+    fn list<'a>(&'a self, location: &'a str) -> SourceFuture<'a, Vec<String>>;
+
+    // fetch returns the content of an item listed by list
+    fn fetch<'a>(&'a self, url: &'a str) -> SourceFuture<'a, RawContent>;
+
+    // parse returns the documents of a fetched item, e.g. one per release of a changelog
+    fn parse(&self, content: RawContent) -> Result<Vec<Document>, Error>;
+
+    // documents returns the documents of a location and records the fetched and parsed items in
+    // stages, the items are fetched one by one and skipped if they can't be fetched or parsed,
+    // sources override it to fetch concurrently
+    fn documents<'a>(
+        &'a self,
+        location: &'a str,
+        stages: &'a mut StageProgress,
+        on_stage: Option<StageCallback>,
+    ) -> SourceFuture<'a, Vec<Document>> {
+        read_items(self, location, stages, on_stage)
+    }
+}
+
+// read_items returns the documents of a location of the source and records the fetched and
+// parsed items in stages, the items are fetched one by one and skipped if they can't be fetched
+// or parsed, it is the default of IngestSource::documents
+pub fn read_items<'a, S: IngestSource + ?Sized>(
+    source: &'a S,
+    location: &'a str,
+    stages: &'a mut StageProgress,
+    on_stage: Option<StageCallback>,
+) -> SourceFuture<'a, Vec<Document>> {
+    Box::pin(async move {
+        let urls = source.list(location).await?;
+        let start = Instant::now();
+        let mut contents = Vec::new();
+        for url in &urls {
+            match source.fetch(url).await {
+                Ok(content) => contents.push(content),
+                Err(e) => info!("Error fetching {} of {}, skipping it: {}", url, location, e),
+            }
+        }
+        record_stage(
+            stages,
+            &on_stage,
+            Stage::Fetched,
+            contents.len(),
+            start.elapsed(),
+        );
+        let start = Instant::now();
+        let mut failed = urls.len() - contents.len();
+        let mut documents = Vec::new();
+        for content in contents {
+            let url = content.url.clone();
+            match source.parse(content) {
+                Ok(parsed) => documents.extend(parsed),
+                Err(e) => {
+                    info!("Error parsing {} of {}, skipping it: {}", url, location, e);
+                    failed += 1;
+                }
+            }
+        }
+        record_stage(
+            stages,
+            &on_stage,
+            Stage::Parsed,
+            documents.len(),
+            start.elapsed(),
+        );
+        record_stage(
+            stages,
+            &on_stage,
+            Stage::Failed,
+            failed,
+            Duration::default(),
+        );
+        info!(
+            "Read {} documents of {} from {} with {} failures",
+            documents.len(),
+            location,
+            source.name(),
+            failed
+        );
+        Ok(documents)
+    })
+}
+
+// record_stage records the items done by a stage in stages and reports them to on_stage
+fn record_stage(
+    stages: &mut StageProgress,
+    on_stage: &Option<StageCallback>,
+    stage: Stage,
+    count: usize,
+    duration: Duration,
+) {
+    stages.record(stage, count, duration);
+    if let Some(on_stage) = on_stage {
+        on_stage(stage, count, duration);
+    }
+}
+
+// documents_of_locations reads the documents of the locations concurrently, a location failing
+// doesn't stop the others, the results are in the order of the locations
+pub async fn documents_of_locations(
+    source: SharedIngestSource,
+    locations: &[String],
+    on_stage: Option<LocationStageCallback>,
+) -> Vec<LocationDocuments> {
+    let mut tasks = tokio::task::JoinSet::new();
+    for (index, location) in locations.iter().enumerate() {
+        let source = source.clone();
+        let location = location.clone();
+        let on_stage: Option<StageCallback> = on_stage.clone().map(|on_stage| {
+            let location = location.clone();
+            let on_location_stage: StageCallback =
+                Arc::new(move |stage, count, duration| on_stage(&location, stage, count, duration));
+            on_location_stage
+        });
+        let span = info_span!("read_location", location = %location);
+        tasks.spawn(
+            async move {
+                let mut stages = StageProgress::default();
+                let documents = source.documents(&location, &mut stages, on_stage).await;
+                (
+                    index,
+                    LocationDocuments {
+                        location: location,
+                        documents: documents,
+                        stages: stages,
+                    },
+                )
+            }
+            .instrument(span),
+        );
+    }
+    let mut read = Vec::new();
+    while let Some(res) = tasks.join_next().await {
+        match res {
+            Ok(location_documents) => read.push(location_documents),
+            Err(e) => info!("Error reading location: {}", e),
+        }
+    }
+    read.sort_by_key(|(index, _)| *index);
+    read.into_iter()
+        .map(|(_, location_documents)| location_documents)
+        .collect()
+}
+
+// register_source makes the source available to uploads by its name, replacing a source of the
+// same name, e.g. to change the options of a built-in source
+pub fn register_source(source: SharedIngestSource) {
+    let name = source.name().to_string();
+    match sources().write().unwrap().insert(name.clone(), source) {
+        Some(_) => info!("Replaced ingest source {}", name),
+        None => info!("Registered ingest source {}", name),
+    }
+}
+
+// ingest_source returns the registered source of the name
+pub fn ingest_source(name: &str) -> Option<SharedIngestSource> {
+    sources().read().unwrap().get(name).cloned()
+}
+
+// ingest_source_names returns the names of the registered sources in alphabetical order
+pub fn ingest_source_names() -> Vec<String> {
+    let mut names: Vec<String> = sources().read().unwrap().keys().cloned().collect();
+    names.sort();
+    names
+}
+
+// sources returns the registered sources, the built-in sources are registered on first use
+fn sources() -> &'static RwLock<HashMap<String, SharedIngestSource>> {
+    INGEST_SOURCES.get_or_init(|| {
+        let built_ins: Vec<SharedIngestSource> = vec![
+            Arc::new(SitemapSource::default()),
+            Arc::new(CrawlerSource::default()),
+            Arc::new(FileSource),
+        ];
+        RwLock::new(
+            built_ins
+                .into_iter()
+                .map(|source| (source.name().to_string(), source))
+                .collect(),
+        )
+    })
+}
+
+// fetch_url returns the content of a url fetched with the client
+async fn fetch_url(client: &reqwest::Client, url: &str) -> Result<RawContent, Error> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    Ok(RawContent {
+        url: url.to_string(),
+        content_type: content_type,
+        bytes: response.bytes().await?.to_vec(),
+    })
+}
+
+// SitemapSource reads the pages of a sitemap.xml, the location is the url of the site or its
+// sitemap.xml
+#[derive(Debug, Clone, Default)]
+pub struct SitemapSource {
+    options: SitemapOptions,
+}
+
+impl SitemapSource {
+    // new returns a sitemap source crawling with the options
+    pub fn new(options: SitemapOptions) -> Self {
+        SitemapSource { options: options }
+    }
+}
+
+impl IngestSource for SitemapSource {
+    fn name(&self) -> &str {
+        SITEMAP_SOURCE
+    }
+
+    fn list<'a>(&'a self, location: &'a str) -> SourceFuture<'a, Vec<String>> {
+        Box::pin(async move {
+            let client = crawl_client(&self.options.profile)?;
+            sitemap_urls(&client, location, &self.options).await
+        })
+    }
+
+    fn fetch<'a>(&'a self, url: &'a str) -> SourceFuture<'a, RawContent> {
+        Box::pin(async move { fetch_url(&crawl_client(&self.options.profile)?, url).await })
+    }
+
+    fn parse(&self, content: RawContent) -> Result<Vec<Document>, Error> {
+        parse_body(
+            content.url,
+            &content.content_type,
+            content.bytes,
+            &self.options.profile,
+        )
+    }
+
+    // documents crawls concurrently, honoring the robots.txt and the crawl delay of the options
+    fn documents<'a>(
+        &'a self,
+        location: &'a str,
+        stages: &'a mut StageProgress,
+        on_stage: Option<StageCallback>,
+    ) -> SourceFuture<'a, Vec<Document>> {
+        Box::pin(sitemap_with_callback(
+            location,
+            &self.options,
+            stages,
+            on_stage,
+        ))
+    }
+}
+
+// CrawlerSource reads a single page, the location is the url of the page
+#[derive(Debug, Clone, Default)]
+pub struct CrawlerSource {
+    profile: CrawlProfile,
+}
+
+impl CrawlerSource {
+    // new returns a crawler source fetching and parsing with the profile
+    pub fn new(profile: CrawlProfile) -> Self {
+        CrawlerSource { profile: profile }
+    }
+}
+
+impl IngestSource for CrawlerSource {
+    fn name(&self) -> &str {
+        CRAWLER_SOURCE
+    }
+
+    fn list<'a>(&'a self, location: &'a str) -> SourceFuture<'a, Vec<String>> {
+        Box::pin(async move { Ok(vec![location.to_string()]) })
+    }
+
+    fn fetch<'a>(&'a self, url: &'a str) -> SourceFuture<'a, RawContent> {
+        Box::pin(async move { fetch_url(&crawl_client(&self.profile)?, url).await })
+    }
+
+    fn parse(&self, content: RawContent) -> Result<Vec<Document>, Error> {
+        parse_body(
+            content.url,
+            &content.content_type,
+            content.bytes,
+            &self.profile,
+        )
+    }
+
+    // documents crawls the further pages of a paginated listing if the profile enables it,
+    // honoring the robots.txt, the single page is read as an item otherwise
+    fn documents<'a>(
+        &'a self,
+        location: &'a str,
+        stages: &'a mut StageProgress,
+        on_stage: Option<StageCallback>,
+    ) -> SourceFuture<'a, Vec<Document>> {
+        if !self.profile.follow_pagination.unwrap_or(false) {
+            return read_items(self, location, stages, on_stage);
+        }
+        Box::pin(async move {
+            let options = SitemapOptions {
+                profile: self.profile.clone(),
+                ..SitemapOptions::default()
+            };
+            let client = crawl_client(&self.profile)?;
+            pages_with_callback(
+                &client,
+                vec![location.to_string()],
+                &options,
+                stages,
+                on_stage,
+            )
+            .await
+        })
+    }
+}
+
+// FileSource reads the markdown, text and restructuredtext files below a directory, the
+// location is the path of the directory and the file path is the url of a document
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSource;
+
+impl IngestSource for FileSource {
+    fn name(&self) -> &str {
+        FILE_SOURCE
+    }
+
+    fn list<'a>(&'a self, location: &'a str) -> SourceFuture<'a, Vec<String>> {
+        Box::pin(async move {
+            Ok(text_files(Path::new(location))?
+                .into_iter()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect())
+        })
+    }
+
+    fn fetch<'a>(&'a self, url: &'a str) -> SourceFuture<'a, RawContent> {
+        Box::pin(async move {
+            Ok(RawContent {
+                url: url.to_string(),
+                content_type: "text/plain".to_string(),
+                bytes: tokio::fs::read(url).await?,
+            })
+        })
+    }
+
+    fn parse(&self, content: RawContent) -> Result<Vec<Document>, Error> {
+        let text = String::from_utf8_lossy(&content.bytes);
+        let document = text_document(Path::new(&content.url), content.url.clone(), &text)?;
+        Ok(document.into_iter().collect())
+    }
+}
@@ -0,0 +1,154 @@
+use crate::data::{Collection, EmbeddedMetadata, ScoredDocument};
+use crate::qdrant::{collection_exists, collection_limit, join_parents, rescore, SearchFilter};
+use crate::retry::retry_qdrant;
+use crate::telemetry::{record_duration, record_keyword_fallback, record_skipped_point};
+use anyhow::Result;
+use log::{error, info};
+use qdrant_client::prelude::*;
+use qdrant_client::qdrant::r#match::MatchValue;
+use qdrant_client::qdrant::{Condition, Filter, ScrollPoints};
+use std::collections::HashSet;
+use std::time::Instant;
+
+// MIN_TERM_CHARS is the minimum number of characters of a query term, shorter words like "a" or
+// "is" match almost every fragment
+static MIN_TERM_CHARS: usize = 3;
+// CANDIDATE_FACTOR is the number of fragments matching any term fetched per result, ranked by
+// their term matches
+static CANDIDATE_FACTOR: u32 = 10;
+// TITLE_WEIGHT is the weight of a term matching the title relative to a term matching the text
+static TITLE_WEIGHT: f32 = 0.5;
+
+// keyword_search searches for documents matching the terms of the query in their text, without
+// embeddings, so searches keep working while the embedding backend is unavailable, the score is
+// the weighted share of the query terms found in the text and title of a fragment
+#[tracing::instrument(skip(client, filter_by_collections))]
+pub async fn keyword_search(
+    client: &QdrantClient,
+    base_collection: &str,
+    filter_by_collections: Vec<Collection>,
+    query: &str,
+    limit: u64,
+    filter: &SearchFilter,
+) -> Result<Vec<ScoredDocument>> {
+    record_keyword_fallback();
+    let terms = query_terms(query);
+    if terms.is_empty() {
+        info!("No keyword search terms in query: {}", query);
+        return Ok(Vec::new());
+    }
+    let total_collections = filter_by_collections.len();
+    let mut results = Vec::new();
+    for filter_collection in filter_by_collections {
+        let collection_limit = collection_limit(limit, total_collections, filter_collection);
+        let mut collection_results = keyword_search_collection(
+            client,
+            base_collection,
+            filter_collection,
+            &terms,
+            collection_limit,
+            filter,
+        )
+        .await?;
+        rescore(&mut collection_results);
+        collection_results.truncate(collection_limit as usize);
+        results.extend(collection_results);
+    }
+    Ok(join_parents(results))
+}
+
+// keyword_search_collection returns the fragments of a collection matching any of the terms,
+// matched by the full text index of the text, ranked by their term matches
+async fn keyword_search_collection(
+    client: &QdrantClient,
+    base_collection: &str,
+    collection: Collection,
+    terms: &[String],
+    limit: u64,
+    filter: &SearchFilter,
+) -> Result<Vec<ScoredDocument>> {
+    let collection_name = format!("{}_{}", base_collection, collection.to_string());
+    if !collection_exists(client, &collection_name).await? {
+        return Err(anyhow::anyhow!(
+            "Collection: {} does not exist",
+            collection_name
+        ));
+    }
+    info!(
+        "Keyword searching collection: {} with terms: {:?}",
+        collection_name, terms
+    );
+    let search_start = Instant::now();
+    let mut keyword_filter = filter.to_filter().unwrap_or_default();
+    keyword_filter.should = terms
+        .iter()
+        .map(|term| Condition::matches("text", MatchValue::Text(term.clone())))
+        .collect();
+    let scroll_points = ScrollPoints {
+        collection_name: collection_name.clone(),
+        filter: Some(keyword_filter),
+        limit: Some(limit as u32 * CANDIDATE_FACTOR),
+        with_payload: Some(true.into()),
+        ..Default::default()
+    };
+    let scroll_result = retry_qdrant("scroll", || client.scroll(&scroll_points)).await?;
+    record_duration("keyword_search", search_start.elapsed());
+    let mut results = Vec::new();
+    for point in scroll_result.result {
+        let metadata_json = serde_json::to_value(&point.payload)?;
+        let metadata = match EmbeddedMetadata::from_payload(metadata_json) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                error!(
+                    "Skipping point {:?} of {}: {}",
+                    point.id, collection_name, e
+                );
+                record_skipped_point(&collection_name);
+                continue;
+            }
+        };
+        if !filter.matches(&metadata) {
+            continue;
+        }
+        let score = keyword_score(&metadata, terms);
+        results.push(ScoredDocument {
+            metadata: metadata,
+            score: score,
+        });
+    }
+    Ok(results)
+}
+
+// keyword_score returns the share of the terms found in the text, terms found in the title add
+// TITLE_WEIGHT, normalized to 1 for fragments matching all terms in text and title
+fn keyword_score(metadata: &EmbeddedMetadata, terms: &[String]) -> f32 {
+    let text = metadata.text.to_lowercase();
+    let title = metadata.title.to_lowercase();
+    let matches: f32 = terms
+        .iter()
+        .map(|term| {
+            let text_match = match text.contains(term.as_str()) {
+                true => 1.0,
+                false => 0.0,
+            };
+            let title_match = match title.contains(term.as_str()) {
+                true => TITLE_WEIGHT,
+                false => 0.0,
+            };
+            text_match + title_match
+        })
+        .sum();
+    matches / (terms.len() as f32 * (1.0 + TITLE_WEIGHT))
+}
+
+// query_terms returns the distinct lowercase words of a query with at least MIN_TERM_CHARS
+// characters, punctuation is stripped
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    query
+        .split(|c: char| !c.is_alphanumeric() && c != '-' && c != '_')
+        .map(|term| term.trim_matches('-').to_lowercase())
+        .filter(|term| term.chars().count() >= MIN_TERM_CHARS)
+        .filter(|term| seen.insert(term.clone()))
+        .collect()
+}
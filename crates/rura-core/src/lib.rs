@@ -0,0 +1,50 @@
+pub mod agent;
+pub mod answer;
+pub mod chat;
+pub mod classify;
+pub mod collection_model;
+pub mod config;
+pub mod control;
+pub mod data;
+pub mod debug;
+pub mod dedup;
+pub mod embedding;
+pub mod embedding_cache;
+pub mod embedding_provider;
+pub mod estimate;
+pub mod eval;
+pub mod faq;
+pub mod forum;
+pub mod git;
+pub mod glossary;
+pub mod help_center;
+pub mod highlight;
+pub mod ingest;
+pub mod ingest_source;
+pub mod keyword;
+pub mod migrate;
+pub mod mirror;
+pub mod ollama;
+pub mod pipeline;
+pub mod progress_store;
+pub mod progress_tracker;
+pub mod promote;
+pub mod prompt_log;
+pub mod qa_dump;
+pub mod qdrant;
+pub mod query;
+pub mod readability;
+pub mod release_notes;
+pub mod report;
+pub mod rerank;
+pub mod retrieval_hook;
+pub mod retriever;
+pub mod retry;
+pub mod robots;
+pub mod scheduler;
+pub mod spelling;
+pub mod summary;
+pub mod telemetry;
+pub mod tiering;
+pub mod transform;
+pub mod wizard;
@@ -0,0 +1,146 @@
+use crate::data::{Collection, EmbeddedMetadata, PAYLOAD_SCHEMA_VERSION};
+use crate::mirror::{mirror, mirror_write};
+use crate::retry::retry_qdrant;
+use anyhow::{Error, Result};
+use log::{error, info};
+use qdrant_client::prelude::*;
+use qdrant_client::qdrant::point_id::PointIdOptions;
+use qdrant_client::qdrant::points_selector::PointsSelectorOneOf;
+use qdrant_client::qdrant::{PointId, PointsIdsList, PointsSelector, ScrollPoints};
+use serde_json::json;
+
+// MIGRATION_SCROLL_LIMIT is the number of points read per scroll request
+static MIGRATION_SCROLL_LIMIT: u32 = 256;
+
+// MigrationReport represents the outcome of the migration of a collection
+#[derive(Debug, Clone, Default)]
+pub struct MigrationReport {
+    pub collection: String,
+    // scanned is the number of points read
+    pub scanned: usize,
+    // migrated is the number of points whose payload was backfilled, or would be on dry runs
+    pub migrated: usize,
+    // failed are the ids of the points whose payload can't be read, e.g. without url or text,
+    // they are skipped by searches and have to be uploaded again
+    pub failed: Vec<String>,
+}
+
+// migrate_collections backfills the payloads of the points of the collections written with an
+// older schema version to PAYLOAD_SCHEMA_VERSION, dry runs only count the points to migrate
+pub async fn migrate_collections(
+    client: &QdrantClient,
+    base_collection: &str,
+    collections: Vec<Collection>,
+    dry_run: bool,
+) -> Result<Vec<MigrationReport>, Error> {
+    let mut reports = Vec::new();
+    for collection in collections {
+        let collection_name = format!("{}_{}", base_collection, collection.to_string());
+        if !retry_qdrant("has_collection", || client.has_collection(&collection_name)).await? {
+            info!("Collection: {} does not exist", collection_name);
+            continue;
+        }
+        let report = migrate_collection(client, collection_name, dry_run).await?;
+        info!(
+            "Migrated collection {} to schema version {}: {} of {} points, {} failed",
+            report.collection,
+            PAYLOAD_SCHEMA_VERSION,
+            report.migrated,
+            report.scanned,
+            report.failed.len()
+        );
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+// migrate_collection scrolls all points of a collection and sets the upgraded payload of the
+// points of an older schema version, the vectors are kept
+async fn migrate_collection(
+    client: &QdrantClient,
+    collection_name: String,
+    dry_run: bool,
+) -> Result<MigrationReport, Error> {
+    let mut report = MigrationReport {
+        collection: collection_name.clone(),
+        ..MigrationReport::default()
+    };
+    let mut offset: Option<PointId> = None;
+    loop {
+        let scroll_points = ScrollPoints {
+            collection_name: collection_name.clone(),
+            filter: None,
+            offset: offset,
+            limit: Some(MIGRATION_SCROLL_LIMIT),
+            with_payload: Some(true.into()),
+            ..Default::default()
+        };
+        let scroll_result = retry_qdrant("scroll", || client.scroll(&scroll_points)).await?;
+        for point in scroll_result.result {
+            report.scanned += 1;
+            let point_id = match point.id {
+                Some(point_id) => point_id,
+                None => continue,
+            };
+            let payload_json = serde_json::to_value(&point.payload)?;
+            let mut metadata: EmbeddedMetadata = match serde_json::from_value(payload_json) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    error!("Error reading payload of {:?}: {}", point_id, e);
+                    report.failed.push(point_id_string(&point_id));
+                    continue;
+                }
+            };
+            if !metadata.upgrade() {
+                continue;
+            }
+            report.migrated += 1;
+            if dry_run {
+                continue;
+            }
+            set_payload(client, &collection_name, point_id, &metadata).await?;
+        }
+        offset = scroll_result.next_page_offset;
+        if offset.is_none() {
+            break;
+        }
+    }
+    Ok(report)
+}
+
+// set_payload sets the payload of a point to the metadata, on the mirror as well if enabled
+async fn set_payload(
+    client: &QdrantClient,
+    collection_name: &str,
+    point_id: PointId,
+    metadata: &EmbeddedMetadata,
+) -> Result<(), Error> {
+    let payload: Payload = json!(metadata)
+        .try_into()
+        .map_err(|e| anyhow::anyhow!("Error converting payload: {}", e))?;
+    let points_selector = PointsSelector {
+        points_selector_one_of: Some(PointsSelectorOneOf::Points(PointsIdsList {
+            ids: vec![point_id],
+        })),
+    };
+    retry_qdrant("set_payload", || {
+        client.set_payload(collection_name, &points_selector, payload.clone(), None)
+    })
+    .await?;
+    if let Some(mirror) = mirror() {
+        mirror_write("set_payload", || {
+            mirror.set_payload(collection_name, &points_selector, payload.clone(), None)
+        })
+        .await;
+    }
+    Ok(())
+}
+
+// point_id_string returns the uuid or number of a point id
+fn point_id_string(point_id: &PointId) -> String {
+    match &point_id.point_id_options {
+        Some(PointIdOptions::Uuid(uuid)) => uuid.clone(),
+        Some(PointIdOptions::Num(num)) => num.to_string(),
+        None => String::new(),
+    }
+}
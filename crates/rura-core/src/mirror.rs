@@ -0,0 +1,126 @@
+use crate::data::Collection;
+use crate::qdrant::get_payload_hashes;
+use crate::retry::{retry, retry_policy, CircuitBreaker};
+use crate::telemetry::record_mirror_failure;
+use anyhow::{Error, Result};
+use log::info;
+use qdrant_client::client::QdrantClient;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::OnceLock;
+
+// MIRROR is the secondary qdrant the upserts and deletes are mirrored to, unset unless dual write
+// is enabled
+static MIRROR: OnceLock<QdrantClient> = OnceLock::new();
+// MIRROR_BREAKER is the circuit breaker of the mirrored calls, kept apart from the breaker of the
+// primary so an unavailable mirror doesn't fail the calls of the primary fast
+static MIRROR_BREAKER: OnceLock<CircuitBreaker> = OnceLock::new();
+
+// enable mirrors the upserts and deletes to the client from now on
+pub fn enable(client: QdrantClient) {
+    match MIRROR.set(client) {
+        Ok(()) => info!("Mirroring writes to the secondary qdrant"),
+        Err(_) => info!("Mirror already enabled, keeping the first one"),
+    }
+}
+
+// mirror returns the client of the secondary qdrant if dual write is enabled
+pub fn mirror() -> Option<&'static QdrantClient> {
+    MIRROR.get()
+}
+
+// mirror_write runs a write against the mirror with retries, failures are logged and counted but
+// don't fail the write of the primary, check_consistency finds the writes the mirror missed
+pub async fn mirror_write<T, F, Fut>(operation: &'static str, call: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let breaker = MIRROR_BREAKER.get_or_init(CircuitBreaker::default);
+    if let Err(e) = retry("mirror", operation, retry_policy(), breaker, call).await {
+        info!("Error mirroring {}: {}", operation, e);
+        record_mirror_failure(operation);
+    }
+}
+
+// MirrorDiff represents the differences of a collection between the primary and the mirror
+#[derive(Debug, Clone, Default)]
+pub struct MirrorDiff {
+    pub collection: String,
+    pub primary_points: usize,
+    pub secondary_points: usize,
+    // missing_in_secondary are the ids of the points only stored by the primary
+    pub missing_in_secondary: Vec<String>,
+    // missing_in_primary are the ids of the points only stored by the mirror
+    pub missing_in_primary: Vec<String>,
+    // differing are the ids of the points whose payload differs
+    pub differing: Vec<String>,
+}
+
+impl MirrorDiff {
+    // is_consistent returns true if both stores have the same points with the same payloads
+    pub fn is_consistent(&self) -> bool {
+        self.missing_in_secondary.is_empty()
+            && self.missing_in_primary.is_empty()
+            && self.differing.is_empty()
+    }
+}
+
+// check_consistency diffs the points of the collections of the primary and the mirror by id and
+// payload hash, the vectors are not compared as they are derived from the payload text
+pub async fn check_consistency(
+    primary: &QdrantClient,
+    secondary: &QdrantClient,
+    base_collection: &str,
+    collections: Vec<Collection>,
+) -> Result<Vec<MirrorDiff>, Error> {
+    let mut diffs = Vec::new();
+    for collection in collections {
+        let primary_hashes = get_payload_hashes(primary, base_collection, collection).await?;
+        let secondary_hashes = get_payload_hashes(secondary, base_collection, collection).await?;
+        let diff = diff_hashes(
+            format!("{}_{}", base_collection, collection.to_string()),
+            &primary_hashes,
+            &secondary_hashes,
+        );
+        info!(
+            "Checked collection {}: {} missing in the mirror, {} missing in the primary, {} differing",
+            diff.collection,
+            diff.missing_in_secondary.len(),
+            diff.missing_in_primary.len(),
+            diff.differing.len()
+        );
+        diffs.push(diff);
+    }
+    Ok(diffs)
+}
+
+// diff_hashes diffs the payload hashes by point id of the primary and the mirror
+pub fn diff_hashes(
+    collection: String,
+    primary: &HashMap<String, String>,
+    secondary: &HashMap<String, String>,
+) -> MirrorDiff {
+    let mut diff = MirrorDiff {
+        collection: collection,
+        primary_points: primary.len(),
+        secondary_points: secondary.len(),
+        ..MirrorDiff::default()
+    };
+    for (id, hash) in primary {
+        match secondary.get(id) {
+            Some(secondary_hash) if secondary_hash != hash => diff.differing.push(id.clone()),
+            Some(_) => continue,
+            None => diff.missing_in_secondary.push(id.clone()),
+        }
+    }
+    diff.missing_in_primary = secondary
+        .keys()
+        .filter(|id| !primary.contains_key(*id))
+        .cloned()
+        .collect();
+    diff.missing_in_secondary.sort();
+    diff.missing_in_primary.sort();
+    diff.differing.sort();
+    diff
+}
@@ -0,0 +1,479 @@
+use crate::prompt_log::PromptLogger;
+use crate::retry::retry_ollama;
+use crate::telemetry::{record_duration, record_generated_tokens};
+use log::{debug, info};
+use ollama_rs::{
+    generation::{
+        completion::{
+            request::GenerationRequest, GenerationFinalResponseData, GenerationResponseStream,
+        },
+        options::GenerationOptions,
+    },
+    Ollama,
+};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+use text_splitter::TextSplitter;
+use tokio::io::{stdout, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+
+// SUMMARY_CHUNK_SIZE is the maximum number of characters summarized in a single request
+static SUMMARY_CHUNK_SIZE: usize = 8192;
+// MAX_SUMMARY_ROUNDS is the maximum number of map-reduce rounds for long documents
+static MAX_SUMMARY_ROUNDS: usize = 3;
+// MAX_QUESTIONS is the maximum number of questions generated per fragment
+pub static MAX_QUESTIONS: usize = 5;
+
+// SPECULATIVE_BUFFER_SIZE is the number of draft parts buffered for slow receivers
+static SPECULATIVE_BUFFER_SIZE: usize = 64;
+
+// TextStream is a stream of generated text parts
+pub type TextStream = Pin<Box<dyn Stream<Item = String> + Send>>;
+
+// SpeculativeEvent represents a part of a speculative generation
+#[derive(Debug, Clone)]
+pub enum SpeculativeEvent {
+    // Draft is the next part of the answer of the fast model
+    Draft(String),
+    // Final is the whole answer of the quality model replacing the draft
+    Final(String),
+}
+
+// SpeculativeStream is a stream of draft parts followed by the final answer
+pub type SpeculativeStream = Pin<Box<dyn Stream<Item = SpeculativeEvent> + Send>>;
+
+// TokenCallback is called with the number of tokens generated since the last call
+pub type TokenCallback = Arc<dyn Fn(usize) + Send + Sync>;
+
+// Llm is a wrapper around the Ollama client
+#[derive(Clone)]
+pub struct Llm {
+    ollama: Ollama,
+    prompt_logger: Option<Arc<PromptLogger>>,
+    on_tokens: Option<TokenCallback>,
+    // max_tokens limits the number of generated tokens, None uses the model default
+    max_tokens: Option<i32>,
+    // request_id is the id of the query the generations belong to, logged with the prompts
+    request_id: Option<String>,
+}
+
+impl Llm {
+    // new creates a new Llm
+    pub fn new(ollama: Ollama) -> Self {
+        Llm {
+            ollama: ollama,
+            prompt_logger: None,
+            on_tokens: None,
+            max_tokens: None,
+            request_id: None,
+        }
+    }
+
+    // with_prompt_logger logs the prompts and completions with the given logger
+    pub fn with_prompt_logger(mut self, prompt_logger: Option<Arc<PromptLogger>>) -> Self {
+        self.prompt_logger = prompt_logger;
+        self
+    }
+
+    // with_token_callback reports the generated tokens while generating, e.g. to track the
+    // progress of a job, the text is then generated with a stream
+    pub fn with_token_callback(mut self, on_tokens: TokenCallback) -> Self {
+        self.on_tokens = Some(on_tokens);
+        self
+    }
+
+    // with_max_tokens limits the number of tokens generated per request, e.g. the maximum of
+    // an answer style
+    pub fn with_max_tokens(mut self, max_tokens: Option<i32>) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    // with_request_id tags the logged prompts with the id of the query, e.g. the id returned to
+    // the caller of the api
+    pub fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    // request returns the generation request of a prompt with the maximum number of tokens and
+    // the temperature, the model defaults are used if None
+    fn request(&self, model: &str, prompt: &str, temperature: Option<f32>) -> GenerationRequest {
+        let request = GenerationRequest::new(model.to_string(), prompt.to_string());
+        if temperature.is_none() && self.max_tokens.is_none() {
+            return request;
+        }
+        let mut options = GenerationOptions::default();
+        if let Some(temperature) = temperature {
+            options = options.temperature(temperature);
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            options = options.num_predict(max_tokens);
+        }
+        request.options(options)
+    }
+
+    // generate generates text from a prompt
+    pub async fn generate(&self, model: &str, prompt: &str) -> Result<String, anyhow::Error> {
+        self.generate_with_temperature(model, prompt, None).await
+    }
+
+    // generate_with_temperature generates text from a prompt, the model default temperature is
+    // used if temperature is None, transient errors are retried with backoff and generations
+    // fail fast while the ollama circuit breaker is open
+    #[tracing::instrument(skip(self, prompt))]
+    pub async fn generate_with_temperature(
+        &self,
+        model: &str,
+        prompt: &str,
+        temperature: Option<f32>,
+    ) -> Result<String, anyhow::Error> {
+        let start = Instant::now();
+        let res = retry_ollama("generate", || {
+            self.generate_once(model, prompt, temperature)
+        })
+        .await;
+        record_duration("generate", start.elapsed());
+        let response = res?;
+        if let Some(prompt_logger) = &self.prompt_logger {
+            prompt_logger
+                .log_or_error(model, prompt, &response, self.request_id.as_deref())
+                .await;
+        }
+        Ok(response)
+    }
+
+    // generate_once generates text from a prompt without retries
+    async fn generate_once(
+        &self,
+        model: &str,
+        prompt: &str,
+        temperature: Option<f32>,
+    ) -> Result<String, anyhow::Error> {
+        let request = self.request(model, prompt, temperature);
+        match &self.on_tokens {
+            Some(on_tokens) => {
+                self.generate_counting(model, request, on_tokens.as_ref())
+                    .await
+            }
+            None => self
+                .ollama
+                .generate(request)
+                .await
+                .map(|res| {
+                    record_tokens(model, &res.final_data);
+                    res.response
+                })
+                .map_err(|e| anyhow::anyhow!("Error generating text: {}", e)),
+        }
+    }
+
+    // generate_counting generates text with a stream, each part of the stream is a token
+    async fn generate_counting(
+        &self,
+        model: &str,
+        request: GenerationRequest,
+        on_tokens: &(dyn Fn(usize) + Send + Sync),
+    ) -> Result<String, anyhow::Error> {
+        let mut stream: GenerationResponseStream = self
+            .ollama
+            .generate_stream(request)
+            .await
+            .map_err(|e| anyhow::anyhow!("Error generating text: {}", e))?;
+        let mut completion = String::new();
+        while let Some(res) = stream.next().await {
+            let res =
+                res.map_err(|_| anyhow::anyhow!("Error generating text: invalid response"))?;
+            completion.push_str(&res.response);
+            record_tokens(model, &res.final_data);
+            on_tokens(1);
+        }
+        Ok(completion)
+    }
+
+    // generate_stream generates a stream of text currently hardwired to stdout from a prompt, an
+    // error of the stream fails the generation after the text printed so far
+    pub async fn generate_stream(&self, model: &str, prompt: &str) -> Result<(), anyhow::Error> {
+        let mut stream: GenerationResponseStream = self
+            .ollama
+            .generate_stream(self.request(model, prompt, None))
+            .await?;
+        let mut stdout = stdout();
+        let mut completion = String::new();
+        while let Some(res) = stream.next().await {
+            let res = res.map_err(|e| anyhow::anyhow!("Error streaming generation: {:?}", e))?;
+            stdout.write_all(res.response.as_bytes()).await?;
+            stdout.flush().await?;
+            completion.push_str(&res.response);
+            record_tokens(model, &res.final_data);
+        }
+        if let Some(prompt_logger) = &self.prompt_logger {
+            prompt_logger
+                .log_or_error(model, prompt, &completion, self.request_id.as_deref())
+                .await;
+        }
+        Ok(())
+    }
+
+    // generate_text_stream generates a stream of text parts from a prompt, the errors of the
+    // stream are logged and their parts skipped
+    pub async fn generate_text_stream(
+        &self,
+        model: &str,
+        prompt: &str,
+    ) -> Result<TextStream, anyhow::Error> {
+        let stream: GenerationResponseStream = self
+            .ollama
+            .generate_stream(self.request(model, prompt, None))
+            .await?;
+        let model = model.to_string();
+        Ok(Box::pin(stream.filter_map(move |res| match res {
+            Ok(res) => {
+                record_tokens(&model, &res.final_data);
+                Some(res.response)
+            }
+            Err(e) => {
+                info!("Error streaming generation of {}: {:?}", model, e);
+                None
+            }
+        })))
+    }
+
+    // generate_speculative fires the fast and the quality model at once, the draft of the fast
+    // model is streamed until the answer of the quality model is ready, if the quality model
+    // fails the draft is kept
+    pub async fn generate_speculative(
+        &self,
+        fast_model: &str,
+        model: &str,
+        prompt: &str,
+    ) -> Result<SpeculativeStream, anyhow::Error> {
+        let mut draft = self.generate_text_stream(fast_model, prompt).await?;
+        let (tx, rx) = mpsc::channel(SPECULATIVE_BUFFER_SIZE);
+        let llm = self.clone();
+        let model = model.to_string();
+        let prompt = prompt.to_string();
+        tokio::spawn(async move {
+            let quality = llm.generate(&model, &prompt);
+            tokio::pin!(quality);
+            let mut draft_done = false;
+            loop {
+                tokio::select! {
+                    // the receiver is gone, e.g. the client disconnected, both generations stop
+                    _ = tx.closed() => {
+                        info!("Speculative answer dropped, stopping the generations");
+                        return;
+                    }
+                    res = &mut quality => {
+                        match res {
+                            Ok(answer) => {
+                                let _ = tx.send(SpeculativeEvent::Final(answer)).await;
+                            }
+                            Err(e) => info!("Error generating answer, keeping draft: {}", e),
+                        }
+                        return;
+                    }
+                    part = draft.next(), if !draft_done => match part {
+                        Some(text) => {
+                            // the receiver is gone, e.g. the client disconnected
+                            if tx.send(SpeculativeEvent::Draft(text)).await.is_err() {
+                                return;
+                            }
+                        }
+                        None => draft_done = true,
+                    },
+                }
+            }
+        });
+        Ok(Box::pin(ReceiverStream::new(rx)))
+    }
+
+    // summarize summarizes a text, long texts are summarized chunk wise (map) and the
+    // chunk summaries are summarized again (reduce) until they fit into a single request
+    #[tracing::instrument(skip(self, text), fields(chars = text.len()))]
+    pub async fn summarize(&self, model: &str, text: &str) -> Result<String, anyhow::Error> {
+        let splitter = TextSplitter::default().with_trim_chunks(true);
+        let mut text = text.to_string();
+        let mut round = 0;
+        while text.chars().count() > SUMMARY_CHUNK_SIZE && round < MAX_SUMMARY_ROUNDS {
+            let chunks: Vec<String> = splitter
+                .chunks(&text, SUMMARY_CHUNK_SIZE)
+                .map(|chunk| chunk.to_string())
+                .collect();
+            if chunks.len() <= 1 {
+                break;
+            }
+            info!(
+                "Summarizing long text in {} chunks, round: {}",
+                chunks.len(),
+                round
+            );
+            let mut summaries = Vec::new();
+            for chunk in chunks {
+                summaries.push(self.summarize_chunk(model, &chunk).await?);
+            }
+            text = summaries.join("\n");
+            round += 1;
+        }
+        self.summarize_chunk(model, &text).await
+    }
+
+    // summarize_chunk summarizes a text in a single request
+    async fn summarize_chunk(&self, model: &str, text: &str) -> Result<String, anyhow::Error> {
+        let formatted_prompt = PROMPT_SUMMARY.replace("{context}", text);
+        debug!("Formatted summary prompt: {}", formatted_prompt);
+        self.generate(model, &formatted_prompt).await
+    }
+
+    // questions generates the questions a text answers, one per line of the reply, list markers
+    // are removed and at most MAX_QUESTIONS are returned
+    #[tracing::instrument(skip(self, text), fields(chars = text.len()))]
+    pub async fn questions(&self, model: &str, text: &str) -> Result<Vec<String>, anyhow::Error> {
+        let formatted_prompt = PROMPT_QUESTIONS
+            .replace("{max_questions}", &MAX_QUESTIONS.to_string())
+            .replace("{context}", text);
+        debug!("Formatted questions prompt: {}", formatted_prompt);
+        let reply = self.generate(model, &formatted_prompt).await?;
+        Ok(reply
+            .lines()
+            .map(|line| {
+                line.trim()
+                    .trim_start_matches(|c: char| c.is_ascii_digit() || "-*.) ".contains(c))
+                    .trim()
+            })
+            .filter(|line| line.ends_with('?'))
+            .take(MAX_QUESTIONS)
+            .map(str::to_string)
+            .collect())
+    }
+
+    // translate translates a text to the language, the paragraphs are translated in chunks of
+    // up to SUMMARY_CHUNK_SIZE characters and joined again, so the headings stay paragraphs
+    #[tracing::instrument(skip(self, text), fields(chars = text.len()))]
+    pub async fn translate(
+        &self,
+        model: &str,
+        text: &str,
+        language: &str,
+    ) -> Result<String, anyhow::Error> {
+        let mut chunks: Vec<String> = Vec::new();
+        for paragraph in text.split("\n\n") {
+            match chunks.last_mut() {
+                Some(chunk)
+                    if chunk.chars().count() + paragraph.chars().count() < SUMMARY_CHUNK_SIZE =>
+                {
+                    chunk.push_str("\n\n");
+                    chunk.push_str(paragraph);
+                }
+                _ => chunks.push(paragraph.to_string()),
+            }
+        }
+        let mut translations = Vec::new();
+        for chunk in chunks {
+            let formatted_prompt = PROMPT_TRANSLATE
+                .replace("{language}", language)
+                .replace("{context}", &chunk);
+            debug!("Formatted translation prompt: {}", formatted_prompt);
+            translations.push(
+                self.generate(model, &formatted_prompt)
+                    .await?
+                    .trim()
+                    .to_string(),
+            );
+        }
+        Ok(translations.join("\n\n"))
+    }
+}
+
+// record_tokens records the prompt and completion tokens of a generation, only the final response
+// of a generation carries them
+fn record_tokens(model: &str, final_data: &Option<GenerationFinalResponseData>) {
+    if let Some(final_data) = final_data {
+        record_generated_tokens(
+            model,
+            final_data.prompt_eval_count as u64,
+            final_data.eval_count as u64,
+        );
+    }
+}
+
+pub static PROMPT: &str = r#"You are a customer support agent, programmed to offer highly accurate and helpful assistance. Your responses should be strictly based on factual information, presented in a friendly yet concise manner. Utilize only the context information provided below, without drawing on any prior knowledge. Your goal is to address the query directly and efficiently, ensuring clarity and relevance in your answer.{style}{citations}
+{glossary}Context:
+{context}
+
+Question: {question}
+Helpful answer thats includes a heading derived from the question:"#;
+
+//pub static PROMPT_SUMMARY: &str = r#"You are an advanced summarization agent, your objective is to craft a succinct and precise summary using only the context information given. Your approach should center on extracting and condensing the critical elements and core details into a brief and clear format. Avoid referencing the creation of a summary in your output or stating that it's a summary.
+pub static PROMPT_SUMMARY: &str = r#"Your role as an advanced summarization agent involves distilling the provided context information into a concise and precise format. Emphasize extracting and synthesizing the main points and critical details, presenting them in a clear, compact form. In your output, seamlessly integrate these key elements without explicitly labeling the output as a summary or indicating the summarization process.
+Context:
+{context}
+"#;
+
+pub static PROMPT_QUESTIONS: &str = r#"Write up to {max_questions} questions a user could ask that are answered by the context below. Use the terms of the context, each question must be understandable without the context. Reply only with the questions, one per line.
+Context:
+{context}
+Questions:"#;
+
+pub static PROMPT_TRANSLATE: &str = r#"Translate the text below to the language {language}. Keep the paragraphs, the headings marked with #, product names, code and urls unchanged. Reply only with the translation.
+Text:
+{context}
+Translation:"#;
+
+pub static PROMPT_AGENT: &str = r#"You are a customer support agent answering questions using only the documentation available through the tools below. Reply with exactly one line calling a tool, or with the final answer.
+Tools:
+SEARCH: <query> searches the documentation again with a refined query
+FETCH: <url> returns the full page of one of the sources
+SOURCES lists the title and url of the sources found so far
+ANSWER: <answer> is the final, helpful answer including a heading derived from the question
+
+{glossary}Question: {question}
+
+{transcript}
+Next tool call or answer:"#;
+
+pub static PROMPT_CLASSIFY: &str = r#"You decide whether a question can be answered by a documentation about the following scope: {scope}
+Reply with IN_SCOPE if the question is related to the scope and with OUT_OF_SCOPE otherwise, do not reply with anything else.
+
+Question: {question}
+Reply:"#;
+
+pub static PROMPT_RERANK: &str = r#"Rate how relevant the context below is to answer the question on a scale from 0, not relevant at all, to 10, answers the question. Reply only with the number.
+
+Context:
+{context}
+
+Question: {question}
+Relevance:"#;
+
+pub static PROMPT_SPELLING: &str = r#"Correct the spelling mistakes of the search query below. Keep the meaning, the word order, product names and acronyms, do not answer the query. Reply only with the corrected query.
+
+Query: {question}
+Corrected query:"#;
+
+pub static PROMPT_RETRY: &str = r#"You are a customer support agent. Answer the question below using only the facts stated in the context. Quote the relevant terms of the context in your answer. If the context only partially covers the question, answer the covered part and name what is missing instead of refusing.{style}{citations}
+{glossary}Context:
+{context}
+
+Question: {question}
+Answer with a heading derived from the question:"#;
+
+pub static PROMPT_CONDENSE: &str = r#"Rewrite the follow-up question below into a standalone search query using the conversation history. Resolve pronouns and references to earlier turns, keep product names and acronyms, do not answer the question. Reply only with the standalone query.
+
+Conversation:
+{history}
+
+Follow-up question: {question}
+Standalone query:"#;
+
+pub static PROMPT_CHAT: &str = r#"You are a customer support agent, programmed to offer highly accurate and helpful assistance in an ongoing conversation. Your responses should be strictly based on factual information, presented in a friendly yet concise manner. Utilize only the context information provided below and the previous turns of the conversation, without drawing on any prior knowledge. Your goal is to address the latest question directly and efficiently, ensuring clarity and relevance in your answer.{style}{citations}
+{glossary}Conversation:
+{history}
+
+Context:
+{context}
+
+Question: {question}
+Answer:"#;
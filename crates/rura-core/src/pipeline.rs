@@ -0,0 +1,346 @@
+use crate::answer::{generate_answer, AnswerStyle, DEFAULT_ANSWER_RETRIES};
+use crate::data::{Collection, Document, ScoredDocument};
+use crate::embedding::{embedding_size, text_embedding_async, DEFAULT_BATCH_SIZE};
+use crate::embedding_provider::SharedEmbeddingProvider;
+use crate::glossary::{Glossary, Synonyms};
+use crate::ingest::{
+    run_upload, QuestionStage, SummaryStage, TransformStage, UploadConfig, UploadOutcome,
+};
+use crate::ollama::Llm;
+use crate::progress_store::ProgressStore;
+use crate::progress_tracker::StageProgress;
+use crate::qdrant::{
+    create_collections, search_scored_documents, CollectionConfig, SearchFilter, VectorSearch,
+    WriteOptions,
+};
+use crate::query::{citations, pack_context, Citation};
+use crate::rerank::Reranker;
+use crate::retrieval_hook::{RequestMetadata, RetrievalHooks};
+use crate::retriever::{sitemap_with_progress, SitemapOptions};
+use crate::transform::TransformChain;
+use anyhow::{Error, Result};
+use chrono::Utc;
+use log::info;
+use qdrant_client::client::QdrantClient;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// DEFAULT_PIPELINE_LIMIT is the default number of fragments retrieved to answer a query
+pub static DEFAULT_PIPELINE_LIMIT: u64 = 5;
+
+// PipelineAnswer represents the answer of a query with the fragments it is based on
+#[derive(Debug, Clone)]
+pub struct PipelineAnswer {
+    pub answer: String,
+    // attempt is the zero based attempt of the served answer
+    pub attempt: usize,
+    pub citations: Vec<Citation>,
+    // docs are the fragments of the context
+    pub docs: Vec<ScoredDocument>,
+}
+
+// RagPipeline runs the ingestion (fetch, summarize, embed, upsert) and the answering (search,
+// prompt, generate) of the crate, so other programs can embed it without the CLI or the server
+//
+// let pipeline = RagPipeline::new(client, provider, llm, "mistral", "docs");
+// pipeline.ingest_url("https://docs.lagoon.sh/sitemap.xml").await?;
+// let answer = pipeline.answer("How do I deploy?").await?;
+pub struct RagPipeline {
+    client: Arc<QdrantClient>,
+    embedding_provider: SharedEmbeddingProvider,
+    llm: Llm,
+    ollama_model: String,
+    base_collection: String,
+    collections: Vec<Collection>,
+    collection_config: CollectionConfig,
+    sitemap_options: SitemapOptions,
+    summary: Option<SummaryStage>,
+    batch_size: usize,
+    write_options: WriteOptions,
+    limit: u64,
+    filter: SearchFilter,
+    vector_search: VectorSearch,
+    answer_style: AnswerStyle,
+    glossary: Glossary,
+    synonyms: Synonyms,
+    reranker: Option<Reranker>,
+    max_context_tokens: Option<usize>,
+    inline_citations: bool,
+    retries: usize,
+    retrieval_hooks: RetrievalHooks,
+}
+
+impl RagPipeline {
+    // new returns a pipeline indexing into and answering from the basic collection of the base
+    // collection without summaries, reranking or context budget
+    pub fn new(
+        client: Arc<QdrantClient>,
+        embedding_provider: SharedEmbeddingProvider,
+        llm: Llm,
+        ollama_model: &str,
+        base_collection: &str,
+    ) -> Self {
+        RagPipeline {
+            client: client,
+            embedding_provider: embedding_provider,
+            llm: llm,
+            ollama_model: ollama_model.to_string(),
+            base_collection: base_collection.to_string(),
+            collections: vec![Collection::Basic],
+            collection_config: CollectionConfig::default(),
+            sitemap_options: SitemapOptions::default(),
+            summary: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            write_options: WriteOptions::default(),
+            limit: DEFAULT_PIPELINE_LIMIT,
+            filter: SearchFilter::default(),
+            vector_search: VectorSearch::default(),
+            answer_style: AnswerStyle::default(),
+            glossary: Glossary::default(),
+            synonyms: Synonyms::default(),
+            reranker: None,
+            max_context_tokens: None,
+            inline_citations: false,
+            retries: DEFAULT_ANSWER_RETRIES,
+            retrieval_hooks: RetrievalHooks::default(),
+        }
+    }
+
+    // with_collections sets the collections indexed into and searched
+    pub fn with_collections(mut self, collections: Vec<Collection>) -> Self {
+        self.collections = collections;
+        self
+    }
+
+    // with_collection_config sets the sharding and replication of the created collections
+    pub fn with_collection_config(mut self, collection_config: CollectionConfig) -> Self {
+        self.collection_config = collection_config;
+        self
+    }
+
+    // with_sitemap_options sets the locales, politeness and crawl profile of ingest_url
+    pub fn with_sitemap_options(mut self, sitemap_options: SitemapOptions) -> Self {
+        self.sitemap_options = sitemap_options;
+        self
+    }
+
+    // with_summary enables the summary stage of the ingestion, None disables it
+    pub fn with_summary(mut self, summary: Option<SummaryStage>) -> Self {
+        self.summary = summary;
+        self
+    }
+
+    // with_batch_size sets the number of fragments embedded per encode call
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    // with_write_options sets whether upserts wait until the points are searchable
+    pub fn with_write_options(mut self, write_options: WriteOptions) -> Self {
+        self.write_options = write_options;
+        self
+    }
+
+    // with_limit sets the number of fragments retrieved to answer a query
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    // with_filter restricts the retrieval by the metadata filter
+    pub fn with_filter(mut self, filter: SearchFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    // with_vector_search sets the vectors searched by the retrieval, e.g. the titles first for
+    // collections with named vectors
+    pub fn with_vector_search(mut self, vector_search: VectorSearch) -> Self {
+        self.vector_search = vector_search;
+        self
+    }
+
+    // with_answer_style sets the length and format of the answers, the maximum number of tokens
+    // of the style is set on the Llm
+    pub fn with_answer_style(mut self, answer_style: AnswerStyle) -> Self {
+        self.answer_style = answer_style;
+        self.llm = self.llm.with_max_tokens(answer_style.max_tokens());
+        self
+    }
+
+    // with_glossary explains the terms of the queries in the prompt and expands the queries
+    pub fn with_glossary(mut self, glossary: Glossary) -> Self {
+        self.glossary = glossary;
+        self
+    }
+
+    // with_synonyms expands the acronyms and synonyms of the queries before embedding
+    pub fn with_synonyms(mut self, synonyms: Synonyms) -> Self {
+        self.synonyms = synonyms;
+        self
+    }
+
+    // with_reranker re-scores the retrieved fragments with the LLM, None disables reranking
+    pub fn with_reranker(mut self, reranker: Option<Reranker>) -> Self {
+        self.reranker = reranker;
+        self
+    }
+
+    // with_max_context_tokens packs the retrieved fragments into a token budget
+    pub fn with_max_context_tokens(mut self, max_context_tokens: Option<usize>) -> Self {
+        self.max_context_tokens = max_context_tokens;
+        self
+    }
+
+    // with_inline_citations asks for numbered references to the sources in the answers
+    pub fn with_inline_citations(mut self, inline_citations: bool) -> Self {
+        self.inline_citations = inline_citations;
+        self
+    }
+
+    // with_retries sets how often empty, refusing or ungrounded answers are retried
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    // with_retrieval_hooks filters and boosts the retrieved fragments by the request metadata
+    // before they are packed into the context
+    pub fn with_retrieval_hooks(mut self, retrieval_hooks: RetrievalHooks) -> Self {
+        self.retrieval_hooks = retrieval_hooks;
+        self
+    }
+
+    // ingest_url fetches the pages of a sitemap and ingests them
+    pub async fn ingest_url(&self, url: &str) -> Result<UploadOutcome, Error> {
+        info!("Fetching {}", url);
+        let mut stages = StageProgress::default();
+        let mut docs = sitemap_with_progress(url, &self.sitemap_options, &mut stages).await?;
+        info!("Fetched {} docs from {}", docs.len(), url);
+        self.ingest(&mut docs, &stages).await
+    }
+
+    // ingest_documents ingests documents read by the caller, e.g. from files
+    pub async fn ingest_documents(&self, mut docs: Vec<Document>) -> Result<UploadOutcome, Error> {
+        self.ingest(&mut docs, &StageProgress::default()).await
+    }
+
+    // ingest creates the collections and transforms, summarizes, embeds and upserts the
+    // documents, the transformers are the ones of the crawl profile of the sitemap options
+    async fn ingest(
+        &self,
+        docs: &mut [Document],
+        fetch_stages: &StageProgress,
+    ) -> Result<UploadOutcome, Error> {
+        create_collections(
+            &self.client,
+            &self.base_collection,
+            self.collections.clone(),
+            &self.embedding_provider.name(),
+            embedding_size(&self.embedding_provider).await?,
+            &self.collection_config,
+        )
+        .await?;
+        let config = UploadConfig::new(
+            self.client.clone(),
+            self.embedding_provider.clone(),
+            &self.base_collection,
+            self.collections.clone(),
+        )
+        .with_summary(self.summary.clone())
+        .with_questions(match self.collections.contains(&Collection::Questions) {
+            true => Some(QuestionStage {
+                llm: self.llm.clone(),
+                ollama_model: self.ollama_model.clone(),
+            }),
+            false => None,
+        })
+        .with_transforms(Some(TransformStage {
+            chain: TransformChain::new(&self.sitemap_options.profile.transforms)?,
+            llm: self.llm.clone(),
+            ollama_model: self.ollama_model.clone(),
+        }))
+        .with_batch_size(self.batch_size)
+        .with_write_options(self.write_options);
+        let id = Uuid::new_v5(
+            &Uuid::NAMESPACE_URL,
+            format!("{}{}", "pipeline", Utc::now()).as_bytes(),
+        );
+        let tracker = Arc::new(ProgressStore::new());
+        Ok(run_upload(&config, id, docs, fetch_stages, &tracker).await)
+    }
+
+    // retrieve returns the fragments of the context of a query without request metadata
+    pub async fn retrieve(&self, query: &str) -> Result<Vec<ScoredDocument>, Error> {
+        self.retrieve_for(query, &RequestMetadata::new()).await
+    }
+
+    // retrieve_for returns the fragments of the context of a query kept by the retrieval hooks
+    // for the request metadata
+    pub async fn retrieve_for(
+        &self,
+        query: &str,
+        metadata: &RequestMetadata,
+    ) -> Result<Vec<ScoredDocument>, Error> {
+        let expanded_query = self.glossary.expand_query(&self.synonyms.expand(query));
+        let embeddings = text_embedding_async(&self.embedding_provider, expanded_query).await?;
+        let docs = search_scored_documents(
+            &self.client,
+            &self.base_collection,
+            self.collections.clone(),
+            embeddings,
+            self.reranker
+                .as_ref()
+                .map_or(self.limit, |reranker| reranker.candidates(self.limit)),
+            &self.filter,
+            self.vector_search,
+        )
+        .await?;
+        let docs = match &self.reranker {
+            Some(reranker) => {
+                reranker
+                    .rerank(&self.llm, &self.ollama_model, query, docs, self.limit)
+                    .await
+            }
+            None => docs,
+        };
+        let docs = self.retrieval_hooks.apply(query, docs, metadata).await?;
+        match self.max_context_tokens {
+            Some(max_context_tokens) => Ok(pack_context(docs, max_context_tokens)?.docs),
+            None => Ok(docs),
+        }
+    }
+
+    // answer retrieves the context of a query and generates the answer from it
+    pub async fn answer(&self, query: &str) -> Result<PipelineAnswer, Error> {
+        self.answer_for(query, &RequestMetadata::new()).await
+    }
+
+    // answer_for answers a query from the context kept by the retrieval hooks for the request
+    // metadata
+    pub async fn answer_for(
+        &self,
+        query: &str,
+        metadata: &RequestMetadata,
+    ) -> Result<PipelineAnswer, Error> {
+        let docs = self.retrieve_for(query, metadata).await?;
+        let generated = generate_answer(
+            &self.llm,
+            &self.ollama_model,
+            query,
+            &docs,
+            self.answer_style,
+            &self.glossary,
+            self.inline_citations,
+            self.retries,
+        )
+        .await?;
+        Ok(PipelineAnswer {
+            answer: generated.answer,
+            attempt: generated.attempt,
+            citations: citations(&docs),
+            docs: docs,
+        })
+    }
+}
@@ -0,0 +1,193 @@
+use crate::progress_tracker::{JobStatus, ProgressTracker};
+use anyhow::{Error, Result};
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::{watch, RwLock};
+use uuid::Uuid;
+
+// ProgressStore holds the progress of the jobs, each job has a watch channel notifying the
+// subscribers of its updates
+//
+// updates only take the read lock of the map, the write lock is taken to add or remove jobs
+pub struct ProgressStore<T> {
+    jobs: RwLock<HashMap<Uuid, watch::Sender<T>>>,
+    // path is the json file the jobs are persisted to by save, None keeps them in memory only
+    path: Option<PathBuf>,
+    // dirty is set by the changes of the jobs not saved yet
+    dirty: AtomicBool,
+}
+
+impl<T> Default for ProgressStore<T> {
+    fn default() -> Self {
+        ProgressStore {
+            jobs: RwLock::new(HashMap::new()),
+            path: None,
+            dirty: AtomicBool::new(false),
+        }
+    }
+}
+
+// PersistedJob represents a job of the json file of the store
+#[derive(Serialize, Deserialize)]
+struct PersistedJob<T> {
+    id: Uuid,
+    progress: T,
+}
+
+impl<T: ProgressTracker + Clone + Send + Sync> ProgressStore<T> {
+    // new returns an empty progress store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // insert adds or replaces the progress of a job
+    pub async fn insert(&self, id: Uuid, progress: T) {
+        let (sender, _) = watch::channel(progress);
+        self.jobs.write().await.insert(id, sender);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    // get returns the progress of a job
+    pub async fn get(&self, id: &Uuid) -> Option<T> {
+        let jobs = self.jobs.read().await;
+        jobs.get(id).map(|sender| sender.borrow().clone())
+    }
+
+    // all returns the progress of all jobs
+    pub async fn all(&self) -> Vec<(Uuid, T)> {
+        let jobs = self.jobs.read().await;
+        jobs.iter()
+            .map(|(id, sender)| (*id, sender.borrow().clone()))
+            .collect()
+    }
+
+    // update modifies the progress of a job and notifies the subscribers, returns false if the
+    // job does not exist
+    pub async fn update<F: FnOnce(&mut T)>(&self, id: &Uuid, f: F) -> bool {
+        let jobs = self.jobs.read().await;
+        self.modify(&jobs, id, f)
+    }
+
+    // blocking_update is update for blocking threads, e.g. the embedding runner, it must not
+    // be called from an async context
+    pub fn blocking_update<F: FnOnce(&mut T)>(&self, id: &Uuid, f: F) -> bool {
+        let jobs = self.jobs.blocking_read();
+        self.modify(&jobs, id, f)
+    }
+
+    // subscribe returns a receiver of the updates of a job
+    pub async fn subscribe(&self, id: &Uuid) -> Option<watch::Receiver<T>> {
+        let jobs = self.jobs.read().await;
+        jobs.get(id).map(|sender| sender.subscribe())
+    }
+
+    // remove_where removes the jobs matching the predicate and returns them, subscribers of
+    // removed jobs see their channel closed
+    pub async fn remove_where<F: Fn(&T) -> bool>(&self, predicate: F) -> Vec<(Uuid, T)> {
+        let mut jobs = self.jobs.write().await;
+        let ids: Vec<Uuid> = jobs
+            .iter()
+            .filter(|(_, sender)| predicate(&sender.borrow()))
+            .map(|(id, _)| *id)
+            .collect();
+        if !ids.is_empty() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        ids.into_iter()
+            .filter_map(|id| jobs.remove(&id).map(|sender| (id, sender.borrow().clone())))
+            .collect()
+    }
+
+    // modify applies f to the progress of a job, send_modify notifies even without receivers
+    fn modify<F: FnOnce(&mut T)>(
+        &self,
+        jobs: &HashMap<Uuid, watch::Sender<T>>,
+        id: &Uuid,
+        f: F,
+    ) -> bool {
+        match jobs.get(id) {
+            Some(sender) => {
+                sender.send_modify(f);
+                self.dirty.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T: ProgressTracker + Clone + Send + Sync + Serialize + DeserializeOwned> ProgressStore<T> {
+    // load returns the store of the jobs persisted at path, empty if the file doesn't exist yet,
+    // jobs still running or paused when the server stopped are marked as failed since nothing
+    // resumes them
+    pub fn load(path: Option<PathBuf>) -> Result<Self, Error> {
+        let persisted: Vec<PersistedJob<T>> = match &path {
+            Some(path) if path.exists() => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+            _ => Vec::new(),
+        };
+        if let Some(path) = &path {
+            info!("Loaded {} jobs from {:?}", persisted.len(), path);
+        }
+        let mut interrupted = 0;
+        let jobs = persisted
+            .into_iter()
+            .map(|mut job| {
+                if !job.progress.status().is_finished() {
+                    job.progress.set_status(JobStatus::Failed);
+                    interrupted += 1;
+                }
+                let (sender, _) = watch::channel(job.progress);
+                (job.id, sender)
+            })
+            .collect();
+        if interrupted > 0 {
+            info!(
+                "Marked {} jobs interrupted by the restart as failed",
+                interrupted
+            );
+        }
+        Ok(ProgressStore {
+            jobs: RwLock::new(jobs),
+            path: path,
+            dirty: AtomicBool::new(interrupted > 0),
+        })
+    }
+
+    // save writes the jobs changed since the last save to a temporary file renamed to the path,
+    // so a crash while writing doesn't lose the jobs, returns false if nothing was written
+    pub async fn save(&self) -> Result<bool, Error> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(false),
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(false);
+        }
+        let jobs: Vec<PersistedJob<T>> = self
+            .all()
+            .await
+            .into_iter()
+            .map(|(id, progress)| PersistedJob {
+                id: id,
+                progress: progress,
+            })
+            .collect();
+        let tmp_path = path.with_extension("tmp");
+        let written = async {
+            tokio::fs::write(&tmp_path, serde_json::to_string(&jobs)?).await?;
+            tokio::fs::rename(&tmp_path, path).await?;
+            Ok::<(), Error>(())
+        }
+        .await;
+        if let Err(e) = written {
+            // the jobs are written again by the next save
+            self.dirty.store(true, Ordering::Relaxed);
+            return Err(e);
+        }
+        Ok(true)
+    }
+}
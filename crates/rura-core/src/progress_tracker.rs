@@ -0,0 +1,185 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+// JobStatus represents the status of a job
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum JobStatus {
+    // the lowercase aliases accept the statuses of filters like GET /jobs?status=completed
+    #[serde(alias = "running")]
+    Running,
+    // Paused jobs wait between two documents until they are resumed or cancelled
+    #[serde(alias = "paused")]
+    Paused,
+    #[serde(alias = "completed")]
+    Completed,
+    // Failed jobs include the jobs interrupted by a restart of the server
+    #[serde(alias = "failed")]
+    Failed,
+    #[serde(alias = "cancelled")]
+    Cancelled,
+}
+
+impl JobStatus {
+    // is_finished returns true if the job is not running anymore
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobStatus::Running | JobStatus::Paused)
+    }
+}
+
+// Stage represents a stage of the upload pipeline
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum Stage {
+    Fetched,
+    Parsed,
+    Summarized,
+    Embedded,
+    Upserted,
+    Failed,
+}
+
+// StageCallback is called with the documents done by a stage since the last call and the time
+// spent on them, e.g. to report the pages of a crawl while it runs
+pub type StageCallback = Arc<dyn Fn(Stage, usize, Duration) + Send + Sync>;
+
+// StageStats represents the number of documents done by a stage and the time spent on them
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct StageStats {
+    pub count: usize,
+    pub duration_ms: u64,
+}
+
+// StageProgress represents the progress of the stages of the upload pipeline
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct StageProgress {
+    pub fetched: StageStats,
+    pub parsed: StageStats,
+    pub summarized: StageStats,
+    pub embedded: StageStats,
+    pub upserted: StageStats,
+    pub failed: StageStats,
+}
+
+impl StageProgress {
+    // get returns the stats of a stage
+    pub fn get(&self, stage: Stage) -> StageStats {
+        match stage {
+            Stage::Fetched => self.fetched,
+            Stage::Parsed => self.parsed,
+            Stage::Summarized => self.summarized,
+            Stage::Embedded => self.embedded,
+            Stage::Upserted => self.upserted,
+            Stage::Failed => self.failed,
+        }
+    }
+
+    // record adds count documents done by a stage in duration
+    pub fn record(&mut self, stage: Stage, count: usize, duration: Duration) {
+        let stats = match stage {
+            Stage::Fetched => &mut self.fetched,
+            Stage::Parsed => &mut self.parsed,
+            Stage::Summarized => &mut self.summarized,
+            Stage::Embedded => &mut self.embedded,
+            Stage::Upserted => &mut self.upserted,
+            Stage::Failed => &mut self.failed,
+        };
+        stats.count += count;
+        stats.duration_ms += duration.as_millis() as u64;
+    }
+
+    // merge adds the stats of the stages of another progress, e.g. of another site of an upload
+    pub fn merge(&mut self, other: &StageProgress) {
+        for stage in [
+            Stage::Fetched,
+            Stage::Parsed,
+            Stage::Summarized,
+            Stage::Embedded,
+            Stage::Upserted,
+            Stage::Failed,
+        ] {
+            let stats = other.get(stage);
+            self.record(stage, stats.count, Duration::from_millis(stats.duration_ms));
+        }
+    }
+}
+
+// SourceProgress represents the crawl of one location of an upload of several sites, the
+// documents of all locations are then uploaded together
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct SourceProgress {
+    pub url: String,
+    // stages are the fetched, parsed and failed items of the location
+    pub stages: StageProgress,
+    // documents is the number of documents read from the location, None while it is read
+    pub documents: Option<usize>,
+    // error is the reason the location couldn't be read, the other locations are uploaded
+    pub error: Option<String>,
+}
+
+impl fmt::Display for StageProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "fetched: {} ({}ms), parsed: {} ({}ms), summarized: {} ({}ms), embedded: {} ({}ms), upserted: {} ({}ms), failed: {}",
+            self.fetched.count,
+            self.fetched.duration_ms,
+            self.parsed.count,
+            self.parsed.duration_ms,
+            self.summarized.count,
+            self.summarized.duration_ms,
+            self.embedded.count,
+            self.embedded.duration_ms,
+            self.upserted.count,
+            self.upserted.duration_ms,
+            self.failed.count
+        )
+    }
+}
+
+pub trait ProgressTracker {
+    // new returns a new progress tracker
+    fn new(total_items: usize) -> Self;
+    // set_total_items sets the total documents, e.g. once the documents of a job are fetched
+    fn set_total_items(&mut self, total_items: usize);
+    // increment_processed increments the progress of total documents processed
+    fn increment_processed(&mut self);
+    // progress_status returns the current progress status
+    fn progress_status(&self) -> (usize, usize);
+    // status returns the status of the job
+    fn status(&self) -> JobStatus;
+    // set_status updates the status of the job
+    fn set_status(&mut self, status: JobStatus);
+    // created_at returns when the job was created
+    fn created_at(&self) -> DateTime<Utc>;
+    // updated_at returns when the job was last updated
+    fn updated_at(&self) -> DateTime<Utc>;
+    // add_generated_tokens adds tokens generated by the LLM, e.g. for summaries
+    fn add_generated_tokens(&mut self, tokens: usize);
+    // generated_tokens returns the number of tokens generated so far
+    fn generated_tokens(&self) -> usize;
+    // record_stage records count documents done by a stage of the pipeline in duration
+    fn record_stage(&mut self, stage: Stage, count: usize, duration: Duration);
+    // stages returns the progress of the stages of the pipeline
+    fn stages(&self) -> StageProgress;
+    // set_current_stage sets the stage the job is working on
+    fn set_current_stage(&mut self, stage: Stage);
+    // current_stage returns the stage the job is working on, None once the job is finished
+    fn current_stage(&self) -> Option<Stage>;
+    // set_sources sets the locations of an upload of several sites
+    fn set_sources(&mut self, urls: &[String]);
+    // record_source_stage records count items of a location done by a stage in duration
+    fn record_source_stage(&mut self, url: &str, stage: Stage, count: usize, duration: Duration);
+    // finish_source records the documents read from a location or the error reading it
+    fn finish_source(&mut self, url: &str, documents: usize, error: Option<String>);
+    // sources returns the progress of the locations, empty for uploads of a single site
+    fn sources(&self) -> Vec<SourceProgress>;
+}
@@ -0,0 +1,136 @@
+use crate::collection_model::{get_collection_model, record_collection_model, CollectionModel};
+use crate::data::Collection;
+use crate::mirror::{mirror, mirror_write};
+use crate::qdrant::forget_named_vectors;
+use crate::retry::retry_qdrant;
+use anyhow::{Error, Result};
+use log::info;
+use qdrant_client::prelude::*;
+use qdrant_client::qdrant::alias_operations::Action;
+use qdrant_client::qdrant::{AliasOperations, ChangeAliases, CreateAlias, DeleteAlias};
+use std::collections::HashMap;
+
+// Promotion represents the alias of a collection of the production base collection pointing to
+// a collection of the staging base collection
+#[derive(Debug, Clone)]
+pub struct Promotion {
+    pub alias: String,
+    pub collection: String,
+    // previous is the collection the alias pointed to before, None on the first promotion
+    pub previous: Option<String>,
+}
+
+// promote_collections points the collections of the production base collection to the ones of
+// the staging base collection by aliases, all aliases are swapped at once so the queries never
+// see a mix of both, the production names must not be collections unless replace deletes them
+// before the swap
+pub async fn promote_collections(
+    client: &QdrantClient,
+    staging_base: &str,
+    production_base: &str,
+    collections: Vec<Collection>,
+    replace: bool,
+) -> Result<Vec<Promotion>, Error> {
+    if staging_base == production_base {
+        return Err(anyhow::anyhow!(
+            "Staging base collection {} is the production base collection",
+            staging_base
+        ));
+    }
+    let aliases: HashMap<String, String> = retry_qdrant("list_aliases", || client.list_aliases())
+        .await?
+        .aliases
+        .into_iter()
+        .map(|alias| (alias.alias_name, alias.collection_name))
+        .collect();
+    let mut promotions = Vec::new();
+    let mut replaced = Vec::new();
+    for collection in collections {
+        let staging_name = format!("{}_{}", staging_base, collection.to_string());
+        let production_name = format!("{}_{}", production_base, collection.to_string());
+        if !retry_qdrant("has_collection", || client.has_collection(&staging_name)).await? {
+            return Err(anyhow::anyhow!(
+                "Staging collection {} does not exist",
+                staging_name
+            ));
+        }
+        if retry_qdrant("has_collection", || client.has_collection(&production_name)).await? {
+            if !replace {
+                return Err(anyhow::anyhow!(
+                    "{} is a collection, not an alias, promote with replace to delete it",
+                    production_name
+                ));
+            }
+            replaced.push(production_name.clone());
+        }
+        promotions.push(Promotion {
+            alias: production_name.clone(),
+            collection: staging_name,
+            previous: aliases.get(&production_name).cloned(),
+        });
+    }
+
+    // the collections holding the production names are deleted right before the swap, the
+    // queries fail in between
+    for collection_name in &replaced {
+        info!(
+            "Deleting collection {} to promote its alias",
+            collection_name
+        );
+        retry_qdrant("delete_collection", || {
+            client.delete_collection(collection_name)
+        })
+        .await?;
+    }
+    let mut actions = Vec::new();
+    for promotion in &promotions {
+        if promotion.previous.is_some() {
+            actions.push(AliasOperations {
+                action: Some(Action::DeleteAlias(DeleteAlias {
+                    alias_name: promotion.alias.clone(),
+                })),
+            });
+        }
+        actions.push(AliasOperations {
+            action: Some(Action::CreateAlias(CreateAlias {
+                collection_name: promotion.collection.clone(),
+                alias_name: promotion.alias.clone(),
+            })),
+        });
+    }
+    let change_aliases = ChangeAliases {
+        actions: actions,
+        timeout: None,
+    };
+    retry_qdrant("update_aliases", || {
+        client.update_aliases(change_aliases.clone())
+    })
+    .await?;
+    if let Some(mirror) = mirror() {
+        mirror_write("update_aliases", || {
+            mirror.update_aliases(change_aliases.clone())
+        })
+        .await;
+    }
+
+    for promotion in &promotions {
+        forget_named_vectors(&promotion.alias);
+        // the queries of the production base collection are validated against the embedding
+        // model of the promoted collection
+        if let Some(collection_model) = get_collection_model(client, &promotion.collection).await? {
+            record_collection_model(
+                client,
+                &CollectionModel {
+                    collection: promotion.alias.clone(),
+                    ..collection_model
+                },
+            )
+            .await?;
+        }
+        info!(
+            "Promoted {} to {}, previously {:?}",
+            promotion.collection, promotion.alias, promotion.previous
+        );
+    }
+    Ok(promotions)
+}
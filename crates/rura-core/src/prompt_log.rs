@@ -0,0 +1,199 @@
+use anyhow::{Error, Result};
+use chrono::Utc;
+use log::{debug, error};
+use serde::Serialize;
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+// REDACTED replaces redacted content
+static REDACTED: &str = "[REDACTED]";
+
+// Redactor redacts sensitive content before it is logged
+pub trait Redactor: Send + Sync {
+    // redact returns the text with sensitive content replaced
+    fn redact(&self, text: &str) -> String;
+}
+
+// EmailRedactor redacts email addresses
+pub struct EmailRedactor;
+
+impl Redactor for EmailRedactor {
+    fn redact(&self, text: &str) -> String {
+        text.split(' ')
+            .map(|word| {
+                let trimmed = word.trim_matches(|c: char| !c.is_alphanumeric());
+                match trimmed.split_once('@') {
+                    Some((user, domain)) if !user.is_empty() && domain.contains('.') => {
+                        word.replace(trimmed, REDACTED)
+                    }
+                    _ => word.to_string(),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+// TermRedactor redacts the configured terms, e.g. customer or product names
+pub struct TermRedactor {
+    terms: Vec<String>,
+}
+
+impl TermRedactor {
+    // new returns a redactor for the given terms, empty terms are ignored
+    pub fn new(terms: Vec<String>) -> Self {
+        TermRedactor {
+            terms: terms.into_iter().filter(|t| !t.is_empty()).collect(),
+        }
+    }
+}
+
+impl Redactor for TermRedactor {
+    fn redact(&self, text: &str) -> String {
+        self.terms
+            .iter()
+            .fold(text.to_string(), |acc, term| acc.replace(term, REDACTED))
+    }
+}
+
+// PromptLogConfig represents the configuration of the prompt log
+#[derive(Debug, Clone)]
+pub struct PromptLogConfig {
+    // path of the jsonl file the prompts and completions are appended to
+    pub path: PathBuf,
+    // sample_rate is the share of prompts logged, between 0.0 and 1.0
+    pub sample_rate: f64,
+    // max_entry_size is the maximum number of characters logged per prompt or completion
+    pub max_entry_size: usize,
+    // max_file_size is the size in bytes after which no more prompts are logged
+    pub max_file_size: u64,
+}
+
+impl PromptLogConfig {
+    // new returns a config logging all prompts to path with default size caps
+    pub fn new(path: PathBuf) -> Self {
+        PromptLogConfig {
+            path: path,
+            sample_rate: 1.0,
+            max_entry_size: 16 * 1024,
+            max_file_size: 100 * 1024 * 1024,
+        }
+    }
+}
+
+// PromptLogEntry represents a logged prompt and its completion
+#[derive(Serialize)]
+struct PromptLogEntry {
+    timestamp: String,
+    model: String,
+    prompt: String,
+    completion: String,
+    truncated: bool,
+    // request_id is the id of the query the prompt was generated for, it correlates the entry
+    // with the logs of the request and the answer reported by a user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+// PromptLogger appends sampled and redacted prompts and completions to a jsonl file
+pub struct PromptLogger {
+    config: PromptLogConfig,
+    redactors: Vec<Box<dyn Redactor>>,
+    lock: Mutex<()>,
+}
+
+impl PromptLogger {
+    // new returns a logger redacting email addresses
+    pub fn new(config: PromptLogConfig) -> Self {
+        PromptLogger {
+            config: config,
+            redactors: vec![Box::new(EmailRedactor)],
+            lock: Mutex::new(()),
+        }
+    }
+
+    // with_redactor adds a redactor applied before logging
+    pub fn with_redactor(mut self, redactor: Box<dyn Redactor>) -> Self {
+        self.redactors.push(redactor);
+        self
+    }
+
+    // sampled returns true if the current prompt should be logged
+    fn sampled(&self) -> bool {
+        let random = (Uuid::new_v4().as_u128() % 10_000) as f64 / 10_000.0;
+        random < self.config.sample_rate
+    }
+
+    // prepare redacts and truncates a text, returning if it was truncated
+    fn prepare(&self, text: &str) -> (String, bool) {
+        let redacted = self
+            .redactors
+            .iter()
+            .fold(text.to_string(), |acc, redactor| redactor.redact(&acc));
+        if redacted.chars().count() > self.config.max_entry_size {
+            (
+                redacted.chars().take(self.config.max_entry_size).collect(),
+                true,
+            )
+        } else {
+            (redacted, false)
+        }
+    }
+
+    // log appends a prompt and its completion to the log file if sampled
+    pub async fn log(
+        &self,
+        model: &str,
+        prompt: &str,
+        completion: &str,
+        request_id: Option<&str>,
+    ) -> Result<(), Error> {
+        if !self.sampled() {
+            return Ok(());
+        }
+        let (prompt, prompt_truncated) = self.prepare(prompt);
+        let (completion, completion_truncated) = self.prepare(completion);
+        let entry = PromptLogEntry {
+            timestamp: Utc::now().to_rfc3339(),
+            model: model.to_string(),
+            prompt: prompt,
+            completion: completion,
+            truncated: prompt_truncated || completion_truncated,
+            request_id: request_id.map(|id| id.to_string()),
+        };
+        let line = format!("{}\n", serde_json::to_string(&entry)?);
+
+        let _lock = self.lock.lock().await;
+        if let Ok(metadata) = tokio::fs::metadata(&self.config.path).await {
+            if metadata.len() + line.len() as u64 > self.config.max_file_size {
+                debug!(
+                    "Prompt log {:?} reached max size, skipping entry",
+                    self.config.path
+                );
+                return Ok(());
+            }
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.config.path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+
+    // log_or_error logs a prompt and its completion, errors are only reported
+    pub async fn log_or_error(
+        &self,
+        model: &str,
+        prompt: &str,
+        completion: &str,
+        request_id: Option<&str>,
+    ) {
+        if let Err(e) = self.log(model, prompt, completion, request_id).await {
+            error!("Error logging prompt: {}", e);
+        }
+    }
+}
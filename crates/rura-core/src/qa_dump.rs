@@ -0,0 +1,265 @@
+use crate::forum::{html_text, thread_status, Post, Thread};
+use anyhow::{Error, Result};
+use chrono::{TimeZone, Utc};
+use log::{error, info};
+use scraper::{ElementRef, Html, Selector};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+// VOTE_BOOST is the boost per logarithm of the votes of a question in searches
+pub static VOTE_BOOST: f32 = 0.05;
+// MAX_VOTE_BOOST caps the boost of highly voted questions
+pub static MAX_VOTE_BOOST: f32 = 1.3;
+
+// DumpFormat represents the format of a question answer export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    // Xml reads the Posts.xml of a stack exchange data dump, questions and answers are rows
+    // linked by their ParentId
+    Xml,
+    // Json reads the questions of the stack exchange api with their answers, as an array or
+    // wrapped in items
+    Json,
+}
+
+// string to dump format
+impl From<&str> for DumpFormat {
+    fn from(s: &str) -> Self {
+        match s {
+            "xml" => DumpFormat::Xml,
+            "json" => DumpFormat::Json,
+            _ => {
+                error!("Error converting dump format, unknown format: {}", s);
+                DumpFormat::Xml
+            }
+        }
+    }
+}
+
+impl DumpFormat {
+    // from_path returns the format of a dump by its extension, xml unless it ends in .json
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => DumpFormat::Json,
+            _ => DumpFormat::Xml,
+        }
+    }
+}
+
+// vote_boost returns the boost of a question by its votes, None for questions without upvotes
+pub fn vote_boost(votes: i64) -> Option<f32> {
+    match votes > 0 {
+        true => Some((1.0 + VOTE_BOOST * (1.0 + votes as f32).ln()).min(MAX_VOTE_BOOST)),
+        false => None,
+    }
+}
+
+// read_dump returns up to max_threads questions of a dump with their accepted answer, the other
+// answers are replies ordered by votes, base_url is the url of the site the questions link to,
+// e.g. https://stackoverflow.example.com
+pub fn read_dump(
+    path: &Path,
+    format: DumpFormat,
+    base_url: &str,
+    max_threads: usize,
+) -> Result<Vec<Thread>, Error> {
+    let content = std::fs::read_to_string(path)?;
+    let base_url = base_url.trim_end_matches('/');
+    let mut threads = match format {
+        DumpFormat::Xml => xml_threads(&content, base_url)?,
+        DumpFormat::Json => json_threads(&content, base_url)?,
+    };
+    threads.truncate(max_threads);
+    info!("Read {} questions of {}", threads.len(), path.display());
+    Ok(threads)
+}
+
+// DumpAnswer represents an answer of a dump with its votes
+struct DumpAnswer {
+    id: String,
+    votes: i64,
+    post: Post,
+}
+
+// xml_threads returns the threads of the rows of a Posts.xml, questions have PostTypeId 1 and
+// answers 2
+//
+// function needs to be non async because scraper::Html is not Send
+fn xml_threads(content: &str, base_url: &str) -> Result<Vec<Thread>, Error> {
+    let document = Html::parse_document(content);
+    let selector =
+        Selector::parse("row").or(Err(anyhow::anyhow!("Failed to parse row selector")))?;
+    // attribute names are lowercased by the html parser
+    let mut questions: Vec<ElementRef> = Vec::new();
+    let mut answers: HashMap<String, Vec<DumpAnswer>> = HashMap::new();
+    for row in document.select(&selector) {
+        match row.value().attr("posttypeid") {
+            Some("1") => questions.push(row),
+            Some("2") => {
+                let parent_id = row.value().attr("parentid").unwrap_or_default();
+                answers
+                    .entry(parent_id.to_string())
+                    .or_default()
+                    .push(DumpAnswer {
+                        id: row.value().attr("id").unwrap_or_default().to_string(),
+                        votes: xml_votes(row),
+                        post: xml_post(row),
+                    });
+            }
+            _ => {}
+        }
+    }
+    let mut threads = Vec::new();
+    for question in questions {
+        let id = question.value().attr("id").unwrap_or_default();
+        threads.push(to_thread(
+            format!("{}/questions/{}", base_url, id),
+            question
+                .value()
+                .attr("title")
+                .unwrap_or_default()
+                .to_string(),
+            xml_post(question),
+            xml_votes(question),
+            question.value().attr("acceptedanswerid"),
+            answers.remove(id).unwrap_or_default(),
+        ));
+    }
+    Ok(threads)
+}
+
+// xml_post returns the post of a row, the author is the display name or the user id
+fn xml_post(row: ElementRef) -> Post {
+    let author = match (
+        row.value().attr("ownerdisplayname"),
+        row.value().attr("owneruserid"),
+    ) {
+        (Some(name), _) => name.to_string(),
+        (None, Some(user_id)) => format!("user {}", user_id),
+        (None, None) => String::new(),
+    };
+    Post {
+        author: author,
+        created_at: row
+            .value()
+            .attr("creationdate")
+            .unwrap_or_default()
+            .to_string(),
+        text: html_text(row.value().attr("body").unwrap_or_default()),
+    }
+}
+
+// xml_votes returns the score of a row
+fn xml_votes(row: ElementRef) -> i64 {
+    row.value()
+        .attr("score")
+        .and_then(|score| score.parse().ok())
+        .unwrap_or(0)
+}
+
+// json_threads returns the threads of the questions of the stack exchange api, fetched with a
+// filter including the bodies and answers
+fn json_threads(content: &str, base_url: &str) -> Result<Vec<Thread>, Error> {
+    let value: Value = serde_json::from_str(content)?;
+    let questions = match value.get("items") {
+        Some(items) => items.as_array().cloned().unwrap_or_default(),
+        None => value
+            .as_array()
+            .cloned()
+            .ok_or(anyhow::anyhow!("Expected an array of questions"))?,
+    };
+    let mut threads = Vec::new();
+    for question in questions.iter() {
+        let id = json_id(&question["question_id"]);
+        let url = match question["link"].as_str() {
+            Some(link) => link.to_string(),
+            None => format!("{}/questions/{}", base_url, id),
+        };
+        let answers = question["answers"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+            .iter()
+            .map(|answer| DumpAnswer {
+                id: json_id(&answer["answer_id"]),
+                votes: answer["score"].as_i64().unwrap_or(0),
+                post: json_post(answer),
+            })
+            .collect();
+        let accepted_id = question["answers"]
+            .as_array()
+            .and_then(|answers| {
+                answers
+                    .iter()
+                    .find(|answer| answer["is_accepted"].as_bool().unwrap_or(false))
+            })
+            .map(|answer| json_id(&answer["answer_id"]))
+            .or(match question["accepted_answer_id"].is_null() {
+                true => None,
+                false => Some(json_id(&question["accepted_answer_id"])),
+            });
+        threads.push(to_thread(
+            url,
+            html_text(question["title"].as_str().unwrap_or_default()),
+            json_post(question),
+            question["score"].as_i64().unwrap_or(0),
+            accepted_id.as_deref(),
+            answers,
+        ));
+    }
+    Ok(threads)
+}
+
+// json_post returns the post of a question or answer of the api, the creation date is unix
+// seconds
+fn json_post(value: &Value) -> Post {
+    let created_at = value["creation_date"]
+        .as_i64()
+        .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+        .map(|date| date.to_rfc3339())
+        .unwrap_or_default();
+    Post {
+        author: value["owner"]["display_name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        created_at: created_at,
+        text: html_text(value["body"].as_str().unwrap_or_default()),
+    }
+}
+
+// json_id returns an id of the api as string, ids are numbers
+fn json_id(value: &Value) -> String {
+    match value.as_str() {
+        Some(id) => id.to_string(),
+        None => value.to_string(),
+    }
+}
+
+// to_thread returns the thread of a question, the accepted answer is the answer and the other
+// answers are replies with the most voted first
+fn to_thread(
+    url: String,
+    title: String,
+    question: Post,
+    votes: i64,
+    accepted_id: Option<&str>,
+    mut answers: Vec<DumpAnswer>,
+) -> Thread {
+    answers.sort_by(|a, b| b.votes.cmp(&a.votes));
+    let answer = accepted_id
+        .and_then(|accepted_id| answers.iter().position(|answer| answer.id == accepted_id))
+        .map(|position| answers.remove(position).post);
+    let replies: Vec<Post> = answers.into_iter().map(|answer| answer.post).collect();
+    Thread {
+        url: url,
+        title: title,
+        status: thread_status(&answer, replies.len(), false),
+        question: question,
+        answer: answer,
+        replies: replies,
+        votes: Some(votes),
+        deprecated: false,
+    }
+}
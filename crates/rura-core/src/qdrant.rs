@@ -0,0 +1,1262 @@
+use crate::collection_model::{record_collection_model, CollectionModel};
+use crate::config::DEFAULT_MAX_MESSAGE_SIZE_MB;
+use crate::data::{Collection, EmbeddedMetadata};
+use crate::estimate::MEGABYTE;
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use log::{debug, error, info};
+use qdrant_client::prelude::*;
+use qdrant_client::qdrant::point_id::PointIdOptions;
+use qdrant_client::qdrant::points_selector::PointsSelectorOneOf;
+use qdrant_client::qdrant::r#match::MatchValue;
+use qdrant_client::qdrant::vectors_config::Config;
+use qdrant_client::qdrant::{
+    CollectionStatus, Condition, CountPoints, CreateCollection, FieldType, Filter,
+    OptimizersConfigDiff, PointId, PointsOperationResponse, PointsSelector, Range, ScrollPoints,
+    SearchPoints, VectorParams, VectorParamsMap, Vectors, VectorsConfig, WriteOrdering,
+    WriteOrderingType,
+};
+use qdrant_client::serde::PayloadConversionError;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{OnceLock, RwLock};
+use std::time::Instant;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+use crate::data::{EmbeddedDocument, ScoredDocument};
+use crate::mirror::{mirror, mirror_write};
+use crate::retry::retry_qdrant;
+use crate::telemetry::{record_duration, record_skipped_point, record_upserted_points};
+
+// SCROLL_LIMIT is the number of points fetched per scroll request
+static SCROLL_LIMIT: u32 = 256;
+// POINT_OVERHEAD is the estimated encoding overhead of a point besides its payload and vector
+static POINT_OVERHEAD: usize = 64;
+// CONTENT_VECTOR and TITLE_VECTOR are the names of the vectors of collections with named vectors
+pub static CONTENT_VECTOR: &str = "content";
+pub static TITLE_VECTOR: &str = "title";
+// NAMED_VECTORS caches by collection name whether a collection has named vectors, collections
+// don't change their vectors after creation
+static NAMED_VECTORS: OnceLock<RwLock<HashMap<String, bool>>> = OnceLock::new();
+
+// SearchFilter restricts a search by the metadata of the documents, unset fields match all
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct SearchFilter {
+    // url_prefix matches the urls or url paths starting with it, e.g. /docs/
+    pub url_prefix: Option<String>,
+    // indexed_after and indexed_before restrict the timestamp of the documents, documents
+    // indexed before the timestamp was stored as unix seconds never match a date range
+    pub indexed_after: Option<DateTime<Utc>>,
+    pub indexed_before: Option<DateTime<Utc>>,
+    // title matches the titles containing it
+    pub title: Option<String>,
+    // version matches the releases of release notes pages of the version, e.g. 2.3 matches
+    // 2.3.0 and 2.3.1
+    pub version: Option<String>,
+    // include_deprecated includes the archived or deprecated articles, downranked by half, they
+    // are excluded by default
+    pub include_deprecated: Option<bool>,
+}
+
+impl SearchFilter {
+    // is_empty returns true if no field is set
+    pub fn is_empty(&self) -> bool {
+        self.url_prefix.is_none()
+            && self.indexed_after.is_none()
+            && self.indexed_before.is_none()
+            && self.title.is_none()
+            && self.version.is_none()
+            && self.include_deprecated.is_none()
+    }
+
+    // to_filter translates the search filter into a qdrant filter, the url prefix is matched
+    // as substring by qdrant and as prefix by matches, deprecated documents are excluded unless
+    // included explicitly
+    pub fn to_filter(&self) -> Option<Filter> {
+        let must_not = match self.include_deprecated.unwrap_or(false) {
+            true => Vec::new(),
+            false => vec![Condition::matches("deprecated", MatchValue::Boolean(true))],
+        };
+        let mut conditions = Vec::new();
+        if let Some(url_prefix) = &self.url_prefix {
+            conditions.push(Condition::matches(
+                "url",
+                MatchValue::Text(url_prefix.clone()),
+            ));
+        }
+        if self.indexed_after.is_some() || self.indexed_before.is_some() {
+            conditions.push(Condition::range(
+                "timestamp_secs",
+                Range {
+                    gte: self.indexed_after.map(|after| after.timestamp() as f64),
+                    lte: self.indexed_before.map(|before| before.timestamp() as f64),
+                    ..Default::default()
+                },
+            ));
+        }
+        if let Some(title) = &self.title {
+            conditions.push(Condition::matches("title", MatchValue::Text(title.clone())));
+        }
+        if let Some(version) = &self.version {
+            conditions.push(Condition::matches(
+                "version_prefixes",
+                MatchValue::Keyword(version.trim_start_matches(['v', 'V']).to_string()),
+            ));
+        }
+        if conditions.is_empty() && must_not.is_empty() {
+            return None;
+        }
+        Some(Filter {
+            must: conditions,
+            must_not: must_not,
+            ..Default::default()
+        })
+    }
+
+    // matches returns true if the url of the document starts with the url prefix, either
+    // as full url or as url path
+    pub fn matches(&self, metadata: &EmbeddedMetadata) -> bool {
+        let url_prefix = match &self.url_prefix {
+            Some(url_prefix) => url_prefix,
+            None => return true,
+        };
+        metadata.url.starts_with(url_prefix.as_str())
+            || Url::parse(&metadata.url)
+                .map(|url| url.path().starts_with(url_prefix.as_str()))
+                .unwrap_or(false)
+    }
+}
+
+// CollectionConfig represents the cluster and storage settings of created collections, unset
+// settings use the defaults of qdrant, existing collections are not changed
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct CollectionConfig {
+    // shard_number is the number of shards the points of a collection are distributed over
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shard_number: Option<u32>,
+    // replication_factor is the number of copies of each shard
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replication_factor: Option<u32>,
+    // write_consistency_factor is the number of replicas which must acknowledge a write
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write_consistency_factor: Option<u32>,
+    // on_disk_payload stores the payloads on disk instead of in memory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_disk_payload: Option<bool>,
+    // on_disk_vectors stores the vectors memory mapped on disk instead of in memory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_disk_vectors: Option<bool>,
+    // memmap_threshold_kb is the segment size in kilobytes from which qdrant memory maps the
+    // vectors of a segment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memmap_threshold_kb: Option<u64>,
+    // named_vectors stores a content and a title vector per point instead of a single vector
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub named_vectors: Option<bool>,
+}
+
+impl CollectionConfig {
+    // validate returns an error if writes would need more replicas than the shards have
+    pub fn validate(&self) -> Result<()> {
+        if let Some(write_consistency_factor) = self.write_consistency_factor {
+            let replication_factor = self.replication_factor.unwrap_or(1);
+            if write_consistency_factor > replication_factor {
+                return Err(anyhow::anyhow!(
+                    "Write consistency factor {} exceeds the replication factor {}",
+                    write_consistency_factor,
+                    replication_factor
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// create_collections creates two collections one for text and one for meta with the given name and size,
+// the embedding model of the created collections is recorded to validate the queries against it
+pub async fn create_collections(
+    client: &QdrantClient,
+    collection_base: &str,
+    collections: Vec<Collection>,
+    model: &str,
+    size: u64,
+    config: &CollectionConfig,
+) -> Result<()> {
+    info!("Creating collections, with base: {}", collection_base);
+    config.validate()?;
+    for collection in collections {
+        let collection_name = format!("{}_{}", collection_base, collection.to_string());
+        if create_collection(client, &collection_name, size, config).await? {
+            let collection_model = CollectionModel {
+                collection: collection_name.clone(),
+                model: Some(model.to_string()),
+                size: size,
+                created_at: Some(Utc::now()),
+            };
+            record_collection_model(client, &collection_model).await?;
+        }
+        if let Some(mirror) = mirror() {
+            if let Err(e) = create_collection(mirror, &collection_name, size, config).await {
+                info!(
+                    "Error creating mirror collection {}: {}",
+                    collection_name, e
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+// create_collection creates a collection if it does not exist, returns true if it was created
+async fn create_collection(
+    client: &QdrantClient,
+    collection: &str,
+    size: u64,
+    config: &CollectionConfig,
+) -> Result<bool> {
+    if !collection_exists(client, &collection).await? {
+        info!("Creating text collection: {} with {:?}", collection, config);
+        let vector_params = VectorParams {
+            size: size,
+            distance: Distance::Cosine.into(),
+            on_disk: config.on_disk_vectors,
+            ..Default::default()
+        };
+        // named vectors store the title embedding next to the content embedding of a point
+        let vectors_config = match config.named_vectors.unwrap_or(false) {
+            true => Config::ParamsMap(VectorParamsMap {
+                map: HashMap::from([
+                    (CONTENT_VECTOR.to_string(), vector_params.clone()),
+                    (TITLE_VECTOR.to_string(), vector_params),
+                ]),
+            }),
+            false => Config::Params(vector_params),
+        };
+        let create_collection = CreateCollection {
+            collection_name: collection.into(),
+            vectors_config: Some(VectorsConfig {
+                config: Some(vectors_config),
+            }),
+            shard_number: config.shard_number,
+            replication_factor: config.replication_factor,
+            write_consistency_factor: config.write_consistency_factor,
+            on_disk_payload: config.on_disk_payload,
+            optimizers_config: config.memmap_threshold_kb.map(|memmap_threshold_kb| {
+                OptimizersConfigDiff {
+                    memmap_threshold: Some(memmap_threshold_kb),
+                    ..Default::default()
+                }
+            }),
+            ..Default::default()
+        };
+        retry_qdrant("create_collection", || {
+            client.create_collection(&create_collection)
+        })
+        .await?;
+        // the full text index of the text serves the keyword fallback while embedding fails,
+        // collections without it match the terms as substrings
+        retry_qdrant("create_field_index", || {
+            client.create_field_index(collection, "text", FieldType::Text, None, None)
+        })
+        .await?;
+        named_vectors_cache().write().unwrap().insert(
+            collection.to_string(),
+            config.named_vectors.unwrap_or(false),
+        );
+        Ok(true)
+    } else {
+        info!("Text collection: {} already exists", collection);
+        Ok(false)
+    }
+}
+
+// collection_exists returns true if a collection or an alias of the name exists, qdrant lists the
+// aliases apart from the collections
+pub async fn collection_exists(client: &QdrantClient, collection_name: &str) -> Result<bool> {
+    if retry_qdrant("has_collection", || client.has_collection(collection_name)).await? {
+        return Ok(true);
+    }
+    let aliases = retry_qdrant("list_aliases", || client.list_aliases()).await?;
+    Ok(aliases
+        .aliases
+        .iter()
+        .any(|alias| alias.alias_name == collection_name))
+}
+
+// named_vectors_cache returns the cache of the vectors of the collections
+fn named_vectors_cache() -> &'static RwLock<HashMap<String, bool>> {
+    NAMED_VECTORS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+// forget_named_vectors removes the vectors of a collection from the cache, e.g. after its alias
+// was pointed to another collection
+pub fn forget_named_vectors(collection_name: &str) {
+    named_vectors_cache()
+        .write()
+        .unwrap()
+        .remove(collection_name);
+}
+
+// has_named_vectors returns true if the collection stores a content and a title vector per point
+pub async fn has_named_vectors(client: &QdrantClient, collection_name: &str) -> Result<bool> {
+    if let Some(named_vectors) = named_vectors_cache().read().unwrap().get(collection_name) {
+        return Ok(*named_vectors);
+    }
+    let named_vectors = retry_qdrant("collection_info", || {
+        client.collection_info(collection_name)
+    })
+    .await?
+    .result
+    .as_ref()
+    .and_then(|info| info.config.as_ref())
+    .and_then(|config| config.params.as_ref())
+    .and_then(|params| params.vectors_config.as_ref())
+    .map_or(false, |vectors_config| {
+        matches!(vectors_config.config, Some(Config::ParamsMap(_)))
+    });
+    named_vectors_cache()
+        .write()
+        .unwrap()
+        .insert(collection_name.to_string(), named_vectors);
+    Ok(named_vectors)
+}
+
+// VectorSearch represents the vectors a search compares the query embedding with, collections
+// without named vectors are always searched by their single content vector
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum VectorSearch {
+    // Content searches the embeddings of the fragments
+    #[default]
+    Content,
+    // Title searches the embeddings of the titles of the documents
+    Title,
+    // TitleFirst searches the titles and fills the remaining results from the content
+    TitleFirst,
+}
+
+// string to vector search, unknown searches are an error
+impl FromStr for VectorSearch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "content" => Ok(VectorSearch::Content),
+            "title" => Ok(VectorSearch::Title),
+            "title_first" => Ok(VectorSearch::TitleFirst),
+            _ => Err(anyhow::anyhow!(
+                "unknown vector search: {}, valid values are: content, title, title_first",
+                s
+            )),
+        }
+    }
+}
+
+// WriteOrder represents the ordering guarantee of upserts across the replicas of a shard
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum WriteOrder {
+    // the lowercase aliases accept the names used by the client in query strings
+    // Weak applies the writes in any order, the fastest
+    #[default]
+    #[serde(alias = "weak")]
+    Weak,
+    // Medium applies the writes in the order of the dynamically elected leader of the shard
+    #[serde(alias = "medium")]
+    Medium,
+    // Strong applies the writes in the order of the permanent leader of the shard
+    #[serde(alias = "strong")]
+    Strong,
+}
+
+// string to write order, unknown orders are an error so a typo doesn't change the consistency
+// of the upserts
+impl FromStr for WriteOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "weak" => Ok(WriteOrder::Weak),
+            "medium" => Ok(WriteOrder::Medium),
+            "strong" => Ok(WriteOrder::Strong),
+            _ => Err(anyhow::anyhow!(
+                "unknown write order: {}, valid values are: weak, medium, strong",
+                s
+            )),
+        }
+    }
+}
+
+// write order to qdrant write ordering
+impl From<WriteOrder> for WriteOrdering {
+    fn from(order: WriteOrder) -> Self {
+        let ordering_type = match order {
+            WriteOrder::Weak => WriteOrderingType::Weak,
+            WriteOrder::Medium => WriteOrderingType::Medium,
+            WriteOrder::Strong => WriteOrderingType::Strong,
+        };
+        WriteOrdering {
+            r#type: ordering_type.into(),
+        }
+    }
+}
+
+// WriteOptions represents the durability of upserts, waiting makes the points searchable once
+// the upsert returns at the cost of latency
+#[derive(Debug, Clone, Copy)]
+pub struct WriteOptions {
+    // wait returns once the points are applied, otherwise once qdrant received them
+    pub wait: bool,
+    pub ordering: WriteOrder,
+    // max_message_size is the maximum size of an upsert request in bytes, larger upserts are
+    // split into batches
+    pub max_message_size: usize,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            wait: false,
+            ordering: WriteOrder::default(),
+            max_message_size: (DEFAULT_MAX_MESSAGE_SIZE_MB * MEGABYTE) as usize,
+        }
+    }
+}
+
+// add_documents adds documents to a collection with the given write options
+#[tracing::instrument(skip(client, documents), fields(documents = documents.len()))]
+pub async fn add_documents(
+    client: &QdrantClient,
+    collection_base: &str,
+    filter_by_collections: Vec<Collection>,
+    documents: Vec<EmbeddedDocument>,
+    write_options: WriteOptions,
+) -> Result<()> {
+    let mut named_vectors_by_collection = HashMap::new();
+    for collection in filter_by_collections.clone() {
+        let collection_name = format!("{}_{}", collection_base, collection.to_string());
+        if !collection_exists(client, &collection_name).await? {
+            return Err(anyhow::anyhow!(
+                "Collection: {} does not exist",
+                collection_name
+            ));
+        }
+        named_vectors_by_collection.insert(
+            collection,
+            has_named_vectors(client, &collection_name).await?,
+        );
+    }
+    // the points are kept with their estimated encoded size to split large upserts
+    let mut text_points: HashMap<Collection, Vec<(PointStruct, usize)>> = HashMap::new();
+    let time_to_add = Instant::now();
+    for document in documents {
+        // check if document by filter_by_collections
+        if !filter_by_collections.contains(&document.metadata.collection) {
+            info!(
+                "Skipping document: {} because it is not in filter_by_collections: {:?}",
+                document.metadata.id, filter_by_collections
+            );
+            continue;
+        }
+
+        let payload_json = json!(document.metadata);
+        let named_vectors = named_vectors_by_collection
+            .get(&document.metadata.collection)
+            .copied()
+            .unwrap_or(false);
+        let vectors = match named_vectors {
+            true => {
+                let title_embeddings = document
+                    .title_embeddings
+                    .clone()
+                    .unwrap_or(document.text_embeddings.clone());
+                Vectors::from(HashMap::from([
+                    (CONTENT_VECTOR.to_string(), document.text_embeddings.clone()),
+                    (TITLE_VECTOR.to_string(), title_embeddings),
+                ]))
+            }
+            false => Vectors::from(document.text_embeddings.clone()),
+        };
+        let vector_count = match named_vectors {
+            true => 2,
+            false => 1,
+        };
+        let size = payload_json.to_string().len()
+            + vector_count * document.text_embeddings.len() * std::mem::size_of::<f32>()
+            + POINT_OVERHEAD;
+        let payload: Result<Payload, PayloadConversionError> = payload_json.try_into();
+        match payload {
+            // get text_points for collection
+            Ok(payload) => {
+                let point = PointStruct {
+                    id: Some(document.metadata.id.clone().into()),
+                    payload: payload.into(),
+                    vectors: Some(vectors),
+                };
+                text_points
+                    .entry(document.metadata.collection.clone())
+                    .or_default()
+                    .push((point, size));
+            }
+            Err(e) => {
+                error!("Error converting payload: {}", e);
+                return Err(anyhow::anyhow!("Error converting payload: {}", e));
+            }
+        }
+    }
+    let mut num_text_points = 0;
+
+    for (collection, points) in text_points {
+        let collection_name = format!("{}_{}", collection_base, collection.to_string());
+        info!(
+            "Adding {} documents to text collection: {}",
+            points.len(),
+            collection_name
+        );
+        num_text_points += points.len();
+        let ordering = Some(WriteOrdering::from(write_options.ordering));
+        let batches = split_batches(points, write_options.max_message_size);
+        if batches.len() > 1 {
+            info!(
+                "Splitting upsert into {} batches below {} bytes",
+                batches.len(),
+                write_options.max_message_size
+            );
+        }
+        for points in batches {
+            retry_qdrant("upsert", || {
+                upsert(
+                    client,
+                    &collection_name,
+                    &points,
+                    write_options.wait,
+                    &ordering,
+                )
+            })
+            .await?;
+            record_upserted_points(&collection_name, points.len());
+            if let Some(mirror) = mirror() {
+                mirror_write("upsert", || {
+                    upsert(
+                        mirror,
+                        &collection_name,
+                        &points,
+                        write_options.wait,
+                        &ordering,
+                    )
+                })
+                .await;
+            }
+        }
+    }
+    info!("Added {} documents to qrdant", num_text_points);
+    record_duration("upsert", time_to_add.elapsed());
+
+    Ok(())
+}
+
+// wait_for_writes waits until the earlier writes to the collections are applied with a waiting
+// delete matching no point, e.g. after upserts which didn't wait, the mirror isn't waited for as
+// searches only use the primary
+pub async fn wait_for_writes(
+    client: &QdrantClient,
+    collection_base: &str,
+    collections: Vec<Collection>,
+    ordering: WriteOrder,
+) -> Result<()> {
+    // a filter is sent to every shard of the collection, an empty id list matches no point
+    let points_selector = PointsSelector {
+        points_selector_one_of: Some(PointsSelectorOneOf::Filter(Filter::must([
+            Condition::has_id(Vec::<PointId>::new()),
+        ]))),
+    };
+    let ordering = Some(WriteOrdering::from(ordering));
+    for collection in collections {
+        let collection_name = format!("{}_{}", collection_base, collection.to_string());
+        if !collection_exists(client, &collection_name).await? {
+            continue;
+        }
+        info!("Waiting for the writes to collection: {}", collection_name);
+        retry_qdrant("wait_for_writes", || {
+            client.delete_points_blocking(&collection_name, &points_selector, ordering.clone())
+        })
+        .await?;
+    }
+    Ok(())
+}
+
+// upsert upserts the points, waiting until they are applied if wait is set
+async fn upsert(
+    client: &QdrantClient,
+    collection_name: &str,
+    points: &[PointStruct],
+    wait: bool,
+    ordering: &Option<WriteOrdering>,
+) -> Result<PointsOperationResponse> {
+    match wait {
+        true => {
+            client
+                .upsert_points_blocking(collection_name, points.to_vec(), ordering.clone())
+                .await
+        }
+        false => {
+            client
+                .upsert_points(collection_name, points.to_vec(), ordering.clone())
+                .await
+        }
+    }
+}
+
+// split_batches splits the points with their estimated size into batches below the max size, a
+// point exceeding it on its own is sent alone
+fn split_batches(points: Vec<(PointStruct, usize)>, max_size: usize) -> Vec<Vec<PointStruct>> {
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_size = 0;
+    for (point, size) in points {
+        if !batch.is_empty() && batch_size + size > max_size {
+            batches.push(std::mem::take(&mut batch));
+            batch_size = 0;
+        }
+        batch_size += size;
+        batch.push(point);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+// search_documents searches for documents in a collection based on cosine distance of embeddings
+pub async fn search_documents(
+    client: &QdrantClient,
+    base_collection: &str,
+    filter_by_collections: Vec<Collection>,
+    embeddings: Vec<f32>,
+    limit: u64,
+    filter: &SearchFilter,
+    vector_search: VectorSearch,
+) -> Result<Vec<EmbeddedDocument>> {
+    let scored_documents = search_scored_documents(
+        client,
+        base_collection,
+        filter_by_collections,
+        embeddings,
+        limit,
+        filter,
+        vector_search,
+    )
+    .await?;
+    Ok(scored_documents
+        .into_iter()
+        .map(|scored_document| EmbeddedDocument {
+            text_embeddings: vec![],
+            title_embeddings: None,
+            metadata: scored_document.metadata,
+        })
+        .collect())
+}
+
+// search_scored_documents searches for documents in a collection based on cosine distance of
+// embeddings and returns them with their score, restricted by the metadata filter
+pub async fn search_scored_documents(
+    client: &QdrantClient,
+    base_collection: &str,
+    filter_by_collections: Vec<Collection>,
+    embeddings: Vec<f32>,
+    limit: u64,
+    filter: &SearchFilter,
+    vector_search: VectorSearch,
+) -> Result<Vec<ScoredDocument>> {
+    // we will limit the search for each collection the same
+    let total_collections = filter_by_collections.len();
+
+    let mut results = Vec::new();
+    for filter_collection in filter_by_collections.clone() {
+        let collection_limit = collection_limit(limit, total_collections, filter_collection);
+        let mut collection_results = search_collection(
+            client,
+            base_collection,
+            filter_collection,
+            embeddings.clone(),
+            collection_limit,
+            filter,
+            vector_search,
+        )
+        .await?;
+        rescore(&mut collection_results);
+        results.extend(collection_results);
+    }
+    Ok(join_parents(results))
+}
+
+// join_parents maps the hits of the questions collection and the chunks of parent windows to
+// their parent, a parent found several times is kept once with its best score
+pub fn join_parents(docs: Vec<ScoredDocument>) -> Vec<ScoredDocument> {
+    let mut joined: Vec<ScoredDocument> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for mut doc in docs {
+        if let Some(parent_id) = doc.metadata.parent_id.clone() {
+            doc.metadata.id = parent_id;
+        }
+        match positions.get(&doc.metadata.id) {
+            Some(&position) => {
+                if doc.score > joined[position].score {
+                    joined[position] = doc;
+                }
+            }
+            None => {
+                positions.insert(doc.metadata.id.clone(), joined.len());
+                joined.push(doc);
+            }
+        }
+    }
+    joined
+}
+
+// rescore boosts the score of the lead fragments, which typically answer overview questions,
+// downweights unanswered threads and deprecated articles and sorts the documents by the adjusted score
+pub fn rescore(docs: &mut [ScoredDocument]) {
+    for doc in docs.iter_mut() {
+        doc.score *= doc.metadata.score_factor();
+    }
+    docs.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+}
+
+// collection_limit returns the search limit of a collection when searching total_collections
+pub fn collection_limit(limit: u64, total_collections: usize, collection: Collection) -> u64 {
+    let mut collection_limit = limit;
+    if total_collections > 1 {
+        // multiply limit by filter_collection ratio
+        collection_limit = (limit as f32 * collection.limit_by_collection()) as u64;
+        if collection_limit == 0 {
+            collection_limit = 1;
+        }
+    }
+    collection_limit
+}
+
+// search_collection searches for documents in a single collection matching the filter by the
+// vectors of the vector search
+#[tracing::instrument(skip(client, embeddings))]
+pub async fn search_collection(
+    client: &QdrantClient,
+    base_collection: &str,
+    collection: Collection,
+    embeddings: Vec<f32>,
+    limit: u64,
+    filter: &SearchFilter,
+    vector_search: VectorSearch,
+) -> Result<Vec<ScoredDocument>> {
+    let collection_name = format!("{}_{}", base_collection, collection.to_string());
+    if !collection_exists(client, &collection_name).await? {
+        return Err(anyhow::anyhow!(
+            "Collection: {} does not exist",
+            collection_name
+        ));
+    }
+    info!(
+        "Searching collection: {} with limit: {} by {:?}",
+        collection_name, limit, vector_search
+    );
+    let vector_names = match has_named_vectors(client, &collection_name).await? {
+        true => match vector_search {
+            VectorSearch::Content => vec![Some(CONTENT_VECTOR.to_string())],
+            VectorSearch::Title => vec![Some(TITLE_VECTOR.to_string())],
+            VectorSearch::TitleFirst => vec![
+                Some(TITLE_VECTOR.to_string()),
+                Some(CONTENT_VECTOR.to_string()),
+            ],
+        },
+        false => vec![None],
+    };
+    let mut results = Vec::new();
+    let mut found = HashSet::new();
+    for vector_name in vector_names {
+        // the content only fills the results the titles left
+        if results.len() as u64 >= limit {
+            break;
+        }
+        let search_start = Instant::now();
+        let search_points = SearchPoints {
+            collection_name: collection_name.clone(),
+            vector: embeddings.clone(),
+            vector_name: vector_name,
+            filter: filter.to_filter(),
+            limit: limit,
+            with_payload: Some(true.into()),
+            ..Default::default()
+        };
+        let search_text_result =
+            retry_qdrant("search", || client.search_points(&search_points)).await?;
+        record_duration("search", search_start.elapsed());
+        for search_result in search_text_result.result {
+            let metadata_json = serde_json::to_value(&search_result.payload)?;
+            match EmbeddedMetadata::from_payload(metadata_json) {
+                Ok(metadata) if !filter.matches(&metadata) => {
+                    debug!("Skipping {} not matching the url prefix", metadata.url);
+                }
+                Ok(metadata) if found.contains(&metadata.id) => {
+                    debug!("Skipping {} found by the title", metadata.id);
+                }
+                Ok(metadata) => {
+                    found.insert(metadata.id.clone());
+                    results.push(ScoredDocument {
+                        metadata: metadata,
+                        score: search_result.score,
+                    });
+                }
+                // a broken point doesn't fail the whole search, `migrate` reports it
+                Err(e) => {
+                    error!(
+                        "Skipping point {:?} of {}: {}",
+                        search_result.id, collection_name, e
+                    );
+                    record_skipped_point(&collection_name);
+                }
+            }
+        }
+    }
+    results.truncate(limit as usize);
+    Ok(results)
+}
+
+// get_page_hashes returns the page hash by url of the documents in a collection whose url
+// starts with url_prefix
+pub async fn get_page_hashes(
+    client: &QdrantClient,
+    base_collection: &str,
+    collection: Collection,
+    url_prefix: &str,
+) -> Result<HashMap<String, String>> {
+    let collection_name = format!("{}_{}", base_collection, collection.to_string());
+    let mut page_hashes = HashMap::new();
+    if !collection_exists(client, &collection_name).await? {
+        info!("Collection: {} does not exist yet", collection_name);
+        return Ok(page_hashes);
+    }
+    let mut offset: Option<PointId> = None;
+    loop {
+        let scroll_points = ScrollPoints {
+            collection_name: collection_name.clone(),
+            filter: None,
+            offset: offset,
+            limit: Some(SCROLL_LIMIT),
+            with_payload: Some(true.into()),
+            ..Default::default()
+        };
+        let scroll_result = retry_qdrant("scroll", || client.scroll(&scroll_points)).await?;
+        for point in scroll_result.result {
+            let payload = serde_json::to_value(&point.payload)?;
+            let url = payload["url"].as_str();
+            let page_hash = payload["page_hash"].as_str();
+            if let (Some(url), Some(page_hash)) = (url, page_hash) {
+                if url.starts_with(url_prefix) {
+                    page_hashes.insert(url.to_string(), page_hash.to_string());
+                }
+            }
+        }
+        offset = scroll_result.next_page_offset;
+        if offset.is_none() {
+            break;
+        }
+    }
+    Ok(page_hashes)
+}
+
+// get_page_timestamps returns the latest index time by url of the documents in a collection whose
+// url starts with url_prefix, fragments are only written again when their page changed, so it
+// approximates the last change of the page
+pub async fn get_page_timestamps(
+    client: &QdrantClient,
+    base_collection: &str,
+    collection: Collection,
+    url_prefix: &str,
+) -> Result<HashMap<String, DateTime<Utc>>> {
+    let collection_name = format!("{}_{}", base_collection, collection.to_string());
+    let mut page_timestamps: HashMap<String, DateTime<Utc>> = HashMap::new();
+    if !collection_exists(client, &collection_name).await? {
+        info!("Collection: {} does not exist yet", collection_name);
+        return Ok(page_timestamps);
+    }
+    let mut offset: Option<PointId> = None;
+    loop {
+        let scroll_points = ScrollPoints {
+            collection_name: collection_name.clone(),
+            filter: None,
+            offset: offset,
+            limit: Some(SCROLL_LIMIT),
+            with_payload: Some(true.into()),
+            ..Default::default()
+        };
+        let scroll_result = retry_qdrant("scroll", || client.scroll(&scroll_points)).await?;
+        for point in scroll_result.result {
+            let payload = serde_json::to_value(&point.payload)?;
+            let url = payload["url"].as_str();
+            let timestamp = payload["timestamp_secs"]
+                .as_i64()
+                .and_then(|secs| Utc.timestamp_opt(secs, 0).single());
+            if let (Some(url), Some(timestamp)) = (url, timestamp) {
+                if url.starts_with(url_prefix) {
+                    let latest = page_timestamps.entry(url.to_string()).or_insert(timestamp);
+                    *latest = (*latest).max(timestamp);
+                }
+            }
+        }
+        offset = scroll_result.next_page_offset;
+        if offset.is_none() {
+            break;
+        }
+    }
+    Ok(page_timestamps)
+}
+
+// get_recorded_commit returns the commit of the most recently indexed document whose url starts
+// with url_prefix, None if no document of the prefix was read from git
+pub async fn get_recorded_commit(
+    client: &QdrantClient,
+    base_collection: &str,
+    collection: Collection,
+    url_prefix: &str,
+) -> Result<Option<String>> {
+    let collection_name = format!("{}_{}", base_collection, collection.to_string());
+    if !collection_exists(client, &collection_name).await? {
+        info!("Collection: {} does not exist yet", collection_name);
+        return Ok(None);
+    }
+    let mut recorded: Option<(i64, String)> = None;
+    let mut offset: Option<PointId> = None;
+    loop {
+        let scroll_points = ScrollPoints {
+            collection_name: collection_name.clone(),
+            filter: None,
+            offset: offset,
+            limit: Some(SCROLL_LIMIT),
+            with_payload: Some(true.into()),
+            ..Default::default()
+        };
+        let scroll_result = retry_qdrant("scroll", || client.scroll(&scroll_points)).await?;
+        for point in scroll_result.result {
+            let payload = serde_json::to_value(&point.payload)?;
+            let url = payload["url"].as_str();
+            let commit = payload["commit"].as_str();
+            let timestamp_secs = payload["timestamp_secs"].as_i64().unwrap_or(0);
+            if let (Some(url), Some(commit)) = (url, commit) {
+                if url.starts_with(url_prefix)
+                    && recorded
+                        .as_ref()
+                        .map_or(true, |(secs, _)| timestamp_secs > *secs)
+                {
+                    recorded = Some((timestamp_secs, commit.to_string()));
+                }
+            }
+        }
+        offset = scroll_result.next_page_offset;
+        if offset.is_none() {
+            break;
+        }
+    }
+    Ok(recorded.map(|(_, commit)| commit))
+}
+
+// get_texts returns the texts of all the points of a collection, e.g. to build the vocabulary
+// of the indexed documents
+pub async fn get_texts(
+    client: &QdrantClient,
+    base_collection: &str,
+    collection: Collection,
+) -> Result<Vec<String>> {
+    let collection_name = format!("{}_{}", base_collection, collection.to_string());
+    let mut texts = Vec::new();
+    if !collection_exists(client, &collection_name).await? {
+        info!("Collection: {} does not exist yet", collection_name);
+        return Ok(texts);
+    }
+    let mut offset: Option<PointId> = None;
+    loop {
+        let scroll_points = ScrollPoints {
+            collection_name: collection_name.clone(),
+            filter: None,
+            offset: offset,
+            limit: Some(SCROLL_LIMIT),
+            with_payload: Some(true.into()),
+            ..Default::default()
+        };
+        let scroll_result = retry_qdrant("scroll", || client.scroll(&scroll_points)).await?;
+        for point in scroll_result.result {
+            let payload = serde_json::to_value(&point.payload)?;
+            if let Some(text) = payload["text"].as_str() {
+                texts.push(text.to_string());
+            }
+        }
+        offset = scroll_result.next_page_offset;
+        if offset.is_none() {
+            break;
+        }
+    }
+    Ok(texts)
+}
+
+// CollectionStats represents the size and health of a collection
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct CollectionStats {
+    pub collection: String,
+    pub points_count: u64,
+    // green is false while qdrant optimizes the collection or if it failed
+    pub green: bool,
+}
+
+// get_collection_stats returns the stats of a collection, None if it does not exist yet
+pub async fn get_collection_stats(
+    client: &QdrantClient,
+    base_collection: &str,
+    collection: Collection,
+) -> Result<Option<CollectionStats>> {
+    let collection_name = format!("{}_{}", base_collection, collection.to_string());
+    if !collection_exists(client, &collection_name).await? {
+        return Ok(None);
+    }
+    let count_points = CountPoints {
+        collection_name: collection_name.clone(),
+        filter: None,
+        exact: Some(false),
+        ..Default::default()
+    };
+    let points_count = retry_qdrant("count", || client.count(&count_points))
+        .await?
+        .result
+        .map_or(0, |result| result.count);
+    let green = retry_qdrant("collection_info", || {
+        client.collection_info(&collection_name)
+    })
+    .await?
+    .result
+    .map_or(false, |info| info.status == CollectionStatus::Green as i32);
+    Ok(Some(CollectionStats {
+        collection: collection_name,
+        points_count: points_count,
+        green: green,
+    }))
+}
+
+// CollectionSummary represents an existing collection with the size and metric of its vectors
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct CollectionSummary {
+    pub collection: String,
+    pub points_count: u64,
+    // vector_size and distance are None for collections with named vectors
+    pub vector_size: Option<u64>,
+    pub distance: Option<String>,
+    // status is the optimization status of qdrant, e.g. Green or Yellow
+    pub status: String,
+}
+
+// list_collections returns the collections of qdrant named after the base collection, sorted by
+// name, with the point count and vector params of their collection info
+pub async fn list_collections(
+    client: &QdrantClient,
+    base_collection: &str,
+) -> Result<Vec<CollectionSummary>> {
+    let prefix = format!("{}_", base_collection);
+    let mut collection_names: Vec<String> =
+        retry_qdrant("list_collections", || client.list_collections())
+            .await?
+            .collections
+            .into_iter()
+            .map(|description| description.name)
+            .filter(|name| name.starts_with(&prefix))
+            .collect();
+    collection_names.sort();
+
+    let mut summaries = Vec::new();
+    for collection_name in collection_names {
+        let info = match retry_qdrant("collection_info", || {
+            client.collection_info(&collection_name)
+        })
+        .await?
+        .result
+        {
+            Some(info) => info,
+            None => continue,
+        };
+        let vector_params = info
+            .config
+            .as_ref()
+            .and_then(|config| config.params.as_ref())
+            .and_then(|params| params.vectors_config.as_ref())
+            .and_then(|vectors_config| match &vectors_config.config {
+                Some(Config::Params(vector_params)) => Some(vector_params.clone()),
+                _ => None,
+            });
+        let status = CollectionStatus::try_from(info.status)
+            .map_or("Unknown".to_string(), |status| {
+                status.as_str_name().to_string()
+            });
+        summaries.push(CollectionSummary {
+            collection: collection_name,
+            points_count: info.points_count.unwrap_or(0),
+            vector_size: vector_params.as_ref().map(|params| params.size),
+            distance: vector_params.and_then(|params| {
+                Distance::try_from(params.distance)
+                    .ok()
+                    .map(|distance| distance.as_str_name().to_string())
+            }),
+            status: status,
+        });
+    }
+    Ok(summaries)
+}
+
+// get_payload_hashes returns the sha1 hash of the payload by point id of the points of a
+// collection, used to diff the points of two stores
+pub async fn get_payload_hashes(
+    client: &QdrantClient,
+    base_collection: &str,
+    collection: Collection,
+) -> Result<HashMap<String, String>> {
+    let collection_name = format!("{}_{}", base_collection, collection.to_string());
+    let mut payload_hashes = HashMap::new();
+    if !collection_exists(client, &collection_name).await? {
+        info!("Collection: {} does not exist", collection_name);
+        return Ok(payload_hashes);
+    }
+    let mut offset: Option<PointId> = None;
+    loop {
+        let scroll_points = ScrollPoints {
+            collection_name: collection_name.clone(),
+            filter: None,
+            offset: offset,
+            limit: Some(SCROLL_LIMIT),
+            with_payload: Some(true.into()),
+            ..Default::default()
+        };
+        let scroll_result = retry_qdrant("scroll", || client.scroll(&scroll_points)).await?;
+        for point in scroll_result.result {
+            let id = match point.id.and_then(|id| id.point_id_options) {
+                Some(PointIdOptions::Uuid(uuid)) => uuid,
+                Some(PointIdOptions::Num(num)) => num.to_string(),
+                None => continue,
+            };
+            // the keys of the json object are sorted, so equal payloads hash equally
+            let payload = serde_json::to_value(&point.payload)?;
+            let mut hasher = Sha1::new();
+            hasher.update(payload.to_string());
+            payload_hashes.insert(id, format!("{:x}", hasher.finalize()));
+        }
+        offset = scroll_result.next_page_offset;
+        if offset.is_none() {
+            break;
+        }
+    }
+    Ok(payload_hashes)
+}
+
+// get_existing_ids returns the ids of the points of a collection which already exist, e.g. to
+// skip fragments of unchanged documents
+pub async fn get_existing_ids(
+    client: &QdrantClient,
+    base_collection: &str,
+    collection: Collection,
+    ids: &[String],
+) -> Result<HashSet<String>> {
+    let collection_name = format!("{}_{}", base_collection, collection.to_string());
+    if ids.is_empty() || !collection_exists(client, &collection_name).await? {
+        return Ok(HashSet::new());
+    }
+    let point_ids: Vec<PointId> = ids.iter().map(|id| id.clone().into()).collect();
+    let response = retry_qdrant("get_points", || {
+        client.get_points(&collection_name, &point_ids, Some(false), Some(false), None)
+    })
+    .await?;
+    Ok(response
+        .result
+        .into_iter()
+        .filter_map(|point| point.id.and_then(|id| id.point_id_options))
+        .map(|id| match id {
+            PointIdOptions::Uuid(uuid) => uuid,
+            PointIdOptions::Num(num) => num.to_string(),
+        })
+        .collect())
+}
+
+// delete_documents_by_url deletes the fragments of the page with the given url from the
+// collections and returns the number of deleted fragments
+pub async fn delete_documents_by_url(
+    client: &QdrantClient,
+    base_collection: &str,
+    collections: Vec<Collection>,
+    url: &str,
+) -> Result<u64> {
+    let filter = Filter::must([Condition::matches("url", url.to_string())]);
+    let mut deleted = 0;
+    for collection in collections {
+        let collection_name = format!("{}_{}", base_collection, collection.to_string());
+        if !collection_exists(client, &collection_name).await? {
+            info!("Collection: {} does not exist", collection_name);
+            continue;
+        }
+        let count_points = CountPoints {
+            collection_name: collection_name.clone(),
+            filter: Some(filter.clone()),
+            exact: Some(true),
+            ..Default::default()
+        };
+        let count = retry_qdrant("count", || client.count(&count_points))
+            .await?
+            .result
+            .map_or(0, |result| result.count);
+        let points_selector = PointsSelector {
+            points_selector_one_of: Some(PointsSelectorOneOf::Filter(filter.clone())),
+        };
+        // the mirror is cleaned up even if the primary has nothing to delete, it may hold the
+        // fragments of a missed delete
+        if let Some(mirror) = mirror() {
+            mirror_write("delete", || {
+                mirror.delete_points_blocking(&collection_name, &points_selector, None)
+            })
+            .await;
+        }
+        if count == 0 {
+            continue;
+        }
+        info!(
+            "Deleting {} fragments of {} from collection: {}",
+            count, url, collection_name
+        );
+        retry_qdrant("delete", || {
+            client.delete_points_blocking(&collection_name, &points_selector, None)
+        })
+        .await?;
+        deleted += count;
+    }
+    Ok(deleted)
+}
+
+// drop_collection drops a collection for both the text and meta collection
+pub async fn drop_collections(client: &QdrantClient, collection: &str) -> Result<()> {
+    let text_collection = format!("{}_text", collection);
+    let meta_collection = format!("{}_meta", collection);
+    for collection_name in vec![text_collection.clone(), meta_collection.clone()] {
+        if retry_qdrant("has_collection", || client.has_collection(&collection_name)).await? {
+            info!("Dropping collection: {}", collection);
+            retry_qdrant("delete_collection", || {
+                client.delete_collection(&collection_name)
+            })
+            .await?;
+        } else {
+            info!("Collection: {} does not exist", collection);
+        }
+    }
+
+    Ok(())
+}
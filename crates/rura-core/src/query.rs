@@ -0,0 +1,274 @@
+use crate::answer::AnswerStyle;
+use crate::data::ScoredDocument;
+use crate::glossary::Glossary;
+use crate::ollama::PROMPT;
+use anyhow::{Error, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use tiktoken_rs::p50k_base;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+// CITATION_INSTRUCTION asks the model to reference the numbered sources of the context
+static CITATION_INSTRUCTION: &str =
+    " Reference the numbered sources of the context inline where you use them, e.g. [1] or [2].";
+// MIN_TRUNCATED_TOKENS is the minimum number of tokens of a truncated chunk, a smaller rest of
+// the context budget is left unused
+static MIN_TRUNCATED_TOKENS: usize = 64;
+
+// ContextChunk represents a retrieved chunk used in the context of a prompt
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ContextChunk {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub score: f32,
+    pub tokens: usize,
+}
+
+// Citation represents a source page of the context of an answer
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct Citation {
+    // index is the one based number of the source, referenced inline as e.g. [1]
+    pub index: usize,
+    pub title: String,
+    pub url: String,
+    // score is the highest score of the fragments of the source
+    pub score: f32,
+}
+
+// citations returns the distinct source pages of the documents in context order, fragments of
+// the same page share a citation
+pub fn citations(docs: &[ScoredDocument]) -> Vec<Citation> {
+    let mut citations: Vec<Citation> = Vec::new();
+    for doc in docs {
+        match citations
+            .iter_mut()
+            .find(|citation| citation.url == doc.metadata.url)
+        {
+            Some(citation) => citation.score = citation.score.max(doc.score),
+            None => citations.push(Citation {
+                index: citations.len() + 1,
+                title: doc.metadata.title.clone(),
+                url: doc.metadata.url.clone(),
+                score: doc.score,
+            }),
+        }
+    }
+    citations
+}
+
+// ContextFragment represents a retrieved fragment of the context of an answer with its text
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContextFragment {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub score: f32,
+    pub text: String,
+}
+
+// context_fragments returns the fragments of the documents in context order
+pub fn context_fragments(docs: &[ScoredDocument]) -> Vec<ContextFragment> {
+    docs.iter()
+        .map(|doc| ContextFragment {
+            id: doc.metadata.id.clone(),
+            title: doc.metadata.title.clone(),
+            url: doc.metadata.url.clone(),
+            score: doc.score,
+            text: doc.metadata.text.clone(),
+        })
+        .collect()
+}
+
+// AnswerOutput represents an answer with its sources, e.g. printed as json by the client
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnswerOutput {
+    pub request_id: String,
+    pub answer: String,
+    // attempt is the zero based attempt of the answer, None if answered without retrieval
+    pub attempt: Option<usize>,
+    pub citations: Vec<Citation>,
+    // context are the retrieved fragments if requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Vec<ContextFragment>>,
+}
+
+// PromptPreview represents a rendered prompt with its context and token usage
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct PromptPreview {
+    pub prompt: String,
+    pub chunks: Vec<ContextChunk>,
+    // context_tokens is the number of tokens used by the retrieved chunks
+    pub context_tokens: usize,
+    // prompt_tokens is the number of tokens of the whole prompt
+    pub prompt_tokens: usize,
+}
+
+// count_tokens returns the number of tokens of a text
+pub fn count_tokens(text: &str) -> Result<usize, Error> {
+    let bpe = p50k_base()?;
+    Ok(bpe.encode_with_special_tokens(text).len())
+}
+
+// PackedContext represents the documents packed into the token budget of a context
+pub struct PackedContext {
+    // docs are the documents of the context by descending score
+    pub docs: Vec<ScoredDocument>,
+    // truncated is true if the last document was truncated to fit the budget
+    pub truncated: bool,
+    // dropped are the lower scored documents not fitting the budget
+    pub dropped: Vec<ScoredDocument>,
+    // tokens is the number of tokens of the context
+    pub tokens: usize,
+}
+
+// pack_context packs the highest scored documents into max_tokens tokens of context, the first
+// document exceeding the rest of the budget is truncated and the lower scored ones are dropped
+pub fn pack_context(
+    mut docs: Vec<ScoredDocument>,
+    max_tokens: usize,
+) -> Result<PackedContext, Error> {
+    let bpe = p50k_base()?;
+    docs.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+    let mut packed = PackedContext {
+        docs: Vec::new(),
+        truncated: false,
+        dropped: Vec::new(),
+        tokens: 0,
+    };
+    for mut doc in docs {
+        let budget = max_tokens - packed.tokens;
+        if packed.truncated || budget == 0 {
+            packed.dropped.push(doc);
+            continue;
+        }
+        let tokens = bpe.encode_with_special_tokens(&render_context(&[doc.clone()]));
+        if tokens.len() <= budget {
+            packed.tokens += tokens.len();
+            packed.docs.push(doc);
+            continue;
+        }
+        if budget < MIN_TRUNCATED_TOKENS {
+            packed.dropped.push(doc);
+            continue;
+        }
+        let text = bpe.encode_with_special_tokens(&doc.metadata.text);
+        // the list item marker and the newline of the rendered chunk take a few tokens
+        let keep = budget
+            .saturating_sub(tokens.len() - text.len())
+            .min(text.len());
+        doc.metadata.text = bpe.decode(text[..keep].to_vec())?;
+        packed.tokens += budget;
+        packed.truncated = true;
+        packed.docs.push(doc);
+    }
+    for doc in &packed.docs {
+        info!("Context chunk: {}, score: {}", doc.metadata.url, doc.score);
+    }
+    for doc in &packed.dropped {
+        info!("Dropped chunk: {}, score: {}", doc.metadata.url, doc.score);
+    }
+    info!(
+        "Packed {} chunks into {}/{} context tokens, truncated: {}, dropped: {}",
+        packed.docs.len(),
+        packed.tokens,
+        max_tokens,
+        packed.truncated,
+        packed.dropped.len()
+    );
+    Ok(packed)
+}
+
+// render_context concats the retrieved documents into the context of a prompt
+pub fn render_context(docs: &[ScoredDocument]) -> String {
+    let mut context = String::new();
+    for doc in docs {
+        context.push_str(&format!("- {}\n", doc.metadata.text.as_str()));
+    }
+    context
+}
+
+// render_numbered_context concats the retrieved documents into the context of a prompt, each
+// prefixed with the number of its citation
+pub fn render_numbered_context(docs: &[ScoredDocument]) -> String {
+    let citations = citations(docs);
+    let mut context = String::new();
+    for doc in docs {
+        let index = citations
+            .iter()
+            .find(|citation| citation.url == doc.metadata.url)
+            .map_or(0, |citation| citation.index);
+        context.push_str(&format!("- [{}] {}\n", index, doc.metadata.text.as_str()));
+    }
+    context
+}
+
+// render_prompt renders the prompt for a question from the retrieved documents
+pub fn render_prompt(
+    question: &str,
+    docs: &[ScoredDocument],
+    style: AnswerStyle,
+    glossary: &Glossary,
+    inline_citations: bool,
+) -> String {
+    render_prompt_with_template(PROMPT, question, docs, style, glossary, inline_citations)
+}
+
+// render_prompt_with_template renders a prompt template with {style}, {citations}, {glossary},
+// {context} and {question}, the glossary section lists the terms used in the question or the
+// context, with inline citations the context is numbered by source
+pub fn render_prompt_with_template(
+    template: &str,
+    question: &str,
+    docs: &[ScoredDocument],
+    style: AnswerStyle,
+    glossary: &Glossary,
+    inline_citations: bool,
+) -> String {
+    let (context, citation_instruction) = match inline_citations {
+        true => (render_numbered_context(docs), CITATION_INSTRUCTION),
+        false => (render_context(docs), ""),
+    };
+    template
+        .replace("{style}", style.instruction())
+        .replace("{citations}", citation_instruction)
+        .replace(
+            "{glossary}",
+            &glossary.prompt_section(&[question, &context]),
+        )
+        .replace("{context}", &context)
+        .replace("{question}", question)
+}
+
+// preview_prompt returns the rendered prompt with its context and token usage
+pub fn preview_prompt(
+    question: &str,
+    docs: &[ScoredDocument],
+    style: AnswerStyle,
+    glossary: &Glossary,
+    inline_citations: bool,
+) -> Result<PromptPreview, Error> {
+    let bpe = p50k_base()?;
+    let mut chunks = Vec::new();
+    for doc in docs {
+        chunks.push(ContextChunk {
+            id: doc.metadata.id.clone(),
+            title: doc.metadata.title.clone(),
+            url: doc.metadata.url.clone(),
+            score: doc.score,
+            tokens: bpe.encode_with_special_tokens(&doc.metadata.text).len(),
+        });
+    }
+    let prompt = render_prompt(question, docs, style, glossary, inline_citations);
+    Ok(PromptPreview {
+        context_tokens: bpe.encode_with_special_tokens(&render_context(docs)).len(),
+        prompt_tokens: bpe.encode_with_special_tokens(&prompt).len(),
+        prompt: prompt,
+        chunks: chunks,
+    })
+}
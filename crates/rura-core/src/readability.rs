@@ -0,0 +1,285 @@
+use crate::data::Section;
+use anyhow::{Error, Result};
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashMap;
+
+// MIN_PARAGRAPH_CHARS is the minimum number of characters of a paragraph scoring its ancestors
+static MIN_PARAGRAPH_CHARS: usize = 25;
+// MIN_CONTENT_CHARS is the minimum number of characters of the main content, pages with less
+// are indexed with the whole body
+static MIN_CONTENT_CHARS: usize = 250;
+// MAX_SECTION_LEVEL is the deepest heading level starting a section, deeper headings stay in the
+// text of their section
+static MAX_SECTION_LEVEL: usize = 3;
+// MAX_LINK_DENSITY is the share of link text above which a boilerplate candidate is removed
+static MAX_LINK_DENSITY: f32 = 0.5;
+// PARAGRAPHS are the elements whose text scores their parent and grandparent
+static PARAGRAPHS: &str = "p, pre, td, blockquote, li";
+// BOILERPLATE are the elements removed from the main content
+static BOILERPLATE: &str = "header, footer, aside, form, noscript, style, iframe, button, dialog";
+// POSITIVE_NAMES and NEGATIVE_NAMES are parts of class and id names of content and boilerplate
+static POSITIVE_NAMES: [&str; 8] = [
+    "article", "body", "content", "entry", "main", "markdown", "post", "text",
+];
+static NEGATIVE_NAMES: [&str; 14] = [
+    "banner",
+    "breadcrumb",
+    "comment",
+    "cookie",
+    "consent",
+    "footer",
+    "header",
+    "menu",
+    "modal",
+    "nav",
+    "related",
+    "share",
+    "sidebar",
+    "social",
+];
+// BLOCKS are the elements starting a new paragraph in the text of the main content
+static BLOCKS: [&str; 21] = [
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "li",
+    "ul",
+    "ol",
+    "pre",
+    "blockquote",
+    "table",
+    "tr",
+    "dl",
+    "dt",
+    "dd",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+
+// main_content returns the html of the main content of a page with the boilerplate removed,
+// the element with the best readability score by its paragraphs, their commas and its class
+// names, penalized by its link density, None if no element holds enough text to be the content
+//
+// function needs to be non async because scraper::Html is not Send
+pub fn main_content(document: &Html) -> Result<Option<String>, Error> {
+    let paragraph_selector = selector(PARAGRAPHS)?;
+    // candidates are keyed by their node id, their score is the sum of their paragraphs
+    let mut candidates = HashMap::new();
+    for paragraph in document.select(&paragraph_selector) {
+        let text = element_text(paragraph);
+        let chars = text.chars().count();
+        if chars < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f32 + (chars as f32 / 100.0).min(3.0);
+        let mut ancestors = paragraph.ancestors().filter_map(ElementRef::wrap);
+        if let Some(parent) = ancestors.next() {
+            candidates
+                .entry(parent.id())
+                .or_insert((parent, initial_score(parent)))
+                .1 += score;
+        }
+        if let Some(grandparent) = ancestors.next() {
+            candidates
+                .entry(grandparent.id())
+                .or_insert((grandparent, initial_score(grandparent)))
+                .1 += score / 2.0;
+        }
+    }
+    let best = candidates
+        .into_values()
+        .map(|(element, score)| (element, score * (1.0 - link_density(element))))
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(element, _)| element);
+    let best = match best {
+        Some(best) if element_text(best).chars().count() >= MIN_CONTENT_CHARS => best,
+        _ => return Ok(None),
+    };
+    // boilerplate inside the content, e.g. share buttons or a related pages box, is removed
+    let boilerplate_selector = selector(BOILERPLATE)?;
+    let mut html = best.html();
+    for element in best.descendants().skip(1).filter_map(ElementRef::wrap) {
+        let boilerplate = boilerplate_selector.matches(&element)
+            || (name_weight(element) < 0.0 && link_density(element) > MAX_LINK_DENSITY);
+        if boilerplate {
+            html = html.replace(element.html().as_str(), "");
+        }
+    }
+    Ok(Some(html))
+}
+
+// structured_text returns the text of a document with a paragraph per block element and the
+// headings prefixed with one # per level, so the heading structure survives the extraction
+pub fn structured_text(document: &Html) -> String {
+    let mut text = String::new();
+    append_text(document.root_element(), &mut text);
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+// split_sections returns the sections of a structured text by its h1 to h3 headings with the
+// breadcrumb of the headings they are nested in, empty if the text has no such heading
+pub fn split_sections(text: &str) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut paragraphs: Vec<&str> = Vec::new();
+    let mut found_heading = false;
+    for paragraph in text.split("\n\n") {
+        let level = paragraph.chars().take_while(|c| *c == '#').count();
+        let heading = paragraph[level..].strip_prefix(' ');
+        match heading {
+            Some(heading) if (1..=MAX_SECTION_LEVEL).contains(&level) => {
+                push_section(&mut sections, &headings, &mut paragraphs);
+                headings.retain(|(heading_level, _)| *heading_level < level);
+                headings.push((level, heading.trim().to_string()));
+                found_heading = true;
+            }
+            _ => paragraphs.push(paragraph),
+        }
+    }
+    push_section(&mut sections, &headings, &mut paragraphs);
+    match found_heading {
+        true => sections,
+        false => Vec::new(),
+    }
+}
+
+// push_section pushes the paragraphs as section below the headings, headings without text
+// before the next heading are skipped
+fn push_section(
+    sections: &mut Vec<Section>,
+    headings: &[(usize, String)],
+    paragraphs: &mut Vec<&str>,
+) {
+    if paragraphs.is_empty() {
+        return;
+    }
+    let breadcrumb = match headings.is_empty() {
+        true => None,
+        false => Some(
+            headings
+                .iter()
+                .map(|(_, heading)| heading.as_str())
+                .collect::<Vec<_>>()
+                .join(" > "),
+        ),
+    };
+    sections.push(Section {
+        breadcrumb: breadcrumb,
+        text: paragraphs.join("\n\n"),
+    });
+    paragraphs.clear();
+}
+
+// append_text appends the text of an element to text, blocks start a new paragraph
+fn append_text(element: ElementRef, text: &mut String) {
+    let name = element.value().name();
+    let block = BLOCKS.contains(&name);
+    if block {
+        text.push_str("\n\n");
+    }
+    if let Some(level) = heading_level(name) {
+        text.push_str(&format!("{} ", "#".repeat(level)));
+    }
+    for child in element.children() {
+        match ElementRef::wrap(child) {
+            Some(child) => append_text(child, text),
+            None => {
+                if let Some(node_text) = child.value().as_text() {
+                    let node_text = node_text.split_whitespace().collect::<Vec<_>>().join(" ");
+                    if !node_text.is_empty() {
+                        if !text.is_empty() && !text.ends_with(char::is_whitespace) {
+                            text.push(' ');
+                        }
+                        text.push_str(&node_text);
+                    }
+                }
+            }
+        }
+    }
+    if block {
+        text.push_str("\n\n");
+    }
+}
+
+// heading_level returns the level of a heading element, e.g. 2 for h2
+fn heading_level(name: &str) -> Option<usize> {
+    match name.strip_prefix('h').map(str::parse::<usize>) {
+        Some(Ok(level)) if (1..=6).contains(&level) => Some(level),
+        _ => None,
+    }
+}
+
+// initial_score returns the score of an element by its tag and class and id names
+fn initial_score(element: ElementRef) -> f32 {
+    let tag_score = match element.value().name() {
+        "article" | "main" => 10.0,
+        "div" | "section" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "form" | "ul" | "ol" | "dl" | "aside" => -3.0,
+        "header" | "footer" | "nav" | "th" => -5.0,
+        _ => 0.0,
+    };
+    tag_score + name_weight(element)
+}
+
+// name_weight returns 25 for elements whose class or id names them content, -25 for names of
+// boilerplate and 0 otherwise, boilerplate names win
+fn name_weight(element: ElementRef) -> f32 {
+    let names = format!(
+        "{} {}",
+        element.value().attr("class").unwrap_or_default(),
+        element.value().id().unwrap_or_default()
+    )
+    .to_lowercase();
+    if NEGATIVE_NAMES.iter().any(|name| names.contains(name)) {
+        -25.0
+    } else if POSITIVE_NAMES.iter().any(|name| names.contains(name)) {
+        25.0
+    } else {
+        0.0
+    }
+}
+
+// link_density returns the share of the text of an element inside links
+fn link_density(element: ElementRef) -> f32 {
+    let chars = element_text(element).chars().count();
+    if chars == 0 {
+        return 0.0;
+    }
+    let link_chars: usize = element
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .filter(|descendant| descendant.value().name() == "a")
+        .map(|link| element_text(link).chars().count())
+        .sum();
+    (link_chars as f32 / chars as f32).min(1.0)
+}
+
+// element_text returns the text of an element as a single line
+fn element_text(element: ElementRef) -> String {
+    element
+        .text()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// selector parses a css selector
+fn selector(selectors: &str) -> Result<Selector, Error> {
+    Selector::parse(selectors).or(Err(anyhow::anyhow!(
+        "Failed to parse selector: {}",
+        selectors
+    )))
+}
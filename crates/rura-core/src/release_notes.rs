@@ -0,0 +1,152 @@
+use log::error;
+use scraper::{ElementRef, Html, Node};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+// HEADINGS are the elements starting a release of a release notes page
+static HEADINGS: [&str; 4] = ["h1", "h2", "h3", "h4"];
+
+// PageParser represents how the content of crawled pages is turned into documents
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum PageParser {
+    // Default indexes each page as one document
+    #[default]
+    Default,
+    // ReleaseNotes splits changelogs and release notes pages by version heading, each release
+    // is a document with its version and date in the metadata
+    ReleaseNotes,
+}
+
+// string to page parser
+impl From<&str> for PageParser {
+    fn from(s: &str) -> Self {
+        match s {
+            "default" => PageParser::Default,
+            "release_notes" => PageParser::ReleaseNotes,
+            _ => {
+                error!("Error converting page parser, unknown parser: {}", s);
+                PageParser::Default
+            }
+        }
+    }
+}
+
+// Release represents the section of a release notes page below a version heading
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    // version is the version of the heading without a leading v, e.g. 2.3.1
+    pub version: String,
+    // date is the release date as yyyy-mm-dd, if the heading or the first line below it has one
+    pub date: Option<String>,
+    // anchor is the id of the heading to link to the release
+    pub anchor: String,
+    pub text: String,
+}
+
+// split_releases returns the releases of a release notes page, the text above the first version
+// heading is dropped, pages without version headings return no releases
+pub fn split_releases(document: &Html) -> Vec<Release> {
+    let mut releases: Vec<Release> = Vec::new();
+    let mut heading: Option<ElementRef> = None;
+    // lines_below is the number of texts below the current heading, the date is looked up in
+    // the heading and the first of them
+    let mut lines_below = 0;
+    for node in document.root_element().descendants() {
+        if let Some(element) = ElementRef::wrap(node) {
+            if !HEADINGS.contains(&element.value().name()) {
+                continue;
+            }
+            let heading_text = element.text().collect::<Vec<_>>().join(" ");
+            if let Some(version) = find_version(&heading_text) {
+                heading = Some(element);
+                lines_below = 0;
+                releases.push(Release {
+                    version: version.clone(),
+                    date: find_date(&heading_text),
+                    anchor: element
+                        .value()
+                        .id()
+                        .map(str::to_string)
+                        .unwrap_or(format!("v{}", version)),
+                    text: String::new(),
+                });
+            }
+            continue;
+        }
+        let text = match node.value() {
+            Node::Text(text) => text.trim(),
+            _ => continue,
+        };
+        // the text of the heading is part of the text of the release as well
+        let in_heading = heading.map_or(false, |heading| {
+            node.ancestors()
+                .any(|ancestor| ancestor.id() == heading.id())
+        });
+        if let (Some(release), false) = (releases.last_mut(), text.is_empty()) {
+            if !in_heading {
+                if release.date.is_none() && lines_below == 0 {
+                    release.date = find_date(text);
+                }
+                lines_below += 1;
+            }
+            release.text = match release.text.is_empty() {
+                true => text.to_string(),
+                false => format!("{} {}", release.text, text),
+            };
+        }
+    }
+    releases
+}
+
+// find_version returns the first version of a text, e.g. 2.3 of "Release v2.3 (2024-01-05)",
+// versions have at least a major and a minor number
+pub fn find_version(text: &str) -> Option<String> {
+    text.split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '[' | ']' | ',' | ':'))
+        .map(|word| word.trim_start_matches(['v', 'V']))
+        .find(|word| is_version(word))
+        .map(str::to_string)
+}
+
+// version_prefixes returns the version and its shorter prefixes, e.g. 2, 2.3 and 2.3.1 of 2.3.1
+pub fn version_prefixes(version: &str) -> Vec<String> {
+    let parts: Vec<&str> = version.split('.').collect();
+    (1..=parts.len())
+        .map(|len| parts[..len].join("."))
+        .collect()
+}
+
+// is_version returns true for dotted numbers with an optional pre-release or build suffix,
+// e.g. 2.3, 2.3.1 or 2.3.0-rc.1, dates like 2024.01.05 are no versions
+fn is_version(word: &str) -> bool {
+    let core = word.split(['-', '+']).next().unwrap_or_default();
+    let parts: Vec<&str> = core.split('.').collect();
+    parts.len() >= 2
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+        && parts[0].len() < 4
+}
+
+// find_date returns the first iso date of a text as yyyy-mm-dd, dates separated by slashes or
+// dots are normalized
+pub fn find_date(text: &str) -> Option<String> {
+    text.split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '[' | ']' | ','))
+        .find_map(|word| {
+            let parts: Vec<&str> = word.split(['-', '/', '.']).collect();
+            match parts.as_slice() {
+                [year, month, day]
+                    if year.len() == 4
+                        && month.len() == 2
+                        && day.len() == 2
+                        && parts
+                            .iter()
+                            .all(|part| part.chars().all(|c| c.is_ascii_digit())) =>
+                {
+                    Some(format!("{}-{}-{}", year, month, day))
+                }
+                _ => None,
+            }
+        })
+}
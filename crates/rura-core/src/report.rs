@@ -0,0 +1,119 @@
+use crate::data::Document;
+use crate::dedup::DedupReport;
+use crate::estimate::IndexEstimate;
+use crate::summary::SummaryReport;
+use anyhow::{Error, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// CrawlDiff represents the changes of a crawl compared to the previous crawl of the same site
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct CrawlDiff {
+    pub url: String,
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub removed: Vec<String>,
+    pub unchanged: usize,
+    // unchanged_urls are the urls of the unchanged pages, kept in memory for the re-crawl
+    // schedules of the sources
+    #[serde(skip)]
+    pub unchanged_urls: Vec<String>,
+}
+
+impl CrawlDiff {
+    // new returns the diff of the crawled documents against the page hashes by url of the
+    // previous crawl
+    pub fn new(url: &str, previous: &HashMap<String, String>, documents: &[Document]) -> Self {
+        let mut diff = CrawlDiff {
+            url: url.to_string(),
+            ..Default::default()
+        };
+        let mut crawled = HashSet::new();
+        for document in documents {
+            crawled.insert(document.url.clone());
+            match previous.get(&document.url) {
+                Some(page_hash) if *page_hash == document.page_hash() => {
+                    diff.unchanged += 1;
+                    diff.unchanged_urls.push(document.url.clone());
+                }
+                Some(_) => diff.modified.push(document.url.clone()),
+                None => diff.added.push(document.url.clone()),
+            }
+        }
+        diff.removed = previous
+            .keys()
+            .filter(|url| !crawled.contains(*url))
+            .cloned()
+            .collect();
+        diff.removed.sort();
+        diff
+    }
+
+    // is_changed returns true if the page of the url was added or modified since the previous
+    // crawl
+    pub fn is_changed(&self, url: &str) -> bool {
+        self.added.iter().any(|added| added == url)
+            || self.modified.iter().any(|modified| modified == url)
+    }
+
+    // merge adds the changes of the crawl of another site, e.g. of an upload of several sites,
+    // the url lists the urls of the sites
+    pub fn merge(&mut self, other: CrawlDiff) {
+        self.url = format!("{}, {}", self.url, other.url);
+        self.added.extend(other.added);
+        self.modified.extend(other.modified);
+        self.removed.extend(other.removed);
+        self.removed.sort();
+        self.unchanged += other.unchanged;
+        self.unchanged_urls.extend(other.unchanged_urls);
+    }
+}
+
+// JobReport represents the outcome of an upload job
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct JobReport {
+    pub id: String,
+    pub crawl_diff: Option<CrawlDiff>,
+    #[serde(default)]
+    pub summaries: Option<SummaryReport>,
+    // skipped_fragments is the number of fragments already stored, set by incremental uploads
+    #[serde(default)]
+    pub skipped_fragments: Option<usize>,
+    // dedup is the number of near duplicate fragments dropped, set by uploads with dedup
+    #[serde(default)]
+    pub dedup: Option<DedupReport>,
+    // estimate is the estimated size of the index, set by dry runs instead of uploading
+    #[serde(default)]
+    pub estimate: Option<IndexEstimate>,
+    // error is the reason of a failed job, e.g. the sitemap could not be fetched
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl JobReport {
+    // new returns an empty report for the job with the given id
+    pub fn new(id: Uuid) -> Self {
+        JobReport {
+            id: id.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+// send_webhook posts the report as json payload to the webhook url
+pub async fn send_webhook(webhook_url: &str, report: &JobReport) -> Result<(), Error> {
+    info!("Sending job report {} to {}", report.id, webhook_url);
+    reqwest::Client::new()
+        .post(webhook_url)
+        .json(report)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
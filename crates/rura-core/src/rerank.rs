@@ -0,0 +1,256 @@
+use crate::data::ScoredDocument;
+use crate::ollama::{Llm, PROMPT_RERANK};
+use log::{debug, info};
+use std::cmp::Ordering;
+
+// DEFAULT_RERANK_TOP_N is the number of retrieved fragments re-scored by default
+pub static DEFAULT_RERANK_TOP_N: usize = 10;
+// MAX_RELEVANCE is the highest relevance the LLM can assign to a fragment
+static MAX_RELEVANCE: u8 = 10;
+
+// Reranker re-scores the top retrieved fragments against the query with the LLM, the vector
+// similarity of small embedding models often ranks loosely related fragments first
+pub struct Reranker {
+    // top_n is the number of retrieved fragments re-scored, the others keep their order after them
+    top_n: usize,
+}
+
+impl Default for Reranker {
+    fn default() -> Self {
+        Reranker {
+            top_n: DEFAULT_RERANK_TOP_N,
+        }
+    }
+}
+
+impl Reranker {
+    // with_top_n sets the number of retrieved fragments re-scored
+    pub fn with_top_n(mut self, top_n: usize) -> Self {
+        self.top_n = top_n;
+        self
+    }
+
+    // candidates returns the number of fragments to retrieve to return limit reranked fragments
+    pub fn candidates(&self, limit: u64) -> u64 {
+        limit.max(self.top_n as u64)
+    }
+
+    // rerank orders the top fragments by the relevance the LLM assigns them and returns the limit
+    // best ones, fragments the LLM fails to score are ranked after the scored ones in their
+    // retrieval order
+    pub async fn rerank(
+        &self,
+        llm: &Llm,
+        model: &str,
+        query: &str,
+        docs: Vec<ScoredDocument>,
+        limit: u64,
+    ) -> Vec<ScoredDocument> {
+        let mut reranked = self.relevances(llm, model, query, docs).await;
+        reranked.truncate(limit as usize);
+        reranked.into_iter().map(|(doc, _)| doc).collect()
+    }
+
+    // relevances returns all fragments in the order of rerank with the relevance the LLM assigned
+    // them, None for the fragments not re-scored or the LLM failed to score, used to explain the
+    // reranking
+    pub async fn relevances(
+        &self,
+        llm: &Llm,
+        model: &str,
+        query: &str,
+        mut docs: Vec<ScoredDocument>,
+    ) -> Vec<(ScoredDocument, Option<u8>)> {
+        docs.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+        let rest = docs.split_off(self.top_n.min(docs.len()));
+        let mut relevances = Vec::with_capacity(docs.len());
+        for doc in &docs {
+            relevances.push(score_relevance(llm, model, query, doc).await);
+        }
+        info!("Reranked {} fragments of query {}", docs.len(), query);
+        let mut reranked = rank_with_relevance(docs, relevances);
+        reranked.extend(rest.into_iter().map(|doc| (doc, None)));
+        reranked
+    }
+}
+
+// rank orders the documents by relevance, the order of documents with equal or no relevance is
+// kept
+pub fn rank(docs: Vec<ScoredDocument>, relevances: Vec<Option<u8>>) -> Vec<ScoredDocument> {
+    rank_with_relevance(docs, relevances)
+        .into_iter()
+        .map(|(doc, _)| doc)
+        .collect()
+}
+
+// rank_with_relevance orders the documents by relevance like rank and keeps the relevance of
+// each document
+fn rank_with_relevance(
+    docs: Vec<ScoredDocument>,
+    relevances: Vec<Option<u8>>,
+) -> Vec<(ScoredDocument, Option<u8>)> {
+    let mut ranked: Vec<(ScoredDocument, Option<u8>)> = docs.into_iter().zip(relevances).collect();
+    // None sorts before Some, the stable sort keeps the retrieval order on ties
+    ranked.sort_by(|a, b| b.1.cmp(&a.1));
+    ranked
+}
+
+// score_relevance asks the LLM how relevant a fragment is to the query, None if the LLM fails
+// or its reply has no relevance
+async fn score_relevance(llm: &Llm, model: &str, query: &str, doc: &ScoredDocument) -> Option<u8> {
+    let prompt = PROMPT_RERANK
+        .replace("{context}", &doc.metadata.text)
+        .replace("{question}", query);
+    match llm.generate(model, &prompt).await {
+        Ok(reply) => {
+            let relevance = parse_relevance(&reply);
+            debug!(
+                "Relevance of {} to query {}: {:?}",
+                doc.metadata.url, query, relevance
+            );
+            relevance
+        }
+        Err(e) => {
+            info!("Error reranking {}: {}", doc.metadata.url, e);
+            None
+        }
+    }
+}
+
+// parse_relevance returns the first number of the reply, capped at the maximum relevance
+pub fn parse_relevance(reply: &str) -> Option<u8> {
+    let digits: String = reply
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits
+        .parse::<u64>()
+        .ok()
+        .map(|relevance| relevance.min(MAX_RELEVANCE as u64) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_relevance, rank, rank_with_relevance, Reranker, DEFAULT_RERANK_TOP_N};
+    use crate::data::{Collection, Document, EmbeddedMetadata, ScoredDocument};
+    use crate::ollama::Llm;
+    use crate::retry::{set_retry_policy, RetryPolicy};
+    use ollama_rs::Ollama;
+
+    // scored returns a basic fragment of the page with the vector score
+    fn scored(page: &str, score: f32) -> ScoredDocument {
+        let url = format!("https://docs.example.com/{}", page);
+        let text = format!("The {} page.", page);
+        let doc = Document::new(Collection::Basic, url, page.to_string(), text.clone());
+        ScoredDocument {
+            metadata: EmbeddedMetadata::from_document(&doc, text, Collection::Basic).unwrap(),
+            score: score,
+        }
+    }
+
+    // pages returns the pages of the documents in their order
+    fn pages(docs: &[ScoredDocument]) -> Vec<&str> {
+        docs.iter().map(|doc| doc.metadata.title.as_str()).collect()
+    }
+
+    #[test]
+    fn rank_orders_by_relevance() {
+        let docs = vec![scored("a", 0.9), scored("b", 0.8), scored("c", 0.7)];
+        let ranked = rank(docs, vec![Some(2), Some(9), Some(5)]);
+        assert_eq!(pages(&ranked), vec!["b", "c", "a"]);
+    }
+
+    #[test]
+    fn rank_keeps_the_vector_order_on_ties() {
+        let docs = vec![
+            scored("a", 0.9),
+            scored("b", 0.8),
+            scored("c", 0.7),
+            scored("d", 0.6),
+        ];
+        let ranked = rank(docs, vec![Some(5), Some(8), Some(5), Some(8)]);
+        assert_eq!(pages(&ranked), vec!["b", "d", "a", "c"]);
+    }
+
+    #[test]
+    fn rank_puts_unscored_fragments_last_in_vector_order() {
+        let docs = vec![
+            scored("a", 0.9),
+            scored("b", 0.8),
+            scored("c", 0.7),
+            scored("d", 0.6),
+        ];
+        let ranked = rank_with_relevance(docs, vec![None, Some(3), None, Some(0)]);
+        let relevances: Vec<Option<u8>> = ranked.iter().map(|(_, relevance)| *relevance).collect();
+        assert_eq!(relevances, vec![Some(3), Some(0), None, None]);
+        let docs: Vec<ScoredDocument> = ranked.into_iter().map(|(doc, _)| doc).collect();
+        assert_eq!(pages(&docs), vec!["b", "d", "a", "c"]);
+    }
+
+    #[test]
+    fn rank_without_relevances_keeps_the_vector_order() {
+        let docs = vec![scored("a", 0.9), scored("b", 0.8), scored("c", 0.7)];
+        let ranked = rank(docs, vec![None, None, None]);
+        assert_eq!(pages(&ranked), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn parse_relevance_reads_the_first_number() {
+        assert_eq!(parse_relevance("7"), Some(7));
+        assert_eq!(parse_relevance("Relevance: 8/10"), Some(8));
+        assert_eq!(parse_relevance(" 0\n"), Some(0));
+        assert_eq!(parse_relevance("10"), Some(10));
+    }
+
+    #[test]
+    fn parse_relevance_caps_out_of_range_replies() {
+        assert_eq!(parse_relevance("11"), Some(10));
+        assert_eq!(parse_relevance("250"), Some(10));
+        assert_eq!(parse_relevance("99999999999999999999999"), None);
+        // the sign isn't read, a negative relevance is its number
+        assert_eq!(parse_relevance("-3"), Some(3));
+    }
+
+    #[test]
+    fn parse_relevance_ignores_garbage() {
+        assert_eq!(parse_relevance(""), None);
+        assert_eq!(parse_relevance("very relevant"), None);
+        assert_eq!(parse_relevance("ten out of ten"), None);
+    }
+
+    #[test]
+    fn candidates_oversample_to_top_n() {
+        let reranker = Reranker::default();
+        assert_eq!(reranker.candidates(3), DEFAULT_RERANK_TOP_N as u64);
+        assert_eq!(reranker.candidates(25), 25);
+        let reranker = Reranker::default().with_top_n(5);
+        assert_eq!(reranker.candidates(0), 5);
+        assert_eq!(reranker.candidates(5), 5);
+        assert_eq!(reranker.candidates(6), 6);
+        assert_eq!(Reranker::default().with_top_n(0).candidates(4), 4);
+    }
+
+    #[tokio::test]
+    async fn rerank_falls_back_to_the_vector_order_without_llm() {
+        set_retry_policy(RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        });
+        // nothing listens on the port, every fragment is left unscored
+        let llm = Llm::new(Ollama::new("http://127.0.0.1".to_string(), 1));
+        let docs = vec![
+            scored("c", 0.7),
+            scored("a", 0.9),
+            scored("d", 0.6),
+            scored("b", 0.8),
+        ];
+        let reranker = Reranker::default().with_top_n(2);
+        let relevances = reranker
+            .relevances(&llm, "llama2", "backups", docs.clone())
+            .await;
+        assert!(relevances.iter().all(|(_, relevance)| relevance.is_none()));
+        let docs = reranker.rerank(&llm, "llama2", "backups", docs, 3).await;
+        assert_eq!(pages(&docs), vec!["a", "b", "c"]);
+    }
+}
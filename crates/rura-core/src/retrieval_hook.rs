@@ -0,0 +1,317 @@
+use crate::data::ScoredDocument;
+use anyhow::{Error, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+
+// LABEL_GATE_HOOK is the name of the built-in hook keeping documents labeled for a value of the
+// request metadata, e.g. the plan of the customer, from other requests
+pub static LABEL_GATE_HOOK: &str = "label_gate";
+// BOOST_HOOK is the name of the built-in hook multiplying the score of matching documents
+pub static BOOST_HOOK: &str = "boost";
+
+// RETRIEVAL_HOOKS are the factories of the hooks the hook specs select by name, the built-in
+// hooks are registered on first use
+static RETRIEVAL_HOOKS: OnceLock<RwLock<HashMap<String, RetrievalHookFactory>>> = OnceLock::new();
+
+// RequestMetadata are the attributes of the caller of a query the hooks decide by, e.g.
+// plan = enterprise, set by the application calling rura
+pub type RequestMetadata = BTreeMap<String, String>;
+
+// HookFuture is the boxed future returned by hooks, boxed so hooks can be chained as trait
+// objects
+pub type HookFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<ScoredDocument>, Error>> + Send + 'a>>;
+
+// SharedRetrievalHook is a hook shared by the queries of the server and the client
+pub type SharedRetrievalHook = Arc<dyn RetrievalHook>;
+
+// RetrievalHookFactory returns a hook configured by the parameters of a hook spec
+pub type RetrievalHookFactory = fn(&BTreeMap<String, String>) -> Result<SharedRetrievalHook, Error>;
+
+// HookSpec represents a retrieval hook of the config file by its name and parameters, e.g.
+//
+// [[retrieval_hooks]]
+// name = "label_gate"
+// params = { key = "plan" }
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct HookSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, String>,
+}
+
+// RetrievalHook filters, boosts or reorders the retrieved documents of a query before they are
+// rendered into the prompt, e.g. by business rules of the deployment, implement it and register
+// its factory with register_retrieval_hook to select it in the config file
+pub trait RetrievalHook: Send + Sync {
+    // name is the name hook specs select the hook by
+    fn name(&self) -> &str;
+
+    // apply returns the documents of the query kept for the request, their order is restored by
+    // score after all hooks ran
+    fn apply<'a>(
+        &'a self,
+        query: &'a str,
+        docs: Vec<ScoredDocument>,
+        metadata: &'a RequestMetadata,
+    ) -> HookFuture<'a>;
+}
+
+// register_retrieval_hook makes the hook of the factory available to the hook specs by the name,
+// replacing a hook of the same name
+pub fn register_retrieval_hook(name: &str, factory: RetrievalHookFactory) {
+    match retrieval_hooks()
+        .write()
+        .unwrap()
+        .insert(name.to_string(), factory)
+    {
+        Some(_) => info!("Replaced retrieval hook {}", name),
+        None => info!("Registered retrieval hook {}", name),
+    }
+}
+
+// retrieval_hook_names returns the names of the registered hooks in alphabetical order
+pub fn retrieval_hook_names() -> Vec<String> {
+    let mut names: Vec<String> = retrieval_hooks().read().unwrap().keys().cloned().collect();
+    names.sort();
+    names
+}
+
+// retrieval_hooks returns the registered factories, the built-in hooks are registered on first
+// use
+fn retrieval_hooks() -> &'static RwLock<HashMap<String, RetrievalHookFactory>> {
+    RETRIEVAL_HOOKS.get_or_init(|| {
+        let built_ins: Vec<(&str, RetrievalHookFactory)> = vec![
+            (LABEL_GATE_HOOK, LabelGate::from_params),
+            (BOOST_HOOK, Boost::from_params),
+        ];
+        RwLock::new(
+            built_ins
+                .into_iter()
+                .map(|(name, factory)| (name.to_string(), factory))
+                .collect(),
+        )
+    })
+}
+
+// retrieval_hook_factory returns the factory of the registered hook of the name
+fn retrieval_hook_factory(name: &str) -> Result<RetrievalHookFactory, Error> {
+    let factory = retrieval_hooks().read().unwrap().get(name).copied();
+    factory.ok_or(anyhow::anyhow!(
+        "unknown retrieval hook: {}, registered hooks: {}",
+        name,
+        retrieval_hook_names().join(", ")
+    ))
+}
+
+// RetrievalHooks represents the hooks run between the search and the prompt, in the order of
+// the config file
+#[derive(Clone, Default)]
+pub struct RetrievalHooks {
+    hooks: Vec<SharedRetrievalHook>,
+}
+
+impl RetrievalHooks {
+    // new returns the hooks of the hook specs, it fails if a hook isn't registered or its
+    // parameters are invalid
+    pub fn new(specs: &[HookSpec]) -> Result<Self, Error> {
+        let mut hooks = Vec::new();
+        for spec in specs {
+            let factory = retrieval_hook_factory(&spec.name)?;
+            let hook = factory(&spec.params)
+                .map_err(|e| anyhow::anyhow!("Invalid retrieval hook {}: {}", spec.name, e))?;
+            hooks.push(hook);
+        }
+        if !hooks.is_empty() {
+            info!("Loaded {} retrieval hooks", hooks.len());
+        }
+        Ok(RetrievalHooks { hooks: hooks })
+    }
+
+    // with_hook appends a hook, e.g. one holding a client of an entitlement service
+    pub fn with_hook(mut self, hook: SharedRetrievalHook) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // is_empty returns true if no hook is configured
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    // apply runs the hooks on the retrieved documents and sorts the kept documents by score
+    #[tracing::instrument(skip_all, fields(docs = docs.len()))]
+    pub async fn apply(
+        &self,
+        query: &str,
+        mut docs: Vec<ScoredDocument>,
+        metadata: &RequestMetadata,
+    ) -> Result<Vec<ScoredDocument>> {
+        if self.hooks.is_empty() {
+            return Ok(docs);
+        }
+        let retrieved = docs.len();
+        for hook in &self.hooks {
+            docs = hook
+                .apply(query, docs, metadata)
+                .await
+                .map_err(|e| anyhow::anyhow!("Retrieval hook {} failed: {}", hook.name(), e))?;
+        }
+        docs.sort_by(|a, b| b.score.total_cmp(&a.score));
+        info!(
+            "Retrieval hooks kept {} of {} documents",
+            docs.len(),
+            retrieved
+        );
+        Ok(docs)
+    }
+}
+
+// LabelGate keeps documents labeled with the label prefix and a value, e.g. plan:enterprise, from
+// requests whose metadata key isn't set to one of the values of their labels, documents without
+// such a label are kept for all requests, the prefix is the key and a colon by default
+pub struct LabelGate {
+    key: String,
+    label_prefix: String,
+}
+
+impl LabelGate {
+    // from_params returns the hook of the mandatory key and the label_prefix parameters
+    pub fn from_params(params: &BTreeMap<String, String>) -> Result<SharedRetrievalHook, Error> {
+        let key = params
+            .get("key")
+            .ok_or(anyhow::anyhow!("missing parameter key"))?;
+        Ok(Arc::new(LabelGate {
+            key: key.clone(),
+            label_prefix: params
+                .get("label_prefix")
+                .cloned()
+                .unwrap_or(format!("{}:", key)),
+        }))
+    }
+
+    // allows returns true if the document isn't gated or the value of the request is one of the
+    // values of its labels
+    fn allows(&self, doc: &ScoredDocument, value: Option<&String>) -> bool {
+        let mut values = doc
+            .metadata
+            .labels
+            .iter()
+            .filter_map(|label| label.strip_prefix(&self.label_prefix))
+            .peekable();
+        if values.peek().is_none() {
+            return true;
+        }
+        match value {
+            Some(value) => values.any(|gated| gated.eq_ignore_ascii_case(value)),
+            None => false,
+        }
+    }
+}
+
+impl RetrievalHook for LabelGate {
+    fn name(&self) -> &str {
+        LABEL_GATE_HOOK
+    }
+
+    fn apply<'a>(
+        &'a self,
+        _query: &'a str,
+        docs: Vec<ScoredDocument>,
+        metadata: &'a RequestMetadata,
+    ) -> HookFuture<'a> {
+        Box::pin(async move {
+            let value = metadata.get(&self.key);
+            Ok(docs
+                .into_iter()
+                .filter(|doc| self.allows(doc, value))
+                .collect())
+        })
+    }
+}
+
+// Boost multiplies the score of the documents whose url starts with the url_prefix parameter or
+// that have the label parameter by the factor parameter, a metadata parameter like plan=trial
+// restricts the boost to the requests with that metadata
+pub struct Boost {
+    factor: f32,
+    url_prefix: Option<String>,
+    label: Option<String>,
+    metadata: Option<(String, String)>,
+}
+
+impl Boost {
+    // from_params returns the hook of the mandatory factor, the url_prefix or label and the
+    // metadata parameters
+    pub fn from_params(params: &BTreeMap<String, String>) -> Result<SharedRetrievalHook, Error> {
+        let factor = params
+            .get("factor")
+            .ok_or(anyhow::anyhow!("missing parameter factor"))?;
+        let factor = factor
+            .parse::<f32>()
+            .map_err(|_| anyhow::anyhow!("invalid parameter factor: {}", factor))?;
+        let url_prefix = params.get("url_prefix").cloned();
+        let label = params.get("label").cloned();
+        if url_prefix.is_none() && label.is_none() {
+            return Err(anyhow::anyhow!("missing parameter url_prefix or label"));
+        }
+        let metadata = match params.get("metadata") {
+            Some(metadata) => Some(
+                metadata
+                    .split_once('=')
+                    .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+                    .ok_or(anyhow::anyhow!("invalid parameter metadata: {}", metadata))?,
+            ),
+            None => None,
+        };
+        Ok(Arc::new(Boost {
+            factor: factor,
+            url_prefix: url_prefix,
+            label: label,
+            metadata: metadata,
+        }))
+    }
+
+    // matches returns true if the document is boosted
+    fn matches(&self, doc: &ScoredDocument) -> bool {
+        let url = self
+            .url_prefix
+            .as_ref()
+            .map_or(false, |url_prefix| doc.metadata.url.starts_with(url_prefix));
+        let label = self
+            .label
+            .as_ref()
+            .map_or(false, |label| doc.metadata.labels.contains(label));
+        url || label
+    }
+}
+
+impl RetrievalHook for Boost {
+    fn name(&self) -> &str {
+        BOOST_HOOK
+    }
+
+    fn apply<'a>(
+        &'a self,
+        _query: &'a str,
+        mut docs: Vec<ScoredDocument>,
+        metadata: &'a RequestMetadata,
+    ) -> HookFuture<'a> {
+        Box::pin(async move {
+            let applies = self
+                .metadata
+                .as_ref()
+                .map_or(true, |(key, value)| metadata.get(key) == Some(value));
+            if applies {
+                for doc in docs.iter_mut().filter(|doc| self.matches(doc)) {
+                    doc.score *= self.factor;
+                }
+            }
+            Ok(docs)
+        })
+    }
+}
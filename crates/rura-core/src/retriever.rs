@@ -0,0 +1,1348 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::CrawlProfile;
+use crate::data::{self, Document};
+use crate::faq::extract_faqs;
+use crate::progress_tracker::{Stage, StageCallback, StageProgress};
+use crate::readability::{main_content, split_sections, structured_text};
+use crate::release_notes::{split_releases, PageParser};
+use crate::robots::{fetch_robots, Robots};
+use crate::telemetry::record_duration;
+use anyhow::{Error, Result};
+use log::info;
+use scraper::{Html, Selector};
+use tokio::sync::Semaphore;
+use tokio::task;
+use tracing::{info_span, Instrument};
+
+// get_urls returns a vector of urls from a sitemap.xml
+//
+// function needs to be non async because scraper::Html is not Send, grmbl
+pub fn get_urls(body: String) -> Result<Vec<String>, Error> {
+    let document = Html::parse_document(&body);
+    let selector =
+        Selector::parse(r#"loc"#).or(Err(anyhow::anyhow!("Failed to parse loc selector")))?;
+
+    let mut urls = Vec::new();
+    for sitemap_url in document.select(&selector) {
+        info!("Fetching {}", sitemap_url.inner_html());
+        // TODO(marco): handle recursive sitemaps
+        urls.push(sitemap_url.inner_html().to_string());
+    }
+    Ok(urls)
+}
+
+// USER_AGENT identifies the crawler to the indexed sites
+pub static USER_AGENT: &str = concat!("rust-a-rag-us/", env!("CARGO_PKG_VERSION"));
+// ROBOTS_USER_AGENT is the user agent matched against the groups of robots.txt files
+static ROBOTS_USER_AGENT: &str = "rust-a-rag-us";
+// DEFAULT_CONCURRENT_REQUESTS is the default maximum number of concurrent requests of a crawl
+pub static DEFAULT_CONCURRENT_REQUESTS: usize = 10;
+// DEFAULT_CRAWL_DELAY is the default minimum delay between two requests to the same host
+pub static DEFAULT_CRAWL_DELAY: Duration = Duration::from_millis(200);
+// DEFAULT_MAX_PAGINATION_PAGES is the default maximum number of further pages followed from a
+// page of a paginated listing
+pub static DEFAULT_MAX_PAGINATION_PAGES: usize = 20;
+// NEXT_PAGE_SELECTORS are the selectors of the link to the next page of a listing in their
+// order, the rel=next links first and common pagination markup after
+static NEXT_PAGE_SELECTORS: &[&str] = &[
+    "link[rel~=next]",
+    "a[rel~=next]",
+    ".pagination a.next",
+    ".pagination .next a",
+    "a.next",
+    "a.next-page",
+    ".nav-next a",
+    "a[aria-label=Next]",
+    "a[aria-label='Next page']",
+];
+// NEXT_PAGE_TEXTS are the lowercase texts of links to the next page of a listing without
+// pagination markup
+static NEXT_PAGE_TEXTS: &[&str] = &[
+    "next",
+    "next page",
+    "next »",
+    "next ›",
+    "older posts",
+    "older entries",
+    "»",
+    "›",
+];
+
+// SitemapOptions represents options used when fetching documents from a sitemap
+#[derive(Debug, Clone)]
+pub struct SitemapOptions {
+    // locales restricts the documents to the given languages in order of preference, e.g. en
+    // or de-ch, the translations of a page are folded into the first locale it is available in,
+    // documents of all languages are fetched if empty
+    pub locales: Vec<String>,
+    // concurrency is the maximum number of concurrent requests of the crawl
+    pub concurrency: usize,
+    // crawl_delay is the minimum delay between two requests to the same host, a longer
+    // crawl-delay of the robots.txt of the host is honored
+    pub crawl_delay: Duration,
+    // respect_robots skips the urls disallowed by the robots.txt of their host
+    pub respect_robots: bool,
+    // profile sets the headers, url patterns, selectors and chunking of the crawl
+    pub profile: CrawlProfile,
+    // max_pages limits the number of fetched pages, e.g. to test a profile, all pages are
+    // fetched if unset
+    pub max_pages: Option<usize>,
+    // skip_urls are not fetched, e.g. the pages of a source not due for a re-crawl
+    pub skip_urls: HashSet<String>,
+}
+
+impl Default for SitemapOptions {
+    fn default() -> Self {
+        SitemapOptions {
+            locales: Vec::new(),
+            concurrency: DEFAULT_CONCURRENT_REQUESTS,
+            crawl_delay: DEFAULT_CRAWL_DELAY,
+            respect_robots: true,
+            profile: CrawlProfile::default(),
+            max_pages: None,
+            skip_urls: HashSet::new(),
+        }
+    }
+}
+
+// sitemap returns a vector of documents from a sitemap.xml
+pub async fn sitemap(url: &str) -> Result<Vec<Document>, Error> {
+    sitemap_with_options(url, &SitemapOptions::default()).await
+}
+
+// sitemap_with_options returns a vector of documents from a sitemap.xml using the given options
+pub async fn sitemap_with_options(
+    url: &str,
+    options: &SitemapOptions,
+) -> Result<Vec<Document>, Error> {
+    sitemap_with_progress(url, options, &mut StageProgress::default()).await
+}
+
+// sitemap_with_progress returns a vector of documents from a sitemap.xml using the given options
+// and records the fetched and parsed pages in stages
+pub async fn sitemap_with_progress(
+    url: &str,
+    options: &SitemapOptions,
+    stages: &mut StageProgress,
+) -> Result<Vec<Document>, Error> {
+    sitemap_with_callback(url, options, stages, None).await
+}
+
+// sitemap_with_callback returns a vector of documents from a sitemap.xml using the given options
+// and records the fetched and parsed pages in stages, each fetched page and the parsing are also
+// reported to on_stage while the crawl runs
+#[tracing::instrument(skip(options, stages, on_stage))]
+pub async fn sitemap_with_callback(
+    url: &str,
+    options: &SitemapOptions,
+    stages: &mut StageProgress,
+    on_stage: Option<StageCallback>,
+) -> Result<Vec<Document>, Error> {
+    let client = crawl_client(&options.profile)?;
+    let urls = sitemap_urls(&client, url, options).await?;
+    pages_with_callback(&client, urls, options, stages, on_stage).await
+}
+
+// pages_with_callback returns a vector of documents from the pages of the urls using the given
+// options, the further pages of paginated listings are followed if the profile enables it,
+// fetched and parsed pages are recorded in stages and reported to on_stage
+pub async fn pages_with_callback(
+    client: &reqwest::Client,
+    urls: Vec<String>,
+    options: &SitemapOptions,
+    stages: &mut StageProgress,
+    on_stage: Option<StageCallback>,
+) -> Result<Vec<Document>, Error> {
+    let mut record = |stage: Stage, count: usize, duration: Duration| {
+        stages.record(stage, count, duration);
+        if let Some(on_stage) = &on_stage {
+            on_stage(stage, count, duration);
+        }
+    };
+    let mut bodies = fetch_bodies(client, urls.clone(), options, &mut record).await?;
+    if options.profile.follow_pagination.unwrap_or(false) {
+        let paginated = fetch_pagination(client, &urls, &bodies, options, &mut record).await?;
+        bodies.extend(paginated);
+    }
+    let total_bodies = bodies.len();
+    let start = Instant::now();
+    let documents = parse_contents(bodies, &options.profile)?;
+    record(Stage::Parsed, documents.len(), start.elapsed());
+    // pages without a body are skipped by the parser
+    record(
+        Stage::Failed,
+        total_bodies - documents.len(),
+        Duration::default(),
+    );
+    let documents = fold_locales(documents, &options.locales);
+    Ok(documents)
+}
+
+// sitemap_urls returns the page urls of a sitemap.xml, filtered by the locales, url patterns,
+// skipped urls and max pages of the options, if the default /sitemap.xml of the site is not
+// found the sitemaps listed in its robots.txt are read instead
+#[tracing::instrument(skip(client, options))]
+pub async fn sitemap_urls(
+    client: &reqwest::Client,
+    url: &str,
+    options: &SitemapOptions,
+) -> Result<Vec<String>, Error> {
+    let mut url_with_sitemap: String = url.to_string();
+    if !url_with_sitemap.ends_with("sitemap.xml") {
+        url_with_sitemap.push_str("/sitemap.xml");
+    }
+    let urls = match fetch_sitemap(client, &url_with_sitemap).await? {
+        Some(urls) => urls,
+        None if is_default_sitemap(url) => robots_sitemap_urls(client, &url_with_sitemap).await?,
+        None => return Err(anyhow::anyhow!("Sitemap {} not found", url_with_sitemap)),
+    };
+    let urls = filter_locale_urls(urls, &options.locales);
+    let mut urls = filter_profile_urls(urls, &options.profile);
+    if !options.skip_urls.is_empty() {
+        let total_urls = urls.len();
+        urls.retain(|url| !options.skip_urls.contains(url));
+        info!("Skipped {} urls not due", total_urls - urls.len());
+    }
+    if let Some(max_pages) = options.max_pages {
+        urls.truncate(max_pages);
+    }
+    Ok(urls)
+}
+
+// fetch_sitemap returns the urls listed in the sitemap, None if the sitemap is not found
+async fn fetch_sitemap(
+    client: &reqwest::Client,
+    sitemap_url: &str,
+) -> Result<Option<Vec<String>>, Error> {
+    let resp = match client.get(sitemap_url).send().await {
+        Ok(x) => x,
+        Err(err) => {
+            return Err(anyhow::anyhow!(
+                "Failed to fetch sitemap: {}",
+                err.to_string()
+            ))
+        }
+    };
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        info!("No sitemap at {}", sitemap_url);
+        return Ok(None);
+    }
+    let text = resp.text().await?;
+    Ok(Some(get_urls(text)?))
+}
+
+// is_default_sitemap returns true if the url is a site or its sitemap.xml at the root, the urls
+// naming another sitemap are not looked up in the robots.txt
+fn is_default_sitemap(url: &str) -> bool {
+    if !url.ends_with("sitemap.xml") {
+        return true;
+    }
+    reqwest::Url::parse(url).map_or(false, |parsed| {
+        parsed.path().trim_start_matches('/') == "sitemap.xml"
+    })
+}
+
+// robots_sitemap_urls returns the urls of the sitemaps listed in the robots.txt of the host of
+// the url, the sitemaps not found are skipped, it fails if none is listed or found
+async fn robots_sitemap_urls(client: &reqwest::Client, url: &str) -> Result<Vec<String>, Error> {
+    let parsed = reqwest::Url::parse(url)?;
+    let robots = fetch_robots(client, &parsed, ROBOTS_USER_AGENT).await;
+    if robots.sitemaps().is_empty() {
+        return Err(anyhow::anyhow!(
+            "Sitemap {} not found and no sitemap listed in robots.txt",
+            url
+        ));
+    }
+    // the pages listed by several sitemaps are read once
+    let mut seen = HashSet::new();
+    let mut urls: Vec<String> = Vec::new();
+    let mut found = false;
+    for sitemap_url in robots.sitemaps() {
+        info!("Reading sitemap {} listed in robots.txt", sitemap_url);
+        if let Some(sitemap_urls) = fetch_sitemap(client, sitemap_url).await? {
+            found = true;
+            for sitemap_url in sitemap_urls {
+                if seen.insert(sitemap_url.clone()) {
+                    urls.push(sitemap_url);
+                }
+            }
+        }
+    }
+    if !found {
+        return Err(anyhow::anyhow!(
+            "Sitemap {} not found nor any of the sitemaps listed in robots.txt: {}",
+            url,
+            robots.sitemaps().join(", ")
+        ));
+    }
+    Ok(urls)
+}
+
+// ISO_639_1 are the two letter language codes of ISO 639-1 in alphabetical order, a path
+// segment of two letters is only a locale if it is one of them, e.g. not go, js or ui
+static ISO_639_1: &[&str] = &[
+    "aa", "ab", "ae", "af", "ak", "am", "an", "ar", "as", "av", "ay", "az", "ba", "be", "bg", "bi",
+    "bm", "bn", "bo", "br", "bs", "ca", "ce", "ch", "co", "cr", "cs", "cu", "cv", "cy", "da", "de",
+    "dv", "dz", "ee", "el", "en", "eo", "es", "et", "eu", "fa", "ff", "fi", "fj", "fo", "fr", "fy",
+    "ga", "gd", "gl", "gn", "gu", "gv", "ha", "he", "hi", "ho", "hr", "ht", "hu", "hy", "hz", "ia",
+    "id", "ie", "ig", "ii", "ik", "io", "is", "it", "iu", "ja", "jv", "ka", "kg", "ki", "kj", "kk",
+    "kl", "km", "kn", "ko", "kr", "ks", "ku", "kv", "kw", "ky", "la", "lb", "lg", "li", "ln", "lo",
+    "lt", "lu", "lv", "mg", "mh", "mi", "mk", "ml", "mn", "mr", "ms", "mt", "my", "na", "nb", "nd",
+    "ne", "ng", "nl", "nn", "no", "nr", "nv", "ny", "oc", "oj", "om", "or", "os", "pa", "pi", "pl",
+    "ps", "pt", "qu", "rm", "rn", "ro", "ru", "rw", "sa", "sc", "sd", "se", "sg", "si", "sk", "sl",
+    "sm", "sn", "so", "sq", "sr", "ss", "st", "su", "sv", "sw", "ta", "te", "tg", "th", "ti", "tk",
+    "tl", "tn", "to", "tr", "ts", "tt", "tw", "ty", "ug", "uk", "ur", "uz", "ve", "vi", "vo", "wa",
+    "wo", "xh", "yi", "yo", "za", "zh", "zu",
+];
+
+// is_locale returns true if a path segment looks like a locale, e.g. en, de-ch or pt_BR, the
+// language must be an ISO 639-1 code
+fn is_locale(segment: &str) -> bool {
+    let is_language = |s: &str| ISO_639_1.binary_search(&s).is_ok();
+    let parts: Vec<&str> = segment.split(|c| c == '-' || c == '_').collect();
+    match parts.as_slice() {
+        [language] => is_language(language),
+        [language, region] => {
+            is_language(language)
+                && region.len() == 2
+                && region.chars().all(|c| c.is_ascii_alphabetic())
+        }
+        _ => false,
+    }
+}
+
+// normalize_locale returns a lower case locale using '-' as separator
+fn normalize_locale(locale: &str) -> String {
+    locale.trim().to_lowercase().replace('_', "-")
+}
+
+// split_locale_url returns the locale of the first path segment and the url without it
+pub fn split_locale_url(url: &str) -> (Option<String>, String) {
+    let mut parsed = match reqwest::Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(_) => return (None, url.to_string()),
+    };
+    let segments: Vec<String> = match parsed.path_segments() {
+        Some(segments) => segments.map(|s| s.to_string()).collect(),
+        None => return (None, url.to_string()),
+    };
+    match segments.first() {
+        Some(segment) if is_locale(segment) => {
+            let locale = normalize_locale(segment);
+            parsed.set_path(&format!("/{}", segments[1..].join("/")));
+            (Some(locale), parsed.to_string())
+        }
+        _ => (None, url.to_string()),
+    }
+}
+
+// locale_allowed returns true if no locales are configured or the locale matches one of them,
+// a configured language without region matches all of its regions
+fn locale_allowed(locale: &str, locales: &[String]) -> bool {
+    if locales.is_empty() {
+        return true;
+    }
+    let locale = normalize_locale(locale);
+    let language = locale.split('-').next().unwrap_or_default().to_string();
+    locales
+        .iter()
+        .map(|l| normalize_locale(l))
+        .any(|l| l == locale || l == language)
+}
+
+// filter_locale_urls removes urls with a locale path segment not matching the locales
+fn filter_locale_urls(urls: Vec<String>, locales: &[String]) -> Vec<String> {
+    let total_urls = urls.len();
+    let urls: Vec<String> = urls
+        .into_iter()
+        .filter(|url| match split_locale_url(url).0 {
+            Some(locale) => locale_allowed(&locale, locales),
+            None => true,
+        })
+        .collect();
+    if urls.len() != total_urls {
+        info!(
+            "Skipped {} urls not matching locales: {:?}",
+            total_urls - urls.len(),
+            locales
+        );
+    }
+    urls
+}
+
+// filter_profile_urls removes urls not matching the include and exclude patterns of the profile
+fn filter_profile_urls(urls: Vec<String>, profile: &CrawlProfile) -> Vec<String> {
+    let total_urls = urls.len();
+    let urls: Vec<String> = urls.into_iter().filter(|url| profile.allows(url)).collect();
+    if urls.len() != total_urls {
+        info!(
+            "Skipped {} urls not matching the profile patterns",
+            total_urls - urls.len()
+        );
+    }
+    urls
+}
+
+// fold_locales removes documents not matching the locales and folds the translations sharing a
+// canonical url into the documents of their preferred locale, the first of the locales one of
+// them matches, all translations are kept if no locales are configured
+fn fold_locales(documents: Vec<Document>, locales: &[String]) -> Vec<Document> {
+    let total_documents = documents.len();
+    let documents: Vec<Document> = documents
+        .into_iter()
+        .filter(|doc| match &doc.language {
+            Some(language) => locale_allowed(language, locales),
+            None => true,
+        })
+        .collect();
+    if documents.len() != total_documents {
+        info!(
+            "Skipped {} documents not matching locales: {:?}",
+            total_documents - documents.len(),
+            locales
+        );
+    }
+    if locales.is_empty() {
+        return documents;
+    }
+    // preferred is the rank and the language of the documents kept by canonical url, the pages
+    // split into several documents keep all of them
+    let mut preferred: HashMap<&String, (usize, Option<&String>)> = HashMap::new();
+    for doc in &documents {
+        if let Some(canonical_url) = &doc.canonical_url {
+            let rank = locale_rank(doc.language.as_deref(), locales);
+            match preferred.get(canonical_url) {
+                Some((best, _)) if *best <= rank => {}
+                _ => {
+                    preferred.insert(canonical_url, (rank, doc.language.as_ref()));
+                }
+            }
+        }
+    }
+    let kept: Vec<bool> = documents
+        .iter()
+        .map(|doc| match &doc.canonical_url {
+            Some(canonical_url) => preferred
+                .get(canonical_url)
+                .map_or(true, |(_, language)| *language == doc.language.as_ref()),
+            None => true,
+        })
+        .collect();
+    let folded = kept.iter().filter(|kept| !**kept).count();
+    if folded > 0 {
+        info!(
+            "Folded {} translations into their preferred locale of {:?}",
+            folded, locales
+        );
+    }
+    documents
+        .into_iter()
+        .zip(kept)
+        .filter_map(|(doc, kept)| kept.then_some(doc))
+        .collect()
+}
+
+// locale_rank returns the position of the first of the locales the language matches, the
+// position after the locales if the language is unknown
+fn locale_rank(language: Option<&str>, locales: &[String]) -> usize {
+    language
+        .and_then(|language| {
+            locales
+                .iter()
+                .position(|locale| locale_allowed(language, std::slice::from_ref(locale)))
+        })
+        .unwrap_or(locales.len())
+}
+
+// detect_language returns the language of a page from the html lang attribute or the url
+fn detect_language(document: &Html, url: &str) -> Option<String> {
+    match document.root_element().value().attr("lang") {
+        Some(lang) if !lang.trim().is_empty() => Some(normalize_locale(lang)),
+        _ => split_locale_url(url).0,
+    }
+}
+
+// detect_canonical_url returns the url grouping the translations of a page, taken from the
+// x-default hreflang alternate or the url without its locale path segment
+fn detect_canonical_url(document: &Html, url: &str) -> Result<Option<String>, Error> {
+    let selector = Selector::parse(r#"link[rel="alternate"][hreflang="x-default"]"#)
+        .or(Err(anyhow::anyhow!("Failed to parse hreflang selector")))?;
+    if let Some(href) = document
+        .select(&selector)
+        .next()
+        .and_then(|link| link.value().attr("href"))
+    {
+        return Ok(Some(href.to_string()));
+    }
+    match split_locale_url(url) {
+        (Some(_), canonical_url) => Ok(Some(canonical_url)),
+        (None, _) => Ok(None),
+    }
+}
+
+// crawl_client returns the http client of crawls identifying itself with the USER_AGENT and
+// sending the headers of the profile
+pub fn crawl_client(profile: &CrawlProfile) -> Result<reqwest::Client, Error> {
+    Ok(reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .default_headers(profile.header_map()?)
+        .build()?)
+}
+
+// HostLimiter spaces the requests to the same host by the crawl delay of the host
+struct HostLimiter {
+    delays: HashMap<String, Duration>,
+    default_delay: Duration,
+    next_requests: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostLimiter {
+    // wait_turn waits until the host of the url may be requested again and reserves the
+    // next slot of the host
+    async fn wait_turn(&self, url: &str) {
+        let host = host_of(url);
+        let delay = self
+            .delays
+            .get(&host)
+            .copied()
+            .unwrap_or(self.default_delay);
+        let wait = {
+            let mut next_requests = self.next_requests.lock().unwrap();
+            let now = Instant::now();
+            let slot = next_requests.get(&host).copied().unwrap_or(now).max(now);
+            next_requests.insert(host, slot + delay);
+            slot - now
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+// host_of returns the origin of a url, e.g. https://docs.lagoon.sh
+fn host_of(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) => parsed.origin().ascii_serialization(),
+        Err(_) => url.to_string(),
+    }
+}
+
+// apply_robots fetches the robots.txt of each host, removes the disallowed urls and returns
+// the limiter spacing the requests by the crawl delay of the hosts
+async fn apply_robots(
+    client: &reqwest::Client,
+    urls: Vec<String>,
+    options: &SitemapOptions,
+) -> (Vec<String>, HostLimiter) {
+    let mut limiter = HostLimiter {
+        delays: HashMap::new(),
+        default_delay: options.crawl_delay,
+        next_requests: Mutex::new(HashMap::new()),
+    };
+    if !options.respect_robots {
+        return (urls, limiter);
+    }
+
+    let mut robots = HashMap::new();
+    for url in &urls {
+        let host = host_of(url);
+        if robots.contains_key(&host) {
+            continue;
+        }
+        let host_robots = match reqwest::Url::parse(url) {
+            Ok(parsed) => fetch_robots(client, &parsed, ROBOTS_USER_AGENT).await,
+            Err(_) => Robots::default(),
+        };
+        if let Some(crawl_delay) = host_robots.crawl_delay() {
+            info!("Crawl delay of {} is {:?}", host, crawl_delay);
+            limiter
+                .delays
+                .insert(host.clone(), crawl_delay.max(options.crawl_delay));
+        }
+        robots.insert(host, host_robots);
+    }
+
+    let total_urls = urls.len();
+    let urls: Vec<String> = urls
+        .into_iter()
+        .filter(|url| match reqwest::Url::parse(url) {
+            Ok(parsed) => robots
+                .get(&host_of(url))
+                .map_or(true, |robots| robots.is_allowed(&parsed)),
+            Err(_) => true,
+        })
+        .collect();
+    if urls.len() != total_urls {
+        info!(
+            "Skipped {} urls disallowed by robots.txt",
+            total_urls - urls.len()
+        );
+    }
+    (urls, limiter)
+}
+
+// PDF_SIGNATURE is the start of pdf files, detects pdfs served without their content type
+static PDF_SIGNATURE: &[u8] = b"%PDF-";
+
+// Content is the body of a url by its content type
+enum Content {
+    Html(String),
+    Pdf(Vec<u8>),
+}
+
+// Body is a struct containing a url and a body
+struct Body {
+    url: String,
+    content: Content,
+}
+
+// read_content reads the body of a response as html or pdf, detected by the content type or the
+// pdf signature of bodies without a specific content type
+async fn read_content(response: reqwest::Response) -> Result<Content, Error> {
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+        .to_lowercase();
+    // text is decoded with the charset of the response
+    if content_type.starts_with("text/") || content_type.contains("html") {
+        return Ok(Content::Html(response.text().await?));
+    }
+    let bytes = response.bytes().await?;
+    Ok(content_of(&content_type, bytes.to_vec()))
+}
+
+// content_of returns the content of a body by its lowercase content type or the pdf signature
+fn content_of(content_type: &str, bytes: Vec<u8>) -> Content {
+    match content_type.starts_with("application/pdf") || bytes.starts_with(PDF_SIGNATURE) {
+        true => Content::Pdf(bytes),
+        false => Content::Html(String::from_utf8_lossy(&bytes).into_owned()),
+    }
+}
+
+// parse_body returns the documents of a body fetched outside of a crawl, e.g. by an ingest
+// source, as html or pdf by its content type, selected and chunked as set by the profile
+pub fn parse_body(
+    url: String,
+    content_type: &str,
+    bytes: Vec<u8>,
+    profile: &CrawlProfile,
+) -> Result<Vec<Document>, Error> {
+    let body = Body {
+        url: url,
+        content: content_of(&content_type.to_lowercase(), bytes),
+    };
+    parse_contents(vec![body], profile)
+}
+
+// content_size returns the size of the content in bytes
+fn content_size(content: &Content) -> usize {
+    match content {
+        Content::Html(html) => html.len(),
+        Content::Pdf(bytes) => bytes.len(),
+    }
+}
+
+// fetch_bodies returns a vector of bodies from a vector of urls, honoring the robots.txt,
+// the concurrency and the crawl delay of the options
+#[tracing::instrument(skip_all, fields(urls = urls.len()))]
+async fn fetch_bodies(
+    client: &reqwest::Client,
+    urls: Vec<String>,
+    options: &SitemapOptions,
+    record: &mut impl FnMut(Stage, usize, Duration),
+) -> Result<Vec<Body>, Error> {
+    let now = std::time::Instant::now();
+    let (urls, limiter) = apply_robots(client, urls, options).await;
+    let limiter = Arc::new(limiter);
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let mut tasks = Vec::new();
+    // the fetched pages are counted by the tasks and recorded while the crawl runs
+    let fetched = Arc::new(AtomicUsize::new(0));
+    let mut progress = FetchProgress {
+        recorded: 0,
+        last: now,
+    };
+
+    for url in urls {
+        let permit = semaphore.clone().acquire_owned().await?;
+        progress.record(&fetched, record);
+        let client = client.clone();
+        let limiter = limiter.clone();
+        let fetched = fetched.clone();
+        let span = info_span!("fetch", url = %url);
+        let task = task::spawn(
+            async move {
+                limiter.wait_turn(&url).await;
+                let response = match client.get(&url).send().await {
+                    Ok(resp) => resp,
+                    Err(err) => return Err(anyhow::anyhow!("Error fetching URL {}: {}", url, err)),
+                };
+
+                let content = read_content(response).await?;
+                drop(permit);
+                fetched.fetch_add(1, Ordering::Relaxed);
+                Ok(Body {
+                    url,
+                    content: content,
+                })
+            }
+            .instrument(span),
+        );
+        tasks.push(task);
+    }
+
+    let mut bodies = Vec::new();
+    for task in tasks {
+        match task.await {
+            Ok(result) => bodies.push(result?),
+            Err(e) => return Err(anyhow::anyhow!("Task error: {}", e)),
+        }
+        progress.record(&fetched, record);
+    }
+    info!("Fetched {} bodies", bodies.len());
+    record_duration("fetch", now.elapsed());
+    Ok(bodies)
+}
+
+// fetch_pagination follows the next page links of the paginated listings among the bodies and
+// returns the bodies of the further pages, at most max_pagination_pages pages after each
+// listing, the pages crawled already are not fetched again
+async fn fetch_pagination(
+    client: &reqwest::Client,
+    urls: &[String],
+    bodies: &[Body],
+    options: &SitemapOptions,
+    record: &mut impl FnMut(Stage, usize, Duration),
+) -> Result<Vec<Body>, Error> {
+    let max_pages = options
+        .profile
+        .max_pagination_pages
+        .unwrap_or(DEFAULT_MAX_PAGINATION_PAGES);
+    let mut seen: HashSet<String> = urls.iter().cloned().collect();
+    let mut next_urls = next_page_urls(bodies, &mut seen, options);
+    let mut paginated = Vec::new();
+    let mut depth = 0;
+    // the listings are followed a page at a time, all listings at once
+    while !next_urls.is_empty() && depth < max_pages {
+        depth += 1;
+        info!(
+            "Following {} next page links to page {} of the listings",
+            next_urls.len(),
+            depth + 1
+        );
+        let next_bodies = fetch_bodies(client, next_urls, options, record).await?;
+        next_urls = next_page_urls(&next_bodies, &mut seen, options);
+        paginated.extend(next_bodies);
+    }
+    if !next_urls.is_empty() {
+        info!(
+            "Stopped following {} listings after {} pages",
+            next_urls.len(),
+            max_pages
+        );
+    }
+    info!("Fetched {} further pages of listings", paginated.len());
+    Ok(paginated)
+}
+
+// next_page_urls returns the next page links of the html bodies not seen yet, allowed by the
+// profile and not skipped by the options
+fn next_page_urls(
+    bodies: &[Body],
+    seen: &mut HashSet<String>,
+    options: &SitemapOptions,
+) -> Vec<String> {
+    bodies
+        .iter()
+        .filter_map(|body| match &body.content {
+            Content::Html(html) => next_page_url(html, &body.url),
+            Content::Pdf(_) => None,
+        })
+        .filter(|url| {
+            options.profile.allows(url)
+                && !options.skip_urls.contains(url)
+                && seen.insert(url.clone())
+        })
+        .collect()
+}
+
+// next_page_url returns the link to the next page of a listing on the same host, the rel=next
+// links and common pagination markup first, then the links labelled next or older posts
+//
+// function needs to be non async because scraper::Html is not Send
+pub fn next_page_url(html: &str, url: &str) -> Option<String> {
+    let mut base = reqwest::Url::parse(url).ok()?;
+    base.set_fragment(None);
+    let resolve = |href: &str| -> Option<String> {
+        let mut next = base.join(href.trim()).ok()?;
+        next.set_fragment(None);
+        match next.host_str() == base.host_str() && next != base {
+            true => Some(next.to_string()),
+            false => None,
+        }
+    };
+    let document = Html::parse_document(html);
+    for selector in NEXT_PAGE_SELECTORS {
+        let selector = match Selector::parse(selector) {
+            Ok(selector) => selector,
+            Err(_) => continue,
+        };
+        let next = document
+            .select(&selector)
+            .filter_map(|element| element.value().attr("href"))
+            .find_map(&resolve);
+        if next.is_some() {
+            return next;
+        }
+    }
+    let links = Selector::parse("a[href]").ok()?;
+    document.select(&links).find_map(|element| {
+        let text = element.text().collect::<String>().trim().to_lowercase();
+        match NEXT_PAGE_TEXTS.contains(&text.as_str()) {
+            true => element.value().attr("href").and_then(&resolve),
+            false => None,
+        }
+    })
+}
+
+// FetchProgress represents the fetched pages already recorded
+struct FetchProgress {
+    recorded: usize,
+    last: Instant,
+}
+
+impl FetchProgress {
+    // record records the pages fetched since the last call with the time since the last call, so
+    // the durations add up to the duration of the crawl
+    fn record(&mut self, fetched: &AtomicUsize, record: &mut impl FnMut(Stage, usize, Duration)) {
+        let fetched = fetched.load(Ordering::Relaxed);
+        if fetched > self.recorded {
+            record(Stage::Fetched, fetched - self.recorded, self.last.elapsed());
+            self.recorded = fetched;
+            self.last = Instant::now();
+        }
+    }
+}
+
+// parse_contents returns a vector of documents from a vector of bodies, the content is selected
+// and chunked as set by the profile
+//
+// function needs to be non async because scraper::Html is not Send, grmbl
+#[tracing::instrument(skip_all, fields(bodies = bodies.len()))]
+fn parse_contents(bodies: Vec<Body>, profile: &CrawlProfile) -> Result<Vec<Document>, Error> {
+    let now = std::time::Instant::now();
+    let mut results = Vec::new();
+    for body in bodies {
+        let _span = info_span!("parse", url = %body.url).entered();
+        let html = match body.content {
+            Content::Html(html) => html,
+            Content::Pdf(bytes) => {
+                // broken pdfs are skipped instead of failing the crawl
+                match parse_pdf(&body.url, &bytes) {
+                    Ok(mut result) => {
+                        result.chunking = profile.chunking();
+                        results.push(result);
+                    }
+                    Err(e) => info!("Error extracting text of pdf {}: {}", body.url, e),
+                }
+                continue;
+            }
+        };
+
+        // Parse the HTML
+        let document = Html::parse_document(&html);
+
+        // Extract the title
+        let title_selector =
+            Selector::parse("title").or(Err(anyhow::anyhow!("Failed to parse title selector")))?;
+
+        let title = document
+            .select(&title_selector)
+            .next()
+            .map_or(String::from(""), |n| n.text().collect());
+
+        info!("found title: {}", title);
+
+        // Create a selector for the body element or the content selector of the profile
+        let content_selector = profile.content_selector.as_deref().unwrap_or("body");
+        let body_selector = Selector::parse(content_selector).or(Err(anyhow::anyhow!(
+            "Failed to parse content selector: {}",
+            content_selector
+        )))?;
+
+        // Extract the body element
+        if let Some(body_element) = document.select(&body_selector).next() {
+            // Remove script and nav elements and the excluded elements of the profile
+            let mut unwanted = vec!["script", "nav"];
+            unwanted.extend(profile.exclude_selectors.iter().map(String::as_str));
+            let unwanted_selector = Selector::parse(&unwanted.join(", ")).or(Err(
+                anyhow::anyhow!("Failed to parse unwanted selector: {}", unwanted.join(", ")),
+            ))?;
+            let cleaned_body_html = body_element
+                .select(&unwanted_selector)
+                .fold(body_element.html(), |acc, unwanted| {
+                    acc.replace(unwanted.html().as_str(), "")
+                });
+
+            // Parse the cleaned body HTML
+            let mut cleaned_body_document = Html::parse_fragment(&cleaned_body_html);
+            // without content selector the main content is detected, keeping its headings,
+            // pages without a clear main content are indexed with the whole body
+            let readability =
+                profile.content_selector.is_none() && profile.readability.unwrap_or(true);
+            let main_content_html = match readability {
+                true => main_content(&cleaned_body_document)?,
+                false => None,
+            };
+            let found_main_content = main_content_html.is_some();
+            if let Some(main_content_html) = main_content_html {
+                info!("found main content in {}", body.url);
+                cleaned_body_document = Html::parse_fragment(&main_content_html);
+            }
+            // the headings split the page into sections, each fragment is cut from a single
+            // section and prefixed with the breadcrumb of its headings
+            let structured = structured_text(&cleaned_body_document);
+            let sections = split_sections(&structured);
+            let text = match found_main_content {
+                true => structured,
+                false => cleaned_body_document.root_element().text().fold(
+                    String::from(""),
+                    |acc, node| {
+                        let text = node.trim();
+                        if text.len() > 0 {
+                            format!("{} {}", acc, text)
+                        } else {
+                            acc
+                        }
+                    },
+                ),
+            };
+            let language = detect_language(&document, &body.url);
+            let canonical_url = detect_canonical_url(&document, &body.url)?;
+            // release notes pages are split into a document per release, pages without
+            // version headings are indexed as a whole
+            let releases = match profile.parser.unwrap_or_default() {
+                PageParser::ReleaseNotes => split_releases(&cleaned_body_document),
+                PageParser::Default => Vec::new(),
+            };
+            if !releases.is_empty() {
+                info!("found {} releases in {}", releases.len(), body.url);
+                for release in releases {
+                    let mut result = Document::new(
+                        data::Collection::Basic,
+                        format!("{}#{}", body.url, release.anchor),
+                        format!("{} {}", title, release.version),
+                        release.text,
+                    );
+                    result.language = language.clone();
+                    result.canonical_url = canonical_url.clone();
+                    result.chunking = profile.chunking();
+                    result.version = Some(release.version);
+                    result.release_date = release.date;
+                    results.push(result);
+                }
+                continue;
+            }
+            let faqs = extract_faqs(&cleaned_body_document)?;
+            if !faqs.is_empty() {
+                info!("found {} faq pairs in {}", faqs.len(), body.url);
+            }
+            let mut result = Document::new(data::Collection::Basic, body.url, title, text);
+            result.language = language;
+            result.canonical_url = canonical_url;
+            result.chunking = profile.chunking();
+            result.faqs = faqs;
+            result.sections = sections;
+            results.push(result);
+        }
+    }
+    info!("Parsed {} documents", results.len());
+    record_duration("parse", now.elapsed());
+    Ok(results)
+}
+
+// parse_pdf returns the document of a pdf, titled by its first line of text
+fn parse_pdf(url: &str, bytes: &[u8]) -> Result<Document, Error> {
+    let text = pdf_extract::extract_text_from_mem(bytes)
+        .map_err(|e| anyhow::anyhow!("Failed to extract text: {}", e))?;
+    let title = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("")
+        .to_string();
+    info!("found pdf title: {}", title);
+    let text_one_liner = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if text_one_liner.is_empty() {
+        return Err(anyhow::anyhow!("No text found, the pdf may be scanned"));
+    }
+    let mut document = Document::new(
+        data::Collection::Basic,
+        url.to_string(),
+        title,
+        text_one_liner,
+    );
+    // pdfs have no lang attribute or hreflang alternates, the locale of the url is used
+    if let (Some(language), canonical_url) = split_locale_url(url) {
+        document.language = Some(language);
+        document.canonical_url = Some(canonical_url);
+    }
+    Ok(document)
+}
+
+// TEXT_FILE_EXTENSIONS are the extensions of the files read by walk_directory
+static TEXT_FILE_EXTENSIONS: &[&str] = &["md", "txt", "rst"];
+// RST_UNDERLINE_CHARACTERS are the characters underlining restructuredtext headings
+static RST_UNDERLINE_CHARACTERS: &str = "=-~^\"'`#*+:._";
+
+// walk_directory returns the documents of the markdown, text and restructuredtext files below a
+// directory, the file path is the url of a document and its first heading the title, hidden
+// files and directories are skipped
+#[tracing::instrument(skip(stages))]
+pub fn walk_directory(path: &Path, stages: &mut StageProgress) -> Result<Vec<Document>, Error> {
+    let now = Instant::now();
+    let mut documents = Vec::new();
+    for path in text_files(path)? {
+        match read_text_file(&path, path.to_string_lossy().to_string()) {
+            Ok(Some(document)) => documents.push(document),
+            Ok(None) => continue,
+            Err(e) => info!("Error reading {:?}, skipping it: {}", path, e),
+        }
+    }
+    info!("Read {} documents from {:?}", documents.len(), path);
+    stages.record(Stage::Fetched, documents.len(), now.elapsed());
+    stages.record(Stage::Parsed, documents.len(), Duration::default());
+    Ok(documents)
+}
+
+// text_files returns the canonical paths of the markdown, text and restructuredtext files below a
+// directory in path order, hidden files and directories are skipped
+pub fn text_files(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = Vec::new();
+    walk(&fs::canonicalize(path)?, &mut paths)?;
+    Ok(paths)
+}
+
+// walk adds the paths of the text files below the directory in path order
+fn walk(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.path());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk(&path, paths)?;
+            continue;
+        }
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if !file_type.is_file() || !TEXT_FILE_EXTENSIONS.contains(&extension.as_str()) {
+            continue;
+        }
+        paths.push(path);
+    }
+    Ok(())
+}
+
+// read_text_file returns the document of a text file with the given url, titled by its first
+// heading or its file name, None if the file is empty
+pub fn read_text_file(path: &Path, url: String) -> Result<Option<Document>, Error> {
+    text_document(path, url, &fs::read_to_string(path)?)
+}
+
+// text_document returns the document of the text of a file with the given url, titled by its
+// first heading or its file name, None if the text is empty
+pub fn text_document(path: &Path, url: String, text: &str) -> Result<Option<Document>, Error> {
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let title = first_heading(text, &extension).unwrap_or(
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    );
+    info!("found title: {}", title);
+    Ok(Some(Document::new(
+        data::Collection::Basic,
+        url,
+        title,
+        text.trim().to_string(),
+    )))
+}
+
+// first_heading returns the first heading of a text file, the first # heading of markdown, the
+// first underlined line of restructuredtext or the first line of plain text
+fn first_heading(text: &str, extension: &str) -> Option<String> {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    let heading = match extension {
+        "md" => lines
+            .iter()
+            .find(|line| line.starts_with('#'))
+            .map(|line| line.trim_start_matches('#').trim()),
+        "rst" => lines
+            .windows(2)
+            .find(|pair| {
+                !pair[0].is_empty()
+                    && pair[1].chars().count() >= pair[0].chars().count()
+                    && is_rst_underline(pair[1])
+            })
+            .map(|pair| pair[0]),
+        _ => lines.iter().find(|line| !line.is_empty()).copied(),
+    };
+    heading
+        .filter(|heading| !heading.is_empty())
+        .map(str::to_string)
+}
+
+// is_rst_underline returns true if the line repeats a single restructuredtext underline character
+fn is_rst_underline(line: &str) -> bool {
+    match line.chars().next() {
+        Some(first) => RST_UNDERLINE_CHARACTERS.contains(first) && line.chars().all(|c| c == first),
+        None => false,
+    }
+}
+
+// fetch_content returns a document from a url
+pub async fn fetch_content(url: String) -> Result<Document, Error> {
+    fetch_content_with_limit(url, usize::MAX).await
+}
+
+// fetch_content_with_limit returns a document from a url, failing if the body exceeds max_size bytes
+#[tracing::instrument]
+pub async fn fetch_content_with_limit(url: String, max_size: usize) -> Result<Document, Error> {
+    let resp = reqwest::get(url.clone()).await?;
+    if let Some(content_length) = resp.content_length() {
+        if content_length > max_size as u64 {
+            return Err(anyhow::anyhow!(
+                "Content of {} is too large: {} bytes, limit: {} bytes",
+                url,
+                content_length,
+                max_size
+            ));
+        }
+    }
+    let content = read_content(resp).await?;
+    if content_size(&content) > max_size {
+        return Err(anyhow::anyhow!(
+            "Content of {} is too large: {} bytes, limit: {} bytes",
+            url,
+            content_size(&content),
+            max_size
+        ));
+    }
+
+    let documents = parse_contents(
+        vec![Body {
+            url: url,
+            content: content,
+        }],
+        &CrawlProfile::default(),
+    )?;
+    if documents.len() != 1 {
+        return Err(anyhow::anyhow!(
+            "Failed to parse content, expected 1 document, got: {}",
+            documents.len()
+        ));
+    }
+
+    return Ok(documents[0].clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Collection;
+
+    #[test]
+    fn default_sitemaps_are_at_the_root() {
+        assert!(is_default_sitemap("https://docs.lagoon.sh"));
+        assert!(is_default_sitemap("https://docs.lagoon.sh/"));
+        assert!(is_default_sitemap("https://docs.lagoon.sh/sitemap.xml"));
+        assert!(!is_default_sitemap(
+            "https://docs.lagoon.sh/docs/sitemap.xml"
+        ));
+        assert!(!is_default_sitemap("docs.lagoon.sh/sitemap.xml"));
+    }
+
+    #[test]
+    fn next_page_follows_rel_next_first() {
+        let html = r#"<html><head><link rel="next" href="/blog/page/3"></head>
+            <body><a href="/blog/page/9">Next</a></body></html>"#;
+        assert_eq!(
+            next_page_url(html, "https://example.com/blog/page/2"),
+            Some("https://example.com/blog/page/3".to_string())
+        );
+    }
+
+    #[test]
+    fn next_page_follows_pagination_markup() {
+        let html = r#"<div class="pagination">
+            <a href="?page=1">1</a><a class="next" href="?page=3">→</a></div>"#;
+        assert_eq!(
+            next_page_url(html, "https://example.com/news?page=2"),
+            Some("https://example.com/news?page=3".to_string())
+        );
+        let html = r#"<a aria-label="Next" href="/changelog/2#top">»</a>"#;
+        assert_eq!(
+            next_page_url(html, "https://example.com/changelog"),
+            Some("https://example.com/changelog/2".to_string())
+        );
+    }
+
+    #[test]
+    fn next_page_follows_labelled_links() {
+        let html = r#"<a href="/about">About</a><a href="/archive/2"> Older Posts </a>"#;
+        assert_eq!(
+            next_page_url(html, "https://example.com/archive"),
+            Some("https://example.com/archive/2".to_string())
+        );
+    }
+
+    #[test]
+    fn next_page_stays_on_the_host() {
+        let html = r#"<a rel="next" href="https://other.example.com/page/2">Next</a>
+            <a href="https://example.com/page/2">›</a>"#;
+        assert_eq!(
+            next_page_url(html, "https://example.com/page/1"),
+            Some("https://example.com/page/2".to_string())
+        );
+        let html = r#"<a rel="next" href="https://other.example.com/page/2">Next</a>"#;
+        assert_eq!(next_page_url(html, "https://example.com/page/1"), None);
+    }
+
+    #[test]
+    fn next_page_skips_links_to_the_page_itself() {
+        let html = r##"<a class="next" href="#comments">Next</a>"##;
+        assert_eq!(next_page_url(html, "https://example.com/post"), None);
+        assert_eq!(
+            next_page_url("<p>No links</p>", "https://example.com/post"),
+            None
+        );
+    }
+
+    #[test]
+    fn locales_are_iso_639_1_languages() {
+        assert!(is_locale("en"));
+        assert!(is_locale("de-ch"));
+        assert!(is_locale("pt_BR"));
+        for segment in ["go", "js", "ui", "db", "xx-ch", "en-", "eng", "EN", "docs"] {
+            assert!(!is_locale(segment), "{} is not a locale", segment);
+        }
+    }
+
+    #[test]
+    fn locale_segments_are_split_from_urls() {
+        assert_eq!(
+            split_locale_url("https://example.com/de-ch/install"),
+            (
+                Some("de-ch".to_string()),
+                "https://example.com/install".to_string()
+            )
+        );
+        assert_eq!(
+            split_locale_url("https://example.com/go/install"),
+            (None, "https://example.com/go/install".to_string())
+        );
+        // only the first segment is a locale
+        assert_eq!(
+            split_locale_url("https://example.com/docs/fr/install"),
+            (None, "https://example.com/docs/fr/install".to_string())
+        );
+    }
+
+    #[test]
+    fn languages_are_detected_from_the_lang_attribute_first() {
+        let html = Html::parse_document(r#"<html lang="fr"><body>Bonjour</body></html>"#);
+        assert_eq!(
+            detect_language(&html, "https://example.com/de/install"),
+            Some("fr".to_string())
+        );
+        let html = Html::parse_document("<html><body>Install</body></html>");
+        assert_eq!(
+            detect_language(&html, "https://example.com/de/install"),
+            Some("de".to_string())
+        );
+        assert_eq!(
+            detect_language(&html, "https://example.com/ui/install"),
+            None
+        );
+    }
+
+    // translation returns a document of the page in the language
+    fn translation(page: &str, language: Option<&str>, canonical_url: Option<&str>) -> Document {
+        let url = match language {
+            Some(language) => format!("https://example.com/{}/{}", language, page),
+            None => format!("https://example.com/{}", page),
+        };
+        let mut doc = Document::new(Collection::Basic, url, page.to_string(), page.to_string());
+        doc.language = language.map(str::to_string);
+        doc.canonical_url = canonical_url.map(str::to_string);
+        doc
+    }
+
+    // urls returns the urls of the documents in their order
+    fn urls(documents: &[Document]) -> Vec<&str> {
+        documents.iter().map(|doc| doc.url.as_str()).collect()
+    }
+
+    #[test]
+    fn translations_are_folded_into_the_preferred_locale() {
+        let install = Some("https://example.com/install");
+        let upgrade = Some("https://example.com/upgrade");
+        let documents = vec![
+            translation("install", Some("de-ch"), install),
+            translation("install", Some("en"), install),
+            translation("install", Some("fr"), install),
+            translation("upgrade", Some("de-ch"), upgrade),
+            translation("upgrade", Some("fr"), upgrade),
+            translation("about", None, None),
+        ];
+        let locales = vec!["en".to_string(), "de".to_string()];
+        let folded = fold_locales(documents.clone(), &locales);
+        assert_eq!(
+            urls(&folded),
+            vec![
+                "https://example.com/en/install",
+                "https://example.com/de-ch/upgrade",
+                "https://example.com/about",
+            ]
+        );
+        // all translations are kept without locales
+        assert_eq!(fold_locales(documents, &[]).len(), 6);
+    }
+
+    #[test]
+    fn folding_keeps_all_documents_of_the_preferred_page() {
+        let install = Some("https://example.com/install");
+        let mut release = translation("install", Some("en"), install);
+        release.version = Some("2.0".to_string());
+        let documents = vec![
+            translation("install", Some("de"), install),
+            translation("install", Some("en"), install),
+            release,
+            translation("install", None, install),
+        ];
+        let folded = fold_locales(documents, &["en".to_string()]);
+        assert_eq!(folded.len(), 2);
+        assert!(folded
+            .iter()
+            .all(|doc| doc.language.as_deref() == Some("en")));
+    }
+}
@@ -0,0 +1,255 @@
+use crate::telemetry::{record_circuit_open, record_retry};
+use anyhow::{Error, Result};
+use log::info;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// TRANSIENT_ERRORS are the parts of the messages of grpc errors worth retrying, the server is
+// restarting, overloaded or the connection dropped
+static TRANSIENT_ERRORS: &[&str] = &[
+    "Unavailable",
+    "DeadlineExceeded",
+    "transport error",
+    "connection reset",
+    "broken pipe",
+    "Connection refused",
+    "error sending request",
+];
+// BREAKER_OPEN is the message of the errors of calls skipped by an open circuit breaker
+static BREAKER_OPEN: &str = "Circuit breaker open";
+// QDRANT_BREAKER is the circuit breaker shared by all qdrant calls
+static QDRANT_BREAKER: OnceLock<CircuitBreaker> = OnceLock::new();
+// OLLAMA_BREAKER is the circuit breaker shared by all generations of the LLM
+static OLLAMA_BREAKER: OnceLock<CircuitBreaker> = OnceLock::new();
+// RETRY_POLICY is the retry policy of the qdrant calls and the generations, the default policy
+// unless set from the config
+static RETRY_POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+// RetryPolicy represents how often and how long transient errors are retried
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    // max_retries is the number of retries after the first attempt
+    pub max_retries: u32,
+    // base_delay is the backoff of the first retry, doubled on each further retry
+    pub base_delay: Duration,
+    // max_delay caps the backoff
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // backoff returns the delay before the retry, a random delay up to the exponential backoff
+    // so clients failing together don't retry together
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let millis = exponential.as_millis().max(1);
+        Duration::from_millis((Uuid::new_v4().as_u128() % millis) as u64)
+    }
+}
+
+// CircuitBreaker fails calls fast once a number of consecutive calls failed with transient
+// errors, after the cooldown a single call probes whether the service is back
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        CircuitBreaker::new(5, Duration::from_secs(30))
+    }
+}
+
+impl CircuitBreaker {
+    // new returns a closed breaker opening after threshold consecutive failures for cooldown
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            threshold: threshold,
+            cooldown: cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    // allow returns false while the breaker is open, once the cooldown passed one call is let
+    // through and the breaker opens again unless it succeeds
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.open_until {
+            Some(open_until) if Instant::now() < open_until => false,
+            Some(_) => {
+                state.open_until = Some(Instant::now() + self.cooldown);
+                true
+            }
+            None => true,
+        }
+    }
+
+    // record_success closes the breaker
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+    }
+
+    // record_failure counts a transient failure and opens the breaker at the threshold
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= self.threshold && state.open_until.is_none() {
+            info!(
+                "Opening circuit breaker after {} failures for {:?}",
+                state.consecutive_failures, self.cooldown
+            );
+            state.open_until = Some(Instant::now() + self.cooldown);
+            record_circuit_open();
+        }
+    }
+
+    // retry_after returns the time until the open breaker lets the next call through, None if
+    // the breaker is closed
+    pub fn retry_after(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        state
+            .open_until
+            .map(|open_until| open_until.saturating_duration_since(Instant::now()))
+    }
+}
+
+// set_retry_policy sets the retry policy of the qdrant calls and the generations, it must be
+// called before the first call, later calls keep the first policy
+pub fn set_retry_policy(policy: RetryPolicy) {
+    match RETRY_POLICY.set(policy) {
+        Ok(()) => info!(
+            "Retrying transient errors up to {} times with a backoff from {:?} to {:?}",
+            policy.max_retries, policy.base_delay, policy.max_delay
+        ),
+        Err(_) => info!("Retry policy already set, keeping the first one"),
+    }
+}
+
+// retry_policy returns the retry policy of the qdrant calls and the generations
+pub fn retry_policy() -> &'static RetryPolicy {
+    RETRY_POLICY.get_or_init(RetryPolicy::default)
+}
+
+// qdrant_breaker returns the circuit breaker shared by all qdrant calls
+pub fn qdrant_breaker() -> &'static CircuitBreaker {
+    QDRANT_BREAKER.get_or_init(CircuitBreaker::default)
+}
+
+// ollama_breaker returns the circuit breaker shared by all generations of the LLM
+pub fn ollama_breaker() -> &'static CircuitBreaker {
+    OLLAMA_BREAKER.get_or_init(CircuitBreaker::default)
+}
+
+// breaker_open returns the error of a call skipped by an open circuit breaker
+pub fn breaker_open(service: &str, operation: &str) -> Error {
+    anyhow::anyhow!("{}, skipping {} {}", BREAKER_OPEN, service, operation)
+}
+
+// is_transient returns true if the error is worth retrying
+pub fn is_transient(error: &Error) -> bool {
+    let message = format!("{:?}", error);
+    TRANSIENT_ERRORS.iter().any(|part| message.contains(part))
+}
+
+// is_outage returns true if the error means the service is down, a transient error or a call
+// skipped by an open circuit breaker
+pub fn is_outage(error: &Error) -> bool {
+    is_transient(error) || format!("{:?}", error).contains(BREAKER_OPEN)
+}
+
+// retry_qdrant runs a qdrant call with the retry policy and the shared circuit breaker
+pub async fn retry_qdrant<T, F, Fut>(operation: &'static str, call: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    retry("qdrant", operation, retry_policy(), qdrant_breaker(), call).await
+}
+
+// retry_ollama runs a call to ollama with the retry policy and the shared circuit breaker
+pub async fn retry_ollama<T, F, Fut>(operation: &'static str, call: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    retry("ollama", operation, retry_policy(), ollama_breaker(), call).await
+}
+
+// retry runs the call of the service until it succeeds, fails with a permanent error or the
+// retries of the policy are used up, transient errors are retried with a jittered exponential
+// backoff
+pub async fn retry<T, F, Fut>(
+    service: &'static str,
+    operation: &'static str,
+    policy: &RetryPolicy,
+    breaker: &CircuitBreaker,
+    mut call: F,
+) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let mut retries = 0;
+    loop {
+        if !breaker.allow() {
+            return Err(breaker_open(service, operation));
+        }
+        match call().await {
+            Ok(result) => {
+                breaker.record_success();
+                if retries > 0 {
+                    info!(
+                        "{} {} succeeded after {} retries",
+                        service, operation, retries
+                    );
+                }
+                return Ok(result);
+            }
+            Err(e) if is_transient(&e) => {
+                breaker.record_failure();
+                if retries >= policy.max_retries {
+                    if retries > 0 {
+                        info!(
+                            "Giving up {} {} after {} retries: {}",
+                            service, operation, retries, e
+                        );
+                    }
+                    return Err(e);
+                }
+                let backoff = policy.backoff(retries);
+                retries += 1;
+                info!(
+                    "Transient error of {} {}, retry {} in {:?}: {}",
+                    service, operation, retries, backoff, e
+                );
+                record_retry(service, operation);
+                tokio::time::sleep(backoff).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
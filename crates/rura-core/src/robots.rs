@@ -0,0 +1,285 @@
+use log::info;
+use reqwest::{Client, Url};
+use std::time::Duration;
+
+// Rule represents an allow or disallow line of a robots.txt group
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: String,
+    allow: bool,
+}
+
+// Robots represents the rules of a robots.txt applying to a user agent, an empty robots.txt
+// allows everything
+#[derive(Debug, Clone, Default)]
+pub struct Robots {
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+    // sitemaps are the sitemap urls listed in the robots.txt, they apply to all user agents
+    sitemaps: Vec<String>,
+}
+
+// Group represents the user agents of a robots.txt group and their rules
+#[derive(Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    // parse returns the rules of the robots.txt body applying to the user agent, the groups
+    // naming the user agent are used if any, the * groups otherwise
+    pub fn parse(body: &str, user_agent: &str) -> Self {
+        let user_agent = user_agent.to_lowercase();
+        let mut groups: Vec<Group> = Vec::new();
+        let mut sitemaps = Vec::new();
+        // consecutive user-agent lines share the rules following them
+        let mut in_agents = false;
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+                None => continue,
+            };
+            match key.as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        groups.push(Group::default());
+                        in_agents = true;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.agents.push(value.to_lowercase());
+                    }
+                }
+                "allow" | "disallow" => {
+                    in_agents = false;
+                    // an empty disallow allows everything
+                    if value.is_empty() {
+                        continue;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.rules.push(Rule {
+                            pattern: value.to_string(),
+                            allow: key == "allow",
+                        });
+                    }
+                }
+                "crawl-delay" => {
+                    in_agents = false;
+                    if let (Some(group), Ok(seconds)) = (groups.last_mut(), value.parse::<f64>()) {
+                        if seconds >= 0.0 {
+                            group.crawl_delay = Some(Duration::from_secs_f64(seconds));
+                        }
+                    }
+                }
+                "sitemap" => sitemaps.push(value.to_string()),
+                _ => in_agents = false,
+            }
+        }
+
+        let named = |group: &&Group| {
+            group
+                .agents
+                .iter()
+                .any(|agent| agent != "*" && user_agent.starts_with(agent.as_str()))
+        };
+        let matching: Vec<&Group> = if groups.iter().any(|group| named(&group)) {
+            groups.iter().filter(named).collect()
+        } else {
+            groups
+                .iter()
+                .filter(|group| group.agents.iter().any(|agent| agent == "*"))
+                .collect()
+        };
+        Robots {
+            rules: matching
+                .iter()
+                .flat_map(|group| group.rules.iter().cloned())
+                .collect(),
+            crawl_delay: matching.iter().filter_map(|group| group.crawl_delay).max(),
+            sitemaps: sitemaps,
+        }
+    }
+
+    // is_allowed returns true if the url may be crawled, the longest matching rule wins and
+    // allow wins over disallow on ties
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let mut best: Option<&Rule> = None;
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| matches(&rule.pattern, &path))
+        {
+            best = match best {
+                Some(current)
+                    if current.pattern.len() > rule.pattern.len()
+                        || (current.pattern.len() == rule.pattern.len() && current.allow) =>
+                {
+                    Some(current)
+                }
+                _ => Some(rule),
+            };
+        }
+        best.map_or(true, |rule| rule.allow)
+    }
+
+    // crawl_delay returns the crawl-delay requested by the site, if any
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+
+    // sitemaps returns the sitemap urls listed in the robots.txt
+    pub fn sitemaps(&self) -> &[String] {
+        &self.sitemaps
+    }
+}
+
+// matches returns true if the path matches the robots.txt pattern, * matches any characters
+// and a trailing $ anchors the pattern at the end of the path
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let mut rest = match path.strip_prefix(parts[0]) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    if parts.len() == 1 {
+        return !anchored || rest.is_empty();
+    }
+    let last = parts.len() - 1;
+    for (i, part) in parts.iter().enumerate().skip(1) {
+        if i == last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+// fetch_robots returns the robots.txt rules of the host of the url for the user agent, a
+// missing or unreachable robots.txt allows everything
+pub async fn fetch_robots(client: &Client, url: &Url, user_agent: &str) -> Robots {
+    let robots_url = match url.join("/robots.txt") {
+        Ok(robots_url) => robots_url,
+        Err(_) => return Robots::default(),
+    };
+    let response = match client.get(robots_url.clone()).send().await {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            info!("No robots.txt at {}: {}", robots_url, response.status());
+            return Robots::default();
+        }
+        Err(e) => {
+            info!("Error fetching {}: {}", robots_url, e);
+            return Robots::default();
+        }
+    };
+    match response.text().await {
+        Ok(body) => Robots::parse(&body, user_agent),
+        Err(e) => {
+            info!("Error reading {}: {}", robots_url, e);
+            Robots::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed(robots: &Robots, url: &str) -> bool {
+        robots.is_allowed(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn named_groups_win_over_the_wildcard_group() {
+        let body = "\
+User-agent: *
+Disallow: /
+
+User-agent: rura
+User-agent: other
+Disallow: /private # comment
+Crawl-delay: 2
+";
+        let robots = Robots::parse(body, "Rura/1.0");
+        assert!(allowed(&robots, "https://example.com/docs"));
+        assert!(!allowed(&robots, "https://example.com/private/page"));
+        assert_eq!(robots.crawl_delay(), Some(Duration::from_secs(2)));
+
+        let robots = Robots::parse(body, "someone-else");
+        assert!(!allowed(&robots, "https://example.com/docs"));
+        assert_eq!(robots.crawl_delay(), None);
+    }
+
+    #[test]
+    fn longest_rule_wins_and_allow_wins_ties() {
+        let body = "\
+User-agent: *
+Disallow: /docs
+Allow: /docs/public
+Disallow: /page
+Allow: /page
+Disallow:
+";
+        let robots = Robots::parse(body, "rura");
+        assert!(!allowed(&robots, "https://example.com/docs/internal"));
+        assert!(allowed(&robots, "https://example.com/docs/public/intro"));
+        assert!(allowed(&robots, "https://example.com/page"));
+        assert!(allowed(&robots, "https://example.com/blog"));
+    }
+
+    #[test]
+    fn empty_robots_allows_everything() {
+        let robots = Robots::parse("", "rura");
+        assert!(allowed(&robots, "https://example.com/anything?q=1"));
+        assert_eq!(robots.crawl_delay(), None);
+    }
+
+    #[test]
+    fn patterns_match_wildcards_and_anchors() {
+        assert!(matches("/docs", "/docs/intro"));
+        assert!(!matches("/docs", "/blog/docs"));
+        assert!(matches("/*.pdf$", "/files/manual.pdf"));
+        assert!(!matches("/*.pdf$", "/files/manual.pdf?download=1"));
+        assert!(matches("/search*q=", "/search?page=2&q=rust"));
+        assert!(matches("/index$", "/index"));
+        assert!(!matches("/index$", "/index.html"));
+    }
+
+    #[test]
+    fn query_strings_are_matched() {
+        let robots = Robots::parse("User-agent: *\nDisallow: /*?session=\n", "rura");
+        assert!(!allowed(&robots, "https://example.com/page?session=1"));
+        assert!(allowed(&robots, "https://example.com/page"));
+    }
+
+    #[test]
+    fn sitemaps_are_listed_for_all_agents() {
+        let body = "\
+Sitemap: https://example.com/sitemap-docs.xml
+User-agent: other
+Disallow: /
+SITEMAP: https://example.com/sitemap-blog.xml # blog
+";
+        let robots = Robots::parse(body, "rura");
+        assert_eq!(
+            robots.sitemaps(),
+            &[
+                "https://example.com/sitemap-docs.xml".to_string(),
+                "https://example.com/sitemap-blog.xml".to_string(),
+            ]
+        );
+    }
+}
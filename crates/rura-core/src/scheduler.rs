@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+// MAX_QUERY_YIELD is the longest a job waits for the queries in flight, so a steady query load
+// slows the jobs down without starving them
+static MAX_QUERY_YIELD: Duration = Duration::from_secs(2);
+
+// JobPriority represents the priority of a background job
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum JobPriority {
+    // low priority jobs, e.g. scheduled full site re-crawls
+    Low,
+    // normal priority jobs, e.g. sitemap uploads
+    #[default]
+    Normal,
+    // high priority jobs, e.g. single document ingests
+    High,
+}
+
+// JobScheduler lets lower priority jobs yield to higher priority jobs and to the queries in
+// flight at stage boundaries, and caps the number of concurrently running jobs
+#[derive(Default)]
+pub struct JobScheduler {
+    active: Mutex<HashMap<JobPriority, usize>>,
+    notify: Notify,
+    // queries is the number of queries in flight
+    queries: AtomicUsize,
+    // job_slots caps the running jobs, None runs all jobs at once
+    job_slots: Option<Arc<Semaphore>>,
+}
+
+// JobGuard marks a job as active until it is dropped
+pub struct JobGuard {
+    scheduler: Arc<JobScheduler>,
+    priority: JobPriority,
+}
+
+// QueryGuard marks a query as in flight until it is dropped
+pub struct QueryGuard {
+    scheduler: Arc<JobScheduler>,
+}
+
+impl JobScheduler {
+    // new returns a new scheduler without active jobs
+    pub fn new() -> Self {
+        JobScheduler::default()
+    }
+
+    // with_max_concurrent_jobs caps the number of jobs running at once, the other jobs wait for
+    // a slot in acquire_slot
+    pub fn with_max_concurrent_jobs(mut self, max_concurrent_jobs: Option<usize>) -> Self {
+        self.job_slots = max_concurrent_jobs.map(|max| Arc::new(Semaphore::new(max.max(1))));
+        self
+    }
+
+    // acquire_slot waits for a free job slot, the slot is released when the returned permit is
+    // dropped, None if the jobs are not capped
+    pub async fn acquire_slot(&self) -> Option<OwnedSemaphorePermit> {
+        match &self.job_slots {
+            Some(job_slots) => job_slots.clone().acquire_owned().await.ok(),
+            None => None,
+        }
+    }
+
+    // register_query marks a query as in flight until the returned guard is dropped
+    pub fn register_query(self: &Arc<Self>) -> QueryGuard {
+        self.queries.fetch_add(1, Ordering::SeqCst);
+        QueryGuard {
+            scheduler: self.clone(),
+        }
+    }
+
+    // register marks a job with the given priority as active until the returned guard is dropped
+    pub fn register(self: &Arc<Self>, priority: JobPriority) -> JobGuard {
+        {
+            let mut active = self.active.lock().unwrap();
+            *active.entry(priority).or_insert(0) += 1;
+        }
+        JobGuard {
+            scheduler: self.clone(),
+            priority: priority,
+        }
+    }
+
+    // has_higher_priority_jobs returns true if a job with a higher priority is active
+    fn has_higher_priority_jobs(&self, priority: JobPriority) -> bool {
+        let active = self.active.lock().unwrap();
+        active.iter().any(|(p, count)| *p > priority && *count > 0)
+    }
+
+    // wait_turn waits until no job with a higher priority is active
+    //
+    // jobs call it at stage boundaries, e.g. before processing the next document
+    pub async fn wait_turn(&self, priority: JobPriority) {
+        loop {
+            // create the notified future before checking to not miss a wakeup
+            let notified = self.notify.notified();
+            if !self.has_higher_priority_jobs(priority) {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    // yield_to_queries waits until no query is in flight or for at most MAX_QUERY_YIELD
+    //
+    // jobs call it before embedding and upserting the next document, so the queries don't
+    // compete with the ingest batches for the embedder and qdrant
+    pub async fn yield_to_queries(&self) {
+        let wait = async {
+            loop {
+                let notified = self.notify.notified();
+                if self.queries.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        let _ = tokio::time::timeout(MAX_QUERY_YIELD, wait).await;
+    }
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        {
+            let mut active = self.scheduler.active.lock().unwrap();
+            if let Some(count) = active.get_mut(&self.priority) {
+                *count = count.saturating_sub(1);
+            }
+        }
+        self.scheduler.notify.notify_waiters();
+    }
+}
+
+impl Drop for QueryGuard {
+    fn drop(&mut self) {
+        if self.scheduler.queries.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.scheduler.notify.notify_waiters();
+        }
+    }
+}
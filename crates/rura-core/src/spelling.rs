@@ -0,0 +1,211 @@
+use crate::data::Collection;
+use crate::ollama::{Llm, PROMPT_SPELLING};
+use crate::qdrant::get_texts;
+use anyhow::{anyhow, Error, Result};
+use log::info;
+use qdrant_client::client::QdrantClient;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+// MIN_CORRECTED_WORD_LENGTH is the minimum length of the words corrected with the dictionary,
+// shorter words are often acronyms
+static MIN_CORRECTED_WORD_LENGTH: usize = 4;
+// LONG_WORD_LENGTH is the length from which two edits are allowed instead of one
+static LONG_WORD_LENGTH: usize = 8;
+// MAX_CORRECTION_GROWTH is the maximum length of an LLM correction relative to the query,
+// longer replies are answers rather than corrections
+static MAX_CORRECTION_GROWTH: f32 = 1.5;
+
+// SpellingMode represents how queries are spell corrected before embedding
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SpellingMode {
+    // Off embeds the queries as they are
+    #[default]
+    Off,
+    // Dictionary replaces unknown words by the closest words of the indexed documents
+    Dictionary,
+    // Llm asks the LLM to fix the typos of the query
+    Llm,
+}
+
+// string to spelling mode, unknown modes are an error so a typo doesn't disable the correction
+impl FromStr for SpellingMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "off" => Ok(SpellingMode::Off),
+            "dictionary" => Ok(SpellingMode::Dictionary),
+            "llm" => Ok(SpellingMode::Llm),
+            _ => Err(anyhow!(
+                "unknown spelling mode: {}, valid values are: off, dictionary, llm",
+                s
+            )),
+        }
+    }
+}
+
+// SpellCorrector normalizes typo-ridden queries before they are embedded
+#[derive(Debug, Clone, Default)]
+pub struct SpellCorrector {
+    mode: SpellingMode,
+    // vocabulary is the lowercase words of the indexed documents with their frequency
+    vocabulary: HashMap<String, usize>,
+}
+
+impl SpellCorrector {
+    // new returns a corrector in the given mode, the dictionary mode needs a vocabulary
+    pub fn new(mode: SpellingMode) -> Self {
+        SpellCorrector {
+            mode: mode,
+            vocabulary: HashMap::new(),
+        }
+    }
+
+    // with_vocabulary sets the words known by the dictionary mode with their frequency
+    pub fn with_vocabulary(mut self, vocabulary: HashMap<String, usize>) -> Self {
+        self.vocabulary = vocabulary;
+        self
+    }
+
+    // correct returns the corrected query, errors of the LLM are logged and the query is kept
+    pub async fn correct(&self, llm: &Llm, model: &str, query: &str) -> String {
+        let corrected = match self.mode {
+            SpellingMode::Off => return query.to_string(),
+            SpellingMode::Dictionary => self.correct_with_dictionary(query),
+            SpellingMode::Llm => correct_with_llm(llm, model, query).await,
+        };
+        if corrected != query {
+            info!("Corrected query {} to {}", query, corrected);
+        }
+        corrected
+    }
+
+    // correct_with_dictionary replaces the unknown words by the most frequent word of the
+    // vocabulary within one edit, or two edits for long words, symspell style
+    pub fn correct_with_dictionary(&self, query: &str) -> String {
+        if self.vocabulary.is_empty() {
+            return query.to_string();
+        }
+        let words: Vec<String> = query
+            .split_whitespace()
+            .map(|word| self.correct_word(word))
+            .collect();
+        words.join(" ")
+    }
+
+    // correct_word corrects a single word, the punctuation around it is kept
+    fn correct_word(&self, word: &str) -> String {
+        let start = word.find(char::is_alphanumeric);
+        let end = word.rfind(char::is_alphanumeric);
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) => {
+                (start, end + word[end..].chars().next().unwrap().len_utf8())
+            }
+            _ => return word.to_string(),
+        };
+        let core = word[start..end].to_lowercase();
+        // acronyms, versions and identifiers like k8s are kept
+        if core.chars().count() < MIN_CORRECTED_WORD_LENGTH
+            || !core.chars().all(char::is_alphabetic)
+            || self.vocabulary.contains_key(&core)
+        {
+            return word.to_string();
+        }
+        let max_distance = match core.chars().count() >= LONG_WORD_LENGTH {
+            true => 2,
+            false => 1,
+        };
+        let best = self
+            .vocabulary
+            .iter()
+            .filter(|(candidate, _)| {
+                candidate.chars().count().abs_diff(core.chars().count()) <= max_distance
+            })
+            .map(|(candidate, frequency)| (candidate, *frequency, edit_distance(&core, candidate)))
+            .filter(|(_, _, distance)| *distance <= max_distance)
+            .min_by(|a, b| a.2.cmp(&b.2).then(b.1.cmp(&a.1)).then(a.0.cmp(b.0)));
+        match best {
+            Some((candidate, _, _)) => format!("{}{}{}", &word[..start], candidate, &word[end..]),
+            None => word.to_string(),
+        }
+    }
+}
+
+// correct_with_llm asks the LLM to fix the typos of the query, replies which are empty or much
+// longer than the query are dropped
+async fn correct_with_llm(llm: &Llm, model: &str, query: &str) -> String {
+    let prompt = PROMPT_SPELLING.replace("{question}", query);
+    match llm.generate(model, &prompt).await {
+        Ok(reply) => {
+            let reply = reply.trim().trim_matches('"').trim();
+            let max_length = (query.chars().count() as f32 * MAX_CORRECTION_GROWTH) as usize + 1;
+            if reply.is_empty() || reply.lines().count() > 1 || reply.chars().count() > max_length {
+                info!("Ignoring spell correction of query {}: {}", query, reply);
+                return query.to_string();
+            }
+            reply.to_string()
+        }
+        Err(e) => {
+            info!("Error correcting query, keeping it: {}", e);
+            query.to_string()
+        }
+    }
+}
+
+// vocabulary returns the lowercase words of the texts with their frequency
+pub fn vocabulary<'a>(texts: impl Iterator<Item = &'a str>) -> HashMap<String, usize> {
+    let mut vocabulary = HashMap::new();
+    for text in texts {
+        for word in text.split(|c: char| !c.is_alphabetic()) {
+            if word.chars().count() >= MIN_CORRECTED_WORD_LENGTH {
+                *vocabulary.entry(word.to_lowercase()).or_insert(0) += 1;
+            }
+        }
+    }
+    vocabulary
+}
+
+// load_vocabulary returns the vocabulary of the basic collection, documents uploaded later are
+// only known after a restart
+pub async fn load_vocabulary(
+    client: &QdrantClient,
+    base_collection: &str,
+) -> Result<HashMap<String, usize>, Error> {
+    let texts = get_texts(client, base_collection, Collection::Basic).await?;
+    let vocabulary = vocabulary(texts.iter().map(String::as_str));
+    info!(
+        "Loaded spelling vocabulary of {} words from {} fragments",
+        vocabulary.len(),
+        texts.len()
+    );
+    Ok(vocabulary)
+}
+
+// edit_distance returns the optimal string alignment distance of two words, insertions,
+// deletions, substitutions and transpositions of adjacent characters count as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for j in 0..=b.len() {
+        distances[0][j] = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            distances[i][j] = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distances[i][j] = distances[i][j].min(distances[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    distances[a.len()][b.len()]
+}
@@ -0,0 +1,204 @@
+use crate::data::{Collection, Document, EmbeddedDocument, EmbeddedMetadata};
+use crate::embedding::text_embeddings_async;
+use crate::embedding_provider::SharedEmbeddingProvider;
+use crate::ollama::Llm;
+use crate::qdrant::{add_documents, WriteOptions};
+use anyhow::{Error, Result};
+use log::{error, info};
+use qdrant_client::client::QdrantClient;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// SummaryPolicy represents how an upload handles failing summaries, e.g. when Ollama is down
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum SummaryPolicy {
+    // FailFast aborts the upload on the first failing summary
+    FailFast,
+    // Skip continues the upload without the summary of the document
+    #[default]
+    Skip,
+    // Queue continues the upload and generates the summary later
+    Queue,
+}
+
+// string to summary policy
+impl From<&str> for SummaryPolicy {
+    fn from(s: &str) -> Self {
+        match s {
+            "fail_fast" => SummaryPolicy::FailFast,
+            "skip" => SummaryPolicy::Skip,
+            "queue" => SummaryPolicy::Queue,
+            _ => {
+                error!("Error converting summary policy, unknown policy: {}", s);
+                SummaryPolicy::Skip
+            }
+        }
+    }
+}
+
+// SummaryReport represents the outcome of the summaries of an upload
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct SummaryReport {
+    pub generated: usize,
+    // skipped are the urls of the documents uploaded without summary
+    pub skipped: Vec<String>,
+    // queued are the urls of the documents whose summary is generated later
+    pub queued: Vec<String>,
+    // failed is the error which aborted the upload with the fail fast policy
+    pub failed: Option<String>,
+}
+
+impl SummaryReport {
+    // mark_generated moves a queued summary to the generated ones
+    pub fn mark_generated(&mut self, url: &str) {
+        self.queued.retain(|queued| queued != url);
+        self.generated += 1;
+    }
+}
+
+// PendingSummary represents a queued summary of a document
+#[derive(Debug, Clone)]
+pub struct PendingSummary {
+    pub job_id: Uuid,
+    pub document: Document,
+    pub base_collection: String,
+    pub ollama_model: String,
+    pub ollama_host: String,
+    pub ollama_port: u16,
+}
+
+// SummaryQueue holds the summaries to generate once the LLM is available again
+#[derive(Default)]
+pub struct SummaryQueue {
+    pending: Mutex<Vec<PendingSummary>>,
+}
+
+impl SummaryQueue {
+    // push queues a summary
+    pub fn push(&self, pending: PendingSummary) {
+        self.pending.lock().unwrap().push(pending);
+    }
+
+    // take returns and removes all queued summaries
+    pub fn take(&self) -> Vec<PendingSummary> {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+    }
+
+    // len returns the number of queued summaries
+    pub fn len(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    // is_empty returns true if no summary is queued
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// add_queued_summary generates the summary of a queued document, embeds and stores it in the
+// summary collection, the basic fragments were already stored by the upload
+pub async fn add_queued_summary(
+    client: &QdrantClient,
+    embedding_provider: &SharedEmbeddingProvider,
+    pending: &PendingSummary,
+) -> Result<()> {
+    let ollama = ollama_rs::Ollama::new(pending.ollama_host.clone(), pending.ollama_port);
+    let llm = Llm::new(ollama);
+    let mut document = pending.document.clone();
+    document.add_summary(&pending.ollama_model, &llm).await?;
+
+    let fragments: Vec<_> = document
+        .to_fragments()?
+        .into_iter()
+        .filter(|fragment| fragment.collection == Collection::Summary)
+        .collect();
+    let texts = fragments
+        .iter()
+        .map(|fragment| fragment.text.clone())
+        .collect();
+    let embeddings = text_embeddings_async(embedding_provider, texts).await?;
+    let mut embedded_documents = Vec::new();
+    for (fragment, text_embeddings) in fragments.into_iter().zip(embeddings) {
+        embedded_documents.push(EmbeddedDocument {
+            text_embeddings: text_embeddings,
+            title_embeddings: None,
+            metadata: EmbeddedMetadata::from_document(
+                &document,
+                fragment.text,
+                Collection::Summary,
+            )?,
+        });
+    }
+    add_documents(
+        client,
+        &pending.base_collection,
+        vec![Collection::Summary],
+        embedded_documents,
+        WriteOptions::default(),
+    )
+    .await?;
+    info!("Added queued summary of {}", document.url);
+    Ok(())
+}
+
+// process_queue generates the queued summaries, summaries failing again are queued again,
+// embedding_provider_of returns the embedding provider of the base collection of a summary,
+// returns the job ids and urls of the generated summaries
+pub async fn process_queue(
+    client: &QdrantClient,
+    embedding_provider_of: impl Fn(&str) -> SharedEmbeddingProvider,
+    queue: &SummaryQueue,
+) -> Vec<(Uuid, String)> {
+    let mut generated = Vec::new();
+    for pending in queue.take() {
+        let embedding_provider = embedding_provider_of(&pending.base_collection);
+        match add_queued_summary(client, &embedding_provider, &pending).await {
+            Ok(_) => generated.push((pending.job_id, pending.document.url.clone())),
+            Err(e) => {
+                info!(
+                    "Error adding queued summary of {}, queuing again: {}",
+                    pending.document.url, e
+                );
+                queue.push(pending);
+            }
+        }
+    }
+    generated
+}
+
+// PolicyOutcome represents what to do with a document whose summary failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyOutcome {
+    // Abort aborts the upload
+    Abort,
+    // Continue uploads the document without summary
+    Continue,
+}
+
+// apply_policy records a failed summary of a document in the report according to the policy
+pub fn apply_policy(
+    policy: SummaryPolicy,
+    report: &mut SummaryReport,
+    url: &str,
+    e: &Error,
+) -> PolicyOutcome {
+    match policy {
+        SummaryPolicy::FailFast => {
+            report.failed = Some(format!("summary of {} failed: {}", url, e));
+            PolicyOutcome::Abort
+        }
+        SummaryPolicy::Skip => {
+            report.skipped.push(url.to_string());
+            PolicyOutcome::Continue
+        }
+        SummaryPolicy::Queue => {
+            report.queued.push(url.to_string());
+            PolicyOutcome::Continue
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use anyhow::{Error, Result};
+use opentelemetry::metrics::{Counter, Histogram, Unit};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::MetricsExporterBuilder;
+use opentelemetry_sdk::metrics::reader::{DefaultAggregationSelector, DefaultTemporalitySelector};
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::runtime;
+use prometheus::proto::MetricType;
+use prometheus::{Encoder, Registry, TextEncoder};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+// METER_NAME is the name of the meter used for all metrics
+static METER_NAME: &str = "rust-a-rag-us";
+
+// REGISTRY is the prometheus registry the metrics are exported to, unset until init
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+// STAGE_DURATION is the histogram of the stage durations in seconds
+static STAGE_DURATION: OnceLock<Histogram<f64>> = OnceLock::new();
+// QDRANT_RETRIES is the counter of the retries of qdrant calls after transient errors
+static QDRANT_RETRIES: OnceLock<Counter<u64>> = OnceLock::new();
+// OLLAMA_RETRIES is the counter of the retries of generations after transient errors
+static OLLAMA_RETRIES: OnceLock<Counter<u64>> = OnceLock::new();
+// MIRROR_FAILURES is the counter of the writes which could not be mirrored
+static MIRROR_FAILURES: OnceLock<Counter<u64>> = OnceLock::new();
+// REUSED_EMBEDDINGS is the counter of the fragments whose embedding was reused from the cache
+static REUSED_EMBEDDINGS: OnceLock<Counter<u64>> = OnceLock::new();
+// QDRANT_CIRCUIT_OPENS is the counter of the times the qdrant circuit breaker opened
+static QDRANT_CIRCUIT_OPENS: OnceLock<Counter<u64>> = OnceLock::new();
+// KEYWORD_FALLBACKS is the counter of the searches answered by keyword matches without embeddings
+static KEYWORD_FALLBACKS: OnceLock<Counter<u64>> = OnceLock::new();
+// CACHED_ANSWERS is the counter of the queries answered from the cache during an outage
+static CACHED_ANSWERS: OnceLock<Counter<u64>> = OnceLock::new();
+// SKIPPED_POINTS is the counter of the points skipped by searches as their payload can't be read
+static SKIPPED_POINTS: OnceLock<Counter<u64>> = OnceLock::new();
+// INGESTED_DOCUMENTS is the counter of the documents upserted by uploads
+static INGESTED_DOCUMENTS: OnceLock<Counter<u64>> = OnceLock::new();
+// EMBEDDED_FRAGMENTS is the counter of the fragments embedded by the embedding provider
+static EMBEDDED_FRAGMENTS: OnceLock<Counter<u64>> = OnceLock::new();
+// UPSERTED_POINTS is the counter of the points upserted to qdrant
+static UPSERTED_POINTS: OnceLock<Counter<u64>> = OnceLock::new();
+// GENERATED_TOKENS is the counter of the prompt and completion tokens of ollama generations
+static GENERATED_TOKENS: OnceLock<Counter<u64>> = OnceLock::new();
+// HTTP_REQUESTS is the counter of the requests handled by the server
+static HTTP_REQUESTS: OnceLock<Counter<u64>> = OnceLock::new();
+// HTTP_DURATION is the histogram of the durations of the requests handled by the server in seconds
+static HTTP_DURATION: OnceLock<Histogram<f64>> = OnceLock::new();
+
+// init initializes logging, spans and metrics, the spans of the fetch, parse, summarize, embed,
+// upsert, search and generate stages are logged with their duration when they close, the metrics
+// are kept in a prometheus registry read by metrics_text, traces and metrics are also exported
+// via OTLP if the standard OTEL_EXPORTER_OTLP_ENDPOINT env variable is set
+pub fn init() -> Result<(), Error> {
+    let registry = REGISTRY.get_or_init(Registry::new);
+    let prometheus_exporter = opentelemetry_prometheus::exporter()
+        .with_registry(registry.clone())
+        .build()?;
+    let mut meter_provider = SdkMeterProvider::builder().with_reader(prometheus_exporter);
+    let mut otlp_layer = None;
+    if std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").is_ok() {
+        // the exporters read the remaining OTEL_* env variables, e.g. OTEL_SERVICE_NAME
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(opentelemetry_otlp::new_exporter().tonic())
+            .install_batch(runtime::Tokio)?;
+        otlp_layer = Some(tracing_opentelemetry::layer().with_tracer(tracer));
+        let otlp_exporter =
+            MetricsExporterBuilder::from(opentelemetry_otlp::new_exporter().tonic())
+                .build_metrics_exporter(
+                    Box::new(DefaultTemporalitySelector::new()),
+                    Box::new(DefaultAggregationSelector::new()),
+                )?;
+        meter_provider = meter_provider
+            .with_reader(PeriodicReader::builder(otlp_exporter, runtime::Tokio).build());
+    }
+    global::set_meter_provider(meter_provider.build());
+
+    // log records are forwarded to the tracing subscriber, RUST_LOG is still honored
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with(tracing_subscriber::fmt::layer().with_span_events(FmtSpan::CLOSE))
+        .with(otlp_layer)
+        .try_init()?;
+    Ok(())
+}
+
+// shutdown flushes the pending spans
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+// metrics_text returns the metrics in the prometheus text format, empty before init
+pub fn metrics_text() -> Result<String, Error> {
+    let families = match REGISTRY.get() {
+        Some(registry) => registry.gather(),
+        None => Vec::new(),
+    };
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&families, &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+// metrics_summary returns a line per counter, gauge and histogram series with its value or its
+// count and sum, e.g. to log the metrics of a client run
+pub fn metrics_summary() -> Vec<String> {
+    let families = match REGISTRY.get() {
+        Some(registry) => registry.gather(),
+        None => Vec::new(),
+    };
+    let mut lines = Vec::new();
+    for family in families {
+        for metric in family.get_metric() {
+            let labels: Vec<String> = metric
+                .get_label()
+                .iter()
+                .filter(|label| !label.get_name().starts_with("otel_scope"))
+                .map(|label| format!("{}={}", label.get_name(), label.get_value()))
+                .collect();
+            let series = match labels.is_empty() {
+                true => family.get_name().to_string(),
+                false => format!("{}{{{}}}", family.get_name(), labels.join(",")),
+            };
+            let value = match family.get_field_type() {
+                MetricType::COUNTER => metric.get_counter().get_value().to_string(),
+                MetricType::GAUGE => metric.get_gauge().get_value().to_string(),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    format!(
+                        "count={} sum={:.3}",
+                        histogram.get_sample_count(),
+                        histogram.get_sample_sum()
+                    )
+                }
+                _ => continue,
+            };
+            lines.push(format!("{} {}", series, value));
+        }
+    }
+    lines
+}
+
+// record_duration records the duration of a pipeline stage, e.g. fetch, embed or generate
+pub fn record_duration(stage: &'static str, duration: Duration) {
+    let histogram = STAGE_DURATION.get_or_init(|| {
+        global::meter(METER_NAME)
+            .f64_histogram("rura.stage.duration")
+            .with_description("Duration of the pipeline stages in seconds")
+            .with_unit(Unit::new("s"))
+            .init()
+    });
+    histogram.record(duration.as_secs_f64(), &[KeyValue::new("stage", stage)]);
+}
+
+// record_retry records a retry of a call of the service, e.g. a qdrant upsert or search or an
+// ollama generate, the mirrored qdrant calls count as qdrant retries
+pub fn record_retry(service: &'static str, operation: &'static str) {
+    let counter = match service {
+        "ollama" => OLLAMA_RETRIES.get_or_init(|| {
+            global::meter(METER_NAME)
+                .u64_counter("rura.ollama.retries")
+                .with_description("Retries of generations after transient errors")
+                .init()
+        }),
+        _ => QDRANT_RETRIES.get_or_init(|| {
+            global::meter(METER_NAME)
+                .u64_counter("rura.qdrant.retries")
+                .with_description("Retries of qdrant calls after transient errors")
+                .init()
+        }),
+    };
+    counter.add(1, &[KeyValue::new("operation", operation)]);
+}
+
+// record_mirror_failure records a write which could not be mirrored to the secondary qdrant
+pub fn record_mirror_failure(operation: &'static str) {
+    let counter = MIRROR_FAILURES.get_or_init(|| {
+        global::meter(METER_NAME)
+            .u64_counter("rura.qdrant.mirror_failures")
+            .with_description("Writes which could not be mirrored to the secondary qdrant")
+            .init()
+    });
+    counter.add(1, &[KeyValue::new("operation", operation)]);
+}
+
+// record_reused_embeddings records the fragments whose embedding was reused from the cache
+pub fn record_reused_embeddings(count: usize) {
+    if count == 0 {
+        return;
+    }
+    let counter = REUSED_EMBEDDINGS.get_or_init(|| {
+        global::meter(METER_NAME)
+            .u64_counter("rura.embedding.reused")
+            .with_description("Fragments whose embedding was reused instead of embedded")
+            .init()
+    });
+    counter.add(count as u64, &[]);
+}
+
+// record_circuit_open records the qdrant circuit breaker opening
+pub fn record_circuit_open() {
+    let counter = QDRANT_CIRCUIT_OPENS.get_or_init(|| {
+        global::meter(METER_NAME)
+            .u64_counter("rura.qdrant.circuit_opens")
+            .with_description("Times the qdrant circuit breaker opened")
+            .init()
+    });
+    counter.add(1, &[]);
+}
+
+// record_keyword_fallback records a search falling back to keyword matches
+pub fn record_keyword_fallback() {
+    let counter = KEYWORD_FALLBACKS.get_or_init(|| {
+        global::meter(METER_NAME)
+            .u64_counter("rura.search.keyword_fallbacks")
+            .with_description("Searches answered by keyword matches while embedding failed")
+            .init()
+    });
+    counter.add(1, &[]);
+}
+
+// record_skipped_point records a point of a collection skipped by a search as its payload can't
+// be read
+pub fn record_skipped_point(collection: &str) {
+    let counter = SKIPPED_POINTS.get_or_init(|| {
+        global::meter(METER_NAME)
+            .u64_counter("rura.search.skipped_points")
+            .with_description("Points skipped by searches as their payload could not be read")
+            .init()
+    });
+    counter.add(1, &[KeyValue::new("collection", collection.to_string())]);
+}
+
+// record_cached_answer records a query answered from the answer cache
+pub fn record_cached_answer() {
+    let counter = CACHED_ANSWERS.get_or_init(|| {
+        global::meter(METER_NAME)
+            .u64_counter("rura.query.cached_answers")
+            .with_description("Queries answered from the cache while qdrant or ollama were down")
+            .init()
+    });
+    counter.add(1, &[]);
+}
+
+// record_ingested_document records a document upserted by an upload
+pub fn record_ingested_document() {
+    let counter = INGESTED_DOCUMENTS.get_or_init(|| {
+        global::meter(METER_NAME)
+            .u64_counter("rura.ingest.documents")
+            .with_description("Documents upserted by uploads")
+            .init()
+    });
+    counter.add(1, &[]);
+}
+
+// record_embedded_fragments records the fragments embedded by the provider, without the fragments
+// whose embedding was reused
+pub fn record_embedded_fragments(provider: &str, count: usize) {
+    if count == 0 {
+        return;
+    }
+    let counter = EMBEDDED_FRAGMENTS.get_or_init(|| {
+        global::meter(METER_NAME)
+            .u64_counter("rura.embedding.fragments")
+            .with_description("Fragments embedded by the embedding provider")
+            .init()
+    });
+    counter.add(
+        count as u64,
+        &[KeyValue::new("provider", provider.to_string())],
+    );
+}
+
+// record_upserted_points records the points upserted to a collection
+pub fn record_upserted_points(collection: &str, count: usize) {
+    let counter = UPSERTED_POINTS.get_or_init(|| {
+        global::meter(METER_NAME)
+            .u64_counter("rura.qdrant.upserted_points")
+            .with_description("Points upserted to qdrant")
+            .init()
+    });
+    counter.add(
+        count as u64,
+        &[KeyValue::new("collection", collection.to_string())],
+    );
+}
+
+// record_generated_tokens records the prompt and completion tokens of an ollama generation
+pub fn record_generated_tokens(model: &str, prompt_tokens: u64, completion_tokens: u64) {
+    let counter = GENERATED_TOKENS.get_or_init(|| {
+        global::meter(METER_NAME)
+            .u64_counter("rura.ollama.tokens")
+            .with_description("Prompt and completion tokens of ollama generations")
+            .init()
+    });
+    counter.add(
+        prompt_tokens,
+        &[
+            KeyValue::new("model", model.to_string()),
+            KeyValue::new("kind", "prompt"),
+        ],
+    );
+    counter.add(
+        completion_tokens,
+        &[
+            KeyValue::new("model", model.to_string()),
+            KeyValue::new("kind", "completion"),
+        ],
+    );
+}
+
+// record_http_request records a request handled by the server by method, route and status
+pub fn record_http_request(method: &str, route: &str, status: u16, duration: Duration) {
+    let attributes = [
+        KeyValue::new("method", method.to_string()),
+        KeyValue::new("route", route.to_string()),
+        KeyValue::new("status", status as i64),
+    ];
+    let counter = HTTP_REQUESTS.get_or_init(|| {
+        global::meter(METER_NAME)
+            .u64_counter("rura.http.requests")
+            .with_description("Requests handled by the server")
+            .init()
+    });
+    counter.add(1, &attributes);
+    let histogram = HTTP_DURATION.get_or_init(|| {
+        global::meter(METER_NAME)
+            .f64_histogram("rura.http.duration")
+            .with_description("Duration of the requests handled by the server in seconds")
+            .with_unit(Unit::new("s"))
+            .init()
+    });
+    histogram.record(duration.as_secs_f64(), &attributes);
+}
@@ -0,0 +1,281 @@
+use crate::data::EmbeddedMetadata;
+use crate::mirror::{mirror, mirror_write};
+use crate::qdrant::list_collections;
+use crate::retry::retry_qdrant;
+use anyhow::{Error, Result};
+use chrono::Utc;
+use log::info;
+use qdrant_client::prelude::*;
+use qdrant_client::qdrant::point_id::PointIdOptions;
+use qdrant_client::qdrant::points_selector::PointsSelectorOneOf;
+use qdrant_client::qdrant::{OptimizersConfigDiff, PointId, PointsIdsList, PointsSelector};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+// ACCESS_COUNT_FIELD is the payload field counting the retrievals of a fragment
+pub static ACCESS_COUNT_FIELD: &str = "access_count";
+// LAST_ACCESSED_FIELD is the payload field of the rfc3339 time a fragment was last retrieved
+pub static LAST_ACCESSED_FIELD: &str = "last_accessed";
+// COLD_MEMMAP_THRESHOLD_KB lets qdrant memory map all segments of a cold collection on disk
+static COLD_MEMMAP_THRESHOLD_KB: u64 = 1;
+// HOT_MEMMAP_THRESHOLD_KB keeps the segments of a hot collection below 100 GB in memory unless
+// a memmap threshold is configured
+pub static HOT_MEMMAP_THRESHOLD_KB: u64 = 100 * 1024 * 1024;
+
+// Tier represents the storage of a collection
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum Tier {
+    // Hot collections keep their segments in memory
+    Hot,
+    // Cold collections are memory mapped on disk
+    Cold,
+}
+
+// CollectionTier represents the tier of a collection set by the last tiering run
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct CollectionTier {
+    pub collection: String,
+    pub tier: Tier,
+    // accesses are the retrievals of fragments of the collection in the interval of the run
+    pub accesses: u64,
+}
+
+// UrlAccess represents the number of retrievals of the fragments of a page
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct UrlAccess {
+    pub url: String,
+    pub accesses: u64,
+}
+
+// AccessReport represents the tiers of the collections and the most retrieved pages
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct AccessReport {
+    pub tiers: Vec<CollectionTier>,
+    // hot_urls are the pages retrieved most often since the server started, most first
+    pub hot_urls: Vec<UrlAccess>,
+}
+
+// AccessCounts represents the retrievals counted in memory
+#[derive(Default)]
+struct AccessCounts {
+    // pending are the retrievals not added to the payloads yet by collection and point id
+    pending: HashMap<String, HashMap<String, u64>>,
+    // collections are the retrievals by collection since the last tiering run
+    collections: HashMap<String, u64>,
+    // urls are the retrievals by url since the server started
+    urls: HashMap<String, u64>,
+}
+
+// AccessTracker counts the retrievals of the fragments, the counts are added to the access
+// count payload of the points by flush, off the query path, and decide the tiers of the
+// collections
+#[derive(Default)]
+pub struct AccessTracker {
+    counts: Mutex<AccessCounts>,
+    tiers: Mutex<HashMap<String, CollectionTier>>,
+}
+
+impl AccessTracker {
+    // record counts the retrieval of the fragments of a query from the collections of the base
+    // collection, a page is counted once per query
+    pub fn record<'a>(
+        &self,
+        base_collection: &str,
+        fragments: impl IntoIterator<Item = &'a EmbeddedMetadata>,
+    ) {
+        let mut counts = self.counts.lock().unwrap();
+        let mut urls = HashSet::new();
+        for metadata in fragments {
+            let collection_name =
+                format!("{}_{}", base_collection, metadata.collection.to_string());
+            *counts
+                .pending
+                .entry(collection_name.clone())
+                .or_default()
+                .entry(metadata.id.clone())
+                .or_insert(0) += 1;
+            *counts.collections.entry(collection_name).or_insert(0) += 1;
+            if urls.insert(metadata.url.as_str()) {
+                *counts.urls.entry(metadata.url.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    // flush adds the pending retrievals to the access count payload of the points, the
+    // retrievals of a collection failing to update are kept for the next flush, returns the
+    // number of updated points
+    pub async fn flush(&self, client: &QdrantClient) -> Result<usize, Error> {
+        let pending = std::mem::take(&mut self.counts.lock().unwrap().pending);
+        let mut updated = 0;
+        let mut result = Ok(());
+        for (collection_name, accesses) in pending {
+            match add_accesses(client, &collection_name, &accesses).await {
+                Ok(points) => updated += points,
+                Err(e) => {
+                    let mut counts = self.counts.lock().unwrap();
+                    let kept = counts.pending.entry(collection_name).or_default();
+                    for (id, count) in accesses {
+                        *kept.entry(id).or_insert(0) += count;
+                    }
+                    result = Err(e);
+                }
+            }
+        }
+        result.map(|_| updated)
+    }
+
+    // retier moves the collections of the base collection retrieved less than min_accesses times
+    // since the last run on disk and the others into memory, hot_memmap_threshold_kb is the
+    // memmap threshold of the hot collections, only changed tiers are applied
+    pub async fn retier(
+        &self,
+        client: &QdrantClient,
+        base_collection: &str,
+        min_accesses: u64,
+        hot_memmap_threshold_kb: u64,
+    ) -> Result<Vec<CollectionTier>, Error> {
+        let collections = std::mem::take(&mut self.counts.lock().unwrap().collections);
+        let mut changed = Vec::new();
+        for summary in list_collections(client, base_collection).await? {
+            let accesses = collections.get(&summary.collection).copied().unwrap_or(0);
+            let tier = match accesses >= min_accesses {
+                true => Tier::Hot,
+                false => Tier::Cold,
+            };
+            let previous = self
+                .tiers
+                .lock()
+                .unwrap()
+                .get(&summary.collection)
+                .map(|collection_tier| collection_tier.tier);
+            let collection_tier = CollectionTier {
+                collection: summary.collection.clone(),
+                tier: tier,
+                accesses: accesses,
+            };
+            if previous != Some(tier) {
+                let memmap_threshold_kb = match tier {
+                    Tier::Hot => hot_memmap_threshold_kb,
+                    Tier::Cold => COLD_MEMMAP_THRESHOLD_KB,
+                };
+                set_memmap_threshold(client, &summary.collection, memmap_threshold_kb).await?;
+                info!(
+                    "Moved collection {} to the {:?} tier after {} accesses",
+                    summary.collection, tier, accesses
+                );
+                changed.push(collection_tier.clone());
+            }
+            self.tiers
+                .lock()
+                .unwrap()
+                .insert(summary.collection, collection_tier);
+        }
+        Ok(changed)
+    }
+
+    // report returns the tiers of the collections and the limit most retrieved pages
+    pub fn report(&self, limit: usize) -> AccessReport {
+        let mut tiers: Vec<CollectionTier> = self.tiers.lock().unwrap().values().cloned().collect();
+        tiers.sort_by(|a, b| a.collection.cmp(&b.collection));
+        let mut hot_urls: Vec<UrlAccess> = self
+            .counts
+            .lock()
+            .unwrap()
+            .urls
+            .iter()
+            .map(|(url, accesses)| UrlAccess {
+                url: url.clone(),
+                accesses: *accesses,
+            })
+            .collect();
+        hot_urls.sort_by(|a, b| b.accesses.cmp(&a.accesses).then(a.url.cmp(&b.url)));
+        hot_urls.truncate(limit);
+        AccessReport {
+            tiers: tiers,
+            hot_urls: hot_urls,
+        }
+    }
+}
+
+// add_accesses adds the retrievals to the access count payload of the points of a collection
+// and sets their last access, on the mirror as well if enabled, deleted points are skipped,
+// returns the number of updated points
+async fn add_accesses(
+    client: &QdrantClient,
+    collection_name: &str,
+    accesses: &HashMap<String, u64>,
+) -> Result<usize, Error> {
+    let point_ids: Vec<PointId> = accesses.keys().map(|id| id.clone().into()).collect();
+    let response = retry_qdrant("get_points", || {
+        client.get_points(collection_name, &point_ids, Some(false), Some(true), None)
+    })
+    .await?;
+    let last_accessed = Utc::now().to_rfc3339();
+    let mut updated = 0;
+    for point in response.result {
+        let point_id = match point.id {
+            Some(point_id) => point_id,
+            None => continue,
+        };
+        let count = match &point_id.point_id_options {
+            Some(PointIdOptions::Uuid(uuid)) => accesses.get(uuid),
+            Some(PointIdOptions::Num(num)) => accesses.get(&num.to_string()),
+            None => None,
+        };
+        let count = match count {
+            Some(count) => *count,
+            None => continue,
+        };
+        let payload_json = serde_json::to_value(&point.payload)?;
+        let access_count = payload_json[ACCESS_COUNT_FIELD].as_u64().unwrap_or(0) + count;
+        let payload: Payload = json!({
+            ACCESS_COUNT_FIELD: access_count,
+            LAST_ACCESSED_FIELD: last_accessed,
+        })
+        .try_into()
+        .map_err(|e| anyhow::anyhow!("Error converting payload: {}", e))?;
+        let points_selector = PointsSelector {
+            points_selector_one_of: Some(PointsSelectorOneOf::Points(PointsIdsList {
+                ids: vec![point_id],
+            })),
+        };
+        retry_qdrant("set_payload", || {
+            client.set_payload(collection_name, &points_selector, payload.clone(), None)
+        })
+        .await?;
+        if let Some(mirror) = mirror() {
+            mirror_write("set_payload", || {
+                mirror.set_payload(collection_name, &points_selector, payload.clone(), None)
+            })
+            .await;
+        }
+        updated += 1;
+    }
+    Ok(updated)
+}
+
+// set_memmap_threshold sets the segment size in kilobytes from which qdrant memory maps the
+// vectors of the segments of a collection, the optimizer moves the segments in the background
+async fn set_memmap_threshold(
+    client: &QdrantClient,
+    collection_name: &str,
+    memmap_threshold_kb: u64,
+) -> Result<(), Error> {
+    let optimizers_config = OptimizersConfigDiff {
+        memmap_threshold: Some(memmap_threshold_kb),
+        ..Default::default()
+    };
+    retry_qdrant("update_collection", || {
+        client.update_collection(collection_name, &optimizers_config)
+    })
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,412 @@
+use crate::data::{Collection, Document};
+use crate::ollama::Llm;
+use crate::readability::split_sections;
+use anyhow::{Error, Result};
+use log::info;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+
+// STRIP_BOILERPLATE_TRANSFORMER is the name of the built-in transformer removing boilerplate
+// lines, e.g. edit this page links or copyright notices
+pub static STRIP_BOILERPLATE_TRANSFORMER: &str = "strip_boilerplate";
+// REDACT_TRANSFORMER is the name of the built-in transformer replacing sensitive text, e.g.
+// email addresses
+pub static REDACT_TRANSFORMER: &str = "redact";
+// TRANSLATE_TRANSFORMER is the name of the built-in transformer translating the documents with
+// the LLM of the upload
+pub static TRANSLATE_TRANSFORMER: &str = "translate";
+// ENRICH_METADATA_TRANSFORMER is the name of the built-in transformer setting the category,
+// labels, author and deprecation of the documents
+pub static ENRICH_METADATA_TRANSFORMER: &str = "enrich_metadata";
+
+// DEFAULT_BOILERPLATE_PATTERN matches the lines of common documentation boilerplate
+pub static DEFAULT_BOILERPLATE_PATTERN: &str = r"(?i)^(skip to (main )?content|edit this page( on github)?|was this (page|article) helpful\??|on this page|table of contents|(copyright|©).*|all rights reserved\.?)$";
+// DEFAULT_REDACT_PATTERN matches email addresses
+pub static DEFAULT_REDACT_PATTERN: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}";
+// DEFAULT_REDACT_REPLACEMENT replaces the redacted text by default
+pub static DEFAULT_REDACT_REPLACEMENT: &str = "[redacted]";
+
+// TRANSFORMERS are the factories of the transformers the transform specs select by name, the
+// built-in transformers are registered on first use
+static TRANSFORMERS: OnceLock<RwLock<HashMap<String, TransformerFactory>>> = OnceLock::new();
+
+// TransformFuture is the boxed future returned by transformers, boxed so transformers can be
+// chained as trait objects
+pub type TransformFuture<'a> = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>>;
+
+// SharedTransformer is a transformer shared by the chains of the uploads
+pub type SharedTransformer = Arc<dyn DocumentTransformer>;
+
+// TransformerFactory returns a transformer configured by the parameters of a transform spec
+pub type TransformerFactory = fn(&BTreeMap<String, String>) -> Result<SharedTransformer, Error>;
+
+// TransformSpec represents a transformer of a crawl profile by its name and parameters, e.g.
+//
+// [[profiles."docs.lagoon.sh".transforms]]
+// name = "redact"
+// params = { pattern = "sk-[A-Za-z0-9]+", replacement = "[token]" }
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransformSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, String>,
+}
+
+// DocumentTransformer changes a parsed document before it is chunked, e.g. to remove
+// boilerplate or redact secrets, implement it and register its factory with
+// register_transformer to select it in the crawl profiles
+pub trait DocumentTransformer: Send + Sync {
+    // name is the name transform specs select the transformer by
+    fn name(&self) -> &str;
+
+    // transform changes the document in place, the llm and model are the ones of the upload
+    fn transform<'a>(
+        &'a self,
+        doc: &'a mut Document,
+        llm: &'a Llm,
+        ollama_model: &'a str,
+    ) -> TransformFuture<'a>;
+}
+
+// register_transformer makes the transformer of the factory available to the transform specs by
+// the name, replacing a transformer of the same name
+pub fn register_transformer(name: &str, factory: TransformerFactory) {
+    match transformers()
+        .write()
+        .unwrap()
+        .insert(name.to_string(), factory)
+    {
+        Some(_) => info!("Replaced transformer {}", name),
+        None => info!("Registered transformer {}", name),
+    }
+}
+
+// transformer_names returns the names of the registered transformers in alphabetical order
+pub fn transformer_names() -> Vec<String> {
+    let mut names: Vec<String> = transformers().read().unwrap().keys().cloned().collect();
+    names.sort();
+    names
+}
+
+// transformers returns the registered factories, the built-in transformers are registered on
+// first use
+fn transformers() -> &'static RwLock<HashMap<String, TransformerFactory>> {
+    TRANSFORMERS.get_or_init(|| {
+        let built_ins: Vec<(&str, TransformerFactory)> = vec![
+            (STRIP_BOILERPLATE_TRANSFORMER, StripBoilerplate::from_params),
+            (REDACT_TRANSFORMER, Redact::from_params),
+            (TRANSLATE_TRANSFORMER, Translate::from_params),
+            (ENRICH_METADATA_TRANSFORMER, EnrichMetadata::from_params),
+        ];
+        RwLock::new(
+            built_ins
+                .into_iter()
+                .map(|(name, factory)| (name.to_string(), factory))
+                .collect(),
+        )
+    })
+}
+
+// TransformChain represents the transformers of a crawl profile, applied in the order of the
+// profile between parsing and chunking
+#[derive(Clone, Default)]
+pub struct TransformChain {
+    transformers: Vec<SharedTransformer>,
+}
+
+impl TransformChain {
+    // new returns the chain of the transform specs, it fails if a transformer isn't registered
+    // or its parameters are invalid
+    pub fn new(specs: &[TransformSpec]) -> Result<Self, Error> {
+        let mut transformers = Vec::new();
+        for spec in specs {
+            let factory = transformer_factory(&spec.name)?;
+            let transformer = factory(&spec.params)
+                .map_err(|e| anyhow::anyhow!("Invalid transformer {}: {}", spec.name, e))?;
+            transformers.push(transformer);
+        }
+        Ok(TransformChain {
+            transformers: transformers,
+        })
+    }
+
+    // is_empty returns true if the chain has no transformers
+    pub fn is_empty(&self) -> bool {
+        self.transformers.is_empty()
+    }
+
+    // names returns the names of the transformers of the chain in their order
+    pub fn names(&self) -> Vec<String> {
+        self.transformers
+            .iter()
+            .map(|transformer| transformer.name().to_string())
+            .collect()
+    }
+
+    // apply runs the transformers on the document, the sections of documents split by their
+    // headings are split again from the transformed basic text
+    #[tracing::instrument(skip_all, fields(url = %doc.url))]
+    pub async fn apply(&self, doc: &mut Document, llm: &Llm, ollama_model: &str) -> Result<()> {
+        let sectioned = !doc.sections.is_empty();
+        for transformer in &self.transformers {
+            transformer
+                .transform(doc, llm, ollama_model)
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Transformer {} failed on {}: {}",
+                        transformer.name(),
+                        doc.url,
+                        e
+                    )
+                })?;
+        }
+        if sectioned {
+            if let Some(text) = doc.text.get(&Collection::Basic) {
+                doc.sections = split_sections(text);
+            }
+        }
+        Ok(())
+    }
+}
+
+// transformer_factory returns the factory of the registered transformer of the name
+fn transformer_factory(name: &str) -> Result<TransformerFactory, Error> {
+    let factory = transformers().read().unwrap().get(name).copied();
+    factory.ok_or(anyhow::anyhow!(
+        "unknown transformer: {}, registered transformers: {}",
+        name,
+        transformer_names().join(", ")
+    ))
+}
+
+// pattern_param returns the regex of the pattern parameter, or of the default pattern if unset
+fn pattern_param(params: &BTreeMap<String, String>, default: &str) -> Result<Regex, Error> {
+    let pattern = params.get("pattern").map_or(default, String::as_str);
+    Regex::new(pattern).map_err(|e| anyhow::anyhow!("invalid pattern {}: {}", pattern, e))
+}
+
+// StripBoilerplate removes the lines of the texts matching the pattern parameter as a whole,
+// DEFAULT_BOILERPLATE_PATTERN by default, paragraphs left empty are removed as well
+pub struct StripBoilerplate {
+    pattern: Regex,
+}
+
+impl StripBoilerplate {
+    // from_params returns the transformer of the pattern parameter
+    pub fn from_params(params: &BTreeMap<String, String>) -> Result<SharedTransformer, Error> {
+        Ok(Arc::new(StripBoilerplate {
+            pattern: pattern_param(params, DEFAULT_BOILERPLATE_PATTERN)?,
+        }))
+    }
+
+    // strip returns the text without the boilerplate lines
+    fn strip(&self, text: &str) -> String {
+        text.split("\n\n")
+            .map(|paragraph| {
+                paragraph
+                    .lines()
+                    .filter(|line| !self.pattern.is_match(line.trim()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .filter(|paragraph| !paragraph.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+impl DocumentTransformer for StripBoilerplate {
+    fn name(&self) -> &str {
+        STRIP_BOILERPLATE_TRANSFORMER
+    }
+
+    fn transform<'a>(
+        &'a self,
+        doc: &'a mut Document,
+        _llm: &'a Llm,
+        _ollama_model: &'a str,
+    ) -> TransformFuture<'a> {
+        Box::pin(async move {
+            for text in doc.text.values_mut() {
+                *text = self.strip(text);
+            }
+            Ok(())
+        })
+    }
+}
+
+// Redact replaces the matches of the pattern parameter, DEFAULT_REDACT_PATTERN by default, with
+// the replacement parameter in the title, texts and faqs
+pub struct Redact {
+    pattern: Regex,
+    replacement: String,
+}
+
+impl Redact {
+    // from_params returns the transformer of the pattern and replacement parameters
+    pub fn from_params(params: &BTreeMap<String, String>) -> Result<SharedTransformer, Error> {
+        Ok(Arc::new(Redact {
+            pattern: pattern_param(params, DEFAULT_REDACT_PATTERN)?,
+            replacement: params
+                .get("replacement")
+                .cloned()
+                .unwrap_or(DEFAULT_REDACT_REPLACEMENT.to_string()),
+        }))
+    }
+
+    // redact returns the text with the matches replaced
+    fn redact(&self, text: &str) -> String {
+        self.pattern
+            .replace_all(text, regex::NoExpand(&self.replacement))
+            .to_string()
+    }
+}
+
+impl DocumentTransformer for Redact {
+    fn name(&self) -> &str {
+        REDACT_TRANSFORMER
+    }
+
+    fn transform<'a>(
+        &'a self,
+        doc: &'a mut Document,
+        _llm: &'a Llm,
+        _ollama_model: &'a str,
+    ) -> TransformFuture<'a> {
+        Box::pin(async move {
+            doc.title = self.redact(&doc.title);
+            for text in doc.text.values_mut() {
+                *text = self.redact(text);
+            }
+            for faq in doc.faqs.iter_mut() {
+                faq.question = self.redact(&faq.question);
+                faq.answer = self.redact(&faq.answer);
+            }
+            Ok(())
+        })
+    }
+}
+
+// Translate translates the title and texts to the language parameter, e.g. en, with the LLM of
+// the upload, documents detected in the language already are kept
+pub struct Translate {
+    language: String,
+}
+
+impl Translate {
+    // from_params returns the transformer of the mandatory language parameter
+    pub fn from_params(params: &BTreeMap<String, String>) -> Result<SharedTransformer, Error> {
+        let language = params
+            .get("language")
+            .ok_or(anyhow::anyhow!("missing parameter language"))?;
+        Ok(Arc::new(Translate {
+            language: language.to_lowercase(),
+        }))
+    }
+
+    // translated returns true if the language of the document is the target language, e.g. en-us
+    // for en
+    fn translated(&self, doc: &Document) -> bool {
+        doc.language.as_deref().map_or(false, |language| {
+            let primary = |tag: &str| tag.split('-').next().unwrap_or("").to_lowercase();
+            primary(language) == primary(&self.language)
+        })
+    }
+}
+
+impl DocumentTransformer for Translate {
+    fn name(&self) -> &str {
+        TRANSLATE_TRANSFORMER
+    }
+
+    fn transform<'a>(
+        &'a self,
+        doc: &'a mut Document,
+        llm: &'a Llm,
+        ollama_model: &'a str,
+    ) -> TransformFuture<'a> {
+        Box::pin(async move {
+            if self.translated(doc) {
+                return Ok(());
+            }
+            info!("Translating {} to {}", doc.url, self.language);
+            doc.title = llm
+                .translate(ollama_model, &doc.title, &self.language)
+                .await?;
+            for text in doc.text.values_mut() {
+                *text = llm.translate(ollama_model, text, &self.language).await?;
+            }
+            doc.language = Some(self.language.clone());
+            Ok(())
+        })
+    }
+}
+
+// EnrichMetadata sets the category and author parameters of documents without category or
+// author, adds the comma separated labels parameter to their labels and flags them as
+// deprecated if the deprecated parameter is true
+pub struct EnrichMetadata {
+    category: Option<String>,
+    author: Option<String>,
+    labels: Vec<String>,
+    deprecated: bool,
+}
+
+impl EnrichMetadata {
+    // from_params returns the transformer of the category, author, labels and deprecated
+    // parameters
+    pub fn from_params(params: &BTreeMap<String, String>) -> Result<SharedTransformer, Error> {
+        let deprecated = match params.get("deprecated") {
+            Some(deprecated) => deprecated
+                .parse::<bool>()
+                .map_err(|_| anyhow::anyhow!("invalid parameter deprecated: {}", deprecated))?,
+            None => false,
+        };
+        Ok(Arc::new(EnrichMetadata {
+            category: params.get("category").cloned(),
+            author: params.get("author").cloned(),
+            labels: params
+                .get("labels")
+                .map(|labels| {
+                    labels
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|label| !label.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            deprecated: deprecated,
+        }))
+    }
+}
+
+impl DocumentTransformer for EnrichMetadata {
+    fn name(&self) -> &str {
+        ENRICH_METADATA_TRANSFORMER
+    }
+
+    fn transform<'a>(
+        &'a self,
+        doc: &'a mut Document,
+        _llm: &'a Llm,
+        _ollama_model: &'a str,
+    ) -> TransformFuture<'a> {
+        Box::pin(async move {
+            doc.category = doc.category.take().or(self.category.clone());
+            doc.author = doc.author.take().or(self.author.clone());
+            for label in &self.labels {
+                if !doc.labels.contains(label) {
+                    doc.labels.push(label.clone());
+                }
+            }
+            doc.deprecated = doc.deprecated || self.deprecated;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,133 @@
+use crate::config::CrawlProfile;
+use crate::retriever::{crawl_client, get_urls};
+use crate::robots::fetch_robots;
+use anyhow::{Error, Result};
+use log::info;
+use reqwest::{Client, Url};
+use scraper::{Html, Selector};
+use std::time::Duration;
+
+// CANDIDATE_SELECTORS are common selectors of the main content of documentation pages, the
+// first one holding enough of the text of the page is proposed
+static CANDIDATE_SELECTORS: &[&str] = &[
+    "main",
+    "article",
+    "[role=main]",
+    "#content",
+    ".content",
+    ".markdown-body",
+];
+// MIN_CONTENT_SHARE is the minimum share of the body text a content selector must hold, smaller
+// matches are e.g. teasers or cards
+static MIN_CONTENT_SHARE: f64 = 0.3;
+// ROBOTS_USER_AGENT is the user agent matched against the groups of the robots.txt
+static ROBOTS_USER_AGENT: &str = "rust-a-rag-us";
+// TEST_INGEST_PAGES is the number of pages test-ingested with a proposed profile
+pub static TEST_INGEST_PAGES: usize = 5;
+
+// Probe represents what the wizard found out about a site
+#[derive(Debug, Clone, Default)]
+pub struct Probe {
+    // domain is the host the crawl profile is keyed by
+    pub domain: String,
+    // robots_sitemaps are the sitemaps listed in the robots.txt
+    pub robots_sitemaps: Vec<String>,
+    // crawl_delay is the crawl-delay requested by the robots.txt
+    pub crawl_delay: Option<Duration>,
+    // sitemap_url is the first sitemap listing pages, None if no sitemap was found
+    pub sitemap_url: Option<String>,
+    // page_count is the number of pages listed in the sitemap
+    pub page_count: usize,
+    // sample_url is the page the content selectors are detected on
+    pub sample_url: Option<String>,
+    // content_selector is the detected selector of the main content, None to index the body
+    pub content_selector: Option<String>,
+}
+
+impl Probe {
+    // profile returns the proposed crawl profile of the site
+    pub fn profile(&self) -> CrawlProfile {
+        CrawlProfile {
+            content_selector: self.content_selector.clone(),
+            crawl_delay_ms: self.crawl_delay.map(|delay| delay.as_millis() as u64),
+            ..Default::default()
+        }
+    }
+}
+
+// probe detects the sitemap, the robots.txt, the page count and the content selector of a site
+pub async fn probe(url: &str) -> Result<Probe, Error> {
+    let parsed = Url::parse(url)?;
+    let domain = parsed
+        .host_str()
+        .ok_or(anyhow::anyhow!("Url without host: {}", url))?
+        .to_lowercase();
+    let client = crawl_client(&CrawlProfile::default())?;
+    let robots = fetch_robots(&client, &parsed, ROBOTS_USER_AGENT).await;
+    let mut probe = Probe {
+        domain: domain,
+        robots_sitemaps: robots.sitemaps().to_vec(),
+        crawl_delay: robots.crawl_delay(),
+        ..Default::default()
+    };
+
+    let mut candidates = robots.sitemaps().to_vec();
+    match url.ends_with("sitemap.xml") {
+        true => candidates.insert(0, url.to_string()),
+        false => candidates.push(format!("{}/sitemap.xml", url.trim_end_matches('/'))),
+    }
+    for candidate in candidates {
+        let urls = match sitemap_urls(&client, &candidate).await {
+            Ok(urls) if !urls.is_empty() => urls,
+            Ok(_) => continue,
+            Err(e) => {
+                info!("No sitemap at {}: {}", candidate, e);
+                continue;
+            }
+        };
+        probe.sitemap_url = Some(candidate);
+        probe.page_count = urls.len();
+        probe.sample_url = urls.into_iter().next();
+        break;
+    }
+
+    let sample_url = probe.sample_url.clone().unwrap_or(url.to_string());
+    let body = client.get(&sample_url).send().await?.text().await?;
+    probe.content_selector = detect_content_selector(&body);
+    probe.sample_url = Some(sample_url);
+    Ok(probe)
+}
+
+// sitemap_urls returns the urls listed in a sitemap
+async fn sitemap_urls(client: &Client, sitemap_url: &str) -> Result<Vec<String>, Error> {
+    let response = client.get(sitemap_url).send().await?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("status {}", response.status()));
+    }
+    get_urls(response.text().await?)
+}
+
+// detect_content_selector returns the first candidate selector holding enough of the text of
+// the page, None if the body should be indexed
+//
+// function needs to be non async because scraper::Html is not Send
+pub fn detect_content_selector(body: &str) -> Option<String> {
+    let document = Html::parse_document(body);
+    let text_length = |selector: &str| -> usize {
+        match Selector::parse(selector) {
+            Ok(selector) => document
+                .select(&selector)
+                .next()
+                .map_or(0, |element| element.text().map(|t| t.trim().len()).sum()),
+            Err(_) => 0,
+        }
+    };
+    let body_length = text_length("body");
+    if body_length == 0 {
+        return None;
+    }
+    CANDIDATE_SELECTORS
+        .iter()
+        .find(|selector| text_length(selector) as f64 >= body_length as f64 * MIN_CONTENT_SHARE)
+        .map(|selector| selector.to_string())
+}
@@ -0,0 +1,109 @@
+use rura_core::query::Citation;
+use rura_core::retrieval_hook::RequestMetadata;
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+// DEFAULT_ANSWER_CACHE_SIZE is the default number of answers kept by the cache
+pub static DEFAULT_ANSWER_CACHE_SIZE: usize = 1000;
+
+// CachedAnswer represents a generated answer with the citations of its context
+#[derive(Debug, Clone)]
+pub struct CachedAnswer {
+    pub answer: String,
+    pub citations: Vec<Citation>,
+}
+
+// AnswerCache keeps the last generated answer of each query so queries can still be answered
+// while qdrant or ollama are unavailable, the oldest answers are evicted once the cache is full
+#[derive(Debug)]
+pub struct AnswerCache {
+    capacity: usize,
+    entries: Mutex<CacheEntries>,
+}
+
+#[derive(Debug, Default)]
+struct CacheEntries {
+    answers: HashMap<String, CachedAnswer>,
+    order: VecDeque<String>,
+}
+
+impl Default for AnswerCache {
+    fn default() -> Self {
+        AnswerCache::new(DEFAULT_ANSWER_CACHE_SIZE)
+    }
+}
+
+impl AnswerCache {
+    // new returns a cache keeping up to capacity answers, 0 disables the cache
+    pub fn new(capacity: usize) -> Self {
+        AnswerCache {
+            capacity: capacity,
+            entries: Mutex::new(CacheEntries::default()),
+        }
+    }
+
+    // get returns the cached answer of the key
+    pub fn get(&self, key: &str) -> Option<CachedAnswer> {
+        self.entries.lock().unwrap().answers.get(key).cloned()
+    }
+
+    // insert caches the answer of the key, replacing an older answer of the same key and
+    // evicting the oldest answers if the cache is full
+    pub fn insert(&self, key: String, answer: CachedAnswer) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.answers.insert(key.clone(), answer).is_some() {
+            return;
+        }
+        while entries.order.len() >= self.capacity {
+            match entries.order.pop_front() {
+                Some(oldest) => entries.answers.remove(&oldest),
+                None => break,
+            };
+        }
+        entries.order.push_back(key);
+    }
+
+    // len returns the number of cached answers
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().answers.len()
+    }
+
+    // is_empty returns true if no answer is cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// answer_key returns the cache key of a query, queries differing only in case or whitespace
+// share their answer, the collection, model and request metadata are part of the key as they
+// change the answer
+pub fn answer_key(
+    base_collection: &str,
+    model: &str,
+    query: &str,
+    metadata: &RequestMetadata,
+) -> String {
+    let query = query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    let mut hasher = Sha1::new();
+    hasher.update(base_collection);
+    hasher.update([0]);
+    hasher.update(model);
+    hasher.update([0]);
+    hasher.update(query);
+    // queries without metadata keep the keys of earlier versions
+    for (key, value) in metadata {
+        hasher.update([0]);
+        hasher.update(key);
+        hasher.update([0]);
+        hasher.update(value);
+    }
+    format!("{:x}", hasher.finalize())
+}
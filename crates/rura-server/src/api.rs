@@ -0,0 +1,2948 @@
+use crate::answer_cache::{answer_key, CachedAnswer};
+use crate::middleware::{ErrorResponse, RequestId};
+use crate::sources::{PageFreshness, Source};
+use crate::state::{AppConfig, AppState};
+use axum::{
+    extract::{rejection::QueryRejection, Path, Query},
+    http::{header, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Json,
+};
+use chrono::{DateTime, Utc};
+use log::info;
+use rura_core::agent::{Agent, AgentStep, DEFAULT_AGENT_STEPS};
+use rura_core::answer::{generate_answer, AnswerStyle};
+use rura_core::chat::{condense_question, render_chat_prompt, ChatTurn};
+use rura_core::classify::QueryClass;
+use rura_core::control::{JobControl, JobSignal};
+use rura_core::data::{
+    parse_collections, ChunkStrategy, Collection, EmbeddedDocument, ScoredDocument,
+};
+use rura_core::debug::{explain_search, ExplainStages, PipelineStage, ScoreBreakdown};
+use rura_core::dedup::{DedupReport, DEFAULT_DEDUP_THRESHOLD};
+use rura_core::embedding::{
+    document_embeddings_async, embedding_size, text_embedding_async, EmbeddingProgress,
+};
+use rura_core::embedding_provider::SharedEmbeddingProvider;
+use rura_core::estimate::{IndexEstimate, MEGABYTE};
+use rura_core::highlight::{preview, score_sentences, top_highlights, Highlight};
+use rura_core::ingest::{
+    diff_crawl, estimate_index, run_upload, QuestionStage, SummaryStage, TransformStage,
+    UploadConfig,
+};
+use rura_core::ingest_source::{
+    documents_of_locations, ingest_source, ingest_source_names, CrawlerSource,
+    LocationStageCallback, SharedIngestSource, SitemapSource, CRAWLER_SOURCE, SITEMAP_SOURCE,
+};
+use rura_core::keyword::keyword_search;
+use rura_core::ollama::{self, SpeculativeEvent};
+use rura_core::progress_store::ProgressStore;
+use rura_core::progress_tracker::{
+    JobStatus, ProgressTracker, SourceProgress, Stage, StageProgress, StageStats,
+};
+use rura_core::qdrant::{
+    add_documents, create_collections, delete_documents_by_url, list_collections, search_documents,
+    search_scored_documents, CollectionStats, CollectionSummary, SearchFilter, VectorSearch,
+    WriteOptions, WriteOrder,
+};
+use rura_core::query::{
+    citations, pack_context, preview_prompt, render_prompt, Citation, ContextChunk, PromptPreview,
+};
+use rura_core::release_notes::PageParser;
+use rura_core::report::{send_webhook, CrawlDiff, JobReport};
+use rura_core::rerank::{Reranker, DEFAULT_RERANK_TOP_N};
+use rura_core::retrieval_hook::RequestMetadata;
+use rura_core::retriever;
+use rura_core::retry::{is_outage, ollama_breaker, qdrant_breaker};
+use rura_core::scheduler::JobPriority;
+use rura_core::summary::{SummaryPolicy, SummaryReport};
+use rura_core::telemetry::{metrics_text, record_cached_answer};
+use rura_core::tiering::{AccessReport, CollectionTier, Tier, UrlAccess};
+use rura_core::transform::TransformChain;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::{
+    convert::Infallible,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tracing::{info_span, Instrument};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
+
+// DEFAULT_PAGE_SIZE is the default number of jobs returned per page
+static DEFAULT_PAGE_SIZE: usize = 50;
+// RETRY_AFTER is the Retry-After of outages detected before the circuit breaker opened
+static RETRY_AFTER: Duration = Duration::from_secs(30);
+
+// JobState represents the progress of a job
+#[derive(Serialize, ToSchema)]
+pub struct JobState {
+    id: Uuid,
+    progress: EmbeddingProgress,
+}
+
+// Define a serializable structure for your response
+#[derive(Serialize)]
+pub struct StateResponse {
+    // Add fields relevant for your response
+    progress_data: Vec<JobState>,
+    total: usize,
+    page: usize,
+    per_page: usize,
+}
+
+#[derive(Deserialize, Default, IntoParams)]
+pub struct StateParams {
+    // page is the zero based page of jobs, ordered by creation date descending
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+    pub status: Option<JobStatus>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        get_state,
+        list_jobs,
+        upload,
+        get_job,
+        cancel_job,
+        pause_job,
+        resume_job,
+        get_job_events,
+        get_job_report,
+        upload_document_url,
+        delete_documents,
+        summarize,
+        search,
+        query,
+        query_stream,
+        chat,
+        debug_query,
+        get_collections,
+        get_tiering,
+        get_metrics,
+        add_source,
+        get_sources
+    ),
+    components(schemas(
+        UploadParams,
+        ErrorResponse,
+        JobStatus,
+        JobState,
+        EmbeddingProgress,
+        Stage,
+        StageProgress,
+        StageStats,
+        SourceProgress,
+        JobReport,
+        CrawlDiff,
+        IndexEstimate,
+        CollectionStats,
+        DocumentUrlParams,
+        DocumentUrlResponse,
+        DeleteDocumentsParams,
+        DeleteDocumentsResponse,
+        SummarizeParams,
+        SummarizeResponse,
+        SearchParams,
+        SearchFilter,
+        SearchResponse,
+        SearchResult,
+        Highlight,
+        QueryParams,
+        AnswerStyle,
+        QueryResponse,
+        PromptPreview,
+        ContextChunk,
+        Citation,
+        ChatParams,
+        ChatResponse,
+        ChatTurn,
+        AgentStep,
+        QueryClass,
+        PipelineStage,
+        DebugQueryResponse,
+        ScoreBreakdown,
+        Collection,
+        JobPriority,
+        SummaryPolicy,
+        WriteOrder,
+        VectorSearch,
+        PageParser,
+        ChunkStrategy,
+        SummaryReport,
+        DedupReport,
+        CollectionsResponse,
+        CollectionSummary,
+        AccessReport,
+        CollectionTier,
+        Tier,
+        UrlAccess,
+        SourceParams,
+        Source,
+        PageFreshness
+    ))
+)]
+pub struct ApiDoc;
+
+/// get-state function returns the current progress state
+///
+/// This route does retrieve the current state, paginated and optionally filtered by status and
+/// creation date.
+#[utoipa::path(
+    get,
+    path = "/get-state",
+    params(StateParams),
+    responses(
+        (status = 200, description = "Success response", body = String),
+        (status = 500, description = "Internal Server Error", body = String)
+    )
+)]
+pub async fn get_state(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    state_params: Option<Query<StateParams>>,
+) -> Json<StateResponse> {
+    let Query(params) = state_params.unwrap_or(Query::default());
+    Json(job_page(&state, params).await)
+}
+
+/// list_jobs function lists the jobs, e.g. the completed ones
+///
+/// This route does return the jobs like `/get-state`, the newest first, paginated and optionally
+/// filtered by status and creation date, e.g. `/jobs?status=completed`. With `JOBS_PATH` the jobs
+/// are persisted and listed after restarts, jobs interrupted by a restart are `Failed`.
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    params(StateParams),
+    responses(
+        (status = 200, description = "Success response", body = String),
+        (status = 500, description = "Internal Server Error", body = String)
+    )
+)]
+pub async fn list_jobs(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    state_params: Option<Query<StateParams>>,
+) -> Json<StateResponse> {
+    let Query(params) = state_params.unwrap_or(Query::default());
+    Json(job_page(&state, params).await)
+}
+
+// job_page returns the page of the jobs matching the state params, newest first
+async fn job_page(state: &AppState<EmbeddingProgress>, params: StateParams) -> StateResponse {
+    let page = params.page.unwrap_or(0);
+    let per_page = params.per_page.unwrap_or(DEFAULT_PAGE_SIZE);
+
+    let mut jobs: Vec<JobState> = state
+        .progress_map
+        .all()
+        .await
+        .into_iter()
+        .filter(|(_, p)| params.status.map_or(true, |status| p.status() == status))
+        .filter(|(_, p)| {
+            params
+                .created_after
+                .map_or(true, |after| p.created_at() >= after)
+        })
+        .filter(|(_, p)| {
+            params
+                .created_before
+                .map_or(true, |before| p.created_at() < before)
+        })
+        .map(|(id, p)| JobState {
+            id: id,
+            progress: p,
+        })
+        .collect();
+
+    jobs.sort_by(|a, b| b.progress.created_at().cmp(&a.progress.created_at()));
+    let total = jobs.len();
+    let progress_data = jobs
+        .into_iter()
+        .skip(page * per_page)
+        .take(per_page)
+        .collect();
+    StateResponse {
+        progress_data: progress_data,
+        total: total,
+        page: page,
+        per_page: per_page,
+    }
+}
+
+#[derive(Deserialize, Default, ToSchema)]
+pub struct UploadParams {
+    #[serde(default)]
+    pub url: String,
+    // urls is a comma separated list of further urls read concurrently and uploaded in the same
+    // job, e.g. to assemble a knowledge base from several sites
+    pub urls: Option<String>,
+    // source is the registered ingest source reading the url, e.g. sitemap, crawler, file or a
+    // custom source, defaults to sitemap
+    pub source: Option<String>,
+    pub ollama_model: Option<String>,
+    pub ollama_host: Option<String>,
+    pub ollama_port: Option<u16>,
+    // filter_collections is a comma separated list of the collections uploaded to, e.g.
+    // basic,summary
+    pub filter_collections: Option<String>,
+    pub base_collection: Option<String>,
+    pub priority: Option<JobPriority>,
+    // locales is a comma separated list of the languages the upload is restricted to, e.g.
+    // en,de-ch
+    pub locales: Option<String>,
+    // webhook_url receives the job report once the upload is done
+    pub webhook_url: Option<String>,
+    // summary_policy handles failing summaries, defaults to Skip
+    pub summary_policy: Option<SummaryPolicy>,
+    // concurrency is the maximum number of concurrent requests of the crawl, defaults to 10
+    pub concurrency: Option<usize>,
+    // crawl_delay_ms is the minimum delay between two requests to the same host, defaults
+    // to 200, a longer crawl-delay of the robots.txt is honored
+    pub crawl_delay_ms: Option<u64>,
+    // respect_robots skips the urls disallowed by robots.txt, defaults to true
+    pub respect_robots: Option<bool>,
+    // incremental skips the fragments already stored by a previous upload, defaults to false
+    pub incremental: Option<bool>,
+    // changed_only uploads only the pages added or modified since the previous crawl, the
+    // unchanged pages are neither summarized nor embedded again, defaults to false
+    pub changed_only: Option<bool>,
+    // lead_first indexes the lead fragments of all pages before their deep fragments so every
+    // page is searchable early, defaults to false
+    pub lead_first: Option<bool>,
+    // dedup drops the fragments nearly identical to an earlier fragment of the upload, e.g.
+    // shared footers, before they are embedded, defaults to false
+    pub dedup: Option<bool>,
+    // dedup_threshold is the estimated similarity from 0 to 1 fragments are dropped at,
+    // defaults to 0.9
+    pub dedup_threshold: Option<f32>,
+    // lead_tokens adds a boosted lead fragment of the first lead_tokens tokens of each page,
+    // defaults to the crawl profile of the domain, disabled if unset
+    pub lead_tokens: Option<usize>,
+    // dry_run crawls and chunks the pages and reports the estimated size of the index in the
+    // job report instead of uploading, defaults to false
+    pub dry_run: Option<bool>,
+    // wait upserts the points before the upload moves on so they are searchable when the job
+    // completes, defaults to false
+    pub wait: Option<bool>,
+    // write_ordering is the ordering guarantee of the upserts across replicas, defaults to Weak
+    pub write_ordering: Option<WriteOrder>,
+    // parser splits the pages into documents, ReleaseNotes splits changelogs by version
+    // heading, defaults to the crawl profile of the domain or Default
+    pub parser: Option<PageParser>,
+    // chunk_strategy measures the fragments in Characters or Tokens, or keeps Sentences whole,
+    // defaults to the crawl profile of the domain or Characters
+    pub chunk_strategy: Option<ChunkStrategy>,
+    // fragment_size and overlap_size set the size of the fragments in the unit of the chunk
+    // strategy, default to the crawl profile of the domain or 1512 and 256
+    pub fragment_size: Option<usize>,
+    pub overlap_size: Option<usize>,
+    // parent_size embeds small chunks of fragment_size and returns their parent window of
+    // parent_size in searches, defaults to the crawl profile of the domain, disabled if unset
+    pub parent_size: Option<usize>,
+    // skip_urls is a comma separated list of the pages of the sitemap not crawled, e.g. the
+    // pages of a source not due for a re-crawl, they are neither fetched nor reported removed
+    pub skip_urls: Option<String>,
+    // follow_pagination crawls the further pages of paginated listings by their next page
+    // links, defaults to the crawl profile of the domain or false
+    pub follow_pagination: Option<bool>,
+    // max_pagination_pages is the maximum number of further pages followed from a listing,
+    // defaults to the crawl profile of the domain or 20
+    pub max_pagination_pages: Option<usize>,
+}
+
+/// upload function starts an upload task
+///
+/// This route does start an upload task and returns its id. The sitemap is fetched by the task,
+/// the fetched and parsed pages show up in the progress of the job while the crawl runs. The
+/// further `urls` are read concurrently and uploaded in the same job, the progress of the job
+/// breaks the crawl down by url.
+#[utoipa::path(
+    post,
+    path = "/upload",
+    params(
+        ("upload_params" = UploadParams, Path, description = "Upload parameters"),
+    ),
+    responses(
+        (status = 200, description = "Success response", body = String),
+        (status = 400, description = "Bad Request", body = ErrorResponse)
+    )
+)]
+pub async fn upload(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    upload_params: Result<Query<UploadParams>, QueryRejection>,
+) -> Result<Json<String>, AppError> {
+    // invalid parameters, e.g. an unknown write ordering, fail the request instead of being
+    // dropped
+    let Query(upload_params) = upload_params.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    let skip_urls = comma_separated(upload_params.skip_urls.as_deref())
+        .into_iter()
+        .collect();
+    let id = start_upload(state.0.clone(), upload_params, skip_urls).await?;
+    Ok(Json(id.to_string()))
+}
+
+// upload_locations returns the url and the comma separated further urls of an upload, without
+// empty and duplicate urls
+fn upload_locations(url: &str, urls: Option<&str>) -> Vec<String> {
+    let mut locations: Vec<String> = Vec::new();
+    for location in std::iter::once(url).chain(urls.unwrap_or_default().split(',')) {
+        let location = location.trim();
+        if !location.is_empty() && !locations.iter().any(|known| known == location) {
+            locations.push(location.to_string());
+        }
+    }
+    locations
+}
+
+// comma_separated returns the trimmed non empty items of a comma separated query parameter
+fn comma_separated(list: Option<&str>) -> Vec<String> {
+    list.unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// start_upload registers an upload job and runs it in a background task, returns the id of the
+// job, used by the upload route and the scheduled re-crawls of the sources
+//
+// skip_urls are the pages not crawled, the skip_urls parameter is parsed by the upload route
+// and ignored here, so the urls of the re-crawls are never split at their commas
+pub async fn start_upload(
+    state: Arc<AppState<EmbeddingProgress>>,
+    upload_params: UploadParams,
+    skip_urls: HashSet<String>,
+) -> Result<Uuid, AppError> {
+    // create uuid
+    let id = Uuid::new_v5(
+        &Uuid::NAMESPACE_URL,
+        format!("{}{}", "upload", Utc::now()).as_bytes(),
+    );
+
+    let ollama_model = upload_params
+        .ollama_model
+        .unwrap_or(state.app_config.ollama_model.clone());
+    info!("Ollama model {}", ollama_model);
+    let ollama_host = upload_params
+        .ollama_host
+        .unwrap_or(state.app_config.ollama_host.clone());
+    info!("Ollama host {}", ollama_host);
+    let ollama_port = upload_params
+        .ollama_port
+        .unwrap_or(state.app_config.ollama_port.clone());
+    let filter_collections = match upload_params.filter_collections.as_deref() {
+        Some(filter_collections) => parse_collections(filter_collections)
+            .map_err(|e| AppError::BadRequest(e.to_string()))?,
+        None => state.app_config.filter_collections.clone(),
+    };
+    let base_collection = upload_params
+        .base_collection
+        .unwrap_or(state.app_config.base_collection.clone());
+    info!("Ollama port {}", ollama_port);
+    let priority = upload_params.priority.unwrap_or_default();
+    let locations = upload_locations(&upload_params.url, upload_params.urls.as_deref());
+    if locations.is_empty() {
+        return Err(AppError::BadRequest("mandatory URL is empty".to_string()));
+    }
+    // the parameters override the crawl profile of the domain of the first url, if any, it
+    // applies to all urls of the job
+    let mut profile = state.app_config.config_file.crawl_profile(&locations[0]);
+    profile.lead_tokens = upload_params.lead_tokens.or(profile.lead_tokens);
+    profile.parser = upload_params.parser.or(profile.parser);
+    profile.chunk_strategy = upload_params.chunk_strategy.or(profile.chunk_strategy);
+    profile.fragment_size = upload_params.fragment_size.or(profile.fragment_size);
+    profile.overlap_size = upload_params.overlap_size.or(profile.overlap_size);
+    profile.parent_size = upload_params.parent_size.or(profile.parent_size);
+    profile.follow_pagination = upload_params
+        .follow_pagination
+        .or(profile.follow_pagination);
+    profile.max_pagination_pages = upload_params
+        .max_pagination_pages
+        .or(profile.max_pagination_pages);
+    // the transformers of the profile are resolved before the job starts, so unknown names fail
+    // the request
+    let transforms = TransformChain::new(&profile.transforms)
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    let default_options = retriever::SitemapOptions::default();
+    let sitemap_options = retriever::SitemapOptions {
+        locales: comma_separated(upload_params.locales.as_deref()),
+        concurrency: upload_params
+            .concurrency
+            .or(profile.concurrency)
+            .unwrap_or(default_options.concurrency),
+        crawl_delay: upload_params
+            .crawl_delay_ms
+            .or(profile.crawl_delay_ms)
+            .map(Duration::from_millis)
+            .unwrap_or(default_options.crawl_delay),
+        respect_robots: upload_params
+            .respect_robots
+            .unwrap_or(default_options.respect_robots),
+        profile: profile,
+        max_pages: None,
+        skip_urls: skip_urls.clone(),
+    };
+    // the sitemap source crawls with the options of the upload, the crawler source with its
+    // profile
+    let ingest_source: SharedIngestSource = match upload_params.source.as_deref() {
+        None => Arc::new(SitemapSource::new(sitemap_options)),
+        Some(name) if name == SITEMAP_SOURCE => Arc::new(SitemapSource::new(sitemap_options)),
+        Some(name) if name == CRAWLER_SOURCE => {
+            Arc::new(CrawlerSource::new(sitemap_options.profile.clone()))
+        }
+        Some(name) => ingest_source(name).ok_or(AppError::BadRequest(format!(
+            "unknown source: {}, registered sources: {}",
+            name,
+            ingest_source_names().join(", ")
+        )))?,
+    };
+    let webhook_url = upload_params.webhook_url;
+    let summary_policy = upload_params.summary_policy.unwrap_or_default();
+    let incremental = upload_params.incremental.unwrap_or(false);
+    let changed_only = upload_params.changed_only.unwrap_or(false);
+    let lead_first = upload_params.lead_first.unwrap_or(false);
+    let dedup_threshold = match upload_params.dedup.unwrap_or(false) {
+        true => Some(
+            upload_params
+                .dedup_threshold
+                .unwrap_or(DEFAULT_DEDUP_THRESHOLD),
+        ),
+        false => None,
+    };
+    let dry_run = upload_params.dry_run.unwrap_or(false);
+    let write_options = WriteOptions {
+        wait: upload_params.wait.unwrap_or(false),
+        ordering: upload_params.write_ordering.unwrap_or_default(),
+        max_message_size: state.app_config.config_file.qdrant.max_message_size(),
+    };
+
+    // the job is registered before fetching so the crawl shows up in its progress
+    let tracker = state.progress_map.clone();
+    let mut progress = EmbeddingProgress::new(0);
+    progress.set_current_stage(Stage::Fetched);
+    if locations.len() > 1 {
+        progress.set_sources(&locations);
+    }
+    tracker.insert(id, progress).await;
+
+    let qdrant_client = state.app_config.qdrant_client.clone();
+    let batch_size = state.app_config.embedding_batch_size;
+    let scheduler = state.scheduler.clone();
+    let reports = state.reports.clone();
+    let summary_queue = state.summary_queue.clone();
+    let embedding_provider = state
+        .app_config
+        .embedding_provider_of(&base_collection)
+        .clone();
+    let embedding_cache = state.app_config.embedding_cache.clone();
+    let prompt_logger = state.app_config.prompt_logger.clone();
+    // keep the job controllable until the task is done
+    let control_guard = state.job_controls.register(id);
+    let memory_budget_bytes = state
+        .app_config
+        .qdrant_memory_budget_mb
+        .map(|memory_budget_mb| memory_budget_mb * MEGABYTE);
+
+    // spawn a background task, traced as a span of its own
+    let job = async move {
+        // wait for a job slot before registering, a waiting job doesn't hold back the running
+        // lower priority jobs, the slot is released when the task is done
+        let _slot = scheduler.acquire_slot().await;
+        // keep the job registered with the scheduler until the task is done
+        let _guard = scheduler.register(priority);
+        info!("Upload job {} running with priority {:?}", id, priority);
+
+        info!("Fetching {}", locations.join(", "));
+        // the callback is sync, the updates are applied in tasks
+        let stage_tracker = tracker.clone();
+        let on_stage: LocationStageCallback = Arc::new(move |location, stage, count, duration| {
+            let stage_tracker = stage_tracker.clone();
+            let location = location.to_string();
+            tokio::spawn(async move {
+                stage_tracker
+                    .update(&id, |progress| {
+                        progress.record_stage(stage, count, duration);
+                        progress.record_source_stage(&location, stage, count, duration);
+                    })
+                    .await;
+            });
+        });
+        // the urls are read concurrently, the documents of the urls read are uploaded if others
+        // fail
+        let mut fetch_stages = StageProgress::default();
+        let mut fetched = Vec::new();
+        let mut errors = Vec::new();
+        for read in documents_of_locations(ingest_source, &locations, Some(on_stage)).await {
+            fetch_stages.merge(&read.stages);
+            let (count, error) = match read.documents {
+                Ok(documents) => {
+                    info!("Fetched {} docs from {}", documents.len(), read.location);
+                    let count = documents.len();
+                    fetched.push((read.location.clone(), documents));
+                    (count, None)
+                }
+                Err(e) => {
+                    info!("Error fetching documents of {}: {}", read.location, e);
+                    errors.push(format!("{}: {}", read.location, e));
+                    (0, Some(e.to_string()))
+                }
+            };
+            tracker
+                .update(&id, |progress| {
+                    progress.finish_source(&read.location, count, error)
+                })
+                .await;
+        }
+        if fetched.is_empty() {
+            let error = errors.join(", ");
+            info!("Error fetching documents of job {}: {}", id, error);
+            tracker
+                .update(&id, |progress| progress.set_status(JobStatus::Failed))
+                .await;
+            let mut report = JobReport::new(id);
+            report.error = Some(error);
+            reports.lock().unwrap().insert(id, report.clone());
+            if let Some(webhook_url) = webhook_url {
+                if let Err(e) = send_webhook(&webhook_url, &report).await {
+                    info!("Error sending webhook: {}", e);
+                }
+            }
+            return;
+        }
+        // the crawl isn't interrupted, a job cancelled meanwhile stops before the upload
+        let control = control_guard.control();
+        if control.signal() == JobSignal::Cancel {
+            info!("Cancelled upload job {} before the upload", id);
+            tracker
+                .update(&id, |progress| progress.set_status(JobStatus::Cancelled))
+                .await;
+            return;
+        }
+
+        info!("Creating Ollama client");
+        let ollama = ollama_rs::Ollama::new(ollama_host.to_string(), ollama_port);
+        // report the tokens of the summaries to show the LLM progress of the job
+        let token_tracker = tracker.clone();
+        let llm = ollama::Llm::new(ollama)
+            .with_prompt_logger(prompt_logger)
+            .with_token_callback(Arc::new(move |tokens| {
+                // the callback is sync, the update is applied in a task
+                let token_tracker = token_tracker.clone();
+                tokio::spawn(async move {
+                    token_tracker
+                        .update(&id, |progress| progress.add_generated_tokens(tokens))
+                        .await;
+                });
+            }));
+        let transform_stage = TransformStage {
+            chain: transforms,
+            llm: llm.clone(),
+            ollama_model: ollama_model.clone(),
+        };
+        let question_stage = match filter_collections.contains(&Collection::Questions) {
+            true => Some(QuestionStage {
+                llm: llm.clone(),
+                ollama_model: ollama_model.clone(),
+            }),
+            false => None,
+        };
+        let summary_stage = match filter_collections.contains(&Collection::Summary) {
+            true => Some(SummaryStage {
+                llm: llm,
+                ollama_model: ollama_model,
+                ollama_host: ollama_host,
+                ollama_port: ollama_port,
+                policy: summary_policy,
+            }),
+            false => None,
+        };
+        let upload_config = UploadConfig::new(
+            qdrant_client,
+            embedding_provider,
+            &base_collection,
+            filter_collections,
+        )
+        .with_summary(summary_stage)
+        .with_questions(question_stage)
+        .with_transforms(Some(transform_stage))
+        .with_batch_size(batch_size)
+        .with_embedding_cache(embedding_cache)
+        .with_scheduler(scheduler.clone(), priority)
+        .with_incremental(incremental)
+        .with_lead_first(lead_first)
+        .with_dedup(dedup_threshold)
+        .with_write_options(write_options)
+        .with_control(control);
+
+        // diff each url against its previous crawl before new page hashes are stored, the
+        // report holds the diffs of all urls
+        let mut report = JobReport::new(id);
+        let mut docs = Vec::new();
+        for (location, mut location_docs) in fetched {
+            match diff_crawl(&upload_config, &location, &location_docs).await {
+                Ok(mut crawl_diff) => {
+                    // the skipped pages are missing from the crawl, not from the site
+                    crawl_diff.removed.retain(|url| !skip_urls.contains(url));
+                    if changed_only {
+                        location_docs.retain(|doc| crawl_diff.is_changed(&doc.url));
+                        info!(
+                            "Uploading {} changed docs of {} of job {}",
+                            location_docs.len(),
+                            location,
+                            id
+                        );
+                    }
+                    report.crawl_diff = Some(match report.crawl_diff.take() {
+                        Some(mut merged) => {
+                            merged.merge(crawl_diff);
+                            merged
+                        }
+                        None => crawl_diff,
+                    });
+                }
+                Err(e) => info!("Error getting previous crawl of {}: {}", location, e),
+            }
+            docs.extend(location_docs);
+        }
+        if dry_run {
+            match estimate_index(&upload_config, &docs, memory_budget_bytes).await {
+                Ok(estimate) => report.estimate = Some(estimate),
+                Err(e) => {
+                    info!("Error estimating index of job {}: {}", id, e);
+                    report.error = Some(format!("Error estimating index: {}", e));
+                }
+            }
+        }
+        {
+            let reports = reports.lock();
+            reports.unwrap().insert(id, report.clone());
+        }
+        if dry_run {
+            // nothing is uploaded, the dry run is finished once its report is stored
+            let status = match report.error {
+                Some(_) => JobStatus::Failed,
+                None => JobStatus::Completed,
+            };
+            tracker
+                .update(&id, |progress| progress.set_status(status))
+                .await;
+            if let Some(webhook_url) = webhook_url {
+                if let Err(e) = send_webhook(&webhook_url, &report).await {
+                    info!("Error sending webhook: {}", e);
+                }
+            }
+            return;
+        }
+
+        let outcome = run_upload(&upload_config, id, &mut docs, &fetch_stages, &tracker).await;
+
+        for pending in outcome.pending {
+            summary_queue.push(pending);
+        }
+        if outcome.summaries.is_some()
+            || outcome.skipped_fragments.is_some()
+            || outcome.dedup.is_some()
+        {
+            report.summaries = outcome.summaries;
+            report.skipped_fragments = outcome.skipped_fragments;
+            report.dedup = outcome.dedup;
+            reports.lock().unwrap().insert(id, report.clone());
+        }
+
+        if let Some(webhook_url) = webhook_url {
+            if let Err(e) = send_webhook(&webhook_url, &report).await {
+                info!("Error sending webhook: {}", e);
+            }
+        }
+    };
+    tokio::spawn(job.instrument(info_span!("upload_job", %id)));
+
+    Ok(id)
+}
+
+/// get_job function returns the progress of a job
+///
+/// This route does return the progress of a job with the counts and timings of each stage.
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    params(
+        ("id" = String, Path, description = "Job id returned by the upload"),
+    ),
+    responses(
+        (status = 200, description = "Success response", body = JobState),
+        (status = 404, description = "Not Found", body = ErrorResponse)
+    )
+)]
+pub async fn get_job(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<JobState>, AppError> {
+    match state.progress_map.get(&id).await {
+        Some(progress) => Ok(Json(JobState {
+            id: id,
+            progress: progress,
+        })),
+        None => Err(AppError::NotFound(format!("job {} not found", id))),
+    }
+}
+
+/// cancel_job function cancels a running upload job
+///
+/// This route does cancel an upload job, the job stops before its next document and keeps the
+/// documents already uploaded. A paused job is cancelled as well.
+#[utoipa::path(
+    post,
+    path = "/jobs/{id}/cancel",
+    params(
+        ("id" = String, Path, description = "Job id returned by the upload"),
+    ),
+    responses(
+        (status = 200, description = "Success response", body = JobState),
+        (status = 404, description = "Not Found", body = ErrorResponse),
+        (status = 409, description = "Job already finished", body = ErrorResponse)
+    )
+)]
+pub async fn cancel_job(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<JobState>, AppError> {
+    let control = job_control(&state, id).await?;
+    info!("Cancelling upload job {}", id);
+    control.cancel();
+    get_job(state, Path(id)).await
+}
+
+/// pause_job function pauses a running upload job
+///
+/// This route does pause an upload job before its next document until it is resumed or
+/// cancelled, the job shows up as Paused once the current document is done.
+#[utoipa::path(
+    post,
+    path = "/jobs/{id}/pause",
+    params(
+        ("id" = String, Path, description = "Job id returned by the upload"),
+    ),
+    responses(
+        (status = 200, description = "Success response", body = JobState),
+        (status = 404, description = "Not Found", body = ErrorResponse),
+        (status = 409, description = "Job already finished", body = ErrorResponse)
+    )
+)]
+pub async fn pause_job(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<JobState>, AppError> {
+    let control = job_control(&state, id).await?;
+    info!("Pausing upload job {}", id);
+    control.pause();
+    get_job(state, Path(id)).await
+}
+
+/// resume_job function resumes a paused upload job
+///
+/// This route does let a paused upload job continue with its next document.
+#[utoipa::path(
+    post,
+    path = "/jobs/{id}/resume",
+    params(
+        ("id" = String, Path, description = "Job id returned by the upload"),
+    ),
+    responses(
+        (status = 200, description = "Success response", body = JobState),
+        (status = 404, description = "Not Found", body = ErrorResponse),
+        (status = 409, description = "Job already finished", body = ErrorResponse)
+    )
+)]
+pub async fn resume_job(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<JobState>, AppError> {
+    let control = job_control(&state, id).await?;
+    info!("Resuming upload job {}", id);
+    control.resume();
+    get_job(state, Path(id)).await
+}
+
+// job_control returns the control of a running job, unknown jobs are not found and finished jobs
+// can't be controlled anymore
+async fn job_control(
+    state: &AppState<EmbeddingProgress>,
+    id: Uuid,
+) -> Result<Arc<JobControl>, AppError> {
+    if state.progress_map.get(&id).await.is_none() {
+        return Err(AppError::NotFound(format!("job {} not found", id)));
+    }
+    match state.job_controls.get(&id) {
+        Some(control) => Ok(control),
+        None => Err(AppError::Conflict(format!(
+            "job {} is already finished",
+            id
+        ))),
+    }
+}
+
+/// get_job_events function streams the progress of a job
+///
+/// This route does stream the progress of a job as server-sent events, an event is sent for
+/// the current progress and for each update until the job is finished or evicted.
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}/events",
+    params(
+        ("id" = String, Path, description = "Job id returned by the upload"),
+    ),
+    responses(
+        (status = 200, description = "Server-sent events with the JobState of each update", body = String),
+        (status = 404, description = "Not Found", body = ErrorResponse)
+    )
+)]
+pub async fn get_job_events(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let mut receiver = match state.progress_map.subscribe(&id).await {
+        Some(receiver) => receiver,
+        None => {
+            return Err(AppError::NotFound(format!("job {} not found", id)));
+        }
+    };
+
+    let (sender, events) = tokio::sync::mpsc::channel(16);
+    tokio::spawn(async move {
+        loop {
+            let progress = receiver.borrow_and_update().clone();
+            let finished = progress.status().is_finished();
+            let job_state = JobState {
+                id: id,
+                progress: progress,
+            };
+            let event = match Event::default().json_data(&job_state) {
+                Ok(event) => event,
+                Err(e) => {
+                    info!("Error serializing job state: {}", e);
+                    return;
+                }
+            };
+            // stop once the client is gone, the job is finished or evicted
+            if sender.send(event).await.is_err() || finished || receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    });
+
+    Ok(Sse::new(ReceiverStream::new(events).map(Ok)).keep_alive(KeepAlive::default()))
+}
+
+/// get_job_report function returns the report of an upload job
+///
+/// This route does retrieve the report, including the diff against the previous crawl.
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}/report",
+    params(
+        ("id" = String, Path, description = "Job id returned by the upload"),
+    ),
+    responses(
+        (status = 200, description = "Success response", body = JobReport),
+        (status = 404, description = "Not Found", body = ErrorResponse)
+    )
+)]
+pub async fn get_job_report(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<JobReport>, AppError> {
+    let reports = state.reports.lock().unwrap();
+    match reports.get(&id) {
+        Some(report) => Ok(Json(report.clone())),
+        None => Err(AppError::NotFound(format!("job report {} not found", id))),
+    }
+}
+
+// MAX_DOCUMENT_SIZE is the maximum size in bytes of a document ingested synchronously
+static MAX_DOCUMENT_SIZE: usize = 2 * 1024 * 1024;
+
+#[derive(Deserialize, Default, ToSchema)]
+pub struct DocumentUrlParams {
+    pub url: String,
+    pub ollama_model: Option<String>,
+    pub ollama_host: Option<String>,
+    pub ollama_port: Option<u16>,
+    // filter_collections is a comma separated list of the collections uploaded to, e.g.
+    // basic,summary
+    pub filter_collections: Option<String>,
+    pub base_collection: Option<String>,
+    // wait returns once the page is searchable, defaults to true
+    pub wait: Option<bool>,
+    // write_ordering is the ordering guarantee of the upserts across replicas, defaults to Weak
+    pub write_ordering: Option<WriteOrder>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DocumentUrlResponse {
+    pub id: String,
+    pub url: String,
+    pub chunk_ids: Vec<String>,
+}
+
+/// upload_document_url function ingests a single document
+///
+/// This route does fetch, chunk, embed and upsert a single page synchronously,
+/// so the page is queryable as soon as the response is returned.
+#[utoipa::path(
+    post,
+    path = "/documents/url",
+    params(
+        ("document_url_params" = DocumentUrlParams, Path, description = "Document URL parameters"),
+    ),
+    responses(
+        (status = 200, description = "Success response", body = DocumentUrlResponse),
+        (status = 400, description = "Bad Request", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+        (status = 502, description = "Qdrant or Ollama Error", body = ErrorResponse),
+        (status = 503, description = "Qdrant or Ollama Unavailable", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all)]
+pub async fn upload_document_url(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    document_url_params: Result<Query<DocumentUrlParams>, QueryRejection>,
+) -> Result<Json<DocumentUrlResponse>, AppError> {
+    let id = Uuid::new_v5(
+        &Uuid::NAMESPACE_URL,
+        format!("{}{}", "document", Utc::now()).as_bytes(),
+    );
+
+    let Query(params) = document_url_params.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    let ollama_model = params
+        .ollama_model
+        .unwrap_or(state.app_config.ollama_model.clone());
+    let ollama_host = params
+        .ollama_host
+        .unwrap_or(state.app_config.ollama_host.clone());
+    let ollama_port = params.ollama_port.unwrap_or(state.app_config.ollama_port);
+    let filter_collections = match params.filter_collections.as_deref() {
+        Some(filter_collections) => parse_collections(filter_collections)
+            .map_err(|e| AppError::BadRequest(e.to_string()))?,
+        None => state.app_config.filter_collections.clone(),
+    };
+    let base_collection = params
+        .base_collection
+        .unwrap_or(state.app_config.base_collection.clone());
+    let write_options = WriteOptions {
+        wait: params.wait.unwrap_or(true),
+        ordering: params.write_ordering.unwrap_or_default(),
+        max_message_size: state.app_config.config_file.qdrant.max_message_size(),
+    };
+    let url = params.url;
+
+    if url.is_empty() {
+        return Err(AppError::BadRequest("mandatory URL is empty".to_string()));
+    }
+
+    // single documents preempt running uploads at their next stage boundary
+    let _guard = state.scheduler.register(JobPriority::High);
+    state
+        .progress_map
+        .insert(id, EmbeddingProgress::new(1))
+        .await;
+    // the job fails unless the document is upserted, whatever error is returned below
+    let finish = FinishGuard {
+        progress_map: state.progress_map.clone(),
+        id: id,
+        completed: false,
+    };
+
+    info!("Fetching single document {}", url);
+    let mut doc = retriever::fetch_content_with_limit(url.clone(), MAX_DOCUMENT_SIZE)
+        .await
+        .map_err(|e| {
+            info!("Error fetching document: {}", e);
+            AppError::BadRequest(e.to_string())
+        })?;
+    // the transformers of the crawl profile of the domain apply to single documents as well
+    let transforms =
+        TransformChain::new(&state.app_config.config_file.crawl_profile(&url).transforms)
+            .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    if !transforms.is_empty() {
+        let ollama = ollama_rs::Ollama::new(ollama_host.to_string(), ollama_port);
+        let llm =
+            ollama::Llm::new(ollama).with_prompt_logger(state.app_config.prompt_logger.clone());
+        transforms
+            .apply(&mut doc, &llm, &ollama_model)
+            .await
+            .map_err(|e| {
+                info!("Error transforming document: {}", e);
+                AppError::Internal(e)
+            })?;
+    }
+
+    let qdrant_client = state.app_config.qdrant_client.clone();
+    let embedding_provider = state
+        .app_config
+        .embedding_provider_of(&base_collection)
+        .clone();
+    let embedding_size = embedding_size(&embedding_provider)
+        .await
+        .map_err(AppError::Internal)?;
+    create_collections(
+        &qdrant_client,
+        &base_collection,
+        filter_collections.clone(),
+        &embedding_provider.name(),
+        embedding_size,
+        &state.app_config.collection_config,
+    )
+    .await
+    .map_err(AppError::Qdrant)?;
+
+    if filter_collections.contains(&Collection::Summary) {
+        info!("Creating summary document");
+        let ollama = ollama_rs::Ollama::new(ollama_host.to_string(), ollama_port);
+        let llm =
+            ollama::Llm::new(ollama).with_prompt_logger(state.app_config.prompt_logger.clone());
+        if let Err(e) = doc.add_summary(&ollama_model, &llm).await {
+            info!("Error adding summary: {}", e);
+        }
+    }
+    if filter_collections.contains(&Collection::Questions) {
+        info!("Generating questions");
+        let ollama = ollama_rs::Ollama::new(ollama_host.to_string(), ollama_port);
+        let llm =
+            ollama::Llm::new(ollama).with_prompt_logger(state.app_config.prompt_logger.clone());
+        if let Err(e) = doc.add_questions(&ollama_model, &llm).await {
+            info!("Error adding questions: {}", e);
+        }
+    }
+
+    // the document is embedded with the provider shared with the queries
+    let start = Instant::now();
+    let embeddings = document_embeddings_async(
+        &embedding_provider,
+        &state.app_config.embedding_cache,
+        doc,
+        state.app_config.embedding_batch_size,
+    )
+    .await
+    .map_err(|e| {
+        info!("Error encoding document: {}", e);
+        AppError::Internal(e)
+    })?;
+    record_document_stage(&state, id, Stage::Embedded, start.elapsed()).await;
+    let chunk_ids = embeddings
+        .iter()
+        .filter(|e| filter_collections.contains(&e.metadata.collection))
+        .map(|e| e.metadata.id.clone())
+        .collect::<Vec<String>>();
+
+    let start = Instant::now();
+    add_documents(
+        &qdrant_client,
+        &base_collection,
+        filter_collections,
+        embeddings,
+        write_options,
+    )
+    .await
+    .map_err(|e| {
+        info!("Error adding documents: {}", e);
+        AppError::Qdrant(e)
+    })?;
+    record_document_stage(&state, id, Stage::Upserted, start.elapsed()).await;
+    finish.complete().await;
+    info!("Ingested {} with {} chunks", url, chunk_ids.len());
+
+    Ok(Json(DocumentUrlResponse {
+        id: id.to_string(),
+        url: url,
+        chunk_ids: chunk_ids,
+    }))
+}
+
+// record_document_stage records a stage of the job of a single document
+async fn record_document_stage(
+    state: &AppState<EmbeddingProgress>,
+    id: Uuid,
+    stage: Stage,
+    duration: Duration,
+) {
+    state
+        .progress_map
+        .update(&id, |progress| progress.record_stage(stage, 1, duration))
+        .await;
+}
+
+// FinishGuard sets the final status of the job of a single document, Completed by complete and
+// Failed if the request returns early or is dropped, e.g. by an error returned with ?
+struct FinishGuard {
+    progress_map: Arc<ProgressStore<EmbeddingProgress>>,
+    id: Uuid,
+    completed: bool,
+}
+
+impl FinishGuard {
+    // complete marks the document processed and the job completed
+    async fn complete(mut self) {
+        self.completed = true;
+        self.progress_map
+            .update(&self.id, |progress| {
+                progress.increment_processed();
+                progress.set_status(JobStatus::Completed);
+            })
+            .await;
+    }
+}
+
+impl Drop for FinishGuard {
+    fn drop(&mut self) {
+        if self.completed {
+            return;
+        }
+        // drop can't wait for the lock of the store, the job is failed by a task
+        let progress_map = self.progress_map.clone();
+        let id = self.id;
+        tokio::spawn(async move {
+            progress_map
+                .update(&id, |progress| progress.set_status(JobStatus::Failed))
+                .await;
+        });
+    }
+}
+
+#[derive(Deserialize, Default, ToSchema)]
+pub struct DeleteDocumentsParams {
+    pub url: String,
+    // filter_collections is a comma separated list of the collections deleted from, e.g.
+    // basic,summary
+    pub filter_collections: Option<String>,
+    pub base_collection: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeleteDocumentsResponse {
+    pub url: String,
+    // deleted is the number of deleted chunks
+    pub deleted: u64,
+}
+
+/// delete_documents function deletes a single page
+///
+/// This route does delete all chunks of the page with the given URL, e.g. a stale or removed
+/// page, from the collections.
+#[utoipa::path(
+    delete,
+    path = "/documents",
+    params(
+        ("delete_documents_params" = DeleteDocumentsParams, Path, description = "Delete documents parameters"),
+    ),
+    responses(
+        (status = 200, description = "Success response", body = DeleteDocumentsResponse),
+        (status = 400, description = "Bad Request", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+        (status = 502, description = "Qdrant or Ollama Error", body = ErrorResponse),
+        (status = 503, description = "Qdrant or Ollama Unavailable", body = ErrorResponse)
+    )
+)]
+pub async fn delete_documents(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    delete_documents_params: Option<Query<DeleteDocumentsParams>>,
+) -> Result<Json<DeleteDocumentsResponse>, AppError> {
+    let Query(params) = delete_documents_params.unwrap_or(Query::default());
+    let filter_collections = match params.filter_collections.as_deref() {
+        Some(filter_collections) => parse_collections(filter_collections)
+            .map_err(|e| AppError::BadRequest(e.to_string()))?,
+        None => state.app_config.filter_collections.clone(),
+    };
+    let base_collection = params
+        .base_collection
+        .unwrap_or(state.app_config.base_collection.clone());
+    let url = params.url;
+
+    if url.is_empty() {
+        return Err(AppError::BadRequest("mandatory URL is empty".to_string()));
+    }
+
+    let deleted = delete_documents_by_url(
+        &state.app_config.qdrant_client,
+        &base_collection,
+        filter_collections,
+        &url,
+    )
+    .await
+    .map_err(|e| {
+        info!("Error deleting documents: {}", e);
+        AppError::Qdrant(e)
+    })?;
+    info!("Deleted {} chunks of {}", deleted, url);
+
+    Ok(Json(DeleteDocumentsResponse {
+        url: url,
+        deleted: deleted,
+    }))
+}
+
+#[derive(Deserialize, Default, ToSchema)]
+pub struct SummarizeParams {
+    pub text: Option<String>,
+    pub url: Option<String>,
+    pub ollama_model: Option<String>,
+    pub ollama_host: Option<String>,
+    pub ollama_port: Option<u16>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SummarizeResponse {
+    pub summary: String,
+    pub url: Option<String>,
+}
+
+/// summarize function returns the summary of a text or a URL
+///
+/// This route does summarize either the given raw text or the content fetched from the URL,
+/// using the same prompt as the summary collection.
+#[utoipa::path(
+    post,
+    path = "/summarize",
+    request_body = SummarizeParams,
+    responses(
+        (status = 200, description = "Success response", body = SummarizeResponse),
+        (status = 400, description = "Bad Request", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+        (status = 502, description = "Qdrant or Ollama Error", body = ErrorResponse),
+        (status = 503, description = "Qdrant or Ollama Unavailable", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all)]
+pub async fn summarize(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    Json(params): Json<SummarizeParams>,
+) -> Result<Json<SummarizeResponse>, AppError> {
+    let ollama_model = params
+        .ollama_model
+        .unwrap_or(state.app_config.ollama_model.clone());
+    let ollama_host = params
+        .ollama_host
+        .unwrap_or(state.app_config.ollama_host.clone());
+    let ollama_port = params.ollama_port.unwrap_or(state.app_config.ollama_port);
+
+    let text = match (params.text, params.url.clone()) {
+        (Some(text), None) => text,
+        (None, Some(url)) => {
+            info!("Fetching {} for summary", url);
+            let doc = retriever::fetch_content_with_limit(url, MAX_DOCUMENT_SIZE)
+                .await
+                .map_err(|e| AppError::BadRequest(e.to_string()))?;
+            doc.text
+                .get(&Collection::Basic)
+                .cloned()
+                .unwrap_or_default()
+        }
+        _ => {
+            return Err(AppError::BadRequest(
+                "exactly one of text or URL is mandatory".to_string(),
+            ));
+        }
+    };
+    if text.trim().is_empty() {
+        return Err(AppError::BadRequest(
+            "nothing to summarize, text is empty".to_string(),
+        ));
+    }
+
+    let ollama = ollama_rs::Ollama::new(ollama_host.to_string(), ollama_port);
+    let llm = ollama::Llm::new(ollama).with_prompt_logger(state.app_config.prompt_logger.clone());
+    let summary = llm.summarize(&ollama_model, &text).await.map_err(|e| {
+        info!("Error summarizing: {}", e);
+        AppError::Ollama(e)
+    })?;
+    info!("Summarized {} characters", text.len());
+
+    Ok(Json(SummarizeResponse {
+        summary: summary,
+        url: params.url,
+    }))
+}
+
+// DEFAULT_SEARCH_LIMIT is the default number of retrieved chunks
+static DEFAULT_SEARCH_LIMIT: u64 = 7;
+// DEFAULT_HIGHLIGHTS is the default number of highlighted sentences per chunk
+static DEFAULT_HIGHLIGHTS: usize = 2;
+
+#[derive(Deserialize, Default, ToSchema)]
+pub struct SearchParams {
+    pub query: String,
+    pub limit: Option<u64>,
+    pub filter_collections: Option<Vec<Collection>>,
+    pub base_collection: Option<String>,
+    // highlights is the number of sentences highlighted per chunk, 0 disables highlighting
+    pub highlights: Option<usize>,
+    // preview_sentences trims each chunk to the given number of sentences around its best
+    // matching sentence instead of returning the full chunk text
+    pub preview_sentences: Option<usize>,
+    // filter restricts the search by url prefix, indexing date or title
+    pub filter: Option<SearchFilter>,
+    // vector_search searches the title vectors of collections with named vectors, defaults to
+    // Content
+    pub vector_search: Option<VectorSearch>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchResult {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub text: String,
+    pub collection: Collection,
+    pub highlights: Vec<Highlight>,
+    // truncated is true if text is a preview of the chunk
+    pub truncated: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchResponse {
+    pub results: Vec<SearchResult>,
+    // degraded is true if the query could not be embedded and the results are keyword matches
+    // without highlights
+    pub degraded: bool,
+}
+
+/// search function returns the chunks most similar to a query
+///
+/// This route does retrieve the chunks without generating an answer. Each chunk contains the
+/// character offsets of the sentences most similar to the query, to be highlighted by frontends.
+/// Optionally the chunk text is trimmed to a preview around its best matching sentence. If the
+/// embedding backend is unavailable the chunks matching the query terms are returned instead.
+#[utoipa::path(
+    post,
+    path = "/search",
+    request_body = SearchParams,
+    responses(
+        (status = 200, description = "Success response", body = SearchResponse),
+        (status = 400, description = "Bad Request", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+        (status = 502, description = "Qdrant or Ollama Error", body = ErrorResponse),
+        (status = 503, description = "Qdrant or Ollama Unavailable", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all)]
+pub async fn search(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    Json(params): Json<SearchParams>,
+) -> Result<Json<SearchResponse>, AppError> {
+    if params.query.is_empty() {
+        return Err(AppError::BadRequest("mandatory query is empty".to_string()));
+    }
+    // the upload jobs yield to the query until it's searched
+    let _query = state.scheduler.register_query();
+    let limit = params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    let filter_collections = params
+        .filter_collections
+        .unwrap_or(state.app_config.filter_collections.clone());
+    let base_collection = params
+        .base_collection
+        .unwrap_or(state.app_config.base_collection.clone());
+    let highlights = params.highlights.unwrap_or(DEFAULT_HIGHLIGHTS);
+
+    info!("Searching {} with limit {}", params.query, limit);
+    let embedding_provider = state.app_config.embedding_provider_of(&base_collection);
+    let query = state
+        .app_config
+        .expand_query(
+            &state.app_config.llm(),
+            &state.app_config.ollama_model,
+            &params.query,
+        )
+        .await;
+    let filter = params.filter.unwrap_or_default();
+    let (docs, embeddings) = match text_embedding_async(embedding_provider, query.clone()).await {
+        Ok(embeddings) => {
+            check_query_embedding(
+                &state.app_config,
+                &base_collection,
+                &filter_collections,
+                embedding_provider,
+                embeddings.len(),
+            )
+            .await?;
+            let docs = search_documents(
+                &state.app_config.qdrant_client,
+                &base_collection,
+                filter_collections,
+                embeddings.clone(),
+                limit,
+                &filter,
+                params.vector_search.unwrap_or_default(),
+            )
+            .await
+            .map_err(|e| {
+                info!("Error searching documents: {}", e);
+                AppError::Qdrant(e)
+            })?;
+            (docs, Some(embeddings))
+        }
+        Err(e) => {
+            info!(
+                "Error embedding query, falling back to keyword search: {}",
+                e
+            );
+            let docs = keyword_search(
+                &state.app_config.qdrant_client,
+                &base_collection,
+                filter_collections,
+                &query,
+                limit,
+                &filter,
+            )
+            .await
+            .map_err(|e| {
+                info!("Error keyword searching documents: {}", e);
+                AppError::Qdrant(e)
+            })?;
+            let docs = docs
+                .into_iter()
+                .map(|doc| EmbeddedDocument {
+                    text_embeddings: vec![],
+                    title_embeddings: None,
+                    metadata: doc.metadata,
+                })
+                .collect::<Vec<EmbeddedDocument>>();
+            (docs, None)
+        }
+    };
+
+    state
+        .app_config
+        .access_tracker
+        .record(&base_collection, docs.iter().map(|doc| &doc.metadata));
+    let texts: Vec<String> = docs.iter().map(|d| d.metadata.text.clone()).collect();
+    let scored_sentences = match &embeddings {
+        Some(embeddings) if highlights > 0 || params.preview_sentences.is_some() => {
+            score_sentences(embedding_provider, &texts, embeddings).await
+        }
+        _ => vec![vec![]; texts.len()],
+    };
+    let results = docs
+        .into_iter()
+        .zip(scored_sentences)
+        .map(|(doc, scored)| {
+            let mut text = doc.metadata.text;
+            let mut doc_highlights = top_highlights(&scored, highlights);
+            let mut truncated = false;
+            if let Some(n) = params.preview_sentences {
+                if let Some((preview_text, preview_highlights)) =
+                    preview(&text, &scored, &doc_highlights, n)
+                {
+                    truncated = preview_text.len() < text.len();
+                    text = preview_text;
+                    doc_highlights = preview_highlights;
+                }
+            }
+            SearchResult {
+                id: doc.metadata.id,
+                title: doc.metadata.title,
+                url: doc.metadata.url,
+                text: text,
+                collection: doc.metadata.collection,
+                highlights: doc_highlights,
+                truncated: truncated,
+            }
+        })
+        .collect::<Vec<SearchResult>>();
+    info!("Found {} results", results.len());
+
+    Ok(Json(SearchResponse {
+        results: results,
+        degraded: embeddings.is_none(),
+    }))
+}
+
+#[derive(Deserialize, Default, ToSchema)]
+pub struct QueryParams {
+    pub query: String,
+    pub limit: Option<u64>,
+    pub filter_collections: Option<Vec<Collection>>,
+    pub base_collection: Option<String>,
+    pub ollama_model: Option<String>,
+    pub ollama_host: Option<String>,
+    pub ollama_port: Option<u16>,
+    // fast_model overrides the configured fast model, an empty string disables it
+    pub fast_model: Option<String>,
+    // answer_style sets the length and detail level of the answer, defaults to normal
+    pub answer_style: Option<AnswerStyle>,
+    // filter restricts the context by url prefix, indexing date or title
+    pub filter: Option<SearchFilter>,
+    // rerank re-scores the retrieved fragments against the query with the LLM
+    pub rerank: Option<bool>,
+    // rerank_top_n is the number of retrieved fragments re-scored, defaults to 10
+    pub rerank_top_n: Option<usize>,
+    // max_context_tokens packs the highest scored fragments into the token budget, lower
+    // scored ones are truncated or dropped, all fragments are used if unset
+    pub max_context_tokens: Option<usize>,
+    // inline_citations numbers the sources of the context and asks the model to reference them
+    // inline, e.g. [1], defaults to false
+    pub inline_citations: Option<bool>,
+    // metadata are the attributes of the caller the retrieval hooks decide by, e.g. the plan
+    // of the customer
+    pub metadata: Option<RequestMetadata>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct QueryOptions {
+    // dry_run returns the rendered prompt without generating an answer
+    pub dry_run: Option<bool>,
+    // agent lets the LLM search again, fetch pages and list sources before answering
+    pub agent: Option<bool>,
+    // agent_steps is the maximum number of tool calls in agent mode
+    pub agent_steps: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct QueryResponse {
+    pub answer: Option<String>,
+    pub explain: Option<PromptPreview>,
+    // steps are the tool calls of the agent mode
+    pub steps: Option<Vec<AgentStep>>,
+    // query_class is the detected kind of the query, only questions use retrieval
+    pub query_class: QueryClass,
+    // attempt is the zero based attempt of the served answer, low quality answers are retried
+    pub attempt: Option<usize>,
+    // citations are the source pages of the context, numbered as referenced by inline citations
+    pub citations: Option<Vec<Citation>>,
+    // cached is true if the answer was generated earlier and served while qdrant or ollama are
+    // unavailable
+    pub cached: bool,
+    // request_id is the id of the request in the logs and the prompt log, also returned in the
+    // x-request-id header
+    pub request_id: String,
+}
+
+/// query function answers a query using the retrieved chunks as context
+///
+/// This route does retrieve the chunks and generate an answer. With `dry_run=true` it returns
+/// the rendered prompt, the selected chunks and the token usage without calling the LLM. With
+/// `agent=true` the LLM can search again, fetch full pages and list the sources in a bounded
+/// loop before answering, the tool calls are returned as steps. While qdrant or ollama are
+/// unavailable an earlier answer of the same query is returned with `cached=true`, without one
+/// the route returns 503 with a `Retry-After` header. The `request_id` of the response, also
+/// returned in the `X-Request-Id` header, is logged with the retrieved context and the prompt.
+#[utoipa::path(
+    post,
+    path = "/query",
+    request_body = QueryParams,
+    params(
+        ("dry_run" = Option<bool>, Query, description = "Return the prompt without generating an answer"),
+        ("agent" = Option<bool>, Query, description = "Let the LLM call tools before answering"),
+        ("agent_steps" = Option<usize>, Query, description = "Maximum number of tool calls in agent mode"),
+    ),
+    responses(
+        (status = 200, description = "Success response", body = QueryResponse),
+        (status = 400, description = "Bad Request", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+        (status = 502, description = "Qdrant or Ollama Error", body = ErrorResponse),
+        (status = 503, description = "Qdrant or Ollama Unavailable", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all)]
+pub async fn query(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    axum::extract::Extension(request_id): axum::extract::Extension<RequestId>,
+    query_options: Option<Query<QueryOptions>>,
+    Json(params): Json<QueryParams>,
+) -> Result<Json<QueryResponse>, AppError> {
+    let Query(query_options) = query_options.unwrap_or(Query::default());
+    let settings = QuerySettings::resolve(params, request_id, &state.app_config)?;
+
+    let (query_class, direct_answer) = classify_query(&state.app_config, &settings).await;
+    if let Some(answer) = direct_answer {
+        return Ok(Json(QueryResponse {
+            answer: Some(answer),
+            explain: None,
+            steps: None,
+            query_class: query_class,
+            attempt: None,
+            citations: None,
+            cached: false,
+            request_id: settings.request_id.clone(),
+        }));
+    }
+
+    if query_options.agent.unwrap_or(false) {
+        let llm = settings.llm(&state.app_config);
+        let agent = Agent::new(
+            &llm,
+            &settings.ollama_model,
+            &state.app_config.qdrant_client,
+            state
+                .app_config
+                .embedding_provider_of(&settings.base_collection),
+            &settings.base_collection,
+            settings.filter_collections.clone(),
+            settings.limit,
+        )
+        .with_max_steps(query_options.agent_steps.unwrap_or(DEFAULT_AGENT_STEPS))
+        .with_glossary(&state.app_config.glossary)
+        .with_synonyms(&state.app_config.synonyms)
+        .with_filter(settings.filter.clone());
+        let agent_answer = agent.answer(&settings.query).await.map_err(|e| {
+            info!("Error answering query with agent: {}", e);
+            AppError::Ollama(e)
+        })?;
+        info!("Answered query with agent");
+        return Ok(Json(QueryResponse {
+            answer: Some(agent_answer.answer),
+            explain: None,
+            steps: Some(agent_answer.steps),
+            query_class: query_class,
+            attempt: None,
+            citations: None,
+            cached: false,
+            request_id: settings.request_id.clone(),
+        }));
+    }
+
+    let dry_run = query_options.dry_run.unwrap_or(false);
+    let key = answer_key(
+        &settings.base_collection,
+        &settings.ollama_model,
+        &settings.query,
+        &settings.metadata,
+    );
+    let docs = match retrieve_query_context(&state.app_config, &settings).await {
+        Ok(docs) => docs,
+        Err(e) if !dry_run => {
+            return cached_answer(&state.app_config, &key, query_class, &settings, e)
+        }
+        Err(e) => return Err(e),
+    };
+
+    if dry_run {
+        let preview = preview_prompt(
+            &settings.query,
+            &docs,
+            settings.answer_style,
+            &state.app_config.glossary,
+            settings.inline_citations,
+        )
+        .map_err(AppError::Internal)?;
+        return Ok(Json(QueryResponse {
+            answer: None,
+            explain: Some(preview),
+            steps: None,
+            query_class: query_class,
+            attempt: None,
+            citations: Some(citations(&docs)),
+            cached: false,
+            request_id: settings.request_id.clone(),
+        }));
+    }
+
+    let llm = settings.llm(&state.app_config);
+    let generated = match generate_answer(
+        &llm,
+        &settings.ollama_model,
+        &settings.query,
+        &docs,
+        settings.answer_style,
+        &state.app_config.glossary,
+        settings.inline_citations,
+        state.app_config.answer_retries,
+    )
+    .await
+    {
+        Ok(generated) => generated,
+        Err(e) => {
+            info!("Error generating answer: {}", e);
+            return cached_answer(
+                &state.app_config,
+                &key,
+                query_class,
+                &settings,
+                AppError::Ollama(e),
+            );
+        }
+    };
+    info!("Answered query, attempt: {}", generated.attempt);
+
+    let citations = citations(&docs);
+    state.app_config.answer_cache.insert(
+        key,
+        CachedAnswer {
+            answer: generated.answer.clone(),
+            citations: citations.clone(),
+        },
+    );
+    Ok(Json(QueryResponse {
+        answer: Some(generated.answer),
+        explain: None,
+        steps: None,
+        query_class: query_class,
+        attempt: Some(generated.attempt),
+        citations: Some(citations),
+        cached: false,
+        request_id: settings.request_id,
+    }))
+}
+
+// cached_answer returns the cached answer of the query if the error is an outage of qdrant or
+// ollama, otherwise or without cached answer the error is returned
+fn cached_answer(
+    app_config: &AppConfig,
+    key: &str,
+    query_class: QueryClass,
+    settings: &QuerySettings,
+    error: AppError,
+) -> Result<Json<QueryResponse>, AppError> {
+    if !error.is_outage() {
+        return Err(error);
+    }
+    let cached = app_config.answer_cache.get(key).ok_or(error)?;
+    info!("Serving cached answer during outage");
+    record_cached_answer();
+    Ok(Json(QueryResponse {
+        answer: Some(cached.answer),
+        explain: None,
+        steps: None,
+        query_class: query_class,
+        attempt: None,
+        citations: Some(cached.citations),
+        cached: true,
+        request_id: settings.request_id.clone(),
+    }))
+}
+
+/// query_stream function streams the answer of a query
+///
+/// This route does retrieve the chunks and stream the generated answer as server-sent events,
+/// each event contains the next part of the answer. With a fast model the parts are a draft of
+/// the fast model, the answer of the quality model replaces it in an event of type `answer`. The
+/// id of the request is returned in the `X-Request-Id` header.
+#[utoipa::path(
+    post,
+    path = "/query/stream",
+    request_body = QueryParams,
+    responses(
+        (status = 200, description = "Server-sent events with the parts of the answer", body = String),
+        (status = 400, description = "Bad Request", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+        (status = 502, description = "Qdrant or Ollama Error", body = ErrorResponse),
+        (status = 503, description = "Qdrant or Ollama Unavailable", body = ErrorResponse)
+    )
+)]
+pub async fn query_stream(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    axum::extract::Extension(request_id): axum::extract::Extension<RequestId>,
+    Json(params): Json<QueryParams>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let settings = QuerySettings::resolve(params, request_id, &state.app_config)?;
+
+    let stream: EventStream = match classify_query(&state.app_config, &settings).await {
+        (_, Some(answer)) => Box::pin(tokio_stream::once(Event::default().data(answer))),
+        (_, None) => generate_query_stream(&state.app_config, &settings).await?,
+    };
+
+    Ok(Sse::new(stream.map(Ok)).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Deserialize, Default, ToSchema)]
+pub struct ChatParams {
+    // session_id continues a conversation, a new session is started if unset or unknown
+    pub session_id: Option<Uuid>,
+    #[serde(flatten)]
+    pub query: QueryParams,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChatResponse {
+    // session_id identifies the conversation, send it with the follow-up questions
+    pub session_id: Uuid,
+    pub answer: String,
+    // standalone_query is the follow-up question rewritten with the history, used for retrieval
+    pub standalone_query: String,
+    pub citations: Vec<Citation>,
+    // history are the previous turns of the conversation, including the answered question
+    pub history: Vec<ChatTurn>,
+    // request_id is the id of the request in the logs and the prompt log
+    pub request_id: String,
+}
+
+/// chat function answers a question of a conversation
+///
+/// This route keeps the history of a conversation by session id. Follow-up questions are
+/// rewritten into standalone queries with the previous turns before retrieval, the answer is
+/// generated with the previous turns and the fresh context. Idle sessions are evicted.
+#[utoipa::path(
+    post,
+    path = "/chat",
+    request_body = ChatParams,
+    responses(
+        (status = 200, description = "Success response", body = ChatResponse),
+        (status = 400, description = "Bad Request", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+        (status = 502, description = "Qdrant or Ollama Error", body = ErrorResponse),
+        (status = 503, description = "Qdrant or Ollama Unavailable", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all)]
+pub async fn chat(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    axum::extract::Extension(request_id): axum::extract::Extension<RequestId>,
+    Json(params): Json<ChatParams>,
+) -> Result<Json<ChatResponse>, AppError> {
+    let session_id = params.session_id.unwrap_or(Uuid::new_v4());
+    let mut session = state.chat_sessions.get(&session_id).unwrap_or_default();
+    let mut settings = QuerySettings::resolve(params.query, request_id, &state.app_config)?;
+
+    let question = settings.query.clone();
+    let llm = settings.llm(&state.app_config);
+    let model = settings
+        .fast_model
+        .as_deref()
+        .unwrap_or(&settings.ollama_model);
+    settings.query = condense_question(&llm, model, session.history(), &question).await;
+
+    let docs = retrieve_query_context(&state.app_config, &settings).await?;
+    let formatted_prompt = render_chat_prompt(
+        &question,
+        session.history(),
+        &docs,
+        settings.answer_style,
+        &state.app_config.glossary,
+        settings.inline_citations,
+    );
+    let answer = llm
+        .generate(&settings.ollama_model, &formatted_prompt)
+        .await
+        .map_err(|e| {
+            info!("Error generating chat answer: {}", e);
+            AppError::Ollama(e)
+        })?;
+    info!("Answered chat question of session {}", session_id);
+
+    session.add_turn(question, answer.clone());
+    let history = session.turns.clone();
+    state.chat_sessions.save(session_id, session);
+    Ok(Json(ChatResponse {
+        session_id: session_id,
+        answer: answer,
+        standalone_query: settings.query,
+        citations: citations(&docs),
+        history: history,
+        request_id: settings.request_id,
+    }))
+}
+
+// EventStream is a stream of server-sent events
+type EventStream = Pin<Box<dyn Stream<Item = Event> + Send>>;
+
+// generate_query_stream retrieves the context and streams the generated answer
+// with a fast model the draft of the fast model is streamed and replaced by the answer of the
+// quality model sent as an event of type answer
+async fn generate_query_stream(
+    app_config: &AppConfig,
+    settings: &QuerySettings,
+) -> Result<EventStream, AppError> {
+    let docs = retrieve_query_context(app_config, settings).await?;
+
+    let formatted_prompt = render_prompt(
+        &settings.query,
+        &docs,
+        settings.answer_style,
+        &app_config.glossary,
+        settings.inline_citations,
+    );
+    // the citations are sent first so clients can link the inline references while streaming
+    let citations_event = Event::default()
+        .event("citations")
+        .json_data(citations(&docs))
+        .map_err(|e| {
+            info!("Error serializing citations: {}", e);
+            AppError::Internal(e.into())
+        })?;
+    let llm = settings.llm(app_config);
+    let map_err = |e: anyhow::Error| {
+        info!("Error generating answer: {}", e);
+        AppError::Ollama(e)
+    };
+    match &settings.fast_model {
+        Some(fast_model) => {
+            let stream = llm
+                .generate_speculative(fast_model, &settings.ollama_model, &formatted_prompt)
+                .await
+                .map_err(map_err)?;
+            Ok(Box::pin(tokio_stream::once(citations_event).chain(
+                stream.map(|event| match event {
+                    SpeculativeEvent::Draft(text) => Event::default().data(text),
+                    SpeculativeEvent::Final(answer) => {
+                        Event::default().event("answer").data(answer)
+                    }
+                }),
+            )))
+        }
+        None => {
+            let stream = llm
+                .generate_text_stream(&settings.ollama_model, &formatted_prompt)
+                .await
+                .map_err(map_err)?;
+            Ok(Box::pin(
+                tokio_stream::once(citations_event)
+                    .chain(stream.map(|text| Event::default().data(text))),
+            ))
+        }
+    }
+}
+
+// classify_query classifies the query and returns the answer of queries skipping retrieval,
+// e.g. greetings or out of scope questions
+async fn classify_query(
+    app_config: &AppConfig,
+    settings: &QuerySettings,
+) -> (QueryClass, Option<String>) {
+    let llm = settings.llm(app_config);
+    let model = settings
+        .fast_model
+        .as_deref()
+        .unwrap_or(&settings.ollama_model);
+    let query_class = app_config
+        .query_classifier
+        .classify(&llm, model, &settings.query)
+        .await;
+    if query_class != QueryClass::Question {
+        info!("Answering {:?} query without retrieval", query_class);
+    }
+    (query_class, app_config.query_classifier.answer(query_class))
+}
+
+// QuerySettings represents the query parameters with the defaults of the app config applied
+struct QuerySettings {
+    query: String,
+    limit: u64,
+    filter_collections: Vec<Collection>,
+    base_collection: String,
+    ollama_model: String,
+    ollama_host: String,
+    ollama_port: u16,
+    // fast_model drafts streamed answers and classifies queries if set
+    fast_model: Option<String>,
+    answer_style: AnswerStyle,
+    filter: SearchFilter,
+    // reranker re-scores the retrieved fragments if reranking is enabled
+    reranker: Option<Reranker>,
+    max_context_tokens: Option<usize>,
+    inline_citations: bool,
+    metadata: RequestMetadata,
+    // request_id tags the logged prompts of the query
+    request_id: String,
+}
+
+impl QuerySettings {
+    // resolve applies the app config defaults to the query parameters
+    fn resolve(
+        params: QueryParams,
+        request_id: RequestId,
+        app_config: &AppConfig,
+    ) -> Result<Self, AppError> {
+        if params.query.is_empty() {
+            return Err(AppError::BadRequest("mandatory query is empty".to_string()));
+        }
+        Ok(QuerySettings {
+            query: params.query,
+            limit: params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT),
+            filter_collections: params
+                .filter_collections
+                .unwrap_or(app_config.filter_collections.clone()),
+            base_collection: params
+                .base_collection
+                .unwrap_or(app_config.base_collection.clone()),
+            ollama_model: params
+                .ollama_model
+                .unwrap_or(app_config.ollama_model.clone()),
+            ollama_host: params.ollama_host.unwrap_or(app_config.ollama_host.clone()),
+            ollama_port: params.ollama_port.unwrap_or(app_config.ollama_port),
+            fast_model: params
+                .fast_model
+                .or(app_config.fast_model.clone())
+                .filter(|fast_model| !fast_model.is_empty()),
+            answer_style: params.answer_style.unwrap_or_default(),
+            filter: params.filter.unwrap_or_default(),
+            reranker: match params.rerank.unwrap_or_default() {
+                true => Some(
+                    Reranker::default()
+                        .with_top_n(params.rerank_top_n.unwrap_or(DEFAULT_RERANK_TOP_N)),
+                ),
+                false => None,
+            },
+            max_context_tokens: params.max_context_tokens,
+            inline_citations: params.inline_citations.unwrap_or(false),
+            metadata: params.metadata.unwrap_or_default(),
+            request_id: request_id.0,
+        })
+    }
+
+    // llm returns the Llm used to answer the query, limited to the tokens of the answer style
+    fn llm(&self, app_config: &AppConfig) -> ollama::Llm {
+        let ollama = ollama_rs::Ollama::new(self.ollama_host.to_string(), self.ollama_port);
+        ollama::Llm::new(ollama)
+            .with_prompt_logger(app_config.prompt_logger.clone())
+            .with_max_tokens(self.answer_style.max_tokens())
+            .with_request_id(Some(self.request_id.clone()))
+    }
+}
+
+// check_query_embedding returns a bad request if the query embedding doesn't match the embedding
+// model the collections were created for
+async fn check_query_embedding(
+    app_config: &AppConfig,
+    base_collection: &str,
+    filter_collections: &[Collection],
+    embedding_provider: &SharedEmbeddingProvider,
+    embedding_size: usize,
+) -> Result<(), AppError> {
+    let collection_names: Vec<String> = filter_collections
+        .iter()
+        .map(|collection| format!("{}_{}", base_collection, collection.to_string()))
+        .collect();
+    app_config
+        .collection_models
+        .check(
+            &app_config.qdrant_client,
+            &collection_names,
+            &embedding_provider.name(),
+            embedding_size,
+        )
+        .await
+        .map_err(|e| {
+            info!("Error validating query embedding: {}", e);
+            AppError::BadRequest(e.to_string())
+        })
+}
+
+// retrieve_query_context returns the documents used as context to answer the query
+async fn retrieve_query_context(
+    app_config: &AppConfig,
+    settings: &QuerySettings,
+) -> Result<Vec<ScoredDocument>, AppError> {
+    info!("Querying {} with limit {}", settings.query, settings.limit);
+    let model = settings
+        .fast_model
+        .as_deref()
+        .unwrap_or(&settings.ollama_model);
+    let llm = settings.llm(app_config);
+    let query = app_config.expand_query(&llm, model, &settings.query).await;
+    let limit = match &settings.reranker {
+        Some(reranker) => reranker.candidates(settings.limit),
+        None => settings.limit,
+    };
+    // the context is retrieved by keyword matches while the embedding backend is unavailable,
+    // so queries are still answered during model outages
+    let embedding_provider = app_config.embedding_provider_of(&settings.base_collection);
+    let docs = match text_embedding_async(embedding_provider, query.clone()).await {
+        Ok(embeddings) => {
+            check_query_embedding(
+                app_config,
+                &settings.base_collection,
+                &settings.filter_collections,
+                embedding_provider,
+                embeddings.len(),
+            )
+            .await?;
+            search_scored_documents(
+                &app_config.qdrant_client,
+                &settings.base_collection,
+                settings.filter_collections.clone(),
+                embeddings,
+                limit,
+                &settings.filter,
+                VectorSearch::default(),
+            )
+            .await
+        }
+        Err(e) => {
+            info!(
+                "Error embedding query, falling back to keyword search: {}",
+                e
+            );
+            keyword_search(
+                &app_config.qdrant_client,
+                &settings.base_collection,
+                settings.filter_collections.clone(),
+                &query,
+                limit,
+                &settings.filter,
+            )
+            .await
+        }
+    }
+    .map_err(|e| {
+        info!("Error searching documents: {}", e);
+        AppError::Qdrant(e)
+    })?;
+    let docs = match &settings.reranker {
+        Some(reranker) => {
+            reranker
+                .rerank(&llm, model, &settings.query, docs, settings.limit)
+                .await
+        }
+        None => docs,
+    };
+    info!(
+        "Retrieved {} documents: {}",
+        docs.len(),
+        docs.iter()
+            .map(|doc| format!("{} ({:.3})", doc.metadata.id, doc.score))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    // the retrieval hooks apply the business rules of the deployment, e.g. by the plan of the
+    // customer, to the context
+    let docs = app_config
+        .retrieval_hooks
+        .apply(&settings.query, docs, &settings.metadata)
+        .await
+        .map_err(|e| {
+            info!("Error applying retrieval hooks: {}", e);
+            AppError::Internal(e)
+        })?;
+    let docs = match settings.max_context_tokens {
+        Some(max_context_tokens) => pack_context(docs, max_context_tokens)
+            .map(|packed| packed.docs)
+            .map_err(|e| {
+                info!("Error packing context: {}", e);
+                AppError::Internal(e)
+            })?,
+        None => docs,
+    };
+    // the retrievals of the context are added to the access counts of the points in the
+    // background
+    app_config.access_tracker.record(
+        &settings.base_collection,
+        docs.iter().map(|doc| &doc.metadata),
+    );
+    Ok(docs)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DebugQueryResponse {
+    pub query: String,
+    pub candidates: Vec<ScoreBreakdown>,
+}
+
+/// debug_query function returns the score breakdown of the retrieval candidates
+///
+/// This route does explain why a chunk was (not) selected for the context of a query. It takes
+/// the body of `/query` and runs the same retrieval: the collection limits, the boosts and
+/// penalties, the reranking, the retrieval hooks and the context budget, without generating an
+/// answer. The candidates are ordered by their rank in the context.
+#[utoipa::path(
+    post,
+    path = "/debug/query",
+    request_body = QueryParams,
+    responses(
+        (status = 200, description = "Success response", body = DebugQueryResponse),
+        (status = 400, description = "Bad Request", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+        (status = 502, description = "Qdrant or Ollama Error", body = ErrorResponse),
+        (status = 503, description = "Qdrant or Ollama Unavailable", body = ErrorResponse)
+    )
+)]
+pub async fn debug_query(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    axum::extract::Extension(request_id): axum::extract::Extension<RequestId>,
+    Json(params): Json<QueryParams>,
+) -> Result<Json<DebugQueryResponse>, AppError> {
+    let settings = QuerySettings::resolve(params, request_id, &state.app_config)?;
+    let model = settings
+        .fast_model
+        .as_deref()
+        .unwrap_or(&settings.ollama_model);
+    let llm = settings.llm(&state.app_config);
+    let query = state
+        .app_config
+        .expand_query(&llm, model, &settings.query)
+        .await;
+    let embedding_provider = state
+        .app_config
+        .embedding_provider_of(&settings.base_collection);
+    let embeddings = text_embedding_async(embedding_provider, query)
+        .await
+        .map_err(|e| {
+            info!("Error embedding query: {}", e);
+            AppError::Internal(e)
+        })?;
+    check_query_embedding(
+        &state.app_config,
+        &settings.base_collection,
+        &settings.filter_collections,
+        embedding_provider,
+        embeddings.len(),
+    )
+    .await?;
+    let stages = ExplainStages {
+        query: &settings.query,
+        reranker: settings.reranker.as_ref(),
+        llm: &llm,
+        model: model,
+        hooks: &state.app_config.retrieval_hooks,
+        metadata: &settings.metadata,
+        max_context_tokens: settings.max_context_tokens,
+    };
+    let candidates = explain_search(
+        &state.app_config.qdrant_client,
+        &settings.base_collection,
+        settings.filter_collections.clone(),
+        embeddings,
+        settings.limit,
+        &settings.filter,
+        &stages,
+    )
+    .await
+    .map_err(|e| {
+        info!("Error explaining search: {}", e);
+        AppError::Qdrant(e)
+    })?;
+
+    Ok(Json(DebugQueryResponse {
+        query: settings.query.clone(),
+        candidates: candidates,
+    }))
+}
+
+#[derive(Deserialize, IntoParams, Default)]
+pub struct CollectionsParams {
+    pub base_collection: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CollectionsResponse {
+    pub collections: Vec<CollectionSummary>,
+}
+
+/// get_collections function lists the collections of the base collection
+///
+/// This route does return the point count, vector size, distance metric and status of the
+/// existing qdrant collections named after the base collection, e.g. `rura_collection_basic`.
+#[utoipa::path(
+    get,
+    path = "/collections",
+    params(CollectionsParams),
+    responses(
+        (status = 200, description = "Success response", body = CollectionsResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+        (status = 502, description = "Qdrant or Ollama Error", body = ErrorResponse),
+        (status = 503, description = "Qdrant or Ollama Unavailable", body = ErrorResponse)
+    )
+)]
+pub async fn get_collections(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    collections_params: Option<Query<CollectionsParams>>,
+) -> Result<Json<CollectionsResponse>, AppError> {
+    let Query(params) = collections_params.unwrap_or(Query::default());
+    let base_collection = params
+        .base_collection
+        .unwrap_or(state.app_config.base_collection.clone());
+    let collections = list_collections(&state.app_config.qdrant_client, &base_collection)
+        .await
+        .map_err(|e| {
+            info!("Error listing collections: {}", e);
+            AppError::Qdrant(e)
+        })?;
+    Ok(Json(CollectionsResponse {
+        collections: collections,
+    }))
+}
+
+// DEFAULT_HOT_URLS is the default number of most retrieved pages of the tiering report
+static DEFAULT_HOT_URLS: usize = 20;
+
+#[derive(Deserialize, IntoParams, Default)]
+pub struct TieringParams {
+    // limit is the number of most retrieved pages returned
+    pub limit: Option<usize>,
+}
+
+/// get_tiering function reports the tiers of the collections and the most retrieved pages
+///
+/// This route does return whether the collections of the base collection are kept in memory or
+/// memory mapped on disk by the last tiering run, with their retrievals in its interval, and the
+/// pages retrieved most often since the server started. The tiers are empty until the first
+/// run if `TIERING_INTERVAL_SECS` is set.
+#[utoipa::path(
+    get,
+    path = "/tiering",
+    params(TieringParams),
+    responses(
+        (status = 200, description = "Success response", body = AccessReport)
+    )
+)]
+pub async fn get_tiering(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    tiering_params: Option<Query<TieringParams>>,
+) -> Json<AccessReport> {
+    let Query(params) = tiering_params.unwrap_or(Query::default());
+    Json(
+        state
+            .app_config
+            .access_tracker
+            .report(params.limit.unwrap_or(DEFAULT_HOT_URLS)),
+    )
+}
+
+/// get_metrics function returns the metrics of the server in the prometheus text format
+///
+/// This route does return the counters and histograms of the ingestion, embedding, qdrant,
+/// ollama and HTTP requests, e.g. `rura_ingest_documents_total` or `rura_http_duration_seconds`.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Prometheus metrics", body = String, content_type = "text/plain"),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse)
+    )
+)]
+pub async fn get_metrics() -> Result<Response, AppError> {
+    let metrics = metrics_text().map_err(AppError::Internal)?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics,
+    )
+        .into_response())
+}
+
+#[derive(Deserialize, Default, ToSchema)]
+pub struct SourceParams {
+    pub url: String,
+    // schedule is the interval between two re-crawls, @hourly, @daily, @weekly or e.g. 30m, 6h
+    // or 2d, at least 5 minutes
+    pub schedule: String,
+    pub filter_collections: Option<Vec<Collection>>,
+    pub base_collection: Option<String>,
+}
+
+/// add_source function registers a source re-crawled on a schedule
+///
+/// This route does register the url, the first crawl starts within a minute. Each crawl is a low
+/// priority incremental upload of the pages changed since the previous crawl.
+#[utoipa::path(
+    post,
+    path = "/sources",
+    request_body = SourceParams,
+    responses(
+        (status = 200, description = "Success response", body = Source),
+        (status = 400, description = "Bad Request", body = ErrorResponse),
+        (status = 409, description = "Conflict", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse)
+    )
+)]
+pub async fn add_source(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+    Json(params): Json<SourceParams>,
+) -> Result<Json<Source>, AppError> {
+    if params.url.is_empty() {
+        return Err(AppError::BadRequest("mandatory URL is empty".to_string()));
+    }
+    if state.sources.contains(&params.url, &params.base_collection) {
+        return Err(AppError::Conflict(format!(
+            "source {} is already registered",
+            params.url
+        )));
+    }
+    let source = Source::new(
+        &params.url,
+        &params.schedule,
+        params.filter_collections,
+        params.base_collection,
+    )
+    .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    state.sources.add(source.clone())?;
+    info!(
+        "Added source {} with schedule {}",
+        source.url, source.schedule
+    );
+    Ok(Json(source))
+}
+
+/// get_sources function lists the sources re-crawled on a schedule
+///
+/// This route does return the registered sources with their schedule and last crawl, the job of
+/// the last crawl is available at `/jobs/{id}`.
+#[utoipa::path(
+    get,
+    path = "/sources",
+    responses(
+        (status = 200, description = "Success response", body = Vec<Source>)
+    )
+)]
+pub async fn get_sources(
+    state: axum::extract::Extension<Arc<AppState<EmbeddingProgress>>>,
+) -> Json<Vec<Source>> {
+    Json(state.sources.list())
+}
+
+// recrawl_params returns the upload parameters of a scheduled crawl of a source, a low priority
+// incremental upload of the changed pages only, and the pages not due at now skipped by it
+pub fn recrawl_params(source: &Source, now: DateTime<Utc>) -> (UploadParams, HashSet<String>) {
+    let params = UploadParams {
+        url: source.url.clone(),
+        filter_collections: source.filter_collections.as_ref().map(|collections| {
+            collections
+                .iter()
+                .map(Collection::to_string)
+                .collect::<Vec<String>>()
+                .join(",")
+        }),
+        base_collection: source.base_collection.clone(),
+        priority: Some(JobPriority::Low),
+        incremental: Some(true),
+        changed_only: Some(true),
+        ..UploadParams::default()
+    };
+    (params, source.skip_urls(now).into_iter().collect())
+}
+
+// AppError represents the errors of the handlers, each kind maps to a status code and is
+// returned as a json ErrorResponse
+#[derive(Debug)]
+pub enum AppError {
+    // BadRequest is an invalid request, e.g. a missing parameter or an unreachable url
+    BadRequest(String),
+    NotFound(String),
+    // Conflict is a request not applicable to the current state, e.g. cancelling a finished job
+    Conflict(String),
+    // Qdrant is a failing call to qdrant, an outage if qdrant is unreachable
+    Qdrant(anyhow::Error),
+    // Ollama is a failing call to the LLM, an outage if ollama is unreachable
+    Ollama(anyhow::Error),
+    Internal(anyhow::Error),
+}
+
+impl AppError {
+    // status returns the status code of the error, upstream errors are bad gateways and
+    // upstream outages are unavailable
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Qdrant(e) | AppError::Ollama(e) if is_outage(e) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            AppError::Qdrant(_) | AppError::Ollama(_) => StatusCode::BAD_GATEWAY,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // code returns the machine readable code of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::Qdrant(e) if is_outage(e) => "qdrant_unavailable",
+            AppError::Qdrant(_) => "qdrant_error",
+            AppError::Ollama(e) if is_outage(e) => "ollama_unavailable",
+            AppError::Ollama(_) => "ollama_error",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    // is_outage returns true if the error is caused by an unreachable qdrant or ollama
+    pub fn is_outage(&self) -> bool {
+        self.status() == StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    // retry_after returns the time clients should wait before retrying an outage, the time
+    // until the circuit breaker of the service lets calls through again or RETRY_AFTER if the
+    // breaker is still closed
+    pub fn retry_after(&self) -> Option<Duration> {
+        let breaker = match self {
+            AppError::Qdrant(_) if self.is_outage() => qdrant_breaker(),
+            AppError::Ollama(_) if self.is_outage() => ollama_breaker(),
+            _ => return None,
+        };
+        Some(
+            breaker
+                .retry_after()
+                .filter(|retry_after| !retry_after.is_zero())
+                .unwrap_or(RETRY_AFTER),
+        )
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let code = self.code().to_string();
+        let retry_after = self.retry_after();
+        // the cause of upstream and internal errors is returned in the details
+        let (message, details) = match self {
+            AppError::BadRequest(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message) => (message, None),
+            AppError::Qdrant(e) if retry_after.is_some() => (
+                "qdrant is unavailable, retry later".to_string(),
+                Some(format!("{:#}", e)),
+            ),
+            AppError::Qdrant(e) => (
+                "qdrant request failed".to_string(),
+                Some(format!("{:#}", e)),
+            ),
+            AppError::Ollama(e) if retry_after.is_some() => (
+                "ollama is unavailable, retry later".to_string(),
+                Some(format!("{:#}", e)),
+            ),
+            AppError::Ollama(e) => (
+                "ollama request failed".to_string(),
+                Some(format!("{:#}", e)),
+            ),
+            AppError::Internal(e) => ("internal error".to_string(), Some(format!("{:#}", e))),
+        };
+        let body = Json(ErrorResponse {
+            code: code,
+            message: message,
+            details: details,
+        });
+        match retry_after {
+            // Retry-After is in whole seconds, rounded up so clients don't retry too early
+            Some(retry_after) => {
+                let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                (status, [(header::RETRY_AFTER, secs.to_string())], body).into_response()
+            }
+            None => (status, body).into_response(),
+        }
+    }
+}
+
+// This enables using `?` on functions that return `Result<_, anyhow::Error>` to turn them into
+// `Result<_, AppError>`, the errors are internal unless mapped to another kind
+impl<E> From<E> for AppError
+where
+    E: Into<anyhow::Error>,
+{
+    fn from(err: E) -> Self {
+        AppError::Internal(err.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        recrawl_params, start_upload, upload_document_url, ApiDoc, DocumentUrlParams, UploadParams,
+    };
+    use crate::sources::{PageFreshness, Source};
+    use crate::state::{AppConfigInput, AppState};
+    use anyhow::Error;
+    use axum::extract::{Extension, Query};
+    use qdrant_client::client::{QdrantClient, QdrantClientConfig};
+    use rura_core::data::{Collection, Document};
+    use rura_core::embedding::EmbeddingProgress;
+    use rura_core::embedding_provider::EmbeddingProvider;
+    use rura_core::ingest_source::{register_source, IngestSource, RawContent, SourceFuture};
+    use rura_core::progress_tracker::{JobStatus, ProgressTracker};
+    use rura_core::retry::{set_retry_policy, RetryPolicy};
+    use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+    use std::collections::{BTreeSet, HashSet};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use utoipa::openapi::{RefOr, Schema};
+    use utoipa::OpenApi;
+    use uuid::Uuid;
+
+    // Names is a deserializer recording the fields of the struct or the variants of the enum
+    // deserialized from it, the deserialization itself always fails
+    #[derive(Default)]
+    struct Names(Vec<&'static str>);
+
+    impl<'de> Deserializer<'de> for &mut Names {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("only structs and enums have names"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            self.0 = fields.to_vec();
+            Err(de::Error::custom("fields recorded"))
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            variants: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            self.0 = variants.to_vec();
+            Err(de::Error::custom("variants recorded"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map identifier
+            ignored_any
+        }
+    }
+
+    // client_names returns the fields or variants of a type of the client
+    fn client_names<T: DeserializeOwned>() -> BTreeSet<String> {
+        let mut names = Names::default();
+        let _ = T::deserialize(&mut names);
+        names.0.iter().map(|name| name.to_string()).collect()
+    }
+
+    // spec_names returns the properties or enum values of a schema of the OpenAPI document
+    fn spec_names(schema: &str) -> BTreeSet<String> {
+        let components = ApiDoc::openapi().components.expect("components");
+        match components.schemas.get(schema) {
+            Some(RefOr::T(Schema::Object(object))) => match &object.enum_values {
+                Some(values) => values
+                    .iter()
+                    .filter_map(|value| value.as_str())
+                    .map(str::to_string)
+                    .collect(),
+                None => object.properties.keys().cloned().collect(),
+            },
+            _ => panic!("schema {} is not an object of the OpenAPI document", schema),
+        }
+    }
+
+    // assert_in_sync fails if the client type differs from the schema served by the server
+    fn assert_in_sync<T: DeserializeOwned>(schema: &str) {
+        assert_eq!(
+            client_names::<T>(),
+            spec_names(schema),
+            "the client type of {} is out of sync with the OpenAPI document",
+            schema
+        );
+    }
+
+    #[test]
+    fn client_params_match_the_spec() {
+        assert_in_sync::<rura_client::UploadParams>("UploadParams");
+        assert_in_sync::<rura_client::DocumentUrlParams>("DocumentUrlParams");
+        assert_in_sync::<rura_client::DeleteDocumentsParams>("DeleteDocumentsParams");
+        assert_in_sync::<rura_client::SummarizeParams>("SummarizeParams");
+        assert_in_sync::<rura_client::SearchParams>("SearchParams");
+        assert_in_sync::<rura_client::SearchFilter>("SearchFilter");
+        assert_in_sync::<rura_client::QueryParams>("QueryParams");
+        assert_in_sync::<rura_client::SourceParams>("SourceParams");
+    }
+
+    #[test]
+    fn client_responses_match_the_spec() {
+        assert_in_sync::<rura_client::JobState>("JobState");
+        assert_in_sync::<rura_client::JobProgress>("EmbeddingProgress");
+        assert_in_sync::<rura_client::StageProgress>("StageProgress");
+        assert_in_sync::<rura_client::StageStats>("StageStats");
+        assert_in_sync::<rura_client::SourceProgress>("SourceProgress");
+        assert_in_sync::<rura_client::JobReport>("JobReport");
+        assert_in_sync::<rura_client::CrawlDiff>("CrawlDiff");
+        assert_in_sync::<rura_client::IndexEstimate>("IndexEstimate");
+        assert_in_sync::<rura_client::SummaryReport>("SummaryReport");
+        assert_in_sync::<rura_client::DedupReport>("DedupReport");
+        assert_in_sync::<rura_client::CollectionStats>("CollectionStats");
+        assert_in_sync::<rura_client::DocumentUrlResponse>("DocumentUrlResponse");
+        assert_in_sync::<rura_client::DeleteDocumentsResponse>("DeleteDocumentsResponse");
+        assert_in_sync::<rura_client::SummarizeResponse>("SummarizeResponse");
+        assert_in_sync::<rura_client::SearchResponse>("SearchResponse");
+        assert_in_sync::<rura_client::SearchResult>("SearchResult");
+        assert_in_sync::<rura_client::Highlight>("Highlight");
+        assert_in_sync::<rura_client::QueryResponse>("QueryResponse");
+        assert_in_sync::<rura_client::PromptPreview>("PromptPreview");
+        assert_in_sync::<rura_client::ContextChunk>("ContextChunk");
+        assert_in_sync::<rura_client::Citation>("Citation");
+        assert_in_sync::<rura_client::AgentStep>("AgentStep");
+        assert_in_sync::<rura_client::ChatResponse>("ChatResponse");
+        assert_in_sync::<rura_client::ChatTurn>("ChatTurn");
+        assert_in_sync::<rura_client::CollectionsResponse>("CollectionsResponse");
+        assert_in_sync::<rura_client::CollectionSummary>("CollectionSummary");
+        assert_in_sync::<rura_client::AccessReport>("AccessReport");
+        assert_in_sync::<rura_client::CollectionTier>("CollectionTier");
+        assert_in_sync::<rura_client::UrlAccess>("UrlAccess");
+        assert_in_sync::<rura_client::Source>("Source");
+        assert_in_sync::<rura_client::PageFreshness>("PageFreshness");
+        assert_in_sync::<rura_client::ErrorResponse>("ErrorResponse");
+    }
+
+    #[test]
+    fn client_enums_match_the_spec() {
+        assert_in_sync::<rura_client::Collection>("Collection");
+        assert_in_sync::<rura_client::JobPriority>("JobPriority");
+        assert_in_sync::<rura_client::SummaryPolicy>("SummaryPolicy");
+        assert_in_sync::<rura_client::WriteOrder>("WriteOrder");
+        assert_in_sync::<rura_client::VectorSearch>("VectorSearch");
+        assert_in_sync::<rura_client::PageParser>("PageParser");
+        assert_in_sync::<rura_client::ChunkStrategy>("ChunkStrategy");
+        assert_in_sync::<rura_client::JobStatus>("JobStatus");
+        assert_in_sync::<rura_client::Stage>("Stage");
+        assert_in_sync::<rura_client::Tier>("Tier");
+        assert_in_sync::<rura_client::AnswerStyle>("AnswerStyle");
+        assert_in_sync::<rura_client::QueryClass>("QueryClass");
+    }
+
+    // CannedEmbeddings embeds every text with the same small vector
+    struct CannedEmbeddings;
+
+    impl EmbeddingProvider for CannedEmbeddings {
+        fn name(&self) -> String {
+            "canned".to_string()
+        }
+
+        fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Error> {
+            Ok(texts.iter().map(|_| vec![0.5; 4]).collect())
+        }
+
+        fn size(&self) -> Result<u64, Error> {
+            Ok(4)
+        }
+    }
+
+    // CannedSource lists a single page of every location without fetching it
+    struct CannedSource;
+
+    impl IngestSource for CannedSource {
+        fn name(&self) -> &str {
+            "canned"
+        }
+
+        fn list<'a>(&'a self, location: &'a str) -> SourceFuture<'a, Vec<String>> {
+            Box::pin(async move { Ok(vec![format!("{}/backups", location)]) })
+        }
+
+        fn fetch<'a>(&'a self, url: &'a str) -> SourceFuture<'a, RawContent> {
+            Box::pin(async move {
+                Ok(RawContent {
+                    url: url.to_string(),
+                    content_type: "text/plain".to_string(),
+                    bytes: b"Backups of the environments are taken every night.".to_vec(),
+                })
+            })
+        }
+
+        fn parse(&self, content: RawContent) -> Result<Vec<Document>, Error> {
+            Ok(vec![Document::new(
+                Collection::Basic,
+                content.url,
+                "Backups".to_string(),
+                String::from_utf8(content.bytes)?,
+            )])
+        }
+    }
+
+    // test_state returns the state of a server with canned embeddings and an unreachable qdrant,
+    // the calls to qdrant fail right away
+    fn test_state() -> Arc<AppState<EmbeddingProgress>> {
+        set_retry_policy(RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        });
+        register_source(Arc::new(CannedSource));
+        let qdrant_client =
+            QdrantClient::new(Some(QdrantClientConfig::from_url("http://127.0.0.1:1"))).unwrap();
+        Arc::new(
+            AppState::new(AppConfigInput {
+                qdrant_client: Some(Arc::new(qdrant_client)),
+                embedding_provider: Some(Arc::new(CannedEmbeddings)),
+                ..AppConfigInput::default()
+            })
+            .unwrap(),
+        )
+    }
+
+    // finished_status waits until the job is finished and returns its status
+    async fn finished_status(state: &AppState<EmbeddingProgress>, id: Uuid) -> JobStatus {
+        tokio::time::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(progress) = state.progress_map.get(&id).await {
+                    if progress.status().is_finished() {
+                        return progress.status();
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("the job didn't finish")
+    }
+
+    #[tokio::test]
+    async fn dry_runs_finish() {
+        let state = test_state();
+        let id = start_upload(
+            state.clone(),
+            UploadParams {
+                url: "https://docs.example.com".to_string(),
+                source: Some("canned".to_string()),
+                dry_run: Some(true),
+                ..UploadParams::default()
+            },
+            HashSet::new(),
+        )
+        .await
+        .unwrap();
+        // the estimate needs the stats of the collections, it fails without qdrant
+        assert_eq!(finished_status(&state, id).await, JobStatus::Failed);
+        let report = state.reports.lock().unwrap().get(&id).cloned().unwrap();
+        assert!(report.error.unwrap().contains("Error estimating index"));
+        assert!(report.estimate.is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_documents_finish() {
+        let state = test_state();
+        // nothing listens on the port, the fetch fails
+        let result = upload_document_url(
+            Extension(state.clone()),
+            Ok(Query(DocumentUrlParams {
+                url: "http://127.0.0.1:1/backups".to_string(),
+                ..DocumentUrlParams::default()
+            })),
+        )
+        .await;
+        assert!(result.is_err());
+        let jobs = state.progress_map.all().await;
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(finished_status(&state, jobs[0].0).await, JobStatus::Failed);
+    }
+
+    #[test]
+    fn recrawls_skip_the_urls_with_commas() {
+        let mut source = Source::new("https://docs.example.com", "@daily", None, None).unwrap();
+        let now = chrono::Utc::now();
+        for url in ["https://docs.example.com/a,b", "https://docs.example.com/c"] {
+            source.pages.insert(
+                url.to_string(),
+                PageFreshness {
+                    checks: 1,
+                    changes: 0,
+                    last_checked_at: now,
+                    last_changed_at: None,
+                    interval_secs: chrono::Duration::hours(1).num_seconds(),
+                },
+            );
+        }
+        let (params, skip_urls) = recrawl_params(&source, now);
+        assert_eq!(
+            skip_urls,
+            HashSet::from([
+                "https://docs.example.com/a,b".to_string(),
+                "https://docs.example.com/c".to_string(),
+            ])
+        );
+        assert!(params.skip_urls.is_none());
+        assert_eq!(params.incremental, Some(true));
+    }
+}
@@ -0,0 +1,6 @@
+pub mod answer_cache;
+pub mod api;
+pub mod middleware;
+pub mod server;
+pub mod sources;
+pub mod state;
@@ -0,0 +1,168 @@
+use anyhow::{anyhow, Error};
+use dotenv::dotenv;
+use qdrant_client::client::QdrantClient;
+use rura_core::answer::DEFAULT_ANSWER_RETRIES;
+use rura_core::classify::QueryClassifier;
+use rura_core::config::Config;
+use rura_core::embedding::{EmbeddingProgress, DEFAULT_BATCH_SIZE};
+use rura_core::embedding_cache::DEFAULT_EMBEDDING_CACHE_SIZE;
+use rura_core::embedding_provider::EmbeddingConfig;
+use rura_core::glossary::{Glossary, Synonyms};
+use rura_core::mirror;
+use rura_core::prompt_log::{PromptLogConfig, PromptLogger, TermRedactor};
+use rura_core::retry::set_retry_policy;
+use rura_core::spelling::{load_vocabulary, SpellCorrector, SpellingMode};
+use rura_core::telemetry;
+use rura_server::answer_cache::DEFAULT_ANSWER_CACHE_SIZE;
+use rura_server::server::{serve, ServeOptions};
+use rura_server::state::{AppConfigInput, AppState};
+use std::sync::Arc;
+
+// DEFAULT_ADDRESS is the address the server listens on by default
+static DEFAULT_ADDRESS: &str = "127.0.0.1:3000";
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    dotenv().ok();
+    telemetry::init()?;
+
+    // the settings, crawl profiles and qdrant connection are read from the config file
+    // CONFIG_PATH points to, or rura.toml, the env variables override the file
+    let config_path = std::env::var("CONFIG_PATH").ok();
+    let config_file = Config::discover(config_path.as_deref().map(std::path::Path::new))
+        .map_err(|e| anyhow!("Error loading config file: {}", e))?
+        .with_env()?;
+
+    // the retries of qdrant and ollama follow the retry settings of the config file
+    set_retry_policy(config_file.retry.policy());
+    let qdrant_client_address = config_file.address();
+    let qdrant_connection = config_file.qdrant.clone();
+    let qdrant_client = QdrantClient::new(Some(
+        qdrant_connection.client_config(&qdrant_client_address),
+    ))
+    .map_err(|e| {
+        anyhow!(
+            "Error creating qdrant client {}: {}",
+            qdrant_client_address,
+            e
+        )
+    })?;
+    // upserts and deletes are mirrored to a secondary qdrant if QDRANT_MIRROR_ADDRESS or the
+    // mirror address of the config file is set, e.g. a second region or the target of a
+    // migration
+    if let Some(mirror_address) = &config_file.server.mirror_address {
+        mirror::enable(
+            QdrantClient::new(Some(qdrant_connection.client_config(mirror_address)))
+                .map_err(|e| anyhow!("Error creating mirror client {}: {}", mirror_address, e))?,
+        );
+    }
+
+    // prompt logging is opt-in by setting PROMPT_LOG_PATH or the prompt log of the config file
+    let prompt_logger = config_file.prompts.log.clone().map(|path| {
+        let settings = &config_file.prompt_log;
+        let mut config = PromptLogConfig::new(path);
+        config.sample_rate = settings.sample_rate.unwrap_or(config.sample_rate);
+        config.max_entry_size = settings.max_entry_size.unwrap_or(config.max_entry_size);
+        config.max_file_size = settings.max_file_size.unwrap_or(config.max_file_size);
+        let redactor = TermRedactor::new(settings.redact_terms.clone());
+        Arc::new(PromptLogger::new(config).with_redactor(Box::new(redactor)))
+    });
+
+    // greetings and meta questions are always answered without retrieval, the out of scope
+    // check with the LLM is enabled by setting QUERY_SCOPE
+    let settings = config_file.server.clone();
+    let mut query_classifier = QueryClassifier::default().with_scope(settings.query_scope);
+    if let Some(message) = settings.out_of_scope_message {
+        query_classifier = query_classifier.with_out_of_scope_message(message);
+    }
+
+    // documents and queries are embedded locally with rust-bert unless EMBEDDING_PROVIDER or the
+    // embedding provider of the config file is set
+    let embedding_config = EmbeddingConfig {
+        api_key: std::env::var("EMBEDDING_API_KEY").ok(),
+        // a second rust-bert model embeds the queries if EMBEDDING_QUERY_LANE is set to true
+        query_lane: settings.embedding_query_lane.unwrap_or(false),
+        ..EmbeddingConfig::default()
+    }
+    .with_settings(&config_file.embedding)?;
+    let embedding_provider = embedding_config
+        .build()
+        .map_err(|e| anyhow!("Error building embedding provider: {}", e))?;
+
+    // the glossary explains internal terms in the prompts and expands queries if GLOSSARY_PATH
+    // points to a json file mapping each term to its definition
+    let glossary = match &config_file.prompts.glossary {
+        Some(path) => Some(
+            Glossary::load(path)
+                .map_err(|e| anyhow!("Error loading glossary {:?}: {}", path, e))?,
+        ),
+        None => None,
+    };
+    // acronyms and synonyms like k8s for kubernetes are appended to queries if SYNONYMS_PATH
+    // points to a json file mapping each term to its synonyms
+    let synonyms = match &config_file.prompts.synonyms {
+        Some(path) => Some(
+            Synonyms::load(path)
+                .map_err(|e| anyhow!("Error loading synonyms {:?}: {}", path, e))?,
+        ),
+        None => None,
+    };
+
+    // queries are spell corrected before embedding if QUERY_SPELLING is set to dictionary, using
+    // the words of the indexed documents, or to llm
+    let spelling_mode = settings.spelling.unwrap_or_default();
+    let mut spell_corrector = SpellCorrector::new(spelling_mode);
+    if spelling_mode == SpellingMode::Dictionary {
+        let vocabulary = load_vocabulary(&qdrant_client, &config_file.base_collection())
+            .await
+            .map_err(|e| anyhow!("Error loading spelling vocabulary: {}", e))?;
+        spell_corrector = spell_corrector.with_vocabulary(vocabulary);
+    }
+
+    // clustered qdrant deployments set the sharding and replication of created collections,
+    // large corpora on modest memory machines store the payloads and vectors on disk, the
+    // settings are validated with the config file
+    let collection_config = config_file.collection.clone();
+
+    let app_config_input = AppConfigInput {
+        address: Some(settings.address.unwrap_or(DEFAULT_ADDRESS.to_string())),
+        base_collection: Some(config_file.base_collection()),
+        filter_collections: Some(config_file.collections()),
+        ollama_model: Some(config_file.ollama.model()),
+        ollama_host: Some(config_file.ollama.host()),
+        ollama_port: Some(config_file.ollama.port()),
+        fast_model: config_file.ollama.fast_model.clone(),
+        qdrant_client: Some(Arc::new(qdrant_client)),
+        prompt_logger: prompt_logger,
+        query_classifier: Some(query_classifier),
+        answer_retries: Some(settings.answer_retries.unwrap_or(DEFAULT_ANSWER_RETRIES)),
+        embedding_batch_size: Some(settings.embedding_batch_size.unwrap_or(DEFAULT_BATCH_SIZE)),
+        embedding_provider: Some(embedding_provider),
+        embedding_config: Some(embedding_config),
+        embedding_cache_size: Some(
+            settings
+                .embedding_cache_size
+                .unwrap_or(DEFAULT_EMBEDDING_CACHE_SIZE),
+        ),
+        answer_cache_size: Some(
+            settings
+                .answer_cache_size
+                .unwrap_or(DEFAULT_ANSWER_CACHE_SIZE),
+        ),
+        glossary: glossary,
+        synonyms: synonyms,
+        spell_corrector: Some(spell_corrector),
+        config_file: Some(config_file),
+        qdrant_memory_budget_mb: settings.memory_budget_mb,
+        collection_config: Some(collection_config),
+        // the retrieval hooks are built from the config file
+        retrieval_hooks: None,
+        sources_path: settings.sources_path,
+        jobs_path: settings.jobs_path,
+        max_concurrent_jobs: settings.max_concurrent_jobs,
+    };
+    let state = Arc::new(AppState::<EmbeddingProgress>::new(app_config_input)?);
+
+    // the request limits and the retention of jobs and chats are read from the env variables
+    serve(state, ServeOptions::from_env()?).await
+}
@@ -0,0 +1,141 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header::CONTENT_LENGTH, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use log::info;
+use rura_core::telemetry::record_http_request;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tracing::{info_span, Instrument};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// REQUEST_ID_HEADER is the header of the request id, set by the caller or generated
+pub static REQUEST_ID_HEADER: &str = "x-request-id";
+// MAX_REQUEST_ID_LEN is the maximum length of a request id set by the caller, longer ids are
+// replaced so callers can't flood the logs
+static MAX_REQUEST_ID_LEN: usize = 128;
+
+// RequestId is the id of a request, available to the handlers as an extension
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+// RequestLimits represents the timeout and the maximum body size of a route
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLimits {
+    pub timeout: Duration,
+    pub max_body_size: usize,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            timeout: Duration::from_secs(30),
+            max_body_size: 1024 * 1024,
+        }
+    }
+}
+
+// ErrorResponse represents a structured error returned by the middleware
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+    // details is the cause of upstream and internal errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+}
+
+// error_response returns a json error response with the given status
+fn error_response(status: StatusCode, code: &str, message: String) -> Response {
+    (
+        status,
+        Json(ErrorResponse {
+            code: code.to_string(),
+            message: message,
+            details: None,
+        }),
+    )
+        .into_response()
+}
+
+// limit_request rejects requests with a body larger than the limit with 413 and aborts
+// handlers running longer than the timeout with 408
+pub async fn limit_request(
+    State(limits): State<RequestLimits>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let content_length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if let Some(content_length) = content_length {
+        if content_length > limits.max_body_size {
+            info!(
+                "Rejecting request to {} with body size {}",
+                request.uri(),
+                content_length
+            );
+            return error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "payload_too_large",
+                format!(
+                    "request body of {} bytes exceeds the limit of {} bytes",
+                    content_length, limits.max_body_size
+                ),
+            );
+        }
+    }
+
+    let uri = request.uri().clone();
+    match tokio::time::timeout(limits.timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            info!("Request to {} timed out after {:?}", uri, limits.timeout);
+            error_response(
+                StatusCode::REQUEST_TIMEOUT,
+                "request_timeout",
+                format!("request timed out after {:?}", limits.timeout),
+            )
+        }
+    }
+}
+
+// assign_request_id takes the request id of the x-request-id header or generates one, runs the
+// request in a span with the id so all its logs carry it and returns it in the response header
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= MAX_REQUEST_ID_LEN)
+        .map(|value| value.to_string())
+        .unwrap_or(Uuid::new_v4().to_string());
+    request.extensions_mut().insert(RequestId(id.clone()));
+    let span = info_span!("request", request_id = %id);
+    let mut response = next.run(request).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+// record_request records the method, route, status and duration of each request, the route is
+// the matched path, e.g. /jobs/:id, so the metrics don't grow with the ids
+pub async fn record_request(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or("unmatched".to_string());
+    let response = next.run(request).await;
+    record_http_request(&method, &route, response.status().as_u16(), start.elapsed());
+    response
+}
@@ -0,0 +1,486 @@
+use crate::api::{
+    add_source, cancel_job, chat, debug_query, delete_documents, get_collections, get_job,
+    get_job_events, get_job_report, get_metrics, get_sources, get_state, get_tiering, list_jobs,
+    pause_job, query, query_stream, recrawl_params, resume_job, search, start_upload, summarize,
+    upload, upload_document_url, ApiDoc,
+};
+use crate::middleware::{assign_request_id, limit_request, record_request, RequestLimits};
+use crate::state::{archive_jobs, AppState};
+use anyhow::{Error, Result};
+use axum::{
+    extract::DefaultBodyLimit, middleware, routing::delete, routing::get, routing::post, Router,
+};
+use log::info;
+use rura_core::config::env_parse;
+use rura_core::data::Collection;
+use rura_core::embedding::{warm_up_embeddings, EmbeddingProgress};
+use rura_core::migrate::migrate_collections;
+use rura_core::qdrant::{get_page_timestamps, list_collections};
+use rura_core::summary::process_queue;
+use rura_core::tiering::HOT_MEMMAP_THRESHOLD_KB;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+// JOB_SAVE_INTERVAL_SECS is the interval the progress of the jobs is persisted, the updates of
+// the last interval are lost if the server stops
+static JOB_SAVE_INTERVAL_SECS: u64 = 5;
+// ACCESS_FLUSH_INTERVAL_SECS is the interval the retrievals are added to the access counts of
+// the points
+static ACCESS_FLUSH_INTERVAL_SECS: u64 = 30;
+
+// ServeOptions represents the request limits and the retention of the server
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    pub max_body_size: usize,
+    pub request_timeout: Duration,
+    // upload_timeout includes the sitemap fetch before the upload returns
+    pub upload_timeout: Duration,
+    // generation_timeout is the timeout of the routes generating text
+    pub generation_timeout: Duration,
+    // job_retention and chat_retention are the retention of finished jobs and idle chats
+    pub job_retention: chrono::Duration,
+    pub chat_retention: chrono::Duration,
+    // job_archive_path is the jsonl file evicted jobs are archived to, None drops them
+    pub job_archive_path: Option<PathBuf>,
+    // job_history_size is the maximum number of finished jobs kept, None keeps all finished
+    // jobs within the retention
+    pub job_history_size: Option<usize>,
+    // summary_retry is the interval queued summaries are retried
+    pub summary_retry: Duration,
+    // migrate_on_startup backfills the payloads of older schema versions in the background
+    pub migrate_on_startup: bool,
+    // warm_up_embeddings loads the embedding model before the server listens, so the first
+    // query doesn't wait for it
+    pub warm_up_embeddings: bool,
+    // tiering_interval is the interval the collections are moved between memory and disk by
+    // their retrievals, None keeps the storage of the collections
+    pub tiering_interval: Option<Duration>,
+    // tiering_min_accesses is the number of retrievals within the tiering interval which keep a
+    // collection in memory
+    pub tiering_min_accesses: u64,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        ServeOptions {
+            max_body_size: 1024 * 1024,
+            request_timeout: Duration::from_secs(30),
+            upload_timeout: Duration::from_secs(120),
+            generation_timeout: Duration::from_secs(300),
+            job_retention: chrono::Duration::seconds(86400),
+            chat_retention: chrono::Duration::seconds(3600),
+            job_archive_path: None,
+            job_history_size: None,
+            summary_retry: Duration::from_secs(300),
+            migrate_on_startup: false,
+            warm_up_embeddings: false,
+            tiering_interval: None,
+            tiering_min_accesses: 1,
+        }
+    }
+}
+
+impl ServeOptions {
+    // from_env returns the options set by the env variables of the server, the defaults for
+    // unset variables
+    pub fn from_env() -> Result<Self, Error> {
+        let default = ServeOptions::default();
+        let secs = |name: &str, default: Duration| {
+            env_parse::<u64>(name).map(|secs| secs.map_or(default, Duration::from_secs))
+        };
+        let retention = |name: &str, default: chrono::Duration| {
+            env_parse::<i64>(name).map(|secs| secs.map_or(default, chrono::Duration::seconds))
+        };
+        Ok(ServeOptions {
+            max_body_size: env_parse("MAX_BODY_SIZE")?.unwrap_or(default.max_body_size),
+            request_timeout: secs("REQUEST_TIMEOUT_SECS", default.request_timeout)?,
+            upload_timeout: secs("UPLOAD_TIMEOUT_SECS", default.upload_timeout)?,
+            generation_timeout: secs("GENERATION_TIMEOUT_SECS", default.generation_timeout)?,
+            job_retention: retention("JOB_RETENTION_SECS", default.job_retention)?,
+            chat_retention: retention("CHAT_RETENTION_SECS", default.chat_retention)?,
+            job_archive_path: std::env::var("JOB_ARCHIVE_PATH").ok().map(PathBuf::from),
+            job_history_size: env_parse("JOB_HISTORY_SIZE")?,
+            summary_retry: secs("SUMMARY_RETRY_SECS", default.summary_retry)?,
+            migrate_on_startup: env_parse("MIGRATE_ON_STARTUP")?
+                .unwrap_or(default.migrate_on_startup),
+            warm_up_embeddings: env_parse("WARM_UP_EMBEDDINGS")?
+                .unwrap_or(default.warm_up_embeddings),
+            tiering_interval: env_parse::<u64>("TIERING_INTERVAL_SECS")?.map(Duration::from_secs),
+            tiering_min_accesses: env_parse("TIERING_MIN_ACCESSES")?
+                .unwrap_or(default.tiering_min_accesses),
+        })
+    }
+}
+
+// serve runs the background tasks of the server and serves the api on the address of the app
+// config until the server fails
+pub async fn serve(
+    state: Arc<AppState<EmbeddingProgress>>,
+    options: ServeOptions,
+) -> Result<(), Error> {
+    spawn_eviction(state.clone(), &options);
+    spawn_job_persistence(state.clone());
+    spawn_summary_retry(state.clone(), options.summary_retry);
+    if options.migrate_on_startup {
+        spawn_migration(state.clone());
+    }
+    spawn_recrawl(state.clone());
+    spawn_access_flush(state.clone());
+    if let Some(tiering_interval) = options.tiering_interval {
+        spawn_tiering(
+            state.clone(),
+            tiering_interval,
+            options.tiering_min_accesses,
+        );
+    }
+    log_missing_collections(&state).await;
+    // queries and uploads share the provider, a failing warm up is retried by the first query
+    if options.warm_up_embeddings {
+        if let Err(e) = warm_up_embeddings(&state.app_config.embedding_provider).await {
+            info!("Error warming up embeddings: {}", e);
+        }
+    }
+
+    let listener = tokio::net::TcpListener::bind(state.app_config.address.as_str()).await?;
+    let app = router(state, &options);
+    info!("listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+// log_missing_collections logs the default filter collections not created in qdrant yet, their
+// searches fail until an upload created them
+async fn log_missing_collections(state: &AppState<EmbeddingProgress>) {
+    let app_config = &state.app_config;
+    let existing =
+        match list_collections(&app_config.qdrant_client, &app_config.base_collection).await {
+            Ok(existing) => existing,
+            Err(e) => {
+                info!("Error listing collections: {}", e);
+                return;
+            }
+        };
+    info!(
+        "Searching the collections {:?} of {} by default",
+        app_config.filter_collections, app_config.base_collection
+    );
+    for collection in &app_config.filter_collections {
+        let collection_name = format!("{}_{}", app_config.base_collection, collection.to_string());
+        if !existing
+            .iter()
+            .any(|summary| summary.collection == collection_name)
+        {
+            info!(
+                "Collection {} does not exist yet, upload to it before searching it",
+                collection_name
+            );
+        }
+    }
+}
+
+// router returns the routes of the api with their request limits
+pub fn router(state: Arc<AppState<EmbeddingProgress>>, options: &ServeOptions) -> Router {
+    // per route timeouts, uploads fetch the sitemap before returning and generation is slow
+    let default_limits = RequestLimits {
+        timeout: options.request_timeout,
+        max_body_size: options.max_body_size,
+    };
+    let upload_limits = RequestLimits {
+        timeout: options.upload_timeout,
+        max_body_size: options.max_body_size,
+    };
+    let generation_limits = RequestLimits {
+        timeout: options.generation_timeout,
+        max_body_size: options.max_body_size,
+    };
+    let limit = |limits: RequestLimits| middleware::from_fn_with_state(limits, limit_request);
+
+    Router::new()
+        .route("/get-state", get(get_state).layer(limit(default_limits)))
+        .route("/upload", post(upload).layer(limit(upload_limits)))
+        .route("/jobs", get(list_jobs).layer(limit(default_limits)))
+        .route("/jobs/:id", get(get_job).layer(limit(default_limits)))
+        .route(
+            "/jobs/:id/cancel",
+            post(cancel_job).layer(limit(default_limits)),
+        )
+        .route(
+            "/jobs/:id/pause",
+            post(pause_job).layer(limit(default_limits)),
+        )
+        .route(
+            "/jobs/:id/resume",
+            post(resume_job).layer(limit(default_limits)),
+        )
+        // progress streams last as long as the job, no timeout
+        .route("/jobs/:id/events", get(get_job_events))
+        .route(
+            "/jobs/:id/report",
+            get(get_job_report).layer(limit(default_limits)),
+        )
+        .route(
+            "/documents/url",
+            post(upload_document_url).layer(limit(generation_limits)),
+        )
+        .route(
+            "/documents",
+            delete(delete_documents).layer(limit(default_limits)),
+        )
+        .route(
+            "/summarize",
+            post(summarize).layer(limit(generation_limits)),
+        )
+        .route("/search", post(search).layer(limit(default_limits)))
+        .route("/query", post(query).layer(limit(generation_limits)))
+        .route("/chat", post(chat).layer(limit(generation_limits)))
+        .route(
+            "/query/stream",
+            post(query_stream).layer(limit(generation_limits)),
+        )
+        .route(
+            "/debug/query",
+            post(debug_query).layer(limit(generation_limits)),
+        )
+        .route(
+            "/collections",
+            get(get_collections).layer(limit(default_limits)),
+        )
+        .route(
+            "/sources",
+            get(get_sources)
+                .post(add_source)
+                .layer(limit(default_limits)),
+        )
+        .route("/tiering", get(get_tiering).layer(limit(default_limits)))
+        .route("/metrics", get(get_metrics).layer(limit(default_limits)))
+        .merge(SwaggerUi::new("/swagger-ui").url("/api-docs", ApiDoc::openapi()))
+        .layer(middleware::from_fn(record_request))
+        .layer(middleware::from_fn(assign_request_id))
+        .layer(DefaultBodyLimit::max(options.max_body_size))
+        .layer(axum::Extension(state))
+}
+
+// spawn_eviction evicts finished jobs after the retention period, archiving them if configured,
+// and idle chat sessions
+fn spawn_eviction(state: Arc<AppState<EmbeddingProgress>>, options: &ServeOptions) {
+    let job_retention = options.job_retention;
+    let chat_retention = options.chat_retention;
+    let job_archive_path = options.job_archive_path.clone();
+    let job_history_size = options.job_history_size;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            let evicted = state
+                .evict_finished_jobs(job_retention, job_history_size)
+                .await;
+            if let (Some(path), false) = (&job_archive_path, evicted.is_empty()) {
+                if let Err(e) = archive_jobs(path, &evicted) {
+                    info!("Error archiving jobs: {}", e);
+                }
+            }
+            state
+                .chat_sessions
+                .evict(chrono::Utc::now() - chat_retention);
+        }
+    });
+}
+
+// spawn_job_persistence saves the changed progress of the jobs every JOB_SAVE_INTERVAL_SECS, so
+// the jobs survive restarts if a jobs path is configured
+fn spawn_job_persistence(state: Arc<AppState<EmbeddingProgress>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(JOB_SAVE_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = state.progress_map.save().await {
+                info!("Error saving jobs: {}", e);
+            }
+        }
+    });
+}
+
+// spawn_access_flush adds the retrievals of the queries to the access counts of the points every
+// ACCESS_FLUSH_INTERVAL_SECS, off the query path
+fn spawn_access_flush(state: Arc<AppState<EmbeddingProgress>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(ACCESS_FLUSH_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            let app_config = &state.app_config;
+            if let Err(e) = app_config
+                .access_tracker
+                .flush(&app_config.qdrant_client)
+                .await
+            {
+                info!("Error flushing access counts: {}", e);
+            }
+        }
+    });
+}
+
+// spawn_tiering moves the collections retrieved less than min_accesses times within the tiering
+// interval on disk and the others into memory, the first run is after one interval so the
+// collections are not moved on disk right after a restart
+fn spawn_tiering(
+    state: Arc<AppState<EmbeddingProgress>>,
+    tiering_interval: Duration,
+    min_accesses: u64,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tiering_interval);
+        // the first tick completes immediately
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let app_config = &state.app_config;
+            let hot_memmap_threshold_kb = app_config
+                .collection_config
+                .memmap_threshold_kb
+                .unwrap_or(HOT_MEMMAP_THRESHOLD_KB);
+            match app_config
+                .access_tracker
+                .retier(
+                    &app_config.qdrant_client,
+                    &app_config.base_collection,
+                    min_accesses,
+                    hot_memmap_threshold_kb,
+                )
+                .await
+            {
+                Ok(changed) => info!("Tiered collections, {} moved", changed.len()),
+                Err(e) => info!("Error tiering collections: {}", e),
+            }
+        }
+    });
+}
+
+// spawn_migration backfills the payloads of the filter collections written with an older schema
+// version, searches upgrade them when read until then
+fn spawn_migration(state: Arc<AppState<EmbeddingProgress>>) {
+    tokio::spawn(async move {
+        if let Err(e) = migrate_collections(
+            &state.app_config.qdrant_client,
+            &state.app_config.base_collection,
+            state.app_config.filter_collections.clone(),
+            false,
+        )
+        .await
+        {
+            info!("Error migrating collections: {}", e);
+        }
+    });
+}
+
+// spawn_recrawl starts an upload job for each source whose next crawl is due, checked every
+// minute, the changes found by the previous crawls adapt the intervals of the pages first
+fn spawn_recrawl(state: Arc<AppState<EmbeddingProgress>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            observe_crawls(&state).await;
+            let now = chrono::Utc::now();
+            for source in state.sources.due(now) {
+                let (params, skip_urls) = recrawl_params(&source, now);
+                let job_id = match start_upload(state.clone(), params, skip_urls).await {
+                    Ok(job_id) => job_id,
+                    Err(e) => {
+                        info!("Error starting crawl of source {}: {:?}", source.url, e);
+                        continue;
+                    }
+                };
+                info!("Started crawl {} of source {}", job_id, source.url);
+                if let Err(e) = state.sources.mark_run(source.id, job_id, now) {
+                    info!("Error saving sources: {}", e);
+                }
+            }
+        }
+    });
+}
+
+// observe_crawls adapts the page intervals of the sources to the crawl diffs of their last jobs
+// not observed yet, the first observation of a source backfills the last changes of its pages
+// from the index time of their fragments
+async fn observe_crawls(state: &Arc<AppState<EmbeddingProgress>>) {
+    for source in state.sources.list() {
+        let job_id = match source.last_job_id {
+            Some(job_id) if source.observed_job_id != Some(job_id) => job_id,
+            _ => continue,
+        };
+        let crawl_diff = {
+            let reports = state.reports.lock().unwrap();
+            match reports
+                .get(&job_id)
+                .and_then(|report| report.crawl_diff.clone())
+            {
+                Some(crawl_diff) => crawl_diff,
+                None => continue,
+            }
+        };
+        let indexed_at = match source.pages.is_empty() {
+            true => {
+                let base_collection = source
+                    .base_collection
+                    .clone()
+                    .unwrap_or(state.app_config.base_collection.clone());
+                get_page_timestamps(
+                    &state.app_config.qdrant_client,
+                    &base_collection,
+                    Collection::Basic,
+                    source.url.trim_end_matches("sitemap.xml"),
+                )
+                .await
+                .unwrap_or_else(|e| {
+                    info!("Error getting page timestamps of {}: {}", source.url, e);
+                    HashMap::new()
+                })
+            }
+            false => HashMap::new(),
+        };
+        if let Err(e) = state
+            .sources
+            .observe(source.id, job_id, &crawl_diff, &indexed_at)
+        {
+            info!("Error saving sources: {}", e);
+        }
+    }
+}
+
+// spawn_summary_retry retries the queued summaries of uploads with the queue summary policy
+fn spawn_summary_retry(state: Arc<AppState<EmbeddingProgress>>, summary_retry: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(summary_retry);
+        loop {
+            interval.tick().await;
+            if state.summary_queue.is_empty() {
+                continue;
+            }
+            info!("Retrying {} queued summaries", state.summary_queue.len());
+            let generated = process_queue(
+                &state.app_config.qdrant_client,
+                |base_collection| {
+                    state
+                        .app_config
+                        .embedding_provider_of(base_collection)
+                        .clone()
+                },
+                &state.summary_queue,
+            )
+            .await;
+            let mut reports = state.reports.lock().unwrap();
+            for (job_id, url) in generated {
+                if let Some(summaries) = reports
+                    .get_mut(&job_id)
+                    .and_then(|report| report.summaries.as_mut())
+                {
+                    summaries.mark_generated(&url);
+                }
+            }
+        }
+    });
+}
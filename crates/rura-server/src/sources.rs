@@ -0,0 +1,484 @@
+use anyhow::{Error, Result};
+use chrono::{DateTime, Duration, Utc};
+use log::info;
+use rura_core::data::Collection;
+use rura_core::report::CrawlDiff;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// MIN_SCHEDULE_SECS is the shortest interval between two re-crawls of a source, a shorter one
+// would start the next crawl of a large site before the previous one finished
+static MIN_SCHEDULE_SECS: i64 = 300;
+// MAX_SCHEDULE_COUNT is the largest number of minutes, hours or days of a schedule
+static MAX_SCHEDULE_COUNT: i64 = 100_000;
+// ADAPTIVE_FACTOR bounds the re-crawl interval of a page between the schedule of its source
+// divided and multiplied by it
+static ADAPTIVE_FACTOR: i32 = 8;
+
+// PageFreshness represents how often a page of a source changed across the re-crawls and when
+// it is crawled next
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct PageFreshness {
+    // checks is the number of re-crawls of the page, changes the ones finding it changed
+    pub checks: u32,
+    pub changes: u32,
+    pub last_checked_at: DateTime<Utc>,
+    // last_changed_at is the last crawl finding the page changed, backfilled from the index
+    // time of its fragments on the first re-crawl
+    pub last_changed_at: Option<DateTime<Utc>>,
+    // interval_secs is halved by each change and doubled by each unchanged re-crawl, within
+    // the bounds of the schedule of the source
+    pub interval_secs: i64,
+}
+
+impl PageFreshness {
+    // next_check_at returns when the page is crawled next
+    pub fn next_check_at(&self) -> DateTime<Utc> {
+        self.last_checked_at + Duration::seconds(self.interval_secs)
+    }
+}
+
+// Source represents a site re-crawled on a schedule to keep its index fresh
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Source {
+    pub id: Uuid,
+    // url is the sitemap or page crawled
+    pub url: String,
+    // schedule is the interval between two re-crawls, @hourly, @daily, @weekly or a number of
+    // minutes, hours or days, e.g. 30m, 6h or 2d
+    pub schedule: String,
+    pub filter_collections: Option<Vec<Collection>>,
+    pub base_collection: Option<String>,
+    pub created_at: DateTime<Utc>,
+    // last_run_at and last_job_id are the start and the upload job of the last crawl, None
+    // until the first crawl
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_job_id: Option<Uuid>,
+    // observed_job_id is the last crawl whose changes adapted the intervals of the pages
+    #[serde(default)]
+    pub observed_job_id: Option<Uuid>,
+    // pages are the re-crawl intervals by url adapted to how often the pages change, a crawl
+    // only fetches the pages due and the pages new to the sitemap
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pages: BTreeMap<String, PageFreshness>,
+}
+
+impl Source {
+    // new returns a source crawled right away and then on the schedule, an invalid schedule is
+    // an error
+    pub fn new(
+        url: &str,
+        schedule: &str,
+        filter_collections: Option<Vec<Collection>>,
+        base_collection: Option<String>,
+    ) -> Result<Self, Error> {
+        parse_schedule(schedule)?;
+        Ok(Source {
+            id: Uuid::new_v4(),
+            url: url.to_string(),
+            schedule: schedule.to_string(),
+            filter_collections: filter_collections,
+            base_collection: base_collection,
+            created_at: Utc::now(),
+            last_run_at: None,
+            last_job_id: None,
+            observed_job_id: None,
+            pages: BTreeMap::new(),
+        })
+    }
+
+    // next_run_at returns when the source is crawled next, its creation if it never was, the
+    // schedule or the first page due before it, at least MIN_SCHEDULE_SECS after the last crawl
+    pub fn next_run_at(&self) -> Result<DateTime<Utc>, Error> {
+        let interval = parse_schedule(&self.schedule)?;
+        let last_run_at = match self.last_run_at {
+            Some(last_run_at) => last_run_at,
+            None => return Ok(self.created_at),
+        };
+        let next_page_at = self.pages.values().map(PageFreshness::next_check_at).min();
+        let next_run_at = match next_page_at {
+            Some(next_page_at) => next_page_at.min(last_run_at + interval),
+            None => last_run_at + interval,
+        };
+        Ok(next_run_at.max(last_run_at + Duration::seconds(MIN_SCHEDULE_SECS)))
+    }
+
+    // skip_urls returns the urls of the pages not due at now
+    pub fn skip_urls(&self, now: DateTime<Utc>) -> Vec<String> {
+        self.pages
+            .iter()
+            .filter(|(_, page)| page.next_check_at() > now)
+            .map(|(url, _)| url.clone())
+            .collect()
+    }
+
+    // observe adapts the intervals of the pages to the changes found by the last crawl, the
+    // first interval of a page is the time since its last change within the bounds or the
+    // schedule if unknown, indexed_at are the index times by url backfilling the last changes of
+    // the unchanged pages
+    pub fn observe(
+        &mut self,
+        job_id: Uuid,
+        crawl_diff: &CrawlDiff,
+        indexed_at: &HashMap<String, DateTime<Utc>>,
+    ) -> Result<(), Error> {
+        let interval = parse_schedule(&self.schedule)?;
+        let min_interval_secs = (interval / ADAPTIVE_FACTOR)
+            .num_seconds()
+            .max(MIN_SCHEDULE_SECS);
+        let max_interval_secs = (interval * ADAPTIVE_FACTOR).num_seconds();
+        let checked_at = self.last_run_at.unwrap_or(Utc::now());
+        let changed = crawl_diff.added.iter().chain(&crawl_diff.modified);
+        for (url, is_changed) in changed
+            .map(|url| (url, true))
+            .chain(crawl_diff.unchanged_urls.iter().map(|url| (url, false)))
+        {
+            let page = self.pages.entry(url.clone()).or_insert_with(|| {
+                // the fragments of a changed page were just indexed again, its last change
+                // before is unknown
+                let last_changed_at = indexed_at.get(url).copied().filter(|_| !is_changed);
+                let interval_secs = last_changed_at
+                    .map_or(interval.num_seconds(), |last_changed_at| {
+                        (checked_at - last_changed_at).num_seconds()
+                    });
+                PageFreshness {
+                    checks: 0,
+                    changes: 0,
+                    last_checked_at: checked_at,
+                    last_changed_at: last_changed_at,
+                    interval_secs: interval_secs,
+                }
+            });
+            // the first crawl of a page keeps its first interval
+            if page.checks > 0 {
+                page.interval_secs = match is_changed {
+                    true => page.interval_secs / 2,
+                    false => page.interval_secs * 2,
+                };
+            }
+            page.interval_secs = page
+                .interval_secs
+                .clamp(min_interval_secs, max_interval_secs);
+            page.checks += 1;
+            page.last_checked_at = checked_at;
+            if is_changed {
+                page.changes += 1;
+                page.last_changed_at = Some(checked_at);
+            }
+        }
+        for url in &crawl_diff.removed {
+            self.pages.remove(url);
+        }
+        self.observed_job_id = Some(job_id);
+        Ok(())
+    }
+}
+
+// SourceRegistry keeps the sources re-crawled by the server, persisted as a json file so the
+// schedules survive restarts
+#[derive(Debug, Default)]
+pub struct SourceRegistry {
+    // path is the json file of the sources, None keeps them in memory only
+    path: Option<PathBuf>,
+    sources: Mutex<Vec<Source>>,
+}
+
+impl SourceRegistry {
+    // load returns the registry of the sources persisted at path, empty if the file doesn't
+    // exist yet
+    pub fn load(path: Option<PathBuf>) -> Result<Self, Error> {
+        let sources = match &path {
+            Some(path) if path.exists() => {
+                let sources: Vec<Source> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+                info!("Loaded {} sources from {:?}", sources.len(), path);
+                sources
+            }
+            _ => Vec::new(),
+        };
+        Ok(SourceRegistry {
+            path: path,
+            sources: Mutex::new(sources),
+        })
+    }
+
+    // list returns the sources in the order they were added
+    pub fn list(&self) -> Vec<Source> {
+        self.sources.lock().unwrap().clone()
+    }
+
+    // contains returns true if the url is already registered for the base collection
+    pub fn contains(&self, url: &str, base_collection: &Option<String>) -> bool {
+        self.sources
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|source| source.url == url && source.base_collection == *base_collection)
+    }
+
+    // add registers the source and persists the registry
+    pub fn add(&self, source: Source) -> Result<(), Error> {
+        let mut sources = self.sources.lock().unwrap();
+        sources.push(source);
+        self.save(&sources)
+    }
+
+    // due returns the sources whose next crawl is at or before now
+    pub fn due(&self, now: DateTime<Utc>) -> Vec<Source> {
+        self.sources
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|source| source.next_run_at().map_or(false, |next| next <= now))
+            .cloned()
+            .collect()
+    }
+
+    // mark_run records the start and the upload job of a crawl of the source and persists the
+    // registry
+    pub fn mark_run(&self, id: Uuid, job_id: Uuid, at: DateTime<Utc>) -> Result<(), Error> {
+        let mut sources = self.sources.lock().unwrap();
+        if let Some(source) = sources.iter_mut().find(|source| source.id == id) {
+            source.last_run_at = Some(at);
+            source.last_job_id = Some(job_id);
+        }
+        self.save(&sources)
+    }
+
+    // observe adapts the page intervals of the source to the changes found by its crawl and
+    // persists the registry
+    pub fn observe(
+        &self,
+        id: Uuid,
+        job_id: Uuid,
+        crawl_diff: &CrawlDiff,
+        indexed_at: &HashMap<String, DateTime<Utc>>,
+    ) -> Result<(), Error> {
+        let mut sources = self.sources.lock().unwrap();
+        if let Some(source) = sources.iter_mut().find(|source| source.id == id) {
+            source.observe(job_id, crawl_diff, indexed_at)?;
+            info!(
+                "Observed crawl {} of source {}, {} changed pages, {} pages tracked",
+                job_id,
+                source.url,
+                crawl_diff.added.len() + crawl_diff.modified.len(),
+                source.pages.len()
+            );
+        }
+        self.save(&sources)
+    }
+
+    // save writes the sources to a temporary file renamed to the path, so a crash while writing
+    // doesn't lose the registry
+    fn save(&self, sources: &[Source]) -> Result<(), Error> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(sources)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+// parse_schedule returns the interval of a schedule, @hourly, @daily, @weekly or a number
+// followed by m, h or d, of at least MIN_SCHEDULE_SECS
+pub fn parse_schedule(schedule: &str) -> Result<Duration, Error> {
+    let schedule = schedule.trim();
+    let interval = match schedule {
+        "@hourly" => Duration::hours(1),
+        "@daily" => Duration::days(1),
+        "@weekly" => Duration::weeks(1),
+        _ => {
+            let invalid = || {
+                anyhow::anyhow!(
+                    "invalid schedule: {}, expected @hourly, @daily, @weekly or e.g. 30m, 6h, 2d",
+                    schedule
+                )
+            };
+            let split = schedule.char_indices().last().map_or(0, |(index, _)| index);
+            let (count, unit) = schedule.split_at(split);
+            // large counts would overflow the duration
+            let count: i64 = count
+                .parse()
+                .ok()
+                .filter(|count| *count <= MAX_SCHEDULE_COUNT)
+                .ok_or_else(invalid)?;
+            match unit {
+                "m" => Duration::minutes(count),
+                "h" => Duration::hours(count),
+                "d" => Duration::days(count),
+                _ => return Err(invalid()),
+            }
+        }
+    };
+    if interval.num_seconds() < MIN_SCHEDULE_SECS {
+        return Err(anyhow::anyhow!(
+            "schedule {} is shorter than the minimum of {} seconds",
+            schedule,
+            MIN_SCHEDULE_SECS
+        ));
+    }
+    Ok(interval)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedules_are_parsed() {
+        assert_eq!(parse_schedule("@hourly").unwrap(), Duration::hours(1));
+        assert_eq!(parse_schedule(" @daily ").unwrap(), Duration::days(1));
+        assert_eq!(parse_schedule("@weekly").unwrap(), Duration::weeks(1));
+        assert_eq!(parse_schedule("30m").unwrap(), Duration::minutes(30));
+        assert_eq!(parse_schedule("6h").unwrap(), Duration::hours(6));
+        assert_eq!(parse_schedule("2d").unwrap(), Duration::days(2));
+        assert_eq!(parse_schedule("5m").unwrap(), Duration::minutes(5));
+    }
+
+    #[test]
+    fn invalid_schedules_are_rejected() {
+        for schedule in ["", "h", "10", "10x", "-1h", "1.5h", "@monthly", "100001d"] {
+            assert!(parse_schedule(schedule).is_err(), "{}", schedule);
+        }
+        let e = parse_schedule("4m").unwrap_err();
+        assert!(e.to_string().contains("shorter than the minimum"));
+        assert!(Source::new("https://example.com", "often", None, None).is_err());
+    }
+
+    #[test]
+    fn sources_run_on_their_schedule() {
+        let registry = SourceRegistry::load(None).unwrap();
+        let source = Source::new("https://example.com", "@hourly", None, None).unwrap();
+        let id = source.id;
+        let created_at = source.created_at;
+        registry.add(source).unwrap();
+        assert_eq!(registry.due(created_at).len(), 1);
+
+        registry.mark_run(id, Uuid::new_v4(), created_at).unwrap();
+        assert!(registry.due(created_at).is_empty());
+        assert!(registry.due(created_at + Duration::minutes(59)).is_empty());
+        assert_eq!(registry.due(created_at + Duration::hours(1)).len(), 1);
+        assert_eq!(
+            registry.list()[0].next_run_at().unwrap(),
+            created_at + Duration::hours(1)
+        );
+    }
+
+    fn diff(changed: &[&str], unchanged: &[&str], removed: &[&str]) -> CrawlDiff {
+        let urls = |urls: &[&str]| urls.iter().map(|url| url.to_string()).collect();
+        CrawlDiff {
+            url: "https://example.com".to_string(),
+            modified: urls(changed),
+            removed: urls(removed),
+            unchanged: unchanged.len(),
+            unchanged_urls: urls(unchanged),
+            ..Default::default()
+        }
+    }
+
+    fn interval(source: &Source, url: &str) -> i64 {
+        source.pages[url].interval_secs
+    }
+
+    #[test]
+    fn first_intervals_are_backfilled_and_clamped() {
+        let mut source = Source::new("https://example.com", "@daily", None, None).unwrap();
+        let now = Utc::now();
+        source.last_run_at = Some(now);
+        let indexed_at = HashMap::from([
+            ("a".to_string(), now - Duration::days(2)),
+            ("b".to_string(), now - Duration::days(30)),
+            ("d".to_string(), now - Duration::days(2)),
+            ("e".to_string(), now - Duration::minutes(1)),
+        ]);
+        let job_id = Uuid::new_v4();
+        source
+            .observe(
+                job_id,
+                &diff(&["d"], &["a", "b", "c", "e"], &[]),
+                &indexed_at,
+            )
+            .unwrap();
+
+        // the unchanged pages start at the time since they were indexed
+        assert_eq!(interval(&source, "a"), Duration::days(2).num_seconds());
+        assert_eq!(
+            source.pages["a"].last_changed_at,
+            Some(now - Duration::days(2))
+        );
+        // within the schedule divided and multiplied by the adaptive factor
+        assert_eq!(interval(&source, "b"), Duration::days(8).num_seconds());
+        assert_eq!(interval(&source, "e"), Duration::hours(3).num_seconds());
+        // the pages without an index time and the changed pages start at the schedule
+        assert_eq!(interval(&source, "c"), Duration::days(1).num_seconds());
+        assert_eq!(source.pages["c"].last_changed_at, None);
+        assert_eq!(interval(&source, "d"), Duration::days(1).num_seconds());
+        assert_eq!(source.pages["d"].last_changed_at, Some(now));
+        assert_eq!(source.pages["d"].changes, 1);
+        assert!(source.pages.values().all(|page| page.checks == 1));
+        assert_eq!(source.observed_job_id, Some(job_id));
+    }
+
+    #[test]
+    fn intervals_halve_on_changes_and_double_otherwise() {
+        let mut source = Source::new("https://example.com", "@daily", None, None).unwrap();
+        let now = Utc::now();
+        source.last_run_at = Some(now);
+        let no_index_times = HashMap::new();
+        source
+            .observe(
+                Uuid::new_v4(),
+                &diff(&[], &["a", "b", "c"], &[]),
+                &no_index_times,
+            )
+            .unwrap();
+        source.last_run_at = Some(now + Duration::days(1));
+        source
+            .observe(
+                Uuid::new_v4(),
+                &diff(&["a"], &["b"], &["c"]),
+                &no_index_times,
+            )
+            .unwrap();
+        assert_eq!(interval(&source, "a"), Duration::hours(12).num_seconds());
+        assert_eq!(interval(&source, "b"), Duration::days(2).num_seconds());
+        assert!(!source.pages.contains_key("c"));
+        assert_eq!(source.pages["a"].checks, 2);
+        assert_eq!(source.pages["a"].last_checked_at, now + Duration::days(1));
+
+        // the intervals stay within the bounds however often the pages change or not
+        for day in 2..10 {
+            source.last_run_at = Some(now + Duration::days(day));
+            source
+                .observe(Uuid::new_v4(), &diff(&["a"], &["b"], &[]), &no_index_times)
+                .unwrap();
+        }
+        assert_eq!(interval(&source, "a"), Duration::hours(3).num_seconds());
+        assert_eq!(interval(&source, "b"), Duration::days(8).num_seconds());
+    }
+
+    #[test]
+    fn only_the_pages_due_are_crawled() {
+        let mut source = Source::new("https://example.com", "@daily", None, None).unwrap();
+        let now = Utc::now();
+        source.last_run_at = Some(now);
+        let indexed_at = HashMap::from([("fresh".to_string(), now - Duration::minutes(1))]);
+        source
+            .observe(
+                Uuid::new_v4(),
+                &diff(&[], &["fresh", "stale"], &[]),
+                &indexed_at,
+            )
+            .unwrap();
+        assert_eq!(source.skip_urls(now), vec!["fresh", "stale"]);
+        assert_eq!(source.skip_urls(now + Duration::hours(3)), vec!["stale"]);
+        // the first page due brings the next crawl forward
+        assert_eq!(source.next_run_at().unwrap(), now + Duration::hours(3));
+    }
+}
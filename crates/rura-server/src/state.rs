@@ -0,0 +1,306 @@
+use crate::answer_cache::{AnswerCache, DEFAULT_ANSWER_CACHE_SIZE};
+use crate::sources::SourceRegistry;
+use anyhow::{Error, Result};
+use chrono::Utc;
+use log::info;
+use qdrant_client::client::{QdrantClient, QdrantClientConfig};
+use rura_core::answer::DEFAULT_ANSWER_RETRIES;
+use rura_core::chat::ChatStore;
+use rura_core::classify::QueryClassifier;
+use rura_core::collection_model::CollectionModels;
+use rura_core::config::{
+    Config, DEFAULT_BASE_COLLECTION, DEFAULT_OLLAMA_HOST, DEFAULT_OLLAMA_MODEL,
+    DEFAULT_OLLAMA_PORT, DEFAULT_QDRANT_ADDRESS,
+};
+use rura_core::control::JobControls;
+use rura_core::data::Collection;
+use rura_core::embedding::DEFAULT_BATCH_SIZE;
+use rura_core::embedding_cache::{EmbeddingCache, DEFAULT_EMBEDDING_CACHE_SIZE};
+use rura_core::embedding_provider::{EmbeddingConfig, SharedEmbeddingProvider};
+use rura_core::glossary::{Glossary, Synonyms};
+use rura_core::ollama::Llm;
+use rura_core::progress_store::ProgressStore;
+use rura_core::progress_tracker::ProgressTracker;
+use rura_core::prompt_log::PromptLogger;
+use rura_core::qdrant::CollectionConfig;
+use rura_core::report::JobReport;
+use rura_core::retrieval_hook::RetrievalHooks;
+use rura_core::scheduler::JobScheduler;
+use rura_core::spelling::SpellCorrector;
+use rura_core::summary::SummaryQueue;
+use rura_core::tiering::AccessTracker;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
+
+pub struct AppConfig {
+    pub address: String,
+    pub base_collection: String,
+    pub filter_collections: Vec<Collection>,
+    pub ollama_model: String,
+    pub ollama_host: String,
+    pub ollama_port: u16,
+    // fast_model drafts streamed answers and classifies queries if set
+    pub fast_model: Option<String>,
+    pub qdrant_client: Arc<QdrantClient>,
+    pub prompt_logger: Option<Arc<PromptLogger>>,
+    pub query_classifier: QueryClassifier,
+    pub answer_retries: usize,
+    pub embedding_batch_size: usize,
+    pub embedding_provider: SharedEmbeddingProvider,
+    // base_embedding_providers are the embedding providers of the base collections declaring
+    // their own model in the config file, the others use the embedding provider
+    pub base_embedding_providers: HashMap<String, SharedEmbeddingProvider>,
+    // collection_models caches the embedding models of the collections to validate the query
+    // embeddings
+    pub collection_models: Arc<CollectionModels>,
+    // embedding_cache keeps the embeddings of fragments across jobs to reuse them for identical
+    // fragments
+    pub embedding_cache: Arc<EmbeddingCache>,
+    // answer_cache keeps the answers of queries to serve them while qdrant or ollama are down
+    pub answer_cache: Arc<AnswerCache>,
+    // glossary explains the terms used in queries, empty if not configured
+    pub glossary: Arc<Glossary>,
+    // synonyms expand the acronyms and synonyms used in queries, empty if not configured
+    pub synonyms: Synonyms,
+    // spell_corrector fixes the typos of queries before embedding, off if not configured
+    pub spell_corrector: SpellCorrector,
+    // config_file is the content of the config file, e.g. the crawl profiles by domain
+    pub config_file: Config,
+    // qdrant_memory_budget_mb is the memory qdrant can use, dry runs warn if the index exceeds it
+    pub qdrant_memory_budget_mb: Option<u64>,
+    // collection_config sets the sharding and replication of created collections
+    pub collection_config: CollectionConfig,
+    // retrieval_hooks filter and boost the retrieved documents by the request metadata before
+    // they are prompted, none if not configured
+    pub retrieval_hooks: RetrievalHooks,
+    // access_tracker counts the retrievals of the fragments for the tiering of the collections
+    pub access_tracker: Arc<AccessTracker>,
+}
+
+impl AppConfig {
+    // embedding_provider_of returns the embedding provider of a base collection
+    pub fn embedding_provider_of(&self, base_collection: &str) -> &SharedEmbeddingProvider {
+        self.base_embedding_providers
+            .get(base_collection)
+            .unwrap_or(&self.embedding_provider)
+    }
+
+    // llm returns the Llm of the default ollama host
+    pub fn llm(&self) -> Llm {
+        let ollama = ollama_rs::Ollama::new(self.ollama_host.to_string(), self.ollama_port);
+        Llm::new(ollama).with_prompt_logger(self.prompt_logger.clone())
+    }
+
+    // expand_query returns the query embedded for retrieval, spell corrected and expanded with
+    // the synonyms and the glossary definitions of the terms it uses
+    pub async fn expand_query(&self, llm: &Llm, model: &str, query: &str) -> String {
+        let query = self.spell_corrector.correct(llm, model, query).await;
+        self.glossary.expand_query(&self.synonyms.expand(&query))
+    }
+}
+
+pub struct AppState<T: ProgressTracker> {
+    pub progress_map: Arc<ProgressStore<T>>,
+    pub scheduler: Arc<JobScheduler>,
+    pub reports: Arc<Mutex<HashMap<Uuid, JobReport>>>,
+    pub summary_queue: Arc<SummaryQueue>,
+    // chat_sessions keeps the conversations of the chat endpoint
+    pub chat_sessions: Arc<ChatStore>,
+    // job_controls keeps the controls pausing and cancelling the running upload jobs
+    pub job_controls: Arc<JobControls>,
+    // sources are the sites re-crawled on their schedule
+    pub sources: Arc<SourceRegistry>,
+    pub app_config: AppConfig,
+}
+
+#[derive(Default)]
+pub struct AppConfigInput {
+    pub address: Option<String>,
+    pub base_collection: Option<String>,
+    pub filter_collections: Option<Vec<Collection>>,
+    pub ollama_model: Option<String>,
+    pub ollama_host: Option<String>,
+    pub ollama_port: Option<u16>,
+    pub fast_model: Option<String>,
+    pub qdrant_client: Option<Arc<QdrantClient>>,
+    pub prompt_logger: Option<Arc<PromptLogger>>,
+    pub query_classifier: Option<QueryClassifier>,
+    pub answer_retries: Option<usize>,
+    pub embedding_batch_size: Option<usize>,
+    pub embedding_provider: Option<SharedEmbeddingProvider>,
+    // embedding_config is the config the embedding providers of the base collections of the
+    // config file are built from, the default config if None
+    pub embedding_config: Option<EmbeddingConfig>,
+    pub embedding_cache_size: Option<usize>,
+    pub answer_cache_size: Option<usize>,
+    pub glossary: Option<Glossary>,
+    pub synonyms: Option<Synonyms>,
+    pub spell_corrector: Option<SpellCorrector>,
+    pub config_file: Option<Config>,
+    pub qdrant_memory_budget_mb: Option<u64>,
+    pub collection_config: Option<CollectionConfig>,
+    // retrieval_hooks replace the retrieval hooks of the config file, e.g. to add hooks built
+    // in code
+    pub retrieval_hooks: Option<RetrievalHooks>,
+    // sources_path is the json file the re-crawled sources are persisted to
+    pub sources_path: Option<PathBuf>,
+    // jobs_path is the json file the progress of the jobs is persisted to
+    pub jobs_path: Option<PathBuf>,
+    // max_concurrent_jobs caps the upload jobs running at once, None runs all jobs at once
+    pub max_concurrent_jobs: Option<usize>,
+}
+
+impl<T: ProgressTracker + Clone + Send + Sync + Serialize + DeserializeOwned> AppState<T> {
+    pub fn new(app_config_input: AppConfigInput) -> Result<Self, Error> {
+        let filter_collection: Vec<Collection> = app_config_input
+            .filter_collections
+            .unwrap_or(vec![Collection::Basic]);
+        let qdrant_config = QdrantClientConfig::from_url(DEFAULT_QDRANT_ADDRESS);
+        let qdrant_client = match app_config_input.qdrant_client {
+            Some(qdrant_client) => qdrant_client,
+            None => Arc::new(QdrantClient::new(Some(qdrant_config))?),
+        };
+        // the default rust-bert provider is an error if built without the rust-bert feature
+        let embedding_provider = match app_config_input.embedding_provider {
+            Some(embedding_provider) => embedding_provider,
+            None => EmbeddingConfig::default().build()?,
+        };
+        let config_file = app_config_input.config_file.unwrap_or_default();
+        let embedding_config = app_config_input.embedding_config.unwrap_or_default();
+        let mut base_embedding_providers = HashMap::new();
+        for (base_collection, settings) in &config_file.embeddings {
+            let provider = embedding_config.with_settings(settings)?.build()?;
+            info!(
+                "Embedding base collection {} with {}",
+                base_collection,
+                provider.name()
+            );
+            base_embedding_providers.insert(base_collection.clone(), provider);
+        }
+        let retrieval_hooks = match app_config_input.retrieval_hooks {
+            Some(retrieval_hooks) => retrieval_hooks,
+            None => RetrievalHooks::new(&config_file.retrieval_hooks)?,
+        };
+        Ok(AppState {
+            progress_map: Arc::new(ProgressStore::load(app_config_input.jobs_path)?),
+            scheduler: Arc::new(
+                JobScheduler::new().with_max_concurrent_jobs(app_config_input.max_concurrent_jobs),
+            ),
+            reports: Arc::new(Mutex::new(HashMap::new())),
+            summary_queue: Arc::new(SummaryQueue::default()),
+            chat_sessions: Arc::new(ChatStore::default()),
+            job_controls: Arc::new(JobControls::default()),
+            sources: Arc::new(SourceRegistry::load(app_config_input.sources_path)?),
+            app_config: AppConfig {
+                address: app_config_input
+                    .address
+                    .unwrap_or("127.0.0.1:3000".to_string()),
+                base_collection: app_config_input
+                    .base_collection
+                    .unwrap_or(DEFAULT_BASE_COLLECTION.to_string()),
+                filter_collections: filter_collection,
+                ollama_model: app_config_input
+                    .ollama_model
+                    .unwrap_or(DEFAULT_OLLAMA_MODEL.to_string()),
+                ollama_host: app_config_input
+                    .ollama_host
+                    .unwrap_or(DEFAULT_OLLAMA_HOST.to_string()),
+                ollama_port: app_config_input.ollama_port.unwrap_or(DEFAULT_OLLAMA_PORT),
+                fast_model: app_config_input.fast_model,
+                qdrant_client: qdrant_client,
+                prompt_logger: app_config_input.prompt_logger,
+                query_classifier: app_config_input.query_classifier.unwrap_or_default(),
+                answer_retries: app_config_input
+                    .answer_retries
+                    .unwrap_or(DEFAULT_ANSWER_RETRIES),
+                embedding_batch_size: app_config_input
+                    .embedding_batch_size
+                    .unwrap_or(DEFAULT_BATCH_SIZE),
+                embedding_provider: embedding_provider,
+                base_embedding_providers: base_embedding_providers,
+                collection_models: Arc::new(CollectionModels::default()),
+                embedding_cache: Arc::new(EmbeddingCache::new(
+                    app_config_input
+                        .embedding_cache_size
+                        .unwrap_or(DEFAULT_EMBEDDING_CACHE_SIZE),
+                )),
+                answer_cache: Arc::new(AnswerCache::new(
+                    app_config_input
+                        .answer_cache_size
+                        .unwrap_or(DEFAULT_ANSWER_CACHE_SIZE),
+                )),
+                glossary: Arc::new(app_config_input.glossary.unwrap_or_default()),
+                synonyms: app_config_input.synonyms.unwrap_or_default(),
+                spell_corrector: app_config_input.spell_corrector.unwrap_or_default(),
+                config_file: config_file,
+                qdrant_memory_budget_mb: app_config_input.qdrant_memory_budget_mb,
+                collection_config: app_config_input.collection_config.unwrap_or_default(),
+                retrieval_hooks: retrieval_hooks,
+                access_tracker: Arc::new(AccessTracker::default()),
+            },
+        })
+    }
+
+    // evict_finished_jobs removes the finished jobs not updated within the retention period and,
+    // with a history size, the oldest finished jobs beyond it together with their reports and
+    // returns them
+    pub async fn evict_finished_jobs(
+        &self,
+        retention: chrono::Duration,
+        history_size: Option<usize>,
+    ) -> Vec<(Uuid, T)> {
+        let mut cutoff = Utc::now() - retention;
+        if let Some(history_size) = history_size {
+            let mut finished: Vec<_> = self
+                .progress_map
+                .all()
+                .await
+                .into_iter()
+                .filter(|(_, progress)| progress.status().is_finished())
+                .map(|(_, progress)| progress.updated_at())
+                .collect();
+            finished.sort_by(|a, b| b.cmp(a));
+            // the jobs updated before the last job of the history are evicted
+            match history_size.checked_sub(1) {
+                Some(last) => {
+                    if let Some(last_kept) = finished.get(last) {
+                        cutoff = cutoff.max(*last_kept);
+                    }
+                }
+                None => cutoff = Utc::now(),
+            }
+        }
+        let evicted = self
+            .progress_map
+            .remove_where(|progress| {
+                progress.status().is_finished() && progress.updated_at() < cutoff
+            })
+            .await;
+        let mut reports = self.reports.lock().unwrap();
+        for (id, _) in &evicted {
+            reports.remove(id);
+        }
+        if !evicted.is_empty() {
+            info!("Evicted {} finished jobs", evicted.len());
+        }
+        evicted
+    }
+}
+
+// archive_jobs appends the jobs as json lines to the archive file
+pub fn archive_jobs<T: Serialize>(path: &Path, jobs: &[(Uuid, T)]) -> Result<(), Error> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    for (id, progress) in jobs {
+        let line = serde_json::json!({ "id": id, "progress": progress });
+        writeln!(file, "{}", line)?;
+    }
+    Ok(())
+}
@@ -1,8 +0,0 @@
-pub mod api;
-pub mod data;
-pub mod embedding;
-pub mod ollama;
-pub mod progress_tracker;
-pub mod qdrant;
-pub mod retriever;
-pub mod state;